@@ -0,0 +1,61 @@
+// Keeps the last copy of each dashboard page, so an installed dashboard
+// still shows something (marked as offline) when tempmon can't be reached.
+const CACHE = "tempmon-v2";
+const PAGES = ["/", "/history"];
+const ASSETS = ["/manifest.webmanifest", "/static/dashboard.css", "/static/icon.svg"];
+
+self.addEventListener("install", (event) => {
+    event.waitUntil(
+        caches.open(CACHE).then((cache) => cache.addAll(ASSETS))
+    );
+    self.skipWaiting();
+});
+
+self.addEventListener("activate", (event) => {
+    event.waitUntil(
+        caches.keys().then((keys) =>
+            Promise.all(keys.filter((key) => key !== CACHE).map((key) => caches.delete(key)))
+        )
+    );
+    self.clients.claim();
+});
+
+self.addEventListener("fetch", (event) => {
+    const url = new URL(event.request.url);
+    if (event.request.method !== "GET" || url.origin !== self.location.origin) {
+        return;
+    }
+    const page = PAGES.includes(url.pathname);
+    if (!page && !ASSETS.includes(url.pathname) && !url.pathname.startsWith("/static/")) {
+        return;
+    }
+    event.respondWith(
+        fetch(event.request)
+            .then((response) => {
+                if (response.ok) {
+                    const copy = response.clone();
+                    caches.open(CACHE).then((cache) => cache.put(event.request, copy));
+                }
+                return response;
+            })
+            .catch(async () => {
+                const cached = await caches.match(event.request);
+                if (!cached) {
+                    return Response.error();
+                }
+                if (!page) {
+                    return cached;
+                }
+                const html = (await cached.text()).replace(
+                    "<body>",
+                    "<body><div class=\"offline\">Offline: tempmon can't be reached, " +
+                        "so these readings are from " +
+                        (cached.headers.get("Last-Modified") || "earlier") +
+                        "</div>"
+                );
+                return new Response(html, {
+                    headers: { "Content-Type": "text/html; charset=utf-8" },
+                });
+            })
+    );
+});
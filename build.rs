@@ -0,0 +1,14 @@
+fn main() {
+    // generates the grpc server from the schema; protoc comes vendored so
+    // building doesn't need it installed
+    #[cfg(feature = "grpc")]
+    {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("no vendored protoc");
+        // SAFETY: build scripts are single threaded
+        unsafe { std::env::set_var("PROTOC", protoc) };
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/tempmon.proto"], &["proto"])
+            .expect("failed to compile proto/tempmon.proto");
+    }
+}
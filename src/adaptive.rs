@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use crate::config::AdaptiveConfig;
+
+/// How long to wait before the next cycle with `[adaptive]` polling: the
+/// shortest interval while a reading is changing fast or an alert is
+/// pending or firing, then doubling back up to the longest once things
+/// have settled.
+#[derive(Default)]
+pub struct Pace {
+    interval: Option<Duration>,
+    /// each probe's value when it was last read, and when
+    previous: HashMap<String, (f32, Instant)>,
+}
+
+impl Pace {
+    /// The interval after a cycle that read `values`, by probe name.
+    pub fn next(
+        &mut self,
+        config: &AdaptiveConfig,
+        values: &HashMap<String, f32>,
+        alerting: bool,
+        now: Instant,
+    ) -> Duration {
+        let changing = values.iter().any(|(name, value)| {
+            self.previous.get(name).is_some_and(|(previous, at)| {
+                let minutes = now.duration_since(*at).as_secs_f32() / 60.0;
+                minutes > 0.0 && (value - previous).abs() / minutes > config.change_rate
+            })
+        });
+        for (name, value) in values {
+            self.previous.insert(name.clone(), (*value, now));
+        }
+
+        let min = Duration::from_secs(config.min_interval);
+        let max = Duration::from_secs(config.max_interval);
+        let interval = match self.interval {
+            Some(interval) if !changing && !alerting => (interval * 2).clamp(min, max),
+            _ => min,
+        };
+        if self.interval != Some(interval) {
+            debug!(changing, alerting, "polling every {}s", interval.as_secs());
+        }
+        self.interval = Some(interval);
+        interval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next() {
+        let config = AdaptiveConfig {
+            min_interval: 10,
+            max_interval: 60,
+            change_rate: 0.5,
+        };
+        let start = Instant::now();
+        let secs = |n| start + Duration::from_secs(n);
+        let values = |temp| HashMap::from([("fermenter".to_string(), temp)]);
+        let mut pace = Pace::default();
+        let mut next = |temp, alerting, at| {
+            pace.next(&config, &values(temp), alerting, secs(at))
+                .as_secs()
+        };
+
+        assert_eq!(next(20.0, false, 0), 10);
+        // settled, so backing off up to the longest interval
+        assert_eq!(next(20.0, false, 10), 20);
+        assert_eq!(next(20.1, false, 30), 40);
+        assert_eq!(next(20.2, false, 70), 60);
+        assert_eq!(next(20.2, false, 130), 60);
+
+        // a degree in a minute is changing fast
+        assert_eq!(next(21.2, false, 190), 10);
+        assert_eq!(next(21.2, false, 200), 20);
+        // as is an alert
+        assert_eq!(next(21.2, true, 220), 10);
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle, sleep};
+use std::time::{Duration, Instant};
+
+use prometheus::{GaugeVec, register_gauge_vec};
+use tracing::{info, warn};
+
+use crate::alert::Alert;
+use crate::config::AlarmConfig;
+use crate::gpio;
+
+/// How often a quiet alarm checks whether it should start sounding.
+const IDLE_POLL: Duration = Duration::from_millis(100);
+
+/// An output played in its pattern on its own thread, so it keeps time
+/// between polling cycles.
+struct Alarm {
+    config: AlarmConfig,
+    sounding: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Alarm {
+    fn start(name: &str, config: &AlarmConfig) -> std::io::Result<Self> {
+        let pin = gpio::Output::open(&config.gpio)?;
+        let sounding = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+        let steps = config.pattern.steps();
+        let thread = {
+            let (name, sounding, stop) = (name.to_string(), sounding.clone(), stop.clone());
+            thread::spawn(move || play(&name, &pin, &steps, &sounding, &stop))
+        };
+        Ok(Alarm {
+            config: config.clone(),
+            sounding,
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Alarm {
+    /// Silences the output before letting go of it.
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Plays `steps` on the pin while `sounding` is set, until `stop` is.
+fn play(name: &str, pin: &gpio::Output, steps: &[u64], sounding: &AtomicBool, stop: &AtomicBool) {
+    let set = |on| {
+        if let Err(e) = pin.set(on) {
+            warn!(alarm = name, "failed to switch alarm: {}", e);
+        }
+    };
+    while !stop.load(Ordering::Relaxed) {
+        if !sounding.load(Ordering::Relaxed) {
+            sleep(IDLE_POLL);
+            continue;
+        }
+        for (i, &ms) in steps.iter().enumerate() {
+            if ms == 0 {
+                continue;
+            }
+            if stop.load(Ordering::Relaxed) || !sounding.load(Ordering::Relaxed) {
+                break;
+            }
+            set(i % 2 == 0);
+            // stop part way through a long step as soon as it's silenced
+            let until = Instant::now() + Duration::from_millis(ms);
+            while Instant::now() < until {
+                if stop.load(Ordering::Relaxed) || !sounding.load(Ordering::Relaxed) {
+                    break;
+                }
+                sleep(
+                    until
+                        .saturating_duration_since(Instant::now())
+                        .min(IDLE_POLL),
+                );
+            }
+        }
+        if !sounding.load(Ordering::Relaxed) {
+            set(false);
+        }
+    }
+    set(false);
+}
+
+/// The outputs in `[alarm]`, sounding while an alert they cover fires.
+pub struct Alarms {
+    alarms: BTreeMap<String, Alarm>,
+    metric: GaugeVec,
+}
+
+impl Alarms {
+    pub fn start(configs: &HashMap<String, AlarmConfig>) -> prometheus::Result<Self> {
+        let mut alarms = Alarms {
+            alarms: BTreeMap::new(),
+            metric: register_gauge_vec!(
+                "dash_alarm_sounding",
+                "whether a local alarm output is sounding (1) or not (0)",
+                &["alarm"]
+            )?,
+        };
+        alarms.reload(configs);
+        Ok(alarms)
+    }
+
+    /// Applies a new `[alarm]` config, reopening any alarm whose settings
+    /// changed. An alarm that was sounding carries on from the next cycle.
+    pub fn reload(&mut self, configs: &HashMap<String, AlarmConfig>) {
+        let stale: Vec<_> = self
+            .alarms
+            .iter()
+            .filter(|(name, alarm)| configs.get(*name) != Some(&alarm.config))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in stale {
+            // dropping it switches it off and releases the pin
+            self.alarms.remove(&name);
+            let _ = self.metric.remove_label_values(&[&name]);
+        }
+        for (name, config) in configs {
+            if self.alarms.contains_key(name) {
+                continue;
+            }
+            match Alarm::start(name, config) {
+                Ok(alarm) => {
+                    self.metric.with_label_values(&[name]).set(0.0);
+                    self.alarms.insert(name.clone(), alarm);
+                }
+                Err(e) => warn!(alarm = %name, "alarm disabled, can't open output: {}", e),
+            }
+        }
+    }
+
+    /// Sounds each alarm covering one of the firing, unsilenced alerts and
+    /// quiets the rest. `matches(target, probe)` says whether an alarm
+    /// target covers an alert's probe.
+    pub fn update(&self, firing: &[Alert], matches: impl Fn(&str, &str) -> bool) {
+        for (name, alarm) in &self.alarms {
+            let sound = firing.iter().any(|a| {
+                alarm
+                    .config
+                    .sounds_for(a.severity, |target| matches(target, &a.probe))
+            });
+            if alarm.sounding.swap(sound, Ordering::Relaxed) != sound {
+                info!(alarm = %name, "alarm {}", if sound { "sounding" } else { "quiet" });
+            }
+            self.metric
+                .with_label_values(&[name])
+                .set(if sound { 1.0 } else { 0.0 });
+        }
+    }
+
+    /// Silences every alarm and releases its pin, e.g. when tempmon stops.
+    pub fn shutdown(&mut self) {
+        self.alarms.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{GpioConfig, NamedPattern, Pattern, Severity};
+
+    #[test]
+    fn test_patterns() {
+        assert_eq!(Pattern::Named(NamedPattern::Blink).steps(), [500, 500]);
+        let sos = Pattern::Named(NamedPattern::Sos).steps();
+        assert_eq!(sos.len(), 18);
+        assert_eq!(sos.iter().step_by(2).filter(|&&on| on == 600).count(), 3);
+        assert_eq!(Pattern::Custom(vec![100, 900]).steps(), [100, 900]);
+    }
+
+    #[test]
+    fn test_sounds_for() {
+        let mut config = AlarmConfig {
+            gpio: GpioConfig {
+                pin: 27,
+                chip: None,
+                active_low: false,
+            },
+            pattern: Pattern::default(),
+            severity: None,
+            targets: Vec::new(),
+        };
+        assert!(config.sounds_for(Severity::Info, |_| false));
+
+        config.severity = Some(Severity::Warning);
+        assert!(!config.sounds_for(Severity::Info, |_| true));
+        assert!(config.sounds_for(Severity::Critical, |_| true));
+
+        config.targets = vec!["fermenter".to_string()];
+        assert!(config.sounds_for(Severity::Warning, |t| t == "fermenter"));
+        assert!(!config.sounds_for(Severity::Warning, |t| t == "freezer"));
+    }
+}
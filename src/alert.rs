@@ -0,0 +1,564 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::{AlertRule, AlertsConfig, Severity};
+
+/// How many resolved alerts are kept for the api.
+const RESOLVED_HISTORY: usize = 20;
+
+/// What a rule alerts on: either side of its thresholds, or a reading that
+/// has stopped changing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Kind {
+    High,
+    Low,
+    Flatline,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 3] = [Kind::High, Kind::Low, Kind::Flatline];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::High => "high",
+            Kind::Low => "low",
+            Kind::Flatline => "flatline",
+        }
+    }
+
+    /// The rule's limit for this kind of alert: a temperature, or seconds
+    /// for flatline alerts.
+    pub fn threshold(self, rule: &AlertRule) -> Option<f32> {
+        match self {
+            Kind::High => rule.high,
+            Kind::Low => rule.low,
+            Kind::Flatline => rule.flatline.map(|secs| secs as f32),
+        }
+    }
+}
+
+/// The kinds of alert that compare each reading against a temperature;
+/// flatline alerts go by how readings change instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Bound {
+    High,
+    Low,
+}
+
+impl Bound {
+    fn breached(self, temp: f32, threshold: f32) -> bool {
+        match self {
+            Bound::High => temp > threshold,
+            Bound::Low => temp < threshold,
+        }
+    }
+
+    /// Whether a firing alert has come back far enough to resolve.
+    fn cleared(self, temp: f32, threshold: f32, hysteresis: f32) -> bool {
+        match self {
+            Bound::High => temp <= threshold - hysteresis,
+            Bound::Low => temp >= threshold + hysteresis,
+        }
+    }
+}
+
+impl From<Bound> for Kind {
+    fn from(bound: Bound) -> Kind {
+        match bound {
+            Bound::High => Kind::High,
+            Bound::Low => Kind::Low,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// breached, but not for long enough to fire yet
+    Pending,
+    Firing,
+    Resolved,
+}
+
+impl State {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            State::Pending => "pending",
+            State::Firing => "firing",
+            State::Resolved => "resolved",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Alert {
+    pub probe: String,
+    pub kind: Kind,
+    pub threshold: f32,
+    /// the reading that last changed the alert's state
+    pub value: f32,
+    pub state: State,
+    /// when the alert entered its current state
+    pub since: SystemTime,
+    /// when the reading first crossed the threshold
+    pub started: SystemTime,
+    pub fired: Option<SystemTime>,
+    pub severity: Severity,
+    breached_at: Instant,
+}
+
+impl Alert {
+    /// A pending alert for a reading that has just crossed `threshold`.
+    pub fn new(probe: &str, kind: Kind, threshold: f32, value: f32) -> Self {
+        let now = SystemTime::now();
+        Alert {
+            probe: probe.to_string(),
+            kind,
+            threshold,
+            value,
+            state: State::Pending,
+            since: now,
+            started: now,
+            fired: None,
+            severity: Severity::default(),
+            breached_at: Instant::now(),
+        }
+    }
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = match self.kind {
+            Kind::High => "above",
+            Kind::Low => "below",
+            Kind::Flatline => {
+                return match self.state {
+                    State::Resolved => {
+                        write!(f, "{} is changing again ({:.2}°C)", self.probe, self.value)
+                    }
+                    _ => write!(
+                        f,
+                        "{} has read {:.2}°C for {:.0}s, the probe may be stuck",
+                        self.probe, self.value, self.threshold
+                    ),
+                };
+            }
+        };
+        match self.state {
+            State::Resolved => write!(
+                f,
+                "{} is back within its {} threshold of {:.2}°C ({:.2}°C)",
+                self.probe,
+                self.kind.as_str(),
+                self.threshold,
+                self.value
+            ),
+            _ => write!(
+                f,
+                "{} is {} {:.2}°C ({:.2}°C)",
+                self.probe, side, self.threshold, self.value
+            ),
+        }
+    }
+}
+
+/// Tracks threshold alerts across polling cycles.
+#[derive(Default)]
+pub struct Engine {
+    alerts: HashMap<(String, Kind), Alert>,
+    /// most recently resolved first
+    resolved: VecDeque<Alert>,
+    /// the raw reading each probe last moved to, and when
+    unchanged: HashMap<String, (f32, Instant)>,
+}
+
+impl Engine {
+    /// Checks a probe's reading against its rule and returns the alerts
+    /// that fired or resolved because of it.
+    pub fn evaluate(
+        &mut self,
+        id: &str,
+        name: &str,
+        temp: f32,
+        config: &AlertsConfig,
+        now: Instant,
+    ) -> Vec<Alert> {
+        self.evaluate_rule(name, temp, config.rule(id, name), config, now)
+    }
+
+    /// Checks the difference between a pair of probes against the delta
+    /// rule called `name`, like [`Engine::evaluate`].
+    pub fn evaluate_delta(
+        &mut self,
+        name: &str,
+        delta: f32,
+        config: &AlertsConfig,
+        now: Instant,
+    ) -> Vec<Alert> {
+        let rule = config.deltas.get(name).map(|d| &d.rule);
+        self.evaluate_rule(name, delta, rule, config, now)
+    }
+
+    fn evaluate_rule(
+        &mut self,
+        name: &str,
+        temp: f32,
+        rule: Option<&AlertRule>,
+        config: &AlertsConfig,
+        now: Instant,
+    ) -> Vec<Alert> {
+        let duration = Duration::from_secs(rule.map_or(0, |r| config.duration(r)));
+        let hysteresis = rule.map_or(0.0, |r| config.hysteresis(r));
+        let severity = rule.map_or(config.severity, |r| config.severity(r));
+
+        let mut changed = Vec::new();
+        for bound in [Bound::High, Bound::Low] {
+            let kind = Kind::from(bound);
+            let key = (name.to_string(), kind);
+            let threshold = rule.and_then(|r| kind.threshold(r));
+            // a pending alert has to stay breached the whole time, a firing
+            // one holds until the reading is back past the hysteresis
+            let firing = self
+                .alerts
+                .get(&key)
+                .is_some_and(|a| a.state == State::Firing);
+            let breached = threshold.is_some_and(|t| {
+                if firing {
+                    !bound.cleared(temp, t, hysteresis)
+                } else {
+                    bound.breached(temp, t)
+                }
+            });
+
+            if !breached {
+                // a rule removed by a reload resolves its alerts as well
+                changed.extend(self.resolve(key, temp));
+                continue;
+            }
+
+            let alert = self.alerts.entry(key).or_insert_with(|| Alert {
+                breached_at: now,
+                ..Alert::new(name, kind, threshold.unwrap(), temp)
+            });
+            alert.threshold = threshold.unwrap();
+            alert.severity = severity;
+            if alert.state == State::Pending {
+                alert.value = temp;
+                if now.duration_since(alert.breached_at) >= duration {
+                    alert.state = State::Firing;
+                    alert.since = SystemTime::now();
+                    alert.fired = Some(alert.since);
+                    changed.push(alert.clone());
+                }
+            }
+        }
+        changed
+    }
+
+    /// Checks whether a probe's raw reading has stayed on the same value
+    /// for its rule's flatline period, which usually means a shorted data
+    /// line returning a frozen scratchpad. Readings less than `tolerance`
+    /// apart count as the same value. Returns the alert if it fired or
+    /// resolved.
+    pub fn check_flatline(
+        &mut self,
+        id: &str,
+        name: &str,
+        raw: f32,
+        tolerance: f32,
+        config: &AlertsConfig,
+        now: Instant,
+    ) -> Option<Alert> {
+        let rule = config.rule(id, name);
+        let period = rule.and_then(|r| r.flatline);
+        let since = match self.unchanged.get(name) {
+            Some(&(value, since)) if (raw - value).abs() < tolerance => since,
+            _ => {
+                self.unchanged.insert(name.to_string(), (raw, now));
+                now
+            }
+        };
+
+        let key = (name.to_string(), Kind::Flatline);
+        let stuck = period.is_some_and(|p| now.duration_since(since) >= Duration::from_secs(p));
+        if !stuck {
+            return self.resolve(key, raw);
+        }
+        if self.alerts.contains_key(&key) {
+            return None;
+        }
+
+        let mut alert = Alert {
+            breached_at: since,
+            severity: rule.map_or(config.severity, |r| config.severity(r)),
+            ..Alert::new(name, Kind::Flatline, period.unwrap() as f32, raw)
+        };
+        alert.state = State::Firing;
+        alert.started = alert.since - now.duration_since(since);
+        alert.fired = Some(alert.since);
+        self.alerts.insert(key, alert.clone());
+        Some(alert)
+    }
+
+    /// Resolves an alert if it was firing, or drops it quietly if it was
+    /// still pending.
+    fn resolve(&mut self, key: (String, Kind), value: f32) -> Option<Alert> {
+        let mut alert = self.alerts.remove(&key)?;
+        if alert.state != State::Firing {
+            return None;
+        }
+        alert.state = State::Resolved;
+        alert.value = value;
+        alert.since = SystemTime::now();
+        self.resolved.push_front(alert.clone());
+        self.resolved.truncate(RESOLVED_HISTORY);
+        Some(alert)
+    }
+
+    /// Pending and firing alerts sorted by probe, then the most recently
+    /// resolved ones, newest first.
+    pub fn alerts(&self) -> Vec<Alert> {
+        let mut alerts: Vec<_> = self.alerts.values().cloned().collect();
+        alerts.sort_by(|a, b| (&a.probe, a.kind).cmp(&(&b.probe, b.kind)));
+        alerts.extend(self.resolved.iter().cloned());
+        alerts
+    }
+
+    pub fn is_firing(&self, name: &str, kind: Kind) -> bool {
+        self.alerts
+            .get(&(name.to_string(), kind))
+            .is_some_and(|a| a.state == State::Firing)
+    }
+
+    /// Moves a probe's alerts over to a new display name.
+    pub fn rename(&mut self, old: &str, new: &str) {
+        for kind in Kind::ALL {
+            if let Some(mut alert) = self.alerts.remove(&(old.to_string(), kind)) {
+                alert.probe = new.to_string();
+                self.alerts.insert((new.to_string(), kind), alert);
+            }
+        }
+        if let Some(unchanged) = self.unchanged.remove(old) {
+            self.unchanged.insert(new.to_string(), unchanged);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(duration: u64) -> AlertsConfig {
+        toml::from_str(&format!(
+            "duration = {}\n[rules.tank]\nhigh = 30.0\nlow = 20.0\n",
+            duration
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_fires_and_resolves() {
+        let config = config(0);
+        let mut engine = Engine::default();
+        let now = Instant::now();
+
+        assert!(
+            engine
+                .evaluate("28-abc", "tank", 25.0, &config, now)
+                .is_empty()
+        );
+
+        let fired = engine.evaluate("28-abc", "tank", 31.0, &config, now);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].kind, Kind::High);
+        assert_eq!(fired[0].state, State::Firing);
+        assert_eq!(fired[0].severity, Severity::Warning);
+
+        // still firing, nothing new to report
+        assert!(
+            engine
+                .evaluate("28-abc", "tank", 32.0, &config, now)
+                .is_empty()
+        );
+        assert_eq!(engine.alerts().len(), 1);
+
+        let resolved = engine.evaluate("28-abc", "tank", 25.0, &config, now);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].state, State::Resolved);
+        assert!(!engine.is_firing("tank", Kind::High));
+
+        // kept in the history
+        let alerts = engine.alerts();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].state, State::Resolved);
+        assert!(alerts[0].fired.is_some());
+    }
+
+    #[test]
+    fn test_waits_for_duration() {
+        let config = config(60);
+        let mut engine = Engine::default();
+        let start = Instant::now();
+
+        assert!(
+            engine
+                .evaluate("28-abc", "tank", 19.0, &config, start)
+                .is_empty()
+        );
+        assert_eq!(engine.alerts()[0].state, State::Pending);
+        let later = start + Duration::from_secs(30);
+        assert!(
+            engine
+                .evaluate("28-abc", "tank", 19.0, &config, later)
+                .is_empty()
+        );
+
+        let later = start + Duration::from_secs(60);
+        let fired = engine.evaluate("28-abc", "tank", 19.5, &config, later);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].kind, Kind::Low);
+    }
+
+    #[test]
+    fn test_pending_clears_quietly() {
+        let config = config(60);
+        let mut engine = Engine::default();
+        let now = Instant::now();
+
+        engine.evaluate("28-abc", "tank", 31.0, &config, now);
+        assert!(
+            engine
+                .evaluate("28-abc", "tank", 25.0, &config, now)
+                .is_empty()
+        );
+        assert!(engine.alerts().is_empty());
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let config: AlertsConfig =
+            toml::from_str("[rules.tank]\nhigh = 30.0\nhysteresis = 2.0\n").unwrap();
+        let mut engine = Engine::default();
+        let now = Instant::now();
+
+        assert_eq!(
+            engine.evaluate("28-abc", "tank", 30.5, &config, now).len(),
+            1
+        );
+        // back under the trigger, but not the clear level
+        assert!(
+            engine
+                .evaluate("28-abc", "tank", 29.0, &config, now)
+                .is_empty()
+        );
+        assert!(
+            engine
+                .evaluate("28-abc", "tank", 30.5, &config, now)
+                .is_empty()
+        );
+
+        let resolved = engine.evaluate("28-abc", "tank", 28.0, &config, now);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].state, State::Resolved);
+    }
+
+    #[test]
+    fn test_removed_rule_resolves() {
+        let mut engine = Engine::default();
+        let now = Instant::now();
+
+        engine.evaluate("28-abc", "tank", 31.0, &config(0), now);
+        let resolved = engine.evaluate("28-abc", "tank", 31.0, &AlertsConfig::default(), now);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].state, State::Resolved);
+    }
+
+    #[test]
+    fn test_delta() {
+        let config: AlertsConfig = toml::from_str(
+            "[deltas.heating]\nprobes = [\"flow\", \"return\"]\nlow = 5.0\nhysteresis = 1.0\n",
+        )
+        .unwrap();
+        let mut engine = Engine::default();
+        let now = Instant::now();
+
+        assert!(
+            engine
+                .evaluate_delta("heating", 12.0, &config, now)
+                .is_empty()
+        );
+        let fired = engine.evaluate_delta("heating", 2.5, &config, now);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].probe, "heating");
+        assert_eq!(fired[0].kind, Kind::Low);
+        assert!(
+            engine
+                .evaluate_delta("heating", 5.5, &config, now)
+                .is_empty()
+        );
+        assert_eq!(engine.evaluate_delta("heating", 6.0, &config, now).len(), 1);
+
+        // removed from the config
+        engine.evaluate_delta("heating", 2.5, &config, now);
+        let resolved = engine.evaluate_delta("heating", 0.0, &AlertsConfig::default(), now);
+        assert_eq!(resolved[0].state, State::Resolved);
+    }
+
+    #[test]
+    fn test_flatline() {
+        let config: AlertsConfig = toml::from_str("[rules.tank]\nflatline = 600\n").unwrap();
+        let mut engine = Engine::default();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(
+            engine
+                .check_flatline("28-abc", "tank", 21.25, 0.125, &config, at(0))
+                .is_none()
+        );
+        // a change smaller than the resolution doesn't count
+        assert!(
+            engine
+                .check_flatline("28-abc", "tank", 21.3, 0.125, &config, at(300))
+                .is_none()
+        );
+
+        let fired = engine
+            .check_flatline("28-abc", "tank", 21.25, 0.125, &config, at(600))
+            .unwrap();
+        assert_eq!(fired.kind, Kind::Flatline);
+        assert_eq!(fired.state, State::Firing);
+        assert!(engine.is_firing("tank", Kind::Flatline));
+        assert!(
+            engine
+                .check_flatline("28-abc", "tank", 21.25, 0.125, &config, at(900))
+                .is_none()
+        );
+
+        let resolved = engine
+            .check_flatline("28-abc", "tank", 21.5, 0.125, &config, at(915))
+            .unwrap();
+        assert_eq!(resolved.state, State::Resolved);
+
+        // the period starts again from the new value
+        assert!(
+            engine
+                .check_flatline("28-abc", "tank", 21.5, 0.125, &config, at(1200))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_flatline_needs_rule() {
+        let mut engine = Engine::default();
+        let start = Instant::now();
+
+        engine.check_flatline("28-abc", "tank", 21.25, 0.125, &config(0), start);
+        let later = start + Duration::from_secs(86400);
+        assert!(
+            engine
+                .check_flatline("28-abc", "tank", 21.25, 0.125, &config(0), later)
+                .is_none()
+        );
+    }
+}
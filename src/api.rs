@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::alert::{Alert, State};
+use crate::state::Silence;
+
+#[derive(Serialize)]
+struct Reading<'a> {
+    probe: &'a str,
+    temperature: Option<f32>,
+}
+
+#[derive(Serialize)]
+struct Readings<'a> {
+    readings: Vec<Reading<'a>>,
+}
+
+#[derive(Serialize)]
+struct Health<'a> {
+    status: &'a str,
+    failing_probes: Vec<&'a str>,
+}
+
+/// Body for an unhealthy `/health` response.
+pub fn unhealthy(failing_probes: Vec<&str>) -> String {
+    serde_json::to_string(&Health {
+        status: "unhealthy",
+        failing_probes,
+    })
+    .unwrap()
+}
+
+#[derive(Serialize)]
+struct AlertStatus<'a> {
+    probe: &'a str,
+    alert: &'a str,
+    state: &'a str,
+    severity: &'a str,
+    threshold: f32,
+    value: f32,
+    /// unix timestamps
+    started_at: u64,
+    fired_at: Option<u64>,
+    resolved_at: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Alerts<'a> {
+    alerts: Vec<AlertStatus<'a>>,
+}
+
+/// Pending, firing and recently resolved alerts as JSON.
+pub fn alerts(alerts: &[Alert]) -> String {
+    let alerts = alerts
+        .iter()
+        .map(|a| AlertStatus {
+            probe: &a.probe,
+            alert: a.kind.as_str(),
+            state: a.state.as_str(),
+            severity: a.severity.as_str(),
+            threshold: a.threshold,
+            value: a.value,
+            started_at: unix_seconds(a.started),
+            fired_at: a.fired.map(unix_seconds),
+            resolved_at: (a.state == State::Resolved).then(|| unix_seconds(a.since)),
+        })
+        .collect();
+    serde_json::to_string(&Alerts { alerts }).unwrap()
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Body of a request to add a silence.
+#[derive(Deserialize)]
+pub struct NewSilence {
+    /// probe id, probe label or alert group
+    pub target: String,
+    /// seconds
+    pub duration: u64,
+    pub comment: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SilenceStatus<'a> {
+    id: u64,
+    target: &'a str,
+    /// unix timestamp
+    until: u64,
+    comment: Option<&'a str>,
+    source: &'a str,
+}
+
+impl<'a> SilenceStatus<'a> {
+    fn new(silence: &'a Silence) -> Self {
+        SilenceStatus {
+            id: silence.id,
+            target: &silence.target,
+            until: unix_seconds(silence.until),
+            comment: silence.comment.as_deref(),
+            source: if silence.from_config { "config" } else { "api" },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Silences<'a> {
+    silences: Vec<SilenceStatus<'a>>,
+}
+
+pub fn silence(silence: &Silence) -> String {
+    serde_json::to_string(&SilenceStatus::new(silence)).unwrap()
+}
+
+pub fn silences(silences: &[Silence]) -> String {
+    let silences = silences.iter().map(SilenceStatus::new).collect();
+    serde_json::to_string(&Silences { silences }).unwrap()
+}
+
+#[derive(Serialize)]
+struct Reset<'a> {
+    probe: &'a str,
+    released: bool,
+}
+
+/// Body for a `/api/v1/probes/<name>/reset` response. `released` is false
+/// when the probe wasn't quarantined.
+pub fn reset(probe: &str, released: bool) -> String {
+    serde_json::to_string(&Reset { probe, released }).unwrap()
+}
+
+#[derive(Serialize)]
+struct TestResult<'a> {
+    notifier: &'a str,
+    ok: bool,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TestResults<'a> {
+    results: Vec<TestResult<'a>>,
+}
+
+/// Body for a `/api/v1/alerts/test` response, one result per notifier.
+pub fn test_results<E: ToString>(results: &[(String, Result<(), E>)]) -> String {
+    let results = results
+        .iter()
+        .map(|(notifier, result)| TestResult {
+            notifier,
+            ok: result.is_ok(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        })
+        .collect();
+    serde_json::to_string(&TestResults { results }).unwrap()
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub first_reading: bool,
+    pub server_bound: bool,
+}
+
+impl Readiness {
+    pub fn new(first_reading: bool, server_bound: bool) -> Self {
+        Readiness {
+            ready: first_reading && server_bound,
+            first_reading,
+            server_bound,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// Current readings as JSON, sorted by probe name.
+pub fn readings(temps: &HashMap<String, Option<f32>>) -> String {
+    let mut readings: Vec<_> = temps
+        .iter()
+        .map(|(probe, temperature)| Reading {
+            probe,
+            temperature: *temperature,
+        })
+        .collect();
+    readings.sort_by_key(|r| r.probe);
+
+    serde_json::to_string(&Readings { readings }).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alerts_json() {
+        use crate::alert::Kind;
+
+        let pending = Alert::new("freezer", Kind::High, -15.0, -14.0);
+        let mut resolved = Alert::new("fermenter", Kind::Low, 18.0, 19.0);
+        resolved.state = State::Resolved;
+        resolved.fired = Some(resolved.started);
+
+        let json: serde_json::Value = serde_json::from_str(&alerts(&[pending, resolved])).unwrap();
+        let alerts = json["alerts"].as_array().unwrap();
+        assert_eq!(alerts[0]["state"], "pending");
+        assert_eq!(alerts[0]["alert"], "high");
+        assert_eq!(alerts[0]["severity"], "warning");
+        assert!(alerts[0]["started_at"].as_u64().unwrap() > 0);
+        assert!(alerts[0]["fired_at"].is_null());
+        assert!(alerts[0]["resolved_at"].is_null());
+        assert_eq!(alerts[1]["state"], "resolved");
+        assert!(alerts[1]["fired_at"].is_u64());
+        assert!(alerts[1]["resolved_at"].is_u64());
+    }
+
+    #[test]
+    fn test_readiness_json() {
+        let json: serde_json::Value =
+            serde_json::from_str(&Readiness::new(false, true).to_json()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"ready": false, "first_reading": false, "server_bound": true})
+        );
+        assert!(Readiness::new(true, true).ready);
+    }
+
+    #[test]
+    fn test_test_results_json() {
+        let results = [
+            ("webhook".to_string(), Ok(())),
+            ("email".to_string(), Err("connection refused")),
+        ];
+        let json: serde_json::Value = serde_json::from_str(&test_results(&results)).unwrap();
+        let results = json["results"].as_array().unwrap();
+        assert_eq!(results[0]["notifier"], "webhook");
+        assert_eq!(results[0]["ok"], true);
+        assert!(results[0]["error"].is_null());
+        assert_eq!(results[1]["ok"], false);
+        assert_eq!(results[1]["error"], "connection refused");
+    }
+
+    #[test]
+    fn test_readings_json() {
+        let mut temps = HashMap::new();
+        temps.insert("cool_side".to_string(), None);
+        temps.insert("basking_spot".to_string(), Some(31.25));
+
+        assert_eq!(
+            readings(&temps),
+            r#"{"readings":[{"probe":"basking_spot","temperature":31.25},{"probe":"cool_side","temperature":null}]}"#
+        );
+    }
+}
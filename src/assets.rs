@@ -0,0 +1,87 @@
+/// A file compiled into the binary.
+pub struct Asset {
+    pub content_type: &'static str,
+    pub body: &'static [u8],
+}
+
+/// Served under `/static/`, for the dashboard and for user templates.
+const ASSETS: &[(&str, Asset)] = &[
+    (
+        "dashboard.css",
+        Asset {
+            content_type: "text/css; charset=utf-8",
+            body: include_bytes!("../assets/dashboard.css"),
+        },
+    ),
+    (
+        "favicon.ico",
+        Asset {
+            content_type: "image/x-icon",
+            body: include_bytes!("../assets/favicon.ico"),
+        },
+    ),
+    (
+        "icon.svg",
+        Asset {
+            content_type: "image/svg+xml",
+            body: include_bytes!("../assets/icon.svg"),
+        },
+    ),
+    (
+        "icon-180.png",
+        Asset {
+            content_type: "image/png",
+            body: include_bytes!("../assets/icon-180.png"),
+        },
+    ),
+    (
+        "icon-192.png",
+        Asset {
+            content_type: "image/png",
+            body: include_bytes!("../assets/icon-192.png"),
+        },
+    ),
+    (
+        "icon-512.png",
+        Asset {
+            content_type: "image/png",
+            body: include_bytes!("../assets/icon-512.png"),
+        },
+    ),
+];
+
+/// The asset at a `/static/<name>` url, or `/favicon.ico`, which browsers
+/// ask for whatever the page says.
+pub fn get(url: &str) -> Option<&'static Asset> {
+    let name = match url {
+        "/favicon.ico" => "favicon.ico",
+        url => url.strip_prefix("/static/")?,
+    };
+    ASSETS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, asset)| asset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get() {
+        assert_eq!(
+            get("/static/icon.svg").unwrap().content_type,
+            "image/svg+xml"
+        );
+        assert!(get("/favicon.ico").unwrap().body.starts_with(&[0, 0, 1, 0]));
+        assert!(
+            get("/static/icon-512.png")
+                .unwrap()
+                .body
+                .starts_with(b"\x89PNG")
+        );
+        assert!(get("/static/../Cargo.toml").is_none());
+        assert!(get("/static/").is_none());
+        assert!(get("/icon.svg").is_none());
+    }
+}
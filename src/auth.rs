@@ -0,0 +1,257 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::{env, fs, io};
+
+use argon2::{Argon2, PasswordVerifier};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use http::Request;
+use http::header::AUTHORIZATION;
+use serde::{Deserialize, Serialize};
+
+use crate::config::AuthConfig;
+
+/// Groups of routes that can be protected or left open independently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteGroup {
+    Dashboard,
+    Api,
+    Metrics,
+    Health,
+}
+
+impl RouteGroup {
+    /// The group a request's path belongs to; any query string is ignored.
+    pub fn for_path(path: &str) -> RouteGroup {
+        let path = path.split_once('?').map_or(path, |(path, _)| path);
+        match path {
+            "/metrics" => RouteGroup::Metrics,
+            "/health" | "/healthz" | "/readyz" => RouteGroup::Health,
+            p if p.starts_with("/api/") => RouteGroup::Api,
+            _ => RouteGroup::Dashboard,
+        }
+    }
+}
+
+const TOKENS_ENV: &str = "TEMPMON_API_TOKENS";
+
+/// How many verified authorization headers are remembered. There's only
+/// one username, so this is plenty, and the cache starts afresh once full.
+const VERIFIED_MAX: usize = 16;
+
+pub struct Authenticator {
+    config: AuthConfig,
+    tokens: Vec<String>,
+    // password hashing is deliberately slow (seconds on a pi zero), so
+    // remember the authorization headers that have already been verified
+    verified: Mutex<HashSet<String>>,
+}
+
+impl Authenticator {
+    pub fn new(config: AuthConfig) -> io::Result<Self> {
+        let mut tokens = config.tokens.clone();
+
+        if let Some(path) = &config.token_file {
+            tokens.extend(parse_tokens(&fs::read_to_string(path)?));
+        }
+        if let Ok(value) = env::var(TOKENS_ENV) {
+            tokens.extend(value.split(',').map(|t| t.trim().to_string()));
+        }
+        tokens.retain(|t| !t.is_empty());
+
+        Ok(Authenticator {
+            config,
+            tokens,
+            verified: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// The scheme advertised to clients in `WWW-Authenticate`.
+    pub fn challenge(&self) -> &'static str {
+        if self.config.password_hash.is_some() {
+            "Basic realm=\"tempmon\""
+        } else {
+            "Bearer realm=\"tempmon\""
+        }
+    }
+
+    pub fn is_public(&self, group: RouteGroup) -> bool {
+        self.config.public_routes.contains(&group)
+    }
+
+    pub fn authorize<B>(&self, request: &Request<B>) -> bool {
+        let group = RouteGroup::for_path(request.uri().path());
+        if self.is_public(group) {
+            return true;
+        }
+
+        let Some(header) = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+        else {
+            return false;
+        };
+
+        if let Some(token) = header.strip_prefix("Bearer ") {
+            self.config.token_routes.contains(&group) && self.check_token(token.trim())
+        } else {
+            self.check_basic(header)
+        }
+    }
+
+    fn check_token(&self, token: &str) -> bool {
+        // check every token so the comparison time doesn't leak which matched
+        self.tokens
+            .iter()
+            .fold(false, |found, t| constant_time_eq(t, token) | found)
+    }
+
+    fn check_basic(&self, header: &str) -> bool {
+        let (Some(expected_user), Some(hash)) = (&self.config.username, &self.config.password_hash)
+        else {
+            return false;
+        };
+
+        if self.verified.lock().unwrap().contains(header) {
+            return true;
+        }
+
+        let Some((username, password)) = parse_basic(header) else {
+            return false;
+        };
+        if &username != expected_user {
+            return false;
+        }
+
+        if verify_password(&password, hash) {
+            let mut verified = self.verified.lock().unwrap();
+            if verified.len() >= VERIFIED_MAX {
+                verified.clear();
+            }
+            verified.insert(header.to_string());
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn parse_tokens(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.bytes()
+        .zip(b.bytes())
+        .fold(0, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+fn parse_basic(header: &str) -> Option<(String, String)> {
+    let encoded = header.strip_prefix("Basic ")?.trim();
+    let decoded = STANDARD.decode(encoded).ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    let (username, password) = credentials.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    if hash.starts_with("$argon2") {
+        Argon2::default()
+            .verify_password(password.as_bytes(), hash)
+            .is_ok()
+    } else {
+        bcrypt::verify(password, hash).unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_groups() {
+        assert_eq!(RouteGroup::for_path("/"), RouteGroup::Dashboard);
+        assert_eq!(RouteGroup::for_path("/metrics"), RouteGroup::Metrics);
+        assert_eq!(RouteGroup::for_path("/metrics?x"), RouteGroup::Metrics);
+        assert_eq!(RouteGroup::for_path("/health"), RouteGroup::Health);
+        assert_eq!(RouteGroup::for_path("/readyz"), RouteGroup::Health);
+        assert_eq!(RouteGroup::for_path("/api/v1/readings"), RouteGroup::Api);
+    }
+
+    #[test]
+    fn test_parse_basic() {
+        // "admin:hunter2"
+        let creds = parse_basic("Basic YWRtaW46aHVudGVyMg==").unwrap();
+        assert_eq!(creds, ("admin".to_string(), "hunter2".to_string()));
+    }
+
+    #[test]
+    fn test_parse_basic_rejects_other_schemes() {
+        assert!(parse_basic("Bearer YWRtaW46aHVudGVyMg==").is_none());
+        assert!(parse_basic("Basic not-base64!").is_none());
+    }
+
+    #[test]
+    fn test_parse_tokens() {
+        let contents = "# scraper\nabc123\n\n  def456  \n";
+        assert_eq!(parse_tokens(contents), vec!["abc123", "def456"]);
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq("abc123", "abc123"));
+        assert!(!constant_time_eq("abc123", "abc124"));
+        assert!(!constant_time_eq("abc123", "abc1234"));
+    }
+
+    #[test]
+    fn test_verify_bcrypt_password() {
+        let hash = bcrypt::hash("hunter2", 4).unwrap();
+        assert!(verify_password("hunter2", &hash));
+        assert!(!verify_password("wrong", &hash));
+    }
+
+    #[test]
+    fn test_verified_cache_is_bounded() {
+        // bcrypt only looks at the first 72 bytes, so these all verify
+        let password = "a".repeat(72);
+        let config: AuthConfig = toml::from_str(&format!(
+            "username = \"admin\"\npassword_hash = \"{}\"\n",
+            bcrypt::hash(&password, 4).unwrap()
+        ))
+        .unwrap();
+        let auth = Authenticator::new(config).unwrap();
+        for i in 0..VERIFIED_MAX + 1 {
+            let credentials = STANDARD.encode(format!("admin:{}{}", password, i));
+            let request = Request::get("/")
+                .header(AUTHORIZATION, format!("Basic {}", credentials))
+                .body(())
+                .unwrap();
+            assert!(auth.authorize(&request));
+        }
+        assert_eq!(auth.verified.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_verify_argon2_password() {
+        use argon2::PasswordHasher;
+
+        let hash = Argon2::default()
+            .hash_password_with_salt(b"hunter2", b"examplesalt12345")
+            .unwrap()
+            .to_string();
+        assert!(verify_password("hunter2", &hash));
+        assert!(!verify_password("wrong", &hash));
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+use tracing::warn;
+
+use crate::config::Config;
+use crate::probe::Probe;
+
+#[cfg(feature = "plugin")]
+pub mod plugin;
+pub mod replay;
+#[cfg(feature = "simulate")]
+pub mod simulate;
+#[cfg(feature = "w1")]
+pub mod w1;
+
+/// Every backend tempmon knows of, whether or not this build has it.
+pub const KNOWN: &[&str] = &["w1", "plugin", "simulate"];
+
+/// Where probes come from: a bus, a protocol, a kind of sensor.
+pub trait Backend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// The probes it can read right now, named from `labels` where they're
+    /// given one.
+    fn discover(&self, labels: &HashMap<String, String>) -> io::Result<Vec<Probe>>;
+}
+
+/// Makes a backend from the config, or from its defaults with none.
+pub type Constructor = fn(Option<&Config>) -> io::Result<Box<dyn Backend>>;
+
+/// The backends compiled into this build, by name.
+const BUILT: &[(&str, Constructor)] = &[
+    #[cfg(feature = "w1")]
+    ("w1", w1::backend),
+    #[cfg(feature = "plugin")]
+    ("plugin", plugin::backend),
+    #[cfg(feature = "simulate")]
+    ("simulate", simulate::backend),
+];
+
+/// The names of the backends compiled into this build.
+pub fn built() -> impl Iterator<Item = &'static str> {
+    BUILT.iter().map(|(name, _)| *name)
+}
+
+/// The backends `settings.backends` asks for.
+#[derive(Default)]
+pub struct Registry {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl Registry {
+    /// The configured backends, or only 1-wire with no config.
+    pub fn new(config: Option<&Config>) -> io::Result<Self> {
+        let default = ["w1".to_string()];
+        let names = config.map_or(&default[..], |c| &c.settings.backends[..]);
+        let backends = names
+            .iter()
+            .map(|name| match BUILT.iter().find(|(built, _)| built == name) {
+                Some((_, construct)) => construct(config),
+                None => Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("tempmon was built without the {} backend", name),
+                )),
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Registry { backends })
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.backends.iter().map(|b| b.name()).collect()
+    }
+
+    /// Looks for probes with `backend` too, after the others.
+    pub fn add(&mut self, backend: Box<dyn Backend>) {
+        self.backends.push(backend);
+    }
+
+    /// The probes every backend can read. A failing backend is skipped with
+    /// a warning, unless they all fail. Where two backends find the same id
+    /// the first keeps it.
+    pub fn discover(&self, labels: &HashMap<String, String>) -> io::Result<Vec<Probe>> {
+        let mut probes = Vec::new();
+        let mut seen = HashSet::new();
+        let mut failed = None;
+        let mut succeeded = false;
+        for backend in &self.backends {
+            match backend.discover(labels) {
+                Ok(found) => {
+                    succeeded = true;
+                    for probe in found {
+                        if seen.insert(probe.id.clone()) {
+                            probes.push(probe);
+                        } else {
+                            warn!(
+                                backend = backend.name(),
+                                "ignoring {}, already found by another backend", probe.id
+                            );
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!(backend = backend.name(), "failed to discover probes: {}", e);
+                    failed = Some(e);
+                }
+            }
+        }
+        match failed {
+            Some(e) if !succeeded => Err(e),
+            _ => Ok(probes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::probe::{ProbeError, Source};
+
+    #[derive(Debug)]
+    struct Fixed;
+
+    impl Source for Fixed {
+        fn read_temperature(&self) -> Result<f32, ProbeError> {
+            Ok(20.0)
+        }
+
+        fn location(&self) -> String {
+            "fixed".to_string()
+        }
+    }
+
+    struct Finds(&'static str, &'static [&'static str]);
+
+    impl Backend for Finds {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn discover(&self, _: &HashMap<String, String>) -> io::Result<Vec<Probe>> {
+            Ok(self
+                .1
+                .iter()
+                .map(|id| Probe {
+                    id: id.to_string(),
+                    name: self.0.to_string(),
+                    source: Arc::new(Fixed),
+                })
+                .collect())
+        }
+    }
+
+    struct Fails;
+
+    impl Backend for Fails {
+        fn name(&self) -> &'static str {
+            "fails"
+        }
+
+        fn discover(&self, _: &HashMap<String, String>) -> io::Result<Vec<Probe>> {
+            Err(io::ErrorKind::NotFound.into())
+        }
+    }
+
+    #[test]
+    fn test_discover() {
+        let registry = Registry {
+            backends: vec![
+                Box::new(Finds("a", &["1", "2"])),
+                Box::new(Fails),
+                Box::new(Finds("b", &["2", "3"])),
+            ],
+        };
+        let probes = registry.discover(&HashMap::new()).unwrap();
+        let found: Vec<_> = probes.iter().map(|p| (&p.id[..], &p.name[..])).collect();
+        assert_eq!(found, [("1", "a"), ("2", "a"), ("3", "b")]);
+
+        let registry = Registry {
+            backends: vec![Box::new(Fails)],
+        };
+        assert!(registry.discover(&HashMap::new()).is_err());
+    }
+}
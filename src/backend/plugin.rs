@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::config::{Config, PluginConfig};
+use crate::probe::{Probe, ProbeError, Source, display_name};
+
+use super::Backend;
+
+/// How long after starting a plugin has to report each of its probes for
+/// discovery to find them.
+const STARTUP_WINDOW: Duration = Duration::from_secs(5);
+const RESTART_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// Probes read by other programs. Each plugin is started once and left
+/// running, printing a JSON object per line on stdout whenever it has a
+/// reading:
+///
+/// ```text
+/// {"id": "max31855-0", "temp": 21.5}
+/// {"id": "max31855-1", "error": "thermocouple open"}
+/// ```
+///
+/// A probe reads as the last line its plugin printed for it, until that's
+/// older than the plugin's `stale_after`.
+pub struct Plugins {
+    running: Vec<Arc<Plugin>>,
+}
+
+pub fn backend(config: Option<&Config>) -> io::Result<Box<dyn Backend>> {
+    let plugins = config.map(|c| &c.plugins[..]).unwrap_or_default();
+    Ok(Box::new(Plugins {
+        running: plugins
+            .iter()
+            .map(|p| Plugin::start(p, STARTUP_WINDOW))
+            .collect(),
+    }))
+}
+
+impl Backend for Plugins {
+    fn name(&self) -> &'static str {
+        "plugin"
+    }
+
+    fn discover(&self, labels: &HashMap<String, String>) -> io::Result<Vec<Probe>> {
+        let mut probes = Vec::new();
+        for plugin in &self.running {
+            for id in plugin.wait_for_ids() {
+                probes.push(Probe {
+                    name: display_name(&id, labels),
+                    source: Arc::new(PluginProbe {
+                        plugin: Arc::clone(plugin),
+                        id: id.clone(),
+                    }),
+                    id,
+                });
+            }
+        }
+        Ok(probes)
+    }
+}
+
+/// A line from a plugin.
+#[derive(Deserialize)]
+struct Line {
+    id: String,
+    temp: Option<f32>,
+    error: Option<String>,
+}
+
+struct Latest {
+    at: Instant,
+    outcome: Result<f32, String>,
+}
+
+/// What a plugin and the thread reading it share.
+struct Shared {
+    name: String,
+    latest: Mutex<HashMap<String, Latest>>,
+    child: Mutex<Option<Child>>,
+    stopped: AtomicBool,
+}
+
+/// A running plugin, stopped once nothing reads from it.
+struct Plugin {
+    shared: Arc<Shared>,
+    command: Vec<String>,
+    stale_after: Duration,
+    started: Instant,
+    startup: Duration,
+}
+
+impl Plugin {
+    fn start(config: &PluginConfig, startup: Duration) -> Arc<Self> {
+        let shared = Arc::new(Shared {
+            name: config.name.clone(),
+            latest: Mutex::new(HashMap::new()),
+            child: Mutex::new(None),
+            stopped: AtomicBool::new(false),
+        });
+        let command = config.command.clone();
+        {
+            let shared = Arc::clone(&shared);
+            let command = command.clone();
+            thread::spawn(move || run(&shared, &command));
+        }
+        Arc::new(Plugin {
+            shared,
+            command,
+            stale_after: Duration::from_secs(config.stale_after),
+            started: Instant::now(),
+            startup,
+        })
+    }
+
+    /// The ids it has reported, once its startup window is over. Waiting
+    /// the whole window, rather than for the first line, means a plugin
+    /// that reports its probes one at a time has all of them found.
+    fn wait_for_ids(&self) -> Vec<String> {
+        thread::sleep(self.startup.saturating_sub(self.started.elapsed()));
+        let latest = self.shared.latest.lock().unwrap();
+        if latest.is_empty() {
+            warn!(plugin = %self.shared.name, "no readings from plugin yet");
+        }
+        let mut ids: Vec<_> = latest.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+        if let Some(child) = self.shared.child.lock().unwrap().as_mut() {
+            let _ = child.kill();
+        }
+    }
+}
+
+/// Keeps the plugin running and its readings up to date until it's
+/// stopped.
+fn run(shared: &Shared, command: &[String]) {
+    let mut backoff = RESTART_BACKOFF_MIN;
+    while !shared.stopped.load(Ordering::SeqCst) {
+        match Command::new(&command[0])
+            .args(&command[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+        {
+            Ok(mut child) => {
+                let stdout = child.stdout.take().expect("stdout is piped");
+                *shared.child.lock().unwrap() = Some(child);
+                // stopped while it was starting
+                if shared.stopped.load(Ordering::SeqCst)
+                    && let Some(mut child) = shared.child.lock().unwrap().take()
+                {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return;
+                }
+
+                for line in BufReader::new(stdout).lines() {
+                    match line {
+                        Ok(line) if line.trim().is_empty() => {}
+                        Ok(line) => {
+                            if record(shared, &line) {
+                                backoff = RESTART_BACKOFF_MIN;
+                            }
+                        }
+                        Err(e) => {
+                            warn!(plugin = %shared.name, "failed to read from plugin: {}", e);
+                            break;
+                        }
+                    }
+                }
+
+                let child = shared.child.lock().unwrap().take();
+                if let Some(mut child) = child {
+                    // it may have only closed stdout
+                    let _ = child.kill();
+                    let status = child.wait();
+                    if shared.stopped.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    match status {
+                        Ok(status) => warn!(
+                            plugin = %shared.name,
+                            "plugin exited with {}, restarting in {}s",
+                            status,
+                            backoff.as_secs()
+                        ),
+                        Err(e) => warn!(
+                            plugin = %shared.name,
+                            "plugin exited ({}), restarting in {}s",
+                            e,
+                            backoff.as_secs()
+                        ),
+                    }
+                }
+            }
+            Err(e) => warn!(
+                plugin = %shared.name,
+                "failed to start {}: {}, retrying in {}s",
+                command[0],
+                e,
+                backoff.as_secs()
+            ),
+        }
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+    }
+}
+
+/// Stores a line's reading, returning whether it was one.
+fn record(shared: &Shared, line: &str) -> bool {
+    let line: Line = match serde_json::from_str(line) {
+        Ok(line) => line,
+        Err(e) => {
+            warn!(plugin = %shared.name, "ignoring {:?}: {}", line, e);
+            return false;
+        }
+    };
+    let outcome = match (line.temp, line.error) {
+        (_, Some(error)) => Err(error),
+        (Some(temp), None) => Ok(temp),
+        (None, None) => {
+            warn!(plugin = %shared.name, "ignoring a line for {} with no temp or error", line.id);
+            return false;
+        }
+    };
+    debug!(plugin = %shared.name, probe = %line.id, "reported {:?}", outcome);
+    let latest = Latest {
+        at: Instant::now(),
+        outcome,
+    };
+    shared.latest.lock().unwrap().insert(line.id, latest);
+    true
+}
+
+/// One of the probes a plugin reports.
+struct PluginProbe {
+    plugin: Arc<Plugin>,
+    id: String,
+}
+
+impl std::fmt::Debug for PluginProbe {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("PluginProbe")
+            .field("plugin", &self.plugin.shared.name)
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl Source for PluginProbe {
+    fn read_temperature(&self) -> Result<f32, ProbeError> {
+        let latest = self.plugin.shared.latest.lock().unwrap();
+        let Some(reading) = latest.get(&self.id) else {
+            return Err(ProbeError::NotFound(format!(
+                "{} hasn't reported {}",
+                self.plugin.shared.name, self.id
+            )));
+        };
+        let age = reading.at.elapsed();
+        if age > self.plugin.stale_after {
+            return Err(ProbeError::Timeout(age));
+        }
+        reading
+            .outcome
+            .clone()
+            .map_err(|e| ProbeError::Io(io::Error::other(e)))
+    }
+
+    fn location(&self) -> String {
+        format!(
+            "plugin {}: {}",
+            self.plugin.shared.name,
+            self.plugin.command.join(" ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Long enough for a script's lines, without slowing the tests down.
+    const STARTUP: Duration = Duration::from_millis(500);
+
+    fn plugin(script: &str) -> PluginConfig {
+        PluginConfig {
+            name: "test".to_string(),
+            command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+            stale_after: 60,
+        }
+    }
+
+    #[test]
+    fn test_discover_and_read() {
+        let backend = Plugins {
+            running: vec![Plugin::start(
+                &plugin(
+                    r#"echo 'not json'
+                    echo '{"id": "b", "error": "thermocouple open"}'
+                    sleep 0.1
+                    echo '{"id": "a", "temp": 21.5}'
+                    echo '{"id": "c"}'
+                    sleep 10"#,
+                ),
+                STARTUP,
+            )],
+        };
+
+        let labels = HashMap::from([("a".to_string(), "mash tun".to_string())]);
+        let probes = backend.discover(&labels).unwrap();
+        let ids: Vec<_> = probes.iter().map(|p| (&p.id[..], &p.name[..])).collect();
+        assert_eq!(ids, [("a", "mash tun"), ("b", "b")]);
+        assert_eq!(probes[0].read_temperature().unwrap(), 21.5);
+        let err = probes[1].read_temperature().unwrap_err();
+        assert_eq!(err.to_string(), "thermocouple open");
+    }
+
+    #[test]
+    fn test_stale_reading() {
+        let mut config = plugin(r#"echo '{"id": "a", "temp": 21.5}'; sleep 10"#);
+        config.stale_after = 0;
+        let backend = Plugins {
+            running: vec![Plugin::start(&config, STARTUP)],
+        };
+        let probes = backend.discover(&HashMap::new()).unwrap();
+        thread::sleep(Duration::from_millis(10));
+        let err = probes[0].read_temperature().unwrap_err();
+        assert!(matches!(err, ProbeError::Timeout(_)));
+    }
+}
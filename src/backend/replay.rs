@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::probe::{Probe, ProbeError, Source, display_name};
+
+use super::Backend;
+
+/// A raw reading as it's recorded, a line of JSON each: the plugin
+/// protocol's with when it was taken, in seconds since the unix epoch.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    at: f64,
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temp: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RecordedError>,
+}
+
+/// A [`ProbeError`] as it's recorded: its `error_type` and what's needed
+/// to make the same error again on replay.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RecordedError {
+    CrcFailure,
+    Parse { message: String },
+    NotFound { message: String },
+    PermissionDenied { message: String },
+    Timeout { millis: u64 },
+    OutOfRange { temp: f32 },
+    Outlier { temp: f32, rate: f32 },
+    Io { message: String },
+}
+
+impl From<&ProbeError> for RecordedError {
+    fn from(e: &ProbeError) -> Self {
+        match e {
+            ProbeError::CrcFailure => RecordedError::CrcFailure,
+            ProbeError::Parse(message) => RecordedError::Parse {
+                message: message.clone(),
+            },
+            ProbeError::NotFound(message) => RecordedError::NotFound {
+                message: message.clone(),
+            },
+            ProbeError::PermissionDenied(message) => RecordedError::PermissionDenied {
+                message: message.clone(),
+            },
+            ProbeError::Timeout(timeout) => RecordedError::Timeout {
+                millis: timeout.as_millis().try_into().unwrap_or(u64::MAX),
+            },
+            ProbeError::OutOfRange(temp) => RecordedError::OutOfRange { temp: *temp },
+            ProbeError::Outlier { temp, rate } => RecordedError::Outlier {
+                temp: *temp,
+                rate: *rate,
+            },
+            ProbeError::Io(e) => RecordedError::Io {
+                message: e.to_string(),
+            },
+        }
+    }
+}
+
+impl From<RecordedError> for ProbeError {
+    fn from(e: RecordedError) -> Self {
+        match e {
+            RecordedError::CrcFailure => ProbeError::CrcFailure,
+            RecordedError::Parse { message } => ProbeError::Parse(message),
+            RecordedError::NotFound { message } => ProbeError::NotFound(message),
+            RecordedError::PermissionDenied { message } => ProbeError::PermissionDenied(message),
+            RecordedError::Timeout { millis } => ProbeError::Timeout(Duration::from_millis(millis)),
+            RecordedError::OutOfRange { temp } => ProbeError::OutOfRange(temp),
+            RecordedError::Outlier { temp, rate } => ProbeError::Outlier { temp, rate },
+            RecordedError::Io { message } => ProbeError::Io(io::Error::other(message)),
+        }
+    }
+}
+
+/// Has every read of `probes` appended to the file at `path`, for
+/// [`Replay`] to play back later.
+pub fn record(probes: Vec<Probe>, path: &Path) -> io::Result<Vec<Probe>> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let file = Arc::new(Mutex::new(LineWriter::new(file)));
+    Ok(probes
+        .into_iter()
+        .map(|probe| Probe {
+            source: Arc::new(Recorded {
+                id: probe.id.clone(),
+                inner: probe.source,
+                file: Arc::clone(&file),
+            }),
+            ..probe
+        })
+        .collect())
+}
+
+#[derive(Debug)]
+struct Recorded {
+    id: String,
+    inner: Arc<dyn Source>,
+    file: Arc<Mutex<LineWriter<File>>>,
+}
+
+impl Source for Recorded {
+    fn read_temperature(&self) -> Result<f32, ProbeError> {
+        let result = self.inner.read_temperature();
+        let record = Record {
+            at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+            id: self.id.clone(),
+            temp: result.as_ref().ok().copied(),
+            error: result.as_ref().err().map(RecordedError::from),
+        };
+        let line = serde_json::to_string(&record).expect("records serialize");
+        if let Err(e) = writeln!(self.file.lock().unwrap(), "{}", line) {
+            warn!(probe = %self.id, "failed to record reading: {}", e);
+        }
+        result
+    }
+
+    fn set_resolution(&self, bits: u8) -> io::Result<()> {
+        self.inner.set_resolution(bits)
+    }
+
+    fn resolution(&self) -> io::Result<u8> {
+        self.inner.resolution()
+    }
+
+    fn location(&self) -> String {
+        self.inner.location()
+    }
+}
+
+/// A probe's recorded readings, oldest first, by when they were taken.
+type Timeline = Vec<(f64, Result<f32, RecordedError>)>;
+
+/// The probes in a recording, reading what they read at the same point
+/// after it started, `speed` times faster. Once it's over they stay on
+/// their last readings.
+pub struct Replay {
+    clock: Arc<Clock>,
+    probes: HashMap<String, Arc<Timeline>>,
+}
+
+impl Replay {
+    pub fn open(path: &Path, speed: f64) -> io::Result<Self> {
+        let invalid = |n: usize, e: &dyn std::fmt::Display| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} line {}: {}", path.display(), n + 1, e),
+            )
+        };
+        let mut probes: HashMap<String, Vec<_>> = HashMap::new();
+        for (n, line) in fs::read_to_string(path)?.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: Record = serde_json::from_str(line).map_err(|e| invalid(n, &e))?;
+            let outcome = match (record.temp, record.error) {
+                (_, Some(error)) => Err(error),
+                (Some(temp), None) => Ok(temp),
+                (None, None) => return Err(invalid(n, &"no temp or error")),
+            };
+            probes
+                .entry(record.id)
+                .or_default()
+                .push((record.at, outcome));
+        }
+        for records in probes.values_mut() {
+            records.sort_by(|a, b| a.0.total_cmp(&b.0));
+        }
+        if probes.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has no readings", path.display()),
+            ));
+        }
+        let times = probes.values().flatten().map(|(at, _)| *at);
+        let first = times.clone().fold(f64::INFINITY, f64::min);
+        let last = times.fold(f64::NEG_INFINITY, f64::max);
+        info!(
+            "replaying {:.0}s of readings from {} at {}x",
+            last - first,
+            path.display(),
+            speed
+        );
+        Ok(Replay {
+            clock: Arc::new(Clock {
+                started: Instant::now(),
+                first,
+                last,
+                speed,
+                finished: AtomicBool::new(false),
+            }),
+            probes: probes
+                .into_iter()
+                .map(|(id, records)| (id, Arc::new(records)))
+                .collect(),
+        })
+    }
+}
+
+impl Backend for Replay {
+    fn name(&self) -> &'static str {
+        "replay"
+    }
+
+    fn discover(&self, labels: &HashMap<String, String>) -> io::Result<Vec<Probe>> {
+        let mut probes: Vec<_> = self
+            .probes
+            .iter()
+            .map(|(id, records)| Probe {
+                id: id.clone(),
+                name: display_name(id, labels),
+                source: Arc::new(Replayed {
+                    clock: Arc::clone(&self.clock),
+                    records: Arc::clone(records),
+                }),
+            })
+            .collect();
+        probes.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(probes)
+    }
+}
+
+/// Where a replay has got to in the recording.
+#[derive(Debug)]
+struct Clock {
+    started: Instant,
+    /// the first and last readings, in seconds since the unix epoch
+    first: f64,
+    last: f64,
+    speed: f64,
+    finished: AtomicBool,
+}
+
+impl Clock {
+    /// The time in the recording, staying at the end once it's all been
+    /// played.
+    fn now(&self) -> f64 {
+        let now = self.first + self.started.elapsed().as_secs_f64() * self.speed;
+        if now <= self.last {
+            return now;
+        }
+        if !self.finished.swap(true, Ordering::Relaxed) {
+            info!("replay finished, holding the last readings");
+        }
+        self.last
+    }
+}
+
+#[derive(Debug)]
+struct Replayed {
+    clock: Arc<Clock>,
+    records: Arc<Timeline>,
+}
+
+impl Replayed {
+    fn read_at(&self, now: f64) -> Result<f32, ProbeError> {
+        match self.records.partition_point(|(at, _)| *at <= now) {
+            0 => Err(ProbeError::NotFound("not recorded yet".to_string())),
+            n => self.records[n - 1].1.clone().map_err(ProbeError::from),
+        }
+    }
+}
+
+impl Source for Replayed {
+    fn read_temperature(&self) -> Result<f32, ProbeError> {
+        self.read_at(self.clock.now())
+    }
+
+    fn location(&self) -> String {
+        "replay".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Fixed(fn() -> Result<f32, ProbeError>);
+
+    impl Source for Fixed {
+        fn read_temperature(&self) -> Result<f32, ProbeError> {
+            (self.0)()
+        }
+
+        fn location(&self) -> String {
+            "fixed".to_string()
+        }
+    }
+
+    fn probe(id: &str, read: fn() -> Result<f32, ProbeError>) -> Probe {
+        Probe {
+            id: id.to_string(),
+            name: id.to_string(),
+            source: Arc::new(Fixed(read)),
+        }
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!("tempmon-replay-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let probes = record(
+            vec![
+                probe("a", || Ok(21.5)),
+                probe("b", || Err(ProbeError::CrcFailure)),
+            ],
+            &path,
+        )
+        .unwrap();
+        for probe in &probes {
+            let _ = probe.read_temperature();
+        }
+
+        let replay = Replay::open(&path, 1.0).unwrap();
+        assert_eq!(replay.probes["a"][0].1, Ok(21.5));
+        assert_eq!(replay.probes["b"][0].1, Err(RecordedError::CrcFailure));
+        let labels = HashMap::from([("a".to_string(), "mash tun".to_string())]);
+        let probes = replay.discover(&labels).unwrap();
+        assert_eq!(probes[0].name, "mash tun");
+        // it's over in an instant, and stays on the last readings
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert_eq!(probes[0].read_temperature().unwrap(), 21.5);
+        assert!(probes[1].read_temperature().is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_at() {
+        let replayed = Replayed {
+            clock: Arc::new(Clock {
+                started: Instant::now(),
+                first: 100.0,
+                last: 130.0,
+                speed: 1.0,
+                finished: AtomicBool::new(false),
+            }),
+            records: Arc::new(vec![
+                (100.0, Ok(20.0)),
+                (115.0, Err(RecordedError::CrcFailure)),
+                (130.0, Ok(21.0)),
+            ]),
+        };
+        assert_eq!(
+            replayed.read_at(99.0).unwrap_err().error_type(),
+            "not_found"
+        );
+        assert_eq!(replayed.read_at(100.0).unwrap(), 20.0);
+        assert_eq!(replayed.read_at(114.9).unwrap(), 20.0);
+        assert_eq!(
+            replayed.read_at(120.0).unwrap_err().error_type(),
+            "crc_failure"
+        );
+        assert_eq!(replayed.read_at(130.0).unwrap(), 21.0);
+    }
+
+    #[test]
+    fn test_errors_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("tempmon-replay-errors-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let probes = record(
+            vec![
+                probe("a", || {
+                    Err(ProbeError::Timeout(Duration::from_millis(1500)))
+                }),
+                probe("b", || {
+                    Err(ProbeError::Outlier {
+                        temp: 85.0,
+                        rate: 12.5,
+                    })
+                }),
+                probe("c", || Err(ProbeError::Parse("t=junk".to_string()))),
+            ],
+            &path,
+        )
+        .unwrap();
+        let recorded: Vec<_> = probes
+            .iter()
+            .map(|probe| probe.read_temperature().unwrap_err())
+            .collect();
+
+        let replay = Replay::open(&path, 1.0).unwrap();
+        let probes = replay.discover(&HashMap::new()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        for (probe, recorded) in probes.iter().zip(&recorded) {
+            let replayed = probe.read_temperature().unwrap_err();
+            assert_eq!(replayed.error_type(), recorded.error_type());
+            assert_eq!(replayed.to_string(), recorded.to_string());
+        }
+        assert!(matches!(
+            probes[0].read_temperature(),
+            Err(ProbeError::Timeout(timeout)) if timeout == Duration::from_millis(1500)
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::config::{Config, SimulateConfig, Waveform};
+use crate::probe::{Probe, ProbeError, Source, display_name};
+
+use super::Backend;
+
+/// Made-up probes, one per `[simulate.<id>]`, for trying tempmon out
+/// without any hardware.
+pub struct Simulated {
+    probes: HashMap<String, SimulateConfig>,
+}
+
+pub fn backend(config: Option<&Config>) -> io::Result<Box<dyn Backend>> {
+    Ok(Box::new(Simulated {
+        probes: config.map(|c| c.simulate.clone()).unwrap_or_default(),
+    }))
+}
+
+impl Backend for Simulated {
+    fn name(&self) -> &'static str {
+        "simulate"
+    }
+
+    fn discover(&self, labels: &HashMap<String, String>) -> io::Result<Vec<Probe>> {
+        let mut probes: Vec<_> = self
+            .probes
+            .iter()
+            .map(|(id, config)| Probe {
+                id: id.clone(),
+                name: display_name(id, labels),
+                source: Arc::new(Signal::new(config.clone(), fastrand::Rng::new())),
+            })
+            .collect();
+        probes.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(probes)
+    }
+}
+
+#[derive(Debug)]
+struct Signal {
+    config: SimulateConfig,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    rng: fastrand::Rng,
+    /// where the random walk has got to
+    walked: f32,
+}
+
+impl Signal {
+    fn new(config: SimulateConfig, rng: fastrand::Rng) -> Self {
+        let walked = config.mean;
+        Signal {
+            config,
+            state: Mutex::new(State { rng, walked }),
+        }
+    }
+
+    fn read_at(&self, at: SystemTime) -> Result<f32, ProbeError> {
+        let config = &self.config;
+        let mut state = self.state.lock().unwrap();
+        if state.rng.f64() < config.failure_rate {
+            return Err(ProbeError::Io(io::Error::other("simulated read failure")));
+        }
+        let value = match config.waveform {
+            Waveform::Sine => {
+                let secs = at
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+                let phase = (secs % config.period) / config.period;
+                config.mean + config.amplitude * (TAU * phase).sin() as f32
+            }
+            Waveform::RandomWalk => {
+                let step = config.step * (state.rng.f32() * 2.0 - 1.0);
+                state.walked = (state.walked + step).clamp(
+                    config.mean - config.amplitude,
+                    config.mean + config.amplitude,
+                );
+                state.walked
+            }
+        };
+        Ok(value + config.noise * (state.rng.f32() * 2.0 - 1.0))
+    }
+}
+
+impl Source for Signal {
+    fn read_temperature(&self) -> Result<f32, ProbeError> {
+        self.read_at(SystemTime::now())
+    }
+
+    fn location(&self) -> String {
+        match self.config.waveform {
+            Waveform::Sine => "simulated sine wave".to_string(),
+            Waveform::RandomWalk => "simulated random walk".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn config(waveform: Waveform) -> SimulateConfig {
+        SimulateConfig {
+            waveform,
+            mean: 20.0,
+            amplitude: 5.0,
+            period: 3600.0,
+            step: 1.0,
+            noise: 0.0,
+            failure_rate: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_sine() {
+        let signal = Signal::new(config(Waveform::Sine), fastrand::Rng::with_seed(1));
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!(signal.read_at(at(0)).unwrap(), 20.0);
+        assert_eq!(signal.read_at(at(900)).unwrap(), 25.0);
+        assert_eq!(signal.read_at(at(3600 + 2700)).unwrap(), 15.0);
+    }
+
+    #[test]
+    fn test_random_walk_stays_in_bounds() {
+        let mut config = config(Waveform::RandomWalk);
+        config.noise = 0.5;
+        let signal = Signal::new(config, fastrand::Rng::with_seed(1));
+        let mut last = 20.0;
+        for _ in 0..1000 {
+            let temp = signal.read_temperature().unwrap();
+            assert!((14.5..=25.5).contains(&temp));
+            // a step and the noise either side of it
+            assert!((temp - last).abs() <= 2.0);
+            last = temp;
+        }
+    }
+
+    #[test]
+    fn test_failure_rate() {
+        let mut config = config(Waveform::Sine);
+        config.failure_rate = 1.0;
+        let signal = Signal::new(config, fastrand::Rng::with_seed(1));
+        assert!(signal.read_temperature().is_err());
+    }
+}
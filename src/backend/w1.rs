@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{env, fs, io};
+
+use crate::config::Config;
+use crate::probe::{Probe, ProbeError, Source, display_name};
+
+use super::Backend;
+
+const SYSFS_ROOT: &str = "/sys";
+const SYSFS_ROOT_ENV: &str = "TEMPMON_SYSFS_ROOT";
+const W1_DEVICES_PATH: &str = "bus/w1/devices";
+
+/// The DS18B20s on the 1-wire bus, through the kernel's w1-therm driver.
+pub struct OneWire {
+    devices: PathBuf,
+}
+
+impl OneWire {
+    /// The bus under a sysfs mounted at `root`, usually `/sys`.
+    pub fn new(root: &Path) -> Self {
+        OneWire {
+            devices: root.join(W1_DEVICES_PATH),
+        }
+    }
+}
+
+/// Looks in `TEMPMON_SYSFS_ROOT`, then `settings.sysfs_root`, then `/sys`.
+pub fn backend(config: Option<&Config>) -> io::Result<Box<dyn Backend>> {
+    let root = env::var_os(SYSFS_ROOT_ENV)
+        .map(PathBuf::from)
+        .or_else(|| config.and_then(|c| c.settings.sysfs_root.clone()))
+        .unwrap_or_else(|| PathBuf::from(SYSFS_ROOT));
+    Ok(Box::new(OneWire::new(&root)))
+}
+
+impl Backend for OneWire {
+    fn name(&self) -> &'static str {
+        "w1"
+    }
+
+    fn discover(&self, labels: &HashMap<String, String>) -> io::Result<Vec<Probe>> {
+        let mut probes = Vec::new();
+
+        if !self.devices.exists() {
+            tracing::warn!(
+                "{} not found. make sure w1-gpio is enabled.",
+                self.devices.display()
+            );
+            return Ok(probes);
+        }
+
+        for entry in fs::read_dir(&self.devices)? {
+            let entry = entry?;
+            let id = entry.file_name().to_string_lossy().to_string();
+
+            if id.starts_with("28-") {
+                let name = display_name(&id, labels);
+                probes.push(Probe {
+                    id,
+                    name,
+                    source: Arc::new(Device::new(entry.path().join("w1_slave"))),
+                });
+            }
+        }
+        probes.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(probes)
+    }
+}
+
+/// A DS18B20, read from its `w1_slave` file.
+#[derive(Debug)]
+pub struct Device {
+    path: PathBuf,
+}
+
+impl Device {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Device { path: path.into() }
+    }
+
+    fn resolution_path(&self) -> PathBuf {
+        self.path.with_file_name("resolution")
+    }
+}
+
+impl Source for Device {
+    fn read_temperature(&self) -> Result<f32, ProbeError> {
+        let data = fs::read_to_string(&self.path).map_err(|e| match e.kind() {
+            // unplugged, most likely
+            io::ErrorKind::NotFound => ProbeError::NotFound(self.path.display().to_string()),
+            _ => e.into(),
+        })?;
+        parse_temperature_data(&data)
+    }
+
+    fn set_resolution(&self, bits: u8) -> io::Result<()> {
+        fs::write(self.resolution_path(), bits.to_string())
+    }
+
+    fn resolution(&self) -> io::Result<u8> {
+        fs::read_to_string(self.resolution_path())?
+            .trim()
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+fn parse_temperature_data(data: &str) -> Result<f32, ProbeError> {
+    // the file format looks like:
+    // 6d 01 55 05 7f a5 a5 66 3e : crc=3e YES
+    // 6d 01 55 05 7f a5 a5 66 3e t=22812
+
+    // check the crc
+    if !data.contains("YES") {
+        return Err(ProbeError::CrcFailure);
+    }
+
+    // find and read the temperature data
+    let Some(temp_pos) = data.find("t=") else {
+        return Err(ProbeError::Parse("no t= field".to_string()));
+    };
+    let temp_str = data[temp_pos + 2..].trim();
+    match temp_str.parse::<i32>() {
+        Ok(temp_raw) => Ok(temp_raw as f32 / 1000.0),
+        Err(e) => Err(ProbeError::Parse(format!("t={}: {}", temp_str, e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover_under_root() {
+        let root = std::env::temp_dir().join(format!("tempmon-w1-{}", std::process::id()));
+        let devices = root.join(W1_DEVICES_PATH);
+        let probe = devices.join("28-000000000001");
+        fs::create_dir_all(&probe).unwrap();
+        fs::create_dir_all(devices.join("w1_bus_master1")).unwrap();
+        fs::write(
+            probe.join("w1_slave"),
+            "6d 01 55 05 7f a5 a5 66 3e : crc=3e YES\n6d 01 55 05 7f a5 a5 66 3e t=22812\n",
+        )
+        .unwrap();
+        fs::write(probe.join("resolution"), "12\n").unwrap();
+
+        let labels = HashMap::from([("28-000000000001".to_string(), "fermenter".to_string())]);
+        let probes = OneWire::new(&root).discover(&labels).unwrap();
+        assert_eq!(probes.len(), 1);
+        assert_eq!(probes[0].name, "fermenter");
+        assert_eq!(probes[0].read_temperature().unwrap(), 22.812);
+        assert_eq!(probes[0].resolution().unwrap(), 12);
+        probes[0].set_resolution(10).unwrap();
+        assert_eq!(fs::read_to_string(probe.join("resolution")).unwrap(), "10");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_parse_valid_temperature() {
+        let data = "6d 01 55 05 7f a5 a5 66 3e : crc=3e YES\n\
+                    6d 01 55 05 7f a5 a5 66 3e t=22812\n";
+
+        let temp = parse_temperature_data(data).unwrap();
+        assert_eq!(temp, 22.812);
+    }
+
+    #[test]
+    fn test_parse_negative_temperature() {
+        let data = "50 05 4b 46 7f ff 0c 10 1c : crc=1c YES\n\
+                    50 05 4b 46 7f ff 0c 10 1c t=-10562\n";
+
+        let temp = parse_temperature_data(data).unwrap();
+        assert_eq!(temp, -10.562);
+    }
+
+    #[test]
+    fn test_parse_zero_temperature() {
+        let data = "00 00 00 00 00 00 00 00 00 : crc=00 YES\n\
+                    00 00 00 00 00 00 00 00 00 t=0\n";
+
+        let temp = parse_temperature_data(data).unwrap();
+        assert_eq!(temp, 0.0);
+    }
+
+    #[test]
+    fn test_parse_crc_failure() {
+        let data = "6d 01 55 05 7f a5 a5 66 3e : crc=3e NO\n\
+                    6d 01 55 05 7f a5 a5 66 3e t=22812\n";
+
+        let result = parse_temperature_data(data);
+        assert!(matches!(result, Err(ProbeError::CrcFailure)));
+    }
+
+    #[test]
+    fn test_parse_missing_temperature() {
+        let data = "6d 01 55 05 7f a5 a5 66 3e : crc=3e YES\n\
+                    6d 01 55 05 7f a5 a5 66 3e\n";
+
+        let result = parse_temperature_data(data);
+        assert!(matches!(result, Err(ProbeError::Parse(_))));
+    }
+
+    #[test]
+    fn test_parse_malformed_temperature() {
+        let data = "6d 01 55 05 7f a5 a5 66 3e : crc=3e YES\n\
+                    6d 01 55 05 7f a5 a5 66 3e t=invalid\n";
+
+        let result = parse_temperature_data(data);
+        assert!(matches!(result, Err(ProbeError::Parse(_))));
+    }
+
+    #[test]
+    fn test_parse_empty_string() {
+        let data = "";
+
+        let result = parse_temperature_data(data);
+        assert!(matches!(result, Err(ProbeError::CrcFailure)));
+    }
+}
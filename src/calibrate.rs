@@ -0,0 +1,308 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::thread::sleep;
+use std::time::Duration;
+
+use toml_edit::{DocumentMut, Item, Table, value};
+use tracing::{info, warn};
+
+use crate::backend::Registry;
+use crate::config::{Calibration, load_config};
+use crate::probe::Probe;
+
+pub struct Options<'a> {
+    /// the true temperature of each bath the probes are put in, one for an
+    /// offset only, two to work out the scale as well
+    pub references: Vec<f32>,
+    pub samples: u32,
+    pub interval: Duration,
+    pub probe: Option<&'a str>,
+    /// write the calibration without asking first
+    pub yes: bool,
+}
+
+/// Samples every probe against one or two known reference temperatures and
+/// stores the resulting corrections in `[calibration_offsets]` and
+/// `[calibration_scales]`.
+pub fn run(config_path: &Path, opts: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    if opts.references.len() > 2 {
+        return Err("calibrate takes one or two reference temperatures".into());
+    }
+    let config = load_config(config_path)?;
+
+    let mut probes = Registry::new(Some(&config))?.discover(&config.probe_labels)?;
+    probes.sort_by(|a, b| a.name.cmp(&b.name));
+    if let Some(only) = opts.probe {
+        probes.retain(|p| p.id == only || p.name == only);
+    }
+    if probes.is_empty() {
+        return Err("no probes to calibrate".into());
+    }
+
+    // mean raw reading per reference, per probe
+    let mut means = Vec::new();
+    for (i, reference) in opts.references.iter().enumerate() {
+        if i > 0 {
+            wait_for_enter(&format!(
+                "move the probes to the {}°c reference and press enter",
+                reference
+            ))?;
+        }
+        means.push(sample(&probes, opts));
+    }
+
+    let two_point = opts.references.len() > 1;
+    let mut results = Vec::new();
+    for (j, probe) in probes.iter().enumerate() {
+        let points: Vec<(f32, f32)> = opts
+            .references
+            .iter()
+            .zip(&means)
+            .filter_map(|(reference, means)| means[j].map(|m| (*reference, m)))
+            .collect();
+        if points.len() < opts.references.len() {
+            warn!("{}: no successful readings, skipping", probe.name);
+            continue;
+        }
+
+        let current = config.calibration(&probe.id);
+        let Some(calibration) = fit(&points, current.scale) else {
+            warn!(
+                "{}: readings didn't change between references, skipping",
+                probe.name
+            );
+            continue;
+        };
+        println!(
+            "{} ({}): scale {:.4}, offset {:+.3} (currently {:.4}, {:+.3})",
+            probe.name,
+            probe.id,
+            calibration.scale,
+            calibration.offset,
+            current.scale,
+            current.offset
+        );
+        results.push((probe.id.clone(), calibration));
+    }
+    if results.is_empty() {
+        return Err("no probe could be read".into());
+    }
+
+    if !opts.yes && !confirm(&format!("write calibration to {}?", config_path.display()))? {
+        info!("calibration not saved");
+        return Ok(());
+    }
+
+    let contents = fs::read_to_string(config_path)?;
+    fs::write(
+        config_path,
+        update_calibration(&contents, &results, two_point)?,
+    )?;
+    info!(
+        "saved calibration for {} probe(s), reload tempmon (SIGHUP) to apply it",
+        results.len()
+    );
+    Ok(())
+}
+
+/// Averages `opts.samples` raw readings from each probe.
+fn sample(probes: &[Probe], opts: &Options) -> Vec<Option<f32>> {
+    info!(
+        "taking {} readings from {} probe(s), {}s apart...",
+        opts.samples,
+        probes.len(),
+        opts.interval.as_secs_f32()
+    );
+    let mut samples = vec![Vec::new(); probes.len()];
+    for i in 0..opts.samples {
+        if i > 0 {
+            sleep(opts.interval);
+        }
+        for (probe, readings) in probes.iter().zip(&mut samples) {
+            match probe.read_temperature() {
+                Ok(temp) => readings.push(temp),
+                Err(e) => warn!("{}: {}", probe.name, e),
+            }
+        }
+    }
+    samples.iter().map(|readings| mean(readings)).collect()
+}
+
+fn mean(readings: &[f32]) -> Option<f32> {
+    if readings.is_empty() {
+        return None;
+    }
+    Some(readings.iter().sum::<f32>() / readings.len() as f32)
+}
+
+/// Fits a calibration through `(reference, mean raw reading)` points. With
+/// a single point only the offset is worked out and `scale` is kept.
+fn fit(points: &[(f32, f32)], scale: f32) -> Option<Calibration> {
+    match points {
+        [(reference, raw)] => Some(Calibration {
+            scale,
+            offset: reference - raw * scale,
+        }),
+        [(ref_a, raw_a), (ref_b, raw_b)] => {
+            if (raw_b - raw_a).abs() < f32::EPSILON {
+                return None;
+            }
+            let scale = (ref_b - ref_a) / (raw_b - raw_a);
+            Some(Calibration {
+                scale,
+                offset: ref_a - raw_a * scale,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn confirm(prompt: &str) -> io::Result<bool> {
+    print!("{} [y/N] ", prompt);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+fn wait_for_enter(prompt: &str) -> io::Result<()> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    io::stdin().lock().read_line(&mut String::new())?;
+    Ok(())
+}
+
+/// A few decimal places is already well past what the probes resolve.
+fn rounded(x: f32, places: usize) -> f64 {
+    format!("{:.*}", places, x).parse().unwrap()
+}
+
+/// Sets the given calibrations in the config, leaving comments and
+/// everything else as it was. Scales are only written for a two-point
+/// calibration.
+fn update_calibration(
+    contents: &str,
+    calibrations: &[(String, Calibration)],
+    with_scale: bool,
+) -> Result<String, toml_edit::TomlError> {
+    let mut doc: DocumentMut = contents.parse()?;
+
+    let offsets = doc
+        .entry("calibration_offsets")
+        .or_insert_with(|| Item::Table(Table::new()));
+    for (id, calibration) in calibrations {
+        offsets[id.as_str()] = value(rounded(calibration.offset, 3));
+    }
+
+    if with_scale {
+        let scales = doc
+            .entry("calibration_scales")
+            .or_insert_with(|| Item::Table(Table::new()));
+        for (id, calibration) in calibrations {
+            scales[id.as_str()] = value(rounded(calibration.scale, 4));
+        }
+    }
+
+    Ok(doc.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn test_fit_one_point() {
+        let c = fit(&[(0.0, 0.5)], 1.0).unwrap();
+        assert_eq!(c.scale, 1.0);
+        assert!(close(c.offset, -0.5));
+
+        // an existing scale is kept
+        let c = fit(&[(100.0, 99.5)], 1.01).unwrap();
+        assert_eq!(c.scale, 1.01);
+        assert!(close(c.apply(99.5), 100.0));
+    }
+
+    #[test]
+    fn test_fit_two_points() {
+        // reads 0.3 low at 5°c and 0.9 low at 60°c
+        let c = fit(&[(5.0, 4.7), (60.0, 59.1)], 1.0).unwrap();
+        assert!(close(c.apply(4.7), 5.0));
+        assert!(close(c.apply(59.1), 60.0));
+        assert!(c.scale > 1.0);
+
+        assert!(fit(&[(0.0, 20.0), (100.0, 20.0)], 1.0).is_none());
+    }
+
+    #[test]
+    fn test_mean() {
+        assert_eq!(mean(&[0.5, 0.25, 0.75]), Some(0.5));
+        assert_eq!(mean(&[]), None);
+    }
+
+    #[test]
+    fn test_update_calibration_preserves_comments() {
+        let contents = "\
+[settings]
+metrics_port = 9184 # the usual port
+
+[calibration_offsets]
+# from the last ice bath
+\"28-abc123\" = 0.1
+";
+        let calibrations = vec![
+            (
+                "28-abc123".to_string(),
+                Calibration {
+                    scale: 1.0,
+                    offset: -0.3126,
+                },
+            ),
+            (
+                "28-def456".to_string(),
+                Calibration {
+                    scale: 1.0,
+                    offset: 0.25,
+                },
+            ),
+        ];
+
+        let updated = update_calibration(contents, &calibrations, false).unwrap();
+        assert!(updated.contains("metrics_port = 9184 # the usual port"));
+        assert!(updated.contains("# from the last ice bath"));
+
+        let doc: toml::Value = toml::from_str(&updated).unwrap();
+        let table = &doc["calibration_offsets"];
+        assert_eq!(table["28-abc123"].as_float(), Some(-0.313));
+        assert_eq!(table["28-def456"].as_float(), Some(0.25));
+        assert!(doc.get("calibration_scales").is_none());
+    }
+
+    #[test]
+    fn test_update_calibration_adds_missing_tables() {
+        let calibration = Calibration {
+            scale: 1.01234,
+            offset: 0.5,
+        };
+        let updated = update_calibration(
+            "[settings]\n",
+            &[("28-abc123".to_string(), calibration)],
+            true,
+        )
+        .unwrap();
+        let doc: toml::Value = toml::from_str(&updated).unwrap();
+        assert_eq!(
+            doc["calibration_offsets"]["28-abc123"].as_float(),
+            Some(0.5)
+        );
+        assert_eq!(
+            doc["calibration_scales"]["28-abc123"].as_float(),
+            Some(1.0123)
+        );
+    }
+}
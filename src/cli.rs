@@ -0,0 +1,320 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::collect;
+use crate::logfile::Rotation;
+use crate::logger;
+use crate::nagios::Threshold;
+
+/// Exports readings from ds18b20 temperature probes over http.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to the config file (default: search TEMPMON_CONFIG, the user
+    /// config dir, then /etc/tempmon/config.toml)
+    #[arg(short, long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Log level, optionally per module, e.g. `warn` or
+    /// `info,tempmon::server=debug`
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILTER",
+        env = logger::LOG_ENV,
+        default_value = "info",
+        value_parser = logger::parse_filter
+    )]
+    pub log_level: String,
+
+    /// Format of log output
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = logger::LOG_FORMAT_ENV,
+        default_value_t = logger::Format::Auto
+    )]
+    pub log_format: logger::Format,
+
+    /// Write logs to this file instead of stderr
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Rotate the log file once it reaches this many megabytes
+    #[arg(long, global = true, value_name = "MB", requires = "log_file")]
+    pub log_max_size: Option<u64>,
+
+    /// Also rotate the log file every hour or day
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = Rotation::Never,
+        requires = "log_file"
+    )]
+    pub log_rotate: Rotation,
+
+    /// Number of rotated log files to keep
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        default_value_t = 5,
+        requires = "log_file"
+    )]
+    pub log_keep: usize,
+
+    /// Append every raw reading to this file as it's taken, for --replay
+    #[arg(long, value_name = "PATH", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Read the probes from a file --record wrote instead of the hardware
+    #[arg(long, value_name = "PATH")]
+    pub replay: Option<PathBuf>,
+
+    /// How many times faster than it was recorded to replay
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1.0,
+        requires = "replay",
+        value_parser = parse_speed
+    )]
+    pub replay_speed: f64,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        _ => Err(format!("{} isn't a positive number", s)),
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Poll the probes and serve the dashboard and metrics (the default)
+    Serve,
+    /// Read every probe (or just one) once and print the calibrated values
+    Read {
+        /// Only read the probe with this id or label
+        probe: Option<String>,
+    },
+    /// Read every probe once and print the readings for telegraf or
+    /// collectd to pick up
+    Collect {
+        #[arg(long, value_enum)]
+        format: collect::Format,
+    },
+    /// Read one probe and report on it as a nagios/icinga plugin
+    Check {
+        /// Id or label of the probe to read
+        #[arg(long)]
+        probe: String,
+        /// Warn outside this range, in the configured units, e.g. `25`
+        /// (0 to 25), `10:` (at least 10) or `~:-15` (at most -15)
+        #[arg(long, value_name = "RANGE", allow_hyphen_values = true)]
+        warn: Option<Threshold>,
+        /// Go critical outside this range
+        #[arg(long, value_name = "RANGE", allow_hyphen_values = true)]
+        crit: Option<Threshold>,
+    },
+    /// Work out calibration offsets from a reference bath
+    Calibrate {
+        /// Actual temperature of the bath the probes are in, e.g. 0 for
+        /// an ice bath. Give it twice (e.g. `--reference 0 --reference 100`)
+        /// to calibrate the scale as well as the offset
+        #[arg(long, allow_negative_numbers = true, required = true)]
+        reference: Vec<f32>,
+        /// Number of readings to average per probe
+        #[arg(long, default_value_t = 10)]
+        samples: u32,
+        /// Seconds between readings
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+        /// Write the offsets without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+        /// Only calibrate the probe with this id or label
+        probe: Option<String>,
+    },
+    /// Print a starting config with the discovered probes filled in
+    GenerateConfig {
+        /// Write the config to this file instead of stdout
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+        /// Overwrite the output file if it already exists
+        #[arg(long, requires = "output")]
+        force: bool,
+    },
+    /// Send a test alert through every configured notifier
+    NotifyTest,
+    /// Discover the connected probes and print their ids
+    List {
+        /// Print the probes as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serve_is_default() {
+        let cli = Cli::try_parse_from(["tempmon"]).unwrap();
+        assert!(cli.command.is_none());
+        assert!(cli.config.is_none());
+        assert_eq!(cli.log_level, "info");
+        assert_eq!(cli.log_format, logger::Format::Auto);
+    }
+
+    #[test]
+    fn test_global_flags_after_subcommand() {
+        let cli = Cli::try_parse_from([
+            "tempmon",
+            "serve",
+            "--config",
+            "/tmp/tempmon.toml",
+            "--log-level",
+            "warn,tempmon::server=debug",
+            "--log-format",
+            "json",
+        ])
+        .unwrap();
+        assert!(matches!(cli.command, Some(Command::Serve)));
+        assert_eq!(cli.config, Some(PathBuf::from("/tmp/tempmon.toml")));
+        assert_eq!(cli.log_level, "warn,tempmon::server=debug");
+        assert_eq!(cli.log_format, logger::Format::Json);
+    }
+
+    #[test]
+    fn test_replay() {
+        let cli =
+            Cli::try_parse_from(["tempmon", "--replay", "brew.ndjson", "--replay-speed", "60"])
+                .unwrap();
+        assert_eq!(cli.replay, Some(PathBuf::from("brew.ndjson")));
+        assert_eq!(cli.replay_speed, 60.0);
+
+        assert!(Cli::try_parse_from(["tempmon", "--replay-speed", "60"]).is_err());
+        assert!(Cli::try_parse_from(["tempmon", "--replay", "a", "--replay-speed", "0"]).is_err());
+        assert!(Cli::try_parse_from(["tempmon", "--replay", "a", "--record", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_list_json() {
+        let cli = Cli::try_parse_from(["tempmon", "list", "--json"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::List { json: true })));
+    }
+
+    #[test]
+    fn test_read_one_probe() {
+        let cli = Cli::try_parse_from(["tempmon", "read", "fermenter"]).unwrap();
+        match cli.command {
+            Some(Command::Read { probe }) => assert_eq!(probe.as_deref(), Some("fermenter")),
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_collect_format() {
+        let cli = Cli::try_parse_from(["tempmon", "collect", "--format", "collectd"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Collect {
+                format: collect::Format::Collectd
+            })
+        ));
+        assert!(Cli::try_parse_from(["tempmon", "collect"]).is_err());
+    }
+
+    #[test]
+    fn test_check_thresholds() {
+        let cli = Cli::try_parse_from([
+            "tempmon", "check", "--probe", "freezer", "--warn", "~:-15", "--crit", "-20:-10",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Command::Check { probe, warn, crit }) => {
+                assert_eq!(probe, "freezer");
+                assert!(warn.unwrap().alerts(-12.0));
+                assert!(!crit.unwrap().alerts(-12.0));
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+        assert!(
+            Cli::try_parse_from(["tempmon", "check", "--probe", "x", "--warn", "hot"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_calibrate_negative_reference() {
+        let cli = Cli::try_parse_from(["tempmon", "calibrate", "--reference", "-0.5"]).unwrap();
+        match cli.command {
+            Some(Command::Calibrate {
+                reference, samples, ..
+            }) => {
+                assert_eq!(reference, vec![-0.5]);
+                assert_eq!(samples, 10);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_log_file_options() {
+        let cli = Cli::try_parse_from([
+            "tempmon",
+            "--log-file",
+            "/var/log/tempmon.log",
+            "--log-max-size",
+            "10",
+            "--log-rotate",
+            "daily",
+        ])
+        .unwrap();
+        assert_eq!(cli.log_file, Some(PathBuf::from("/var/log/tempmon.log")));
+        assert_eq!(cli.log_max_size, Some(10));
+        assert_eq!(cli.log_rotate, Rotation::Daily);
+        assert_eq!(cli.log_keep, 5);
+
+        // rotation settings make no sense without a file
+        assert!(Cli::try_parse_from(["tempmon", "--log-max-size", "10"]).is_err());
+    }
+
+    #[test]
+    fn test_calibrate_two_references() {
+        let cli = Cli::try_parse_from([
+            "tempmon",
+            "calibrate",
+            "--reference",
+            "0",
+            "--reference",
+            "100",
+            "fermenter",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Calibrate { reference, probe, .. })
+                if reference == vec![0.0, 100.0] && probe.as_deref() == Some("fermenter")
+        ));
+    }
+
+    #[test]
+    fn test_rejects_unknown_arguments() {
+        assert!(Cli::try_parse_from(["tempmon", "--bogus"]).is_err());
+        assert!(Cli::try_parse_from(["tempmon", "--log-level", "tempmon=loud"]).is_err());
+    }
+
+    #[test]
+    fn verify_cli() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+}
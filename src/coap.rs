@@ -0,0 +1,644 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use tracing::{debug, info};
+
+use crate::api;
+use crate::config::CoapConfig;
+use crate::state::{Readings, TempData};
+
+const VERSION: u8 = 1;
+
+const CONFIRMABLE: u8 = 0;
+const NON_CONFIRMABLE: u8 = 1;
+const ACKNOWLEDGEMENT: u8 = 2;
+const RESET: u8 = 3;
+
+/// Codes are a class and a detail, written c.dd.
+const fn code(class: u8, detail: u8) -> u8 {
+    class << 5 | detail
+}
+const EMPTY: u8 = code(0, 0);
+const GET: u8 = code(0, 1);
+const CONTENT: u8 = code(2, 5);
+const BAD_OPTION: u8 = code(4, 2);
+const NOT_FOUND: u8 = code(4, 4);
+const METHOD_NOT_ALLOWED: u8 = code(4, 5);
+
+const OBSERVE: u16 = 6;
+const URI_PATH: u16 = 11;
+const CONTENT_FORMAT: u16 = 12;
+const URI_QUERY: u16 = 15;
+const ACCEPT: u16 = 17;
+
+const TEXT_PLAIN: u32 = 0;
+const LINK_FORMAT: u32 = 40;
+const JSON: u32 = 50;
+
+/// Past this, clients get a reading but aren't kept up to date.
+const MAX_OBSERVERS: usize = 64;
+/// Every this many notifications one is confirmable, and an observer that
+/// hasn't acknowledged it by the next is forgotten.
+const CONFIRM_EVERY: u32 = 10;
+/// How often to check for a new poll while no requests arrive.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Message {
+    kind: u8,
+    code: u8,
+    id: u16,
+    token: Vec<u8>,
+    /// in order of option number
+    options: Vec<(u16, Vec<u8>)>,
+    payload: Vec<u8>,
+}
+
+/// A client kept up to date with a resource.
+struct Observer {
+    peer: SocketAddr,
+    token: Vec<u8>,
+    path: Vec<String>,
+    /// the payload it was last sent
+    last: Vec<u8>,
+    notifications: u32,
+    /// the last notification's message id
+    last_id: u16,
+    /// a confirmable notification's message id, until it's acknowledged
+    unacknowledged: Option<u16>,
+}
+
+struct Server {
+    observers: Vec<Observer>,
+    next_id: u16,
+    /// the observe sequence number, which only has to increase
+    sequence: u32,
+    /// when the readings observers last heard about were taken
+    updated: Option<SystemTime>,
+}
+
+/// Serves the readings over CoAP from a thread of its own:
+///
+/// - `/readings` all of them as JSON, the same as `/api/v1/readings`
+/// - `/readings/<probe>` one probe's temperature as plain text, empty while
+///   it's failing
+/// - `/.well-known/core` a list of the resources
+///
+/// The readings can be observed, in which case a notification is sent
+/// after each poll that changes them.
+pub fn start(config: &CoapConfig, temps: TempData) -> io::Result<()> {
+    let socket = UdpSocket::bind(&config.bind)?;
+    socket.set_read_timeout(Some(CHECK_INTERVAL))?;
+    info!("answering coap on {}", socket.local_addr()?);
+
+    thread::spawn(move || {
+        let mut server = Server::new();
+        let mut buf = [0; 65535];
+        let send = |message: &Message, peer: SocketAddr| {
+            if let Err(e) = socket.send_to(&message.encode(), peer) {
+                debug!(%peer, "failed to send coap message: {}", e);
+            }
+        };
+        loop {
+            match socket.recv_from(&mut buf) {
+                Ok((len, peer)) => match Message::parse(&buf[..len]) {
+                    Some(request) => {
+                        let reply = server.handle(&temps.snapshot(), request, peer);
+                        if let Some(reply) = reply {
+                            send(&reply, peer);
+                        }
+                    }
+                    None => debug!(%peer, "ignoring invalid coap message"),
+                },
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) => {}
+                Err(e) => debug!("failed to receive coap message: {}", e),
+            }
+            let notifications = server.notify(&temps.snapshot());
+            for (peer, notification) in notifications {
+                send(&notification, peer);
+            }
+        }
+    });
+    Ok(())
+}
+
+impl Server {
+    fn new() -> Self {
+        Server {
+            observers: Vec::new(),
+            next_id: rand_id(),
+            sequence: 0,
+            updated: None,
+        }
+    }
+
+    fn next_id(&mut self) -> u16 {
+        self.next_id = self.next_id.wrapping_add(1);
+        self.next_id
+    }
+
+    /// The reply to a message, if it needs one.
+    fn handle(
+        &mut self,
+        readings: &Readings,
+        request: Message,
+        peer: SocketAddr,
+    ) -> Option<Message> {
+        match (request.kind, request.code) {
+            (ACKNOWLEDGEMENT, _) => {
+                for observer in &mut self.observers {
+                    if observer.peer == peer && observer.unacknowledged == Some(request.id) {
+                        observer.unacknowledged = None;
+                    }
+                }
+                return None;
+            }
+            // the client has lost interest in a notification
+            (RESET, _) => {
+                self.observers
+                    .retain(|o| o.peer != peer || o.last_id != request.id);
+                return None;
+            }
+            // a ping
+            (CONFIRMABLE, EMPTY) => {
+                return Some(Message::new(RESET, EMPTY, request.id, Vec::new()));
+            }
+            (NON_CONFIRMABLE, EMPTY) => return None,
+            _ => {}
+        }
+
+        // responses are only for requests
+        if request.code >> 5 != 0 {
+            return None;
+        }
+        let (kind, id) = match request.kind {
+            CONFIRMABLE => (ACKNOWLEDGEMENT, request.id),
+            _ => (NON_CONFIRMABLE, self.next_id()),
+        };
+        let mut reply = Message::new(kind, METHOD_NOT_ALLOWED, id, request.token.clone());
+        // an odd option number is one we must understand to answer, but
+        // queries and accept don't change anything here
+        if request
+            .options
+            .iter()
+            .any(|(n, _)| n % 2 == 1 && !matches!(*n, URI_PATH | URI_QUERY | ACCEPT))
+        {
+            reply.code = BAD_OPTION;
+            return Some(reply);
+        }
+        if request.code != GET {
+            return Some(reply);
+        }
+
+        let path = request.path();
+        let (format, payload) = match resource(&path, readings) {
+            Some(found) => found,
+            None => {
+                reply.code = NOT_FOUND;
+                return Some(reply);
+            }
+        };
+        reply.code = CONTENT;
+
+        let observing = |o: &Observer| o.peer == peer && o.token == request.token;
+        match request.option(OBSERVE).map(decode_uint) {
+            Some(0) if path != [".well-known", "core"] => {
+                self.observers.retain(|o| !observing(o));
+                if self.observers.len() < MAX_OBSERVERS {
+                    self.observers.push(Observer {
+                        peer,
+                        token: request.token.clone(),
+                        path,
+                        last: payload.clone(),
+                        notifications: 0,
+                        last_id: id,
+                        unacknowledged: None,
+                    });
+                    reply.options.push((OBSERVE, encode_uint(self.sequence)));
+                    debug!(%peer, "coap client observing");
+                }
+            }
+            _ => self.observers.retain(|o| !observing(o)),
+        }
+        reply.options.push((CONTENT_FORMAT, encode_uint(format)));
+        reply.payload = payload;
+        Some(reply)
+    }
+
+    /// Notifications for observers whose resource has changed since the
+    /// last poll.
+    fn notify(&mut self, readings: &Readings) -> Vec<(SocketAddr, Message)> {
+        if self.updated == Some(readings.updated) {
+            return Vec::new();
+        }
+        self.updated = Some(readings.updated);
+        self.sequence = (self.sequence + 1) & 0xff_ffff;
+
+        // forget anyone who didn't acknowledge the last confirmable one
+        self.observers.retain(|o| o.unacknowledged.is_none());
+
+        let mut notifications = Vec::new();
+        let mut observers = std::mem::take(&mut self.observers);
+        for observer in &mut observers {
+            let Some((format, payload)) = resource(&observer.path, readings) else {
+                continue;
+            };
+            if payload == observer.last {
+                continue;
+            }
+            observer.notifications += 1;
+            let confirmable = observer.notifications % CONFIRM_EVERY == 0;
+            let id = self.next_id();
+            let kind = if confirmable {
+                CONFIRMABLE
+            } else {
+                NON_CONFIRMABLE
+            };
+            let mut message = Message::new(kind, CONTENT, id, observer.token.clone());
+            message.options = vec![
+                (OBSERVE, encode_uint(self.sequence)),
+                (CONTENT_FORMAT, encode_uint(format)),
+            ];
+            message.payload = payload.clone();
+            observer.last = payload;
+            observer.last_id = id;
+            observer.unacknowledged = confirmable.then_some(id);
+            notifications.push((observer.peer, message));
+        }
+        self.observers = observers;
+        notifications
+    }
+}
+
+/// A resource's content format and contents.
+fn resource(path: &[String], readings: &Readings) -> Option<(u32, Vec<u8>)> {
+    match path {
+        [readings_] if readings_ == "readings" => {
+            Some((JSON, api::readings(&readings.temps).into_bytes()))
+        }
+        [readings_, probe @ ..] if readings_ == "readings" && !probe.is_empty() => {
+            // probe names can have slashes, e.g. federated ones
+            let temp = readings.temps.get(&probe.join("/"))?;
+            let text = temp.map(|t| t.to_string()).unwrap_or_default();
+            Some((TEXT_PLAIN, text.into_bytes()))
+        }
+        [well_known, core] if well_known == ".well-known" && core == "core" => {
+            Some((LINK_FORMAT, links(readings).into_bytes()))
+        }
+        _ => None,
+    }
+}
+
+/// The resources in CoRE link format.
+fn links(readings: &Readings) -> String {
+    let mut names: Vec<&String> = readings.temps.keys().collect();
+    names.sort();
+    let mut links = vec![format!("</readings>;ct={};obs", JSON)];
+    links.extend(
+        names
+            .iter()
+            .map(|name| format!("</readings/{}>;ct={};obs", uri_path(name), TEXT_PLAIN)),
+    );
+    links.join(",")
+}
+
+/// Percent-encodes a probe name for a path, leaving its slashes.
+fn uri_path(name: &str) -> String {
+    let mut encoded = String::new();
+    for b in name.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~' | b'/') {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+/// A message id to start from, so a restart doesn't repeat recent ones.
+fn rand_id() -> u16 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u16)
+        .unwrap_or_default()
+}
+
+impl Message {
+    fn new(kind: u8, code: u8, id: u16, token: Vec<u8>) -> Self {
+        Message {
+            kind,
+            code,
+            id,
+            token,
+            options: Vec::new(),
+            payload: Vec::new(),
+        }
+    }
+
+    fn option(&self, number: u16) -> Option<&[u8]> {
+        self.options
+            .iter()
+            .find(|(n, _)| *n == number)
+            .map(|(_, value)| &value[..])
+    }
+
+    fn path(&self) -> Vec<String> {
+        self.options
+            .iter()
+            .filter(|(n, _)| *n == URI_PATH)
+            .map(|(_, segment)| String::from_utf8_lossy(segment).into_owned())
+            .collect()
+    }
+
+    fn parse(data: &[u8]) -> Option<Self> {
+        let (header, mut rest) = data.split_at_checked(4)?;
+        let token_len = (header[0] & 0x0f) as usize;
+        if header[0] >> 6 != VERSION || token_len > 8 {
+            return None;
+        }
+        let (token, after) = rest.split_at_checked(token_len)?;
+        rest = after;
+
+        let mut options = Vec::new();
+        let mut number = 0u16;
+        let mut payload = Vec::new();
+        while let Some((&first, after)) = rest.split_first() {
+            rest = after;
+            if first == 0xff {
+                if rest.is_empty() {
+                    return None;
+                }
+                payload = rest.to_vec();
+                break;
+            }
+            let delta = extended(first >> 4, &mut rest)?;
+            let len = extended(first & 0x0f, &mut rest)?;
+            number = number.checked_add(u16::try_from(delta).ok()?)?;
+            let (value, after) = rest.split_at_checked(len)?;
+            options.push((number, value.to_vec()));
+            rest = after;
+        }
+        Some(Message {
+            kind: (header[0] >> 4) & 0x03,
+            code: header[1],
+            id: u16::from_be_bytes([header[2], header[3]]),
+            token: token.to_vec(),
+            options,
+            payload,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = vec![
+            VERSION << 6 | self.kind << 4 | self.token.len() as u8,
+            self.code,
+        ];
+        out.extend(self.id.to_be_bytes());
+        out.extend(&self.token);
+
+        let mut options = self.options.clone();
+        options.sort_by_key(|(n, _)| *n);
+        let mut number = 0;
+        for (n, value) in &options {
+            let (delta, delta_ext) = nibble((n - number).into());
+            let (len, len_ext) = nibble(value.len());
+            out.push(delta << 4 | len);
+            out.extend(delta_ext);
+            out.extend(len_ext);
+            out.extend(value);
+            number = *n;
+        }
+        if !self.payload.is_empty() {
+            out.push(0xff);
+            out.extend(&self.payload);
+        }
+        out
+    }
+}
+
+/// An option delta or length as its nibble and any extended bytes.
+fn nibble(value: usize) -> (u8, Vec<u8>) {
+    match value {
+        0..13 => (value as u8, Vec::new()),
+        13..269 => (13, vec![(value - 13) as u8]),
+        _ => (14, ((value - 269) as u16).to_be_bytes().to_vec()),
+    }
+}
+
+/// Reads an option delta or length, taking any extended bytes off `data`.
+fn extended(nibble: u8, data: &mut &[u8]) -> Option<usize> {
+    Some(match nibble {
+        0..13 => nibble.into(),
+        13 => {
+            let (&b, rest) = data.split_first()?;
+            *data = rest;
+            usize::from(b) + 13
+        }
+        14 => {
+            let (bytes, rest) = data.split_at_checked(2)?;
+            *data = rest;
+            usize::from(u16::from_be_bytes([bytes[0], bytes[1]])) + 269
+        }
+        _ => return None,
+    })
+}
+
+/// An unsigned option value, in as few bytes as it takes.
+fn encode_uint(n: u32) -> Vec<u8> {
+    n.to_be_bytes()
+        .into_iter()
+        .skip_while(|b| *b == 0)
+        .collect()
+}
+
+fn decode_uint(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |n, b| n << 8 | u32::from(*b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer() -> SocketAddr {
+        "192.168.1.50:5683".parse().unwrap()
+    }
+
+    fn get(kind: u8, id: u16, path: &[&str], observe: Option<u32>) -> Message {
+        let mut request = Message::new(kind, GET, id, vec![0xab, 0xcd]);
+        if let Some(observe) = observe {
+            request.options.push((OBSERVE, encode_uint(observe)));
+        }
+        for segment in path {
+            request
+                .options
+                .push((URI_PATH, segment.as_bytes().to_vec()));
+        }
+        request
+    }
+
+    #[test]
+    fn test_encoding() {
+        let mut message = get(CONFIRMABLE, 0x1234, &["readings", "fermenter"], Some(0));
+        message.options.push((60, vec![0; 300]));
+        message.payload = b"18.5".to_vec();
+        let encoded = message.encode();
+        assert_eq!(&encoded[..6], [0x42, GET, 0x12, 0x34, 0xab, 0xcd]);
+        assert_eq!(Message::parse(&encoded), Some(message));
+
+        assert_eq!(encode_uint(0), Vec::<u8>::new());
+        assert_eq!(encode_uint(0x1234), [0x12, 0x34]);
+        assert_eq!(decode_uint(&[0x12, 0x34]), 0x1234);
+        // no payload after a payload marker
+        assert_eq!(Message::parse(&[0x40, GET, 0, 1, 0xff]), None);
+        assert_eq!(Message::parse(&[0x80, GET, 0, 1]), None);
+    }
+
+    #[test]
+    fn test_get() {
+        let mut readings = Readings::new(["fermenter", "garage/freezer"]);
+        readings.record("fermenter", Some(18.5));
+        let mut server = Server::new();
+
+        let reply = server
+            .handle(
+                &readings,
+                get(CONFIRMABLE, 7, &["readings", "fermenter"], None),
+                peer(),
+            )
+            .unwrap();
+        assert_eq!(
+            (reply.kind, reply.code, reply.id),
+            (ACKNOWLEDGEMENT, CONTENT, 7)
+        );
+        assert_eq!(reply.token, [0xab, 0xcd]);
+        assert_eq!(reply.payload, b"18.5");
+        assert_eq!(reply.option(CONTENT_FORMAT), Some(&[][..]));
+
+        let reply = server
+            .handle(
+                &readings,
+                get(NON_CONFIRMABLE, 8, &["readings", "garage", "freezer"], None),
+                peer(),
+            )
+            .unwrap();
+        assert_eq!((reply.kind, reply.code), (NON_CONFIRMABLE, CONTENT));
+        assert!(reply.payload.is_empty());
+
+        let reply = server
+            .handle(&readings, get(CONFIRMABLE, 9, &["readings"], None), peer())
+            .unwrap();
+        assert!(
+            String::from_utf8(reply.payload)
+                .unwrap()
+                .starts_with("{\"readings\":")
+        );
+
+        let reply = server
+            .handle(
+                &readings,
+                get(CONFIRMABLE, 10, &["readings", "mash"], None),
+                peer(),
+            )
+            .unwrap();
+        assert_eq!(reply.code, NOT_FOUND);
+
+        let mut put = get(CONFIRMABLE, 11, &["readings"], None);
+        put.code = code(0, 3);
+        assert_eq!(
+            server.handle(&readings, put, peer()).unwrap().code,
+            METHOD_NOT_ALLOWED
+        );
+
+        let reply = server
+            .handle(
+                &readings,
+                get(CONFIRMABLE, 12, &[".well-known", "core"], None),
+                peer(),
+            )
+            .unwrap();
+        assert_eq!(
+            reply.payload,
+            b"</readings>;ct=50;obs,</readings/fermenter>;ct=0;obs,</readings/garage/freezer>;ct=0;obs"
+        );
+
+        // pings are answered with a reset
+        let ping = Message::new(CONFIRMABLE, EMPTY, 13, Vec::new());
+        assert_eq!(server.handle(&readings, ping, peer()).unwrap().kind, RESET);
+    }
+
+    #[test]
+    fn test_observe() {
+        let mut readings = Readings::new(["fermenter", "ambient"]);
+        readings.record("fermenter", Some(18.5));
+        let mut server = Server::new();
+        server.notify(&readings);
+
+        let request = get(CONFIRMABLE, 1, &["readings", "fermenter"], Some(0));
+        let reply = server.handle(&readings, request, peer()).unwrap();
+        assert!(reply.option(OBSERVE).is_some());
+        assert_eq!(server.observers.len(), 1);
+
+        // nothing's sent until a poll changes the reading
+        assert!(server.notify(&readings).is_empty());
+        readings.record("ambient", Some(12.0));
+        readings.updated += Duration::from_secs(15);
+        assert!(server.notify(&readings).is_empty());
+
+        readings.record("fermenter", Some(18.75));
+        readings.updated += Duration::from_secs(15);
+        let notifications = server.notify(&readings);
+        assert_eq!(notifications.len(), 1);
+        let (to, notification) = &notifications[0];
+        assert_eq!(*to, peer());
+        assert_eq!(notification.token, [0xab, 0xcd]);
+        assert_eq!(notification.payload, b"18.75");
+        assert!(
+            decode_uint(notification.option(OBSERVE).unwrap())
+                > decode_uint(reply.option(OBSERVE).unwrap())
+        );
+
+        // a reset to a notification cancels the observation
+        let reset = Message::new(RESET, EMPTY, notification.id, Vec::new());
+        assert!(server.handle(&readings, reset, peer()).is_none());
+        assert!(server.observers.is_empty());
+
+        // as does a get without observe
+        let request = get(NON_CONFIRMABLE, 2, &["readings"], Some(0));
+        server.handle(&readings, request, peer());
+        assert_eq!(server.observers.len(), 1);
+        server.handle(
+            &readings,
+            get(NON_CONFIRMABLE, 3, &["readings"], Some(1)),
+            peer(),
+        );
+        assert!(server.observers.is_empty());
+    }
+
+    #[test]
+    fn test_unacknowledged_observer_is_dropped() {
+        let mut readings = Readings::new(["fermenter"]);
+        let mut server = Server::new();
+        let request = get(NON_CONFIRMABLE, 1, &["readings", "fermenter"], Some(0));
+        server.handle(&readings, request, peer());
+
+        let mut confirmable = None;
+        for i in 0..CONFIRM_EVERY {
+            readings.record("fermenter", Some(i as f32));
+            readings.updated += Duration::from_secs(15);
+            let notifications = server.notify(&readings);
+            confirmable = Some(notifications[0].1.clone());
+        }
+        assert_eq!(confirmable.unwrap().kind, CONFIRMABLE);
+
+        readings.record("fermenter", Some(20.0));
+        readings.updated += Duration::from_secs(15);
+        assert!(server.notify(&readings).is_empty());
+        assert!(server.observers.is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// InfluxDB line protocol, for telegraf's exec input
+    Influx,
+    /// PUTVAL lines for collectd's exec plugin
+    Collectd,
+}
+
+/// Where a collectd value is going: the host and interval collectd passes
+/// its exec plugins.
+pub struct Collectd {
+    pub host: String,
+    pub interval: u64,
+}
+
+/// A probe's reading of `temp` °c at `at` in `format`.
+pub fn line(
+    format: Format,
+    collectd: &Collectd,
+    id: &str,
+    name: &str,
+    temp: f32,
+    at: SystemTime,
+) -> String {
+    let since_epoch = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+    match format {
+        Format::Influx => format!(
+            "tempmon,probe={},id={} temperature={} {}",
+            influx_tag(name),
+            influx_tag(id),
+            temp,
+            since_epoch.as_nanos()
+        ),
+        Format::Collectd => format!(
+            "PUTVAL \"{}/tempmon/temperature-{}\" interval={} {}:{}",
+            collectd.host,
+            collectd_instance(name),
+            collectd.interval,
+            since_epoch.as_secs(),
+            temp
+        ),
+    }
+}
+
+/// Escapes a tag value for the line protocol.
+fn influx_tag(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A type instance collectd will take: no slashes, quotes or spaces.
+fn collectd_instance(name: &str) -> String {
+    name.chars()
+        .map(|c| if matches!(c, '/' | '"' | ' ') { '_' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_line() {
+        let at = UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let collectd = Collectd {
+            host: "brewery".to_string(),
+            interval: 15,
+        };
+        assert_eq!(
+            line(
+                Format::Influx,
+                &collectd,
+                "28-abc123",
+                "fermenter",
+                18.5,
+                at
+            ),
+            "tempmon,probe=fermenter,id=28-abc123 temperature=18.5 1800000000000000000"
+        );
+        assert_eq!(
+            line(
+                Format::Influx,
+                &collectd,
+                "28-def",
+                "cold side,1",
+                -2.25,
+                at
+            ),
+            "tempmon,probe=cold\\ side\\,1,id=28-def temperature=-2.25 1800000000000000000"
+        );
+        assert_eq!(
+            line(
+                Format::Collectd,
+                &collectd,
+                "28-abc123",
+                "mash tun",
+                66.0,
+                at
+            ),
+            "PUTVAL \"brewery/tempmon/temperature-mash_tun\" interval=15 1800000000:66"
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use crate::backend::Registry;
+use crate::collect::{self, Collectd};
+use crate::config::{self, Config, load_config};
+use crate::nagios::{self, Status, Threshold};
+use crate::notify;
+use crate::poll;
+use crate::probe::Probe;
+
+#[derive(Serialize)]
+struct ProbeInfo {
+    id: String,
+    path: String,
+    label: Option<String>,
+    resolution: Option<u8>,
+}
+
+impl ProbeInfo {
+    fn new(probe: &Probe, labels: &HashMap<String, String>) -> Self {
+        ProbeInfo {
+            id: probe.id.clone(),
+            path: probe.source.location(),
+            label: labels.get(&probe.id).cloned(),
+            resolution: probe.resolution().ok(),
+        }
+    }
+}
+
+/// The one-shot commands are useful before there's a config at all, so
+/// carry on without labels or calibration if it can't be loaded.
+fn load_config_or_warn(config_path: &Path) -> Option<Config> {
+    match load_config(config_path) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            warn!(
+                "failed to load config, ignoring labels and calibration: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Finds the probes with the configured backends, or 1-wire's without a
+/// config.
+fn discover(
+    config: Option<&Config>,
+    labels: &HashMap<String, String>,
+) -> std::io::Result<Vec<Probe>> {
+    Registry::new(config)?.discover(labels)
+}
+
+/// Runs discovery once and prints the probes that were found.
+pub fn list(config_path: &Path, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config_or_warn(config_path);
+    let labels = config
+        .as_ref()
+        .map(|c| c.probe_labels.clone())
+        .unwrap_or_default();
+
+    let mut probes: Vec<_> = discover(config.as_ref(), &labels)?
+        .iter()
+        .map(|p| ProbeInfo::new(p, &labels))
+        .collect();
+    probes.sort_by(|a, b| a.id.cmp(&b.id));
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&probes)?);
+    } else {
+        print!("{}", format_table(&probes));
+    }
+    Ok(())
+}
+
+/// Reads every probe, or only the one matching `only` by id or label, and
+/// prints the calibrated temperatures. Returns false if any read failed.
+pub fn read(config_path: &Path, only: Option<&str>) -> Result<bool, Box<dyn std::error::Error>> {
+    let config = load_config_or_warn(config_path);
+    let labels = config
+        .as_ref()
+        .map(|c| c.probe_labels.clone())
+        .unwrap_or_default();
+
+    let mut probes = discover(config.as_ref(), &labels)?;
+    probes.sort_by(|a, b| a.name.cmp(&b.name));
+    if let Some(only) = only {
+        probes.retain(|p| p.id == only || p.name == only);
+        if probes.is_empty() {
+            return Err(format!("no probe with id or label {}", only).into());
+        }
+    } else if probes.is_empty() {
+        return Err("no probes found".into());
+    }
+
+    let mut ok = true;
+    for probe in &probes {
+        let result = match &config {
+            Some(config) => poll::read(probe, config).map(|s| s.temp),
+            None => probe.read_temperature(),
+        };
+        match result {
+            Ok(temp) => println!("{}: {:.2}°c", probe.name, temp),
+            Err(e) => {
+                error!("{}: {}", probe.name, e);
+                ok = false;
+            }
+        }
+    }
+    Ok(ok)
+}
+
+/// Reads every probe once and prints a line per reading in `format`, for
+/// running under telegraf's or collectd's exec plugin. A probe that fails
+/// is left out; returns false only if every one did.
+pub fn collect(
+    config_path: &Path,
+    format: collect::Format,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let config = load_config_or_warn(config_path);
+    let labels = config
+        .as_ref()
+        .map(|c| c.probe_labels.clone())
+        .unwrap_or_default();
+
+    let mut probes = discover(config.as_ref(), &labels)?;
+    if probes.is_empty() {
+        return Err("no probes found".into());
+    }
+    probes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // collectd tells its exec plugins these
+    let collectd = Collectd {
+        host: env::var("COLLECTD_HOSTNAME")
+            .ok()
+            .or_else(|| {
+                std::fs::read_to_string("/proc/sys/kernel/hostname")
+                    .ok()
+                    .map(|h| h.trim().to_string())
+            })
+            .unwrap_or_else(|| "localhost".to_string()),
+        interval: env::var("COLLECTD_INTERVAL")
+            .ok()
+            .and_then(|i| i.parse::<f64>().ok())
+            .map(|i| i.round() as u64)
+            .or(config.as_ref().map(|c| c.settings.probe_interval))
+            .unwrap_or(60),
+    };
+
+    let mut read = 0;
+    for probe in &probes {
+        let result = match &config {
+            Some(config) => poll::read(probe, config).map(|s| s.temp),
+            None => probe.read_temperature(),
+        };
+        match result {
+            Ok(temp) => {
+                let at = SystemTime::now();
+                println!(
+                    "{}",
+                    collect::line(format, &collectd, &probe.id, &probe.name, temp, at)
+                );
+                read += 1;
+            }
+            Err(e) => error!("{}: {}", probe.name, e),
+        }
+    }
+    Ok(read > 0)
+}
+
+/// Reads one probe by id or label and prints the result as a nagios plugin,
+/// thresholds and all, in the configured units. Everything goes to stdout,
+/// which is all the monitoring server sees, and failures are `UNKNOWN`.
+pub fn check(
+    config_path: &Path,
+    probe: &str,
+    warn: Option<&Threshold>,
+    crit: Option<&Threshold>,
+) -> Status {
+    let config = load_config_or_warn(config_path);
+    let labels = config
+        .as_ref()
+        .map(|c| c.probe_labels.clone())
+        .unwrap_or_default();
+    let unit = config
+        .as_ref()
+        .map(|c| c.settings.units)
+        .unwrap_or_default();
+
+    let probes = match discover(config.as_ref(), &labels) {
+        Ok(probes) => probes,
+        Err(e) => {
+            println!(
+                "{}",
+                nagios::unknown(&format!("failed to discover probes: {}", e))
+            );
+            return Status::Unknown;
+        }
+    };
+    let Some(found) = probes.iter().find(|p| p.id == probe || p.name == probe) else {
+        println!(
+            "{}",
+            nagios::unknown(&format!("no probe with id or label {}", probe))
+        );
+        return Status::Unknown;
+    };
+
+    let result = match &config {
+        Some(config) => poll::read(found, config).map(|s| s.temp),
+        None => found.read_temperature(),
+    };
+    match result {
+        Ok(temp) => {
+            let (status, output) =
+                nagios::report(&found.name, unit.convert(temp), unit.symbol(), warn, crit);
+            println!("{}", output);
+            status
+        }
+        Err(e) => {
+            println!("{}", nagios::unknown(&format!("{}: {}", found.name, e)));
+            Status::Unknown
+        }
+    }
+}
+
+/// Sends a test alert through each notifier in the config and prints how
+/// each one got on. Returns false if any of them failed.
+pub fn notify_test(config_path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let config = load_config(config_path)?;
+    let results = notify::send_test(&config.alerts);
+    if results.is_empty() {
+        return Err("no notifiers configured in [alerts]".into());
+    }
+
+    let mut ok = true;
+    for (notifier, result) in results {
+        match result {
+            Ok(()) => println!("{}: sent", notifier),
+            Err(e) => {
+                error!("{}: {}", notifier, e);
+                ok = false;
+            }
+        }
+    }
+    Ok(ok)
+}
+
+/// Writes a config for the probes that are currently connected, to stdout
+/// or to `output`.
+pub fn generate_config(
+    output: Option<&Path>,
+    force: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut ids: Vec<_> = discover(None, &HashMap::new())?
+        .into_iter()
+        .map(|p| p.id)
+        .collect();
+    ids.sort();
+    if ids.is_empty() {
+        warn!("no probes found, [probe_labels] will be empty");
+    }
+
+    let contents = config::generate(&ids);
+    match output {
+        Some(path) => {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .create_new(!force)
+                .open(path)
+                .map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+            file.write_all(contents.as_bytes())?;
+            info!(
+                "wrote config for {} probe(s) to {}",
+                ids.len(),
+                path.display()
+            );
+        }
+        None => print!("{}", contents),
+    }
+    Ok(())
+}
+
+fn format_table(probes: &[ProbeInfo]) -> String {
+    let rows: Vec<[String; 4]> = probes
+        .iter()
+        .map(|p| {
+            [
+                p.id.clone(),
+                p.label.clone().unwrap_or_else(|| "-".to_string()),
+                p.resolution
+                    .map(|r| format!("{} bit", r))
+                    .unwrap_or_else(|| "-".to_string()),
+                p.path.clone(),
+            ]
+        })
+        .collect();
+
+    let header = ["ID", "LABEL", "RESOLUTION", "PATH"].map(String::from);
+    let mut widths = header.clone().map(|h| h.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_table() {
+        let probes = vec![
+            ProbeInfo {
+                id: "28-000000000001".to_string(),
+                path: "/sys/bus/w1/devices/28-000000000001/w1_slave".to_string(),
+                label: Some("fermenter".to_string()),
+                resolution: Some(12),
+            },
+            ProbeInfo {
+                id: "28-000000000002".to_string(),
+                path: "/sys/bus/w1/devices/28-000000000002/w1_slave".to_string(),
+                label: None,
+                resolution: None,
+            },
+        ];
+
+        assert_eq!(
+            format_table(&probes),
+            "ID               LABEL      RESOLUTION  PATH\n\
+             28-000000000001  fermenter  12 bit      /sys/bus/w1/devices/28-000000000001/w1_slave\n\
+             28-000000000002  -          -           /sys/bus/w1/devices/28-000000000002/w1_slave\n"
+        );
+    }
+}
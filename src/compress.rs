@@ -0,0 +1,115 @@
+use std::io::{self, Write};
+
+use flate2::Compression;
+use flate2::write::{GzEncoder, ZlibEncoder};
+
+// small bodies aren't worth the cpu on a pi zero
+const MIN_COMPRESS_SIZE: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// Picks the preferred supported encoding from an `Accept-Encoding` header.
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let mut best: Option<(Encoding, f32)> = None;
+
+    for entry in accept_encoding.split(',') {
+        let mut parts = entry.split(';');
+        let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let quality = parts
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        let encoding = match name.as_str() {
+            "gzip" | "x-gzip" => Encoding::Gzip,
+            "deflate" => Encoding::Deflate,
+            _ => continue,
+        };
+
+        if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+            best = Some((encoding, quality));
+        }
+    }
+
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Compresses `data`, returning `None` when it's too small to bother.
+pub fn compress(data: &[u8], encoding: Encoding) -> io::Result<Option<Vec<u8>>> {
+    if data.len() < MIN_COMPRESS_SIZE {
+        return Ok(None);
+    }
+
+    let compressed = match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?
+        }
+        Encoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?
+        }
+    };
+
+    Ok(Some(compressed))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    #[test]
+    fn test_negotiate_prefers_gzip() {
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate"), Some(Encoding::Deflate));
+    }
+
+    #[test]
+    fn test_negotiate_honours_quality() {
+        assert_eq!(negotiate("gzip;q=0.5, deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip;q=0"), None);
+    }
+
+    #[test]
+    fn test_negotiate_unsupported() {
+        assert_eq!(negotiate("br, identity"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        let data = "dash_temp_readings{probe=\"basking_spot\"} 31.25\n".repeat(40);
+        let compressed = compress(data.as_bytes(), Encoding::Gzip).unwrap().unwrap();
+        assert!(compressed.len() < data.len());
+
+        let mut decoded = String::new();
+        GzDecoder::new(&compressed[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_compress_skips_small_bodies() {
+        assert!(compress(b"OK", Encoding::Gzip).unwrap().is_none());
+    }
+}
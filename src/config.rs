@@ -1,95 +1,3052 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::auth::RouteGroup;
+use crate::backend;
+use crate::schedule;
 
 const CONFIG_PATH: &str = "/etc/tempmon/config.toml";
 
-#[derive(Debug, Deserialize)]
-pub struct Config {
-    pub settings: Settings,
-    pub probe_labels: HashMap<String, String>,
-    #[serde(default)]
-    pub calibration_offsets: HashMap<String, f32>,
-}
+/// The longest `read_timeout`, `splay` or `read_splay`, in seconds. Far
+/// longer than any of them should be, but well short of overflowing the
+/// timers they're added to.
+const MAX_WAIT: f64 = 24.0 * 3600.0;
+
+/// Notifier names that alerts can be routed to.
+const NOTIFIERS: &[&str] = &[
+    "webhook",
+    "email",
+    "ntfy",
+    "pushover",
+    "gotify",
+    "telegram",
+    "slack",
+    "discord",
+    "alertmanager",
+    "mqtt",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    pub settings: Settings,
+    pub probe_labels: HashMap<String, String>,
+    #[serde(default)]
+    pub calibration_offsets: HashMap<String, f32>,
+    /// per-probe multipliers for a two-point (linear) calibration
+    #[serde(default)]
+    pub calibration_scales: HashMap<String, f32>,
+    /// readings outside these bounds are treated as failed reads
+    #[serde(default)]
+    pub plausible_ranges: HashMap<String, PlausibleRange>,
+    /// per-probe limit on how fast readings can change, in °c per second
+    #[serde(default)]
+    pub max_change_rate: HashMap<String, f32>,
+    /// per-probe smoothing factor (0 < alpha <= 1) for an exponential
+    /// moving average, lower is smoother
+    #[serde(default)]
+    pub smoothing: HashMap<String, f32>,
+    /// programs the `plugin` backend reads probes from
+    #[serde(default)]
+    pub plugins: Vec<PluginConfig>,
+    /// made-up probes for the `simulate` backend, keyed by id
+    #[serde(default)]
+    pub simulate: HashMap<String, SimulateConfig>,
+    pub auth: Option<AuthConfig>,
+    pub cors: Option<CorsConfig>,
+    pub rate_limit: Option<RateLimitConfig>,
+    #[serde(default)]
+    pub health: HealthConfig,
+    pub quarantine: Option<QuarantineConfig>,
+    /// poll faster while readings are changing and slower once they settle
+    pub adaptive: Option<AdaptiveConfig>,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    pub mqtt: Option<MqttConfig>,
+    pub zabbix: Option<ZabbixConfig>,
+    pub snmp: Option<SnmpConfig>,
+    pub coap: Option<CoapConfig>,
+    /// needs tempmon built with the `grpc` feature
+    pub grpc: Option<GrpcConfig>,
+    pub mdns: Option<MdnsConfig>,
+    pub dbus: Option<DbusConfig>,
+    /// service discovery this instance registers itself with
+    pub registry: Option<RegistryConfig>,
+    /// other tempmon instances whose readings are shown alongside these
+    pub federation: Option<FederationConfig>,
+    /// outputs driven from a probe's readings, keyed by a name for each
+    #[serde(default)]
+    pub control: HashMap<String, ControlConfig>,
+    /// local buzzers and leds sounded by firing alerts, keyed by a name for
+    /// each
+    #[serde(default)]
+    pub alarm: HashMap<String, AlarmConfig>,
+    /// where the dashboard's readings change colour
+    #[serde(default)]
+    pub colors: ColorsConfig,
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    /// the order and grouping of the dashboard's rows
+    #[serde(default)]
+    pub layout: LayoutConfig,
+}
+
+/// A linear correction applied to raw readings: `raw * scale + offset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    pub scale: f32,
+    pub offset: f32,
+}
+
+impl Calibration {
+    pub fn apply(&self, raw: f32) -> f32 {
+        raw * self.scale + self.offset
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    /// probes to show first, in this order; the rest follow alphabetically
+    pub order: Vec<String>,
+    pub groups: Vec<Group>,
+}
+
+/// A heading on the dashboard with its probes under it, in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Group {
+    pub name: String,
+    pub probes: Vec<String>,
+    /// start folded away until it's opened
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
+impl LayoutConfig {
+    /// Sorts `names` for the dashboard: each group's probes under it, in
+    /// the groups' order, then the rest, without a group, by `order` and
+    /// alphabetically. Groups none of `names` are in are left out.
+    pub fn arrange<'a>(&'a self, names: &[&'a str]) -> Vec<(Option<&'a Group>, Vec<&'a str>)> {
+        let mut arranged: Vec<_> = self
+            .groups
+            .iter()
+            .map(|group| {
+                let probes: Vec<&str> = group
+                    .probes
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|p| names.contains(p))
+                    .collect();
+                (Some(group), probes)
+            })
+            .filter(|(_, probes)| !probes.is_empty())
+            .collect();
+
+        let mut rest: Vec<&str> = names
+            .iter()
+            .copied()
+            .filter(|name| {
+                !self
+                    .groups
+                    .iter()
+                    .any(|g| g.probes.iter().any(|p| p == name))
+            })
+            .collect();
+        rest.sort_by_key(|name| {
+            let position = self.order.iter().position(|p| p == name);
+            (position.is_none(), position, *name)
+        });
+        if !rest.is_empty() {
+            arranged.push((None, rest));
+        }
+        arranged
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut seen = HashSet::new();
+        for name in &self.order {
+            if !seen.insert(name) {
+                return Err(format!("{} is in order more than once", name));
+            }
+        }
+        let mut grouped = HashSet::new();
+        let mut groups = HashSet::new();
+        for group in &self.groups {
+            if group.name.is_empty() {
+                return Err("a group needs a name".to_string());
+            }
+            if !groups.insert(&group.name) {
+                return Err(format!("more than one group is called {}", group.name));
+            }
+            for probe in &group.probes {
+                if !grouped.insert(probe) {
+                    return Err(format!("{} is grouped more than once", probe));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Colours of the dashboard's palette that can be overridden.
+pub const THEME_COLORS: &[&str] = &[
+    "background",
+    "surface",
+    "header",
+    "text",
+    "muted",
+    "border",
+    "accent",
+    "accent_hover",
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub mode: ThemeMode,
+    /// palette colours by name, in place of the mode's own
+    pub colors: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeMode {
+    /// nord's polar night
+    #[default]
+    Dark,
+    /// nord's snow storm
+    Light,
+    /// light or dark to match the browser's `prefers-color-scheme`
+    Auto,
+}
+
+impl ThemeConfig {
+    fn validate(&self) -> Result<(), String> {
+        for (name, color) in &self.colors {
+            if !THEME_COLORS.contains(&name.as_str()) {
+                return Err(format!(
+                    "unknown color {:?}, expected one of {}",
+                    name,
+                    THEME_COLORS.join(", ")
+                ));
+            }
+            // it goes into the page's css as it is
+            let hex = color.strip_prefix('#').is_some_and(|hex| {
+                matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+            });
+            let named = !color.is_empty() && color.chars().all(|c| c.is_ascii_alphabetic());
+            if !(hex || named) {
+                return Err(format!(
+                    "{}: expected a color like \"#88c0d0\" or \"teal\", got {:?}",
+                    name, color
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// °c where a reading on the dashboard turns from blue to green, green to
+/// yellow and yellow to red.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColorBands {
+    pub cold: f32,
+    pub warm: f32,
+    pub hot: f32,
+}
+
+impl ColorBands {
+    fn validate(&self) -> Result<(), String> {
+        let ColorBands { cold, warm, hot } = *self;
+        if !(cold.is_finite() && hot.is_finite() && cold <= warm && warm <= hot) {
+            return Err(format!(
+                "need cold <= warm <= hot, got {}, {}, {}",
+                cold, warm, hot
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The dashboard's colour bands, for every probe and per probe.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorsConfig {
+    pub cold: f32,
+    pub warm: f32,
+    pub hot: f32,
+    /// bands keyed by probe label, or id for a probe without one
+    pub probes: HashMap<String, ColorBands>,
+}
+
+impl Default for ColorsConfig {
+    fn default() -> Self {
+        ColorsConfig {
+            cold: 22.0,
+            warm: 38.0,
+            hot: 42.0,
+            probes: HashMap::new(),
+        }
+    }
+}
+
+impl ColorsConfig {
+    /// A probe's bands, or the ones for every probe.
+    pub fn bands(&self, name: &str) -> ColorBands {
+        self.probes
+            .get(name)
+            .copied()
+            .unwrap_or_else(|| self.global())
+    }
+
+    fn global(&self) -> ColorBands {
+        ColorBands {
+            cold: self.cold,
+            warm: self.warm,
+            hot: self.hot,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PlausibleRange {
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+}
+
+impl PlausibleRange {
+    pub fn contains(&self, temp: f32) -> bool {
+        self.min.is_none_or(|min| temp >= min) && self.max.is_none_or(|max| temp <= max)
+    }
+}
+
+impl Config {
+    pub fn calibration(&self, id: &str) -> Calibration {
+        Calibration {
+            scale: self.calibration_scales.get(id).copied().unwrap_or(1.0),
+            offset: self.calibration_offsets.get(id).copied().unwrap_or(0.0),
+        }
+    }
+
+    /// Catches values that parse but can't be used.
+    pub fn validate(&self) -> Result<(), String> {
+        let timeout = self.settings.read_timeout;
+        if !(timeout.is_finite() && timeout > 0.0) {
+            return Err(format!(
+                "settings.read_timeout must be a positive number of seconds, got {}",
+                timeout
+            ));
+        }
+        if timeout > MAX_WAIT {
+            return Err(format!(
+                "settings.read_timeout can't be more than {} seconds, got {}",
+                MAX_WAIT, timeout
+            ));
+        }
+        for (key, splay) in [
+            ("splay", self.settings.splay),
+            ("read_splay", self.settings.read_splay),
+        ] {
+            if !(splay.is_finite() && splay >= 0.0) {
+                return Err(format!(
+                    "settings.{} can't be a negative number of seconds, got {}",
+                    key, splay
+                ));
+            }
+            if splay > MAX_WAIT {
+                return Err(format!(
+                    "settings.{} can't be more than {} seconds, got {}",
+                    key, MAX_WAIT, splay
+                ));
+            }
+        }
+        validate_schedule(&self.settings.probe_schedule)
+            .map_err(|e| format!("settings.probe_schedule: {}", e))?;
+        if self
+            .settings
+            .probe_schedule
+            .iter()
+            .any(|p| p.value.probe_interval == 0)
+        {
+            return Err(
+                "settings.probe_schedule: intervals have to be at least 1 second".to_string(),
+            );
+        }
+        if let Some(timezone) = &self.settings.timezone
+            && schedule::zone(Some(timezone)).is_none()
+        {
+            return Err(format!("unknown timezone {:?}", timezone));
+        }
+        if self.settings.backends.is_empty() {
+            return Err("settings.backends needs at least one backend".to_string());
+        }
+        for name in &self.settings.backends {
+            if !backend::KNOWN.contains(&name.as_str()) {
+                return Err(format!(
+                    "unknown backend {:?} in settings.backends, expected one of {}",
+                    name,
+                    backend::KNOWN.join(", ")
+                ));
+            }
+        }
+        for (i, plugin) in self.plugins.iter().enumerate() {
+            if plugin.command.is_empty() {
+                return Err(format!("plugins.{}: command can't be empty", plugin.name));
+            }
+            if self.plugins[..i].iter().any(|p| p.name == plugin.name) {
+                return Err(format!("more than one plugin is called {}", plugin.name));
+            }
+        }
+        let alerts = &self.alerts;
+        for silence in &alerts.silences {
+            silence.until()?;
+        }
+        for (target, rule) in &alerts.rules {
+            validate_schedule(&rule.schedule)
+                .map_err(|e| format!("alerts.rules.{}: {}", target, e))?;
+        }
+        for (name, delta) in &alerts.deltas {
+            validate_schedule(&delta.rule.schedule)
+                .map_err(|e| format!("alerts.deltas.{}: {}", name, e))?;
+            if delta.rule.flatline.is_some() {
+                return Err(format!(
+                    "alerts.deltas.{}: flatline only works on probes",
+                    name
+                ));
+            }
+            if self.probe_labels.contains_key(name) || self.probe_labels.values().any(|l| l == name)
+            {
+                return Err(format!(
+                    "alerts.deltas.{}: the name is already used by a probe",
+                    name
+                ));
+            }
+        }
+        for (name, control) in &self.control {
+            control
+                .validate()
+                .map_err(|e| format!("control.{}: {}", name, e))?;
+        }
+        for (id, simulate) in &self.simulate {
+            simulate
+                .validate()
+                .map_err(|e| format!("simulate.{}: {}", id, e))?;
+        }
+        for (name, alarm) in &self.alarm {
+            if let Pattern::Custom(steps) = &alarm.pattern
+                && (steps.is_empty() || steps.contains(&0))
+            {
+                return Err(format!(
+                    "alarm.{}: a pattern needs at least one step, and no step can be 0 ms",
+                    name
+                ));
+            }
+        }
+        self.colors
+            .global()
+            .validate()
+            .map_err(|e| format!("colors: {}", e))?;
+        self.theme.validate().map_err(|e| format!("theme: {}", e))?;
+        if let Some(registry) = &self.registry
+            && registry.ttl < 3
+        {
+            return Err(format!(
+                "registry.ttl must be at least 3 seconds, got {}",
+                registry.ttl
+            ));
+        }
+        if let Some(auth) = &self.auth
+            && auth.username.is_some() != auth.password_hash.is_some()
+        {
+            return Err("auth: username and password_hash have to be set together".to_string());
+        }
+        if let Some(adaptive) = &self.adaptive {
+            adaptive
+                .validate()
+                .map_err(|e| format!("adaptive: {}", e))?;
+        }
+        if let Some(federation) = &self.federation {
+            federation
+                .validate()
+                .map_err(|e| format!("federation: {}", e))?;
+        }
+        self.layout
+            .validate()
+            .map_err(|e| format!("layout: {}", e))?;
+        for (name, bands) in &self.colors.probes {
+            bands
+                .validate()
+                .map_err(|e| format!("colors.probes.{}: {}", name, e))?;
+        }
+        for notifier in alerts.routes.values().flatten() {
+            if !NOTIFIERS.contains(&notifier.as_str()) {
+                return Err(format!(
+                    "unknown notifier {:?} in alerts.routes, expected one of {}",
+                    notifier,
+                    NOTIFIERS.join(", ")
+                ));
+            }
+        }
+        let rules = alerts.deltas.values().map(|d| &d.rule);
+        let hystereses = alerts
+            .rules
+            .values()
+            .chain(rules)
+            .filter_map(|r| r.hysteresis);
+        for hysteresis in hystereses.chain([alerts.hysteresis]) {
+            if !(hysteresis.is_finite() && hysteresis >= 0.0) {
+                return Err(format!(
+                    "alert hysteresis can't be negative, got {}",
+                    hysteresis
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the configured calibration for a probe to a raw reading.
+    pub fn calibrate(&self, id: &str, raw: f32) -> f32 {
+        self.calibration(id).apply(raw)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    /// one address or a list of addresses to listen on
+    #[serde(
+        rename = "bind_address",
+        default = "default_bind_addresses",
+        deserialize_with = "one_or_many"
+    )]
+    pub bind_addresses: Vec<String>,
+    pub metrics_port: u16,
+    /// serve `/metrics` on its own port instead of `metrics_port`
+    pub dedicated_metrics_port: Option<u16>,
+    /// serve http on a unix domain socket at this path
+    pub unix_socket: Option<PathBuf>,
+    /// permissions for the unix socket, e.g. `0o660`
+    pub unix_socket_mode: Option<u32>,
+    pub probe_interval: u64,
+    /// intervals that change through the week, e.g. a longer one overnight
+    #[serde(default)]
+    pub probe_schedule: Vec<Period<ProbeInterval>>,
+    pub probe_resolution: u8,
+    /// reload automatically when the config file changes
+    #[serde(default)]
+    pub watch_config: bool,
+    /// consecutive reads per probe each cycle, publishing the median
+    #[serde(default = "default_median_samples")]
+    pub median_samples: u32,
+    /// extra attempts at a read that failed its crc check
+    #[serde(default = "default_read_retries")]
+    pub read_retries: u32,
+    /// seconds to wait for a probe's read before counting it as failed
+    #[serde(default = "default_read_timeout")]
+    pub read_timeout: f64,
+    /// up to this many seconds' random wait before each cycle's reads
+    #[serde(default)]
+    pub splay: f64,
+    /// up to this many seconds' random wait before each probe's read
+    #[serde(default)]
+    pub read_splay: f64,
+    /// polling intervals a cycle's reads can take before the poller is
+    /// restarted, 0 to never restart it
+    #[serde(default = "default_watchdog_cycles")]
+    pub watchdog_cycles: u32,
+    /// consecutive failed reads before the dashboard stops showing the
+    /// last good value and marks a probe down
+    #[serde(default = "default_down_after")]
+    pub down_after: u32,
+    /// what to do when discovery doesn't find any probes at startup
+    #[serde(default)]
+    pub on_no_probes: NoProbes,
+    /// IANA timezone schedules are in and the dashboard shows times in,
+    /// e.g. "Europe/London"; the system's without one
+    pub timezone: Option<String>,
+    /// what the dashboard shows temperatures in
+    #[serde(default)]
+    pub units: Unit,
+    /// also export readings in °f, as `dash_temp_readings_fahrenheit`
+    #[serde(default)]
+    pub fahrenheit_metrics: bool,
+    /// a minijinja template to render the dashboard with instead of the
+    /// built-in page
+    pub template: Option<PathBuf>,
+    /// where to look for probes, in order, e.g. `["w1"]`
+    #[serde(default = "default_backends")]
+    pub backends: Vec<String>,
+    /// where sysfs is mounted, for the w1 backend; `TEMPMON_SYSFS_ROOT`
+    /// overrides it (default: /sys)
+    pub sysfs_root: Option<PathBuf>,
+    /// where to keep the last readings across a restart
+    pub state_file: Option<PathBuf>,
+}
+
+impl Settings {
+    /// Seconds between polling cycles as scheduled at `at`.
+    pub fn probe_interval(&self, at: time::OffsetDateTime) -> u64 {
+        schedule::current(&self.probe_schedule, at)
+            .map_or(self.probe_interval, |p| p.probe_interval)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+impl Unit {
+    /// A unit by name or its first letter, e.g. from `?unit=f`.
+    pub fn parse(s: &str) -> Option<Unit> {
+        match s.to_ascii_lowercase().as_str() {
+            "c" | "celsius" => Some(Unit::Celsius),
+            "f" | "fahrenheit" => Some(Unit::Fahrenheit),
+            "k" | "kelvin" => Some(Unit::Kelvin),
+            _ => None,
+        }
+    }
+
+    /// Converts a temperature from °c.
+    pub fn convert(self, celsius: f32) -> f32 {
+        match self {
+            Unit::Celsius => celsius,
+            Unit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+            Unit::Kelvin => celsius + 273.15,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Unit::Celsius => "celsius",
+            Unit::Fahrenheit => "fahrenheit",
+            Unit::Kelvin => "kelvin",
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Celsius => "°C",
+            Unit::Fahrenheit => "°F",
+            Unit::Kelvin => " K",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoProbes {
+    /// exit with an error so the service manager notices
+    #[default]
+    Exit,
+    /// keep retrying discovery with backoff until probes appear
+    Wait,
+}
+
+fn default_down_after() -> u32 {
+    3
+}
+
+fn default_backends() -> Vec<String> {
+    vec!["w1".to_string()]
+}
+
+fn default_median_samples() -> u32 {
+    1
+}
+
+fn default_read_retries() -> u32 {
+    2
+}
+
+fn default_watchdog_cycles() -> u32 {
+    3
+}
+
+fn default_read_timeout() -> f64 {
+    5.0
+}
+
+fn default_bind_addresses() -> Vec<String> {
+    vec!["0.0.0.0".to_string()]
+}
+
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(s) => vec![s],
+        OneOrMany::Many(v) => v,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthConfig {
+    pub username: Option<String>,
+    /// bcrypt (`$2b$...`) or argon2 (`$argon2id$...`) password hash
+    pub password_hash: Option<String>,
+    /// route groups reachable without credentials
+    #[serde(default)]
+    pub public_routes: Vec<RouteGroup>,
+    /// static bearer tokens
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    /// file containing additional bearer tokens, one per line
+    pub token_file: Option<PathBuf>,
+    /// route groups that accept bearer tokens
+    #[serde(default = "default_token_routes")]
+    pub token_routes: Vec<RouteGroup>,
+}
+
+fn default_token_routes() -> Vec<RouteGroup> {
+    vec![RouteGroup::Api, RouteGroup::Metrics]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorsConfig {
+    /// origins allowed to call the json api, or `"*"` for any
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_cors_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default = "default_cors_headers")]
+    pub allowed_headers: Vec<String>,
+    /// seconds browsers may cache a preflight response
+    #[serde(default = "default_cors_max_age")]
+    pub max_age: u64,
+}
+
+fn default_cors_methods() -> Vec<String> {
+    vec!["GET".to_string(), "OPTIONS".to_string()]
+}
+
+fn default_cors_headers() -> Vec<String> {
+    vec!["Authorization".to_string(), "Content-Type".to_string()]
+}
+
+fn default_cors_max_age() -> u64 {
+    600
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// sustained requests allowed per client ip
+    pub requests_per_minute: Option<u32>,
+    /// requests a client may make in a burst before being limited
+    #[serde(default = "default_rate_limit_burst")]
+    pub burst: u32,
+    /// open connections allowed per listener; more are closed when accepted
+    pub max_connections: Option<usize>,
+}
+
+fn default_rate_limit_burst() -> u32 {
+    10
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// consecutive failed reads before a probe counts as failing
+    pub failing_cycles: u32,
+    /// fraction of probes that must be failing to report unhealthy
+    pub failing_fraction: f32,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            failing_cycles: 3,
+            failing_fraction: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineConfig {
+    /// consecutive failed reads before a probe is quarantined
+    #[serde(default = "default_quarantine_after")]
+    pub after_failures: u32,
+    /// longest wait between retries of a quarantined probe, in seconds
+    #[serde(default = "default_quarantine_max_backoff")]
+    pub max_backoff: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdaptiveConfig {
+    /// shortest wait between polling cycles, in seconds
+    pub min_interval: u64,
+    /// longest wait between polling cycles, in seconds
+    pub max_interval: u64,
+    /// °c per minute a reading has to move by to be polled at `min_interval`
+    #[serde(default = "default_adaptive_change_rate")]
+    pub change_rate: f32,
+}
+
+impl AdaptiveConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.min_interval == 0 || self.min_interval > self.max_interval {
+            return Err(format!(
+                "min_interval has to be at least 1 and no more than max_interval, got {} and {}",
+                self.min_interval, self.max_interval
+            ));
+        }
+        if !(self.change_rate.is_finite() && self.change_rate > 0.0) {
+            return Err(format!(
+                "change_rate has to be more than 0, got {}",
+                self.change_rate
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertsConfig {
+    /// seconds a threshold must stay breached before its alert fires,
+    /// unless a rule sets its own
+    pub duration: u64,
+    /// °c a reading must come back past a threshold before a firing alert
+    /// resolves, unless a rule sets its own
+    pub hysteresis: f32,
+    /// severity of alerts from rules that don't set their own
+    pub severity: Severity,
+    /// notifiers that get alerts of each severity; severities without a
+    /// route go to every notifier
+    pub routes: HashMap<Severity, Vec<String>>,
+    /// named sets of probe ids that share a rule
+    pub groups: HashMap<String, Vec<String>>,
+    /// thresholds keyed by probe id, probe label or group name
+    pub rules: HashMap<String, AlertRule>,
+    /// thresholds on the difference between two probes, keyed by a name
+    /// for the pair
+    pub deltas: HashMap<String, DeltaRule>,
+    /// planned silences, e.g. while defrosting a freezer
+    pub silences: Vec<SilenceConfig>,
+    pub webhook: Option<WebhookConfig>,
+    pub email: Option<EmailConfig>,
+    pub ntfy: Option<NtfyConfig>,
+    pub pushover: Option<PushoverConfig>,
+    pub gotify: Option<GotifyConfig>,
+    pub telegram: Option<TelegramConfig>,
+    pub slack: Option<ChatWebhookConfig>,
+    pub discord: Option<ChatWebhookConfig>,
+    pub alertmanager: Option<AlertmanagerConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub high: Option<f32>,
+    pub low: Option<f32>,
+    pub duration: Option<u64>,
+    pub hysteresis: Option<f32>,
+    /// seconds a reading can stay on the same value before it looks stuck
+    pub flatline: Option<u64>,
+    pub severity: Option<Severity>,
+    /// thresholds that change through the week, e.g. a lower high at night
+    #[serde(default)]
+    pub schedule: Vec<Period<Thresholds>>,
+}
+
+impl AlertRule {
+    /// The rule with its schedule's thresholds at `at`, where set.
+    pub fn scheduled(&self, at: time::OffsetDateTime) -> AlertRule {
+        let mut rule = self.clone();
+        if let Some(thresholds) = schedule::current(&self.schedule, at) {
+            rule.high = thresholds.high.or(rule.high);
+            rule.low = thresholds.low.or(rule.low);
+        }
+        rule
+    }
+}
+
+/// One period of a weekly schedule, in effect from when it starts until
+/// the next one does.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Period<T> {
+    /// days the period starts on, every day if empty
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// local time the period starts, as "HH:MM"
+    pub from: String,
+    #[serde(flatten)]
+    pub value: T,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Setpoint {
+    pub setpoint: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProbeInterval {
+    pub probe_interval: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Thresholds {
+    pub high: Option<f32>,
+    pub low: Option<f32>,
+}
+
+fn validate_schedule<T>(periods: &[Period<T>]) -> Result<(), String> {
+    for period in periods {
+        if schedule::parse_time(&period.from).is_none() {
+            return Err(format!(
+                "schedule times must be \"HH:MM\", got {:?}",
+                period.from
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeltaRule {
+    /// probe ids or labels; the rule applies to the first minus the second
+    pub probes: [String; 2],
+    #[serde(flatten)]
+    pub rule: AlertRule,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// every url is sent each alert as a JSON POST
+    pub urls: Vec<String>,
+    /// extra attempts at a failed delivery, with backoff
+    #[serde(default = "default_notify_retries")]
+    pub retries: u32,
+    /// seconds to wait for each request
+    #[serde(default = "default_notify_timeout")]
+    pub timeout: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertmanagerConfig {
+    /// alertmanager base urls, e.g. http://localhost:9093; every one is
+    /// sent each alert
+    pub urls: Vec<String>,
+    /// added to every alert's labels, e.g. instance or env
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// added to every alert's annotations, e.g. runbook_url
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
+    #[serde(default = "default_notify_retries")]
+    pub retries: u32,
+    #[serde(default = "default_notify_timeout")]
+    pub timeout: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    /// smtp server to send through
+    pub host: String,
+    /// defaults to 587 for starttls, 465 for tls and 25 without
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// e.g. "tempmon <tempmon@example.com>"
+    pub from: String,
+    pub to: Vec<String>,
+    /// seconds to wait for the server
+    #[serde(default = "default_notify_timeout")]
+    pub timeout: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// upgrade a plain connection with STARTTLS
+    #[default]
+    Starttls,
+    /// connect over TLS from the start
+    Tls,
+    /// no encryption, only for a relay on the local network
+    None,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NtfyConfig {
+    /// ntfy server, ntfy.sh unless self-hosted
+    #[serde(default = "default_ntfy_server")]
+    pub server: String,
+    pub topic: String,
+    /// access token for a protected topic
+    pub token: Option<String>,
+    /// ntfy priority for firing alerts, e.g. "high" or "urgent"
+    #[serde(default = "default_ntfy_priority")]
+    pub priority: String,
+    #[serde(default = "default_notify_retries")]
+    pub retries: u32,
+    #[serde(default = "default_notify_timeout")]
+    pub timeout: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushoverConfig {
+    /// the application's api token
+    pub token: String,
+    /// the user or group key to notify
+    pub user_key: String,
+    /// -2 (silent) to 2 (emergency, repeats until acknowledged)
+    #[serde(default = "default_pushover_priority")]
+    pub priority: i8,
+    #[serde(default)]
+    pub resolved_priority: i8,
+    #[serde(default = "default_notify_retries")]
+    pub retries: u32,
+    #[serde(default = "default_notify_timeout")]
+    pub timeout: u64,
+}
+
+fn default_pushover_priority() -> i8 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GotifyConfig {
+    pub server: String,
+    /// the application token
+    pub token: String,
+    /// 0 to 10, 8 and above usually make the phone ring
+    #[serde(default = "default_gotify_priority")]
+    pub priority: u8,
+    #[serde(default = "default_gotify_resolved_priority")]
+    pub resolved_priority: u8,
+    #[serde(default = "default_notify_retries")]
+    pub retries: u32,
+    #[serde(default = "default_notify_timeout")]
+    pub timeout: u64,
+}
+
+fn default_gotify_priority() -> u8 {
+    8
+}
+
+fn default_gotify_resolved_priority() -> u8 {
+    4
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelegramConfig {
+    /// the bot token from @BotFather
+    pub token: String,
+    /// chats to send alerts to, and the only ones whose commands are answered
+    pub chat_ids: Vec<i64>,
+    /// answer /temps with the current readings
+    #[serde(default)]
+    pub commands: bool,
+    #[serde(default = "default_notify_retries")]
+    pub retries: u32,
+    #[serde(default = "default_notify_timeout")]
+    pub timeout: u64,
+}
+
+/// An incoming webhook for a chat service, i.e. Slack or Discord.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatWebhookConfig {
+    pub webhook_url: String,
+    #[serde(default = "default_notify_retries")]
+    pub retries: u32,
+    #[serde(default = "default_notify_timeout")]
+    pub timeout: u64,
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+fn default_ntfy_priority() -> String {
+    "high".to_string()
+}
+
+fn default_notify_retries() -> u32 {
+    3
+}
+
+fn default_notify_timeout() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SilenceConfig {
+    /// probe id, probe label or group name
+    pub target: String,
+    /// a toml datetime with an offset, e.g. 2026-10-20T12:00:00Z
+    pub until: toml::value::Datetime,
+    pub comment: Option<String>,
+}
+
+impl SilenceConfig {
+    pub fn until(&self) -> Result<SystemTime, String> {
+        let until = time::OffsetDateTime::parse(
+            &self.until.to_string(),
+            &time::format_description::well_known::Rfc3339,
+        )
+        .map_err(|_| {
+            format!(
+                "silence for {} must end at a date and time with an offset, e.g. 2026-10-20T12:00:00Z, got {}",
+                self.target, self.until
+            )
+        })?;
+        Ok(until.into())
+    }
+}
+
+impl AlertsConfig {
+    /// The rule for a probe: one keyed by its id, then by its label, then
+    /// by a group it is in.
+    pub fn rule(&self, id: &str, name: &str) -> Option<&AlertRule> {
+        self.rules
+            .get(id)
+            .or_else(|| self.rules.get(name))
+            .or_else(|| {
+                let mut groups: Vec<_> = self
+                    .groups
+                    .iter()
+                    .filter(|(_, ids)| ids.iter().any(|i| i == id))
+                    .map(|(group, _)| group)
+                    .collect();
+                // be deterministic when a probe is in several groups
+                groups.sort();
+                groups.into_iter().find_map(|group| self.rules.get(group))
+            })
+    }
+
+    /// Whether a rule or silence `target` covers a probe.
+    pub fn matches(&self, target: &str, id: &str, name: &str) -> bool {
+        target == id
+            || target == name
+            || self
+                .groups
+                .get(target)
+                .is_some_and(|ids| ids.iter().any(|i| i == id))
+    }
+
+    /// How long a rule's threshold must stay breached before it fires.
+    pub fn duration(&self, rule: &AlertRule) -> u64 {
+        rule.duration.unwrap_or(self.duration)
+    }
+
+    /// How far past a rule's threshold a reading must come back to resolve.
+    pub fn hysteresis(&self, rule: &AlertRule) -> f32 {
+        rule.hysteresis.unwrap_or(self.hysteresis)
+    }
+
+    pub fn severity(&self, rule: &AlertRule) -> Severity {
+        rule.severity.unwrap_or(self.severity)
+    }
+
+    /// The alerts config with every rule's thresholds as scheduled at `at`.
+    pub fn scheduled(&self, at: time::OffsetDateTime) -> AlertsConfig {
+        let mut alerts = self.clone();
+        let deltas = alerts.deltas.values_mut().map(|d| &mut d.rule);
+        for rule in alerts.rules.values_mut().chain(deltas) {
+            *rule = rule.scheduled(at);
+        }
+        alerts
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// where each reading is published, `{probe}` is replaced with the
+    /// probe's label and `{id}` with its hardware id
+    #[serde(default = "default_mqtt_topic")]
+    pub topic: String,
+    #[serde(default)]
+    pub qos: u8,
+    /// keep the last reading on the broker for new subscribers
+    #[serde(default)]
+    pub retain: bool,
+    /// where alerts are published as JSON when they fire and resolve,
+    /// `{probe}` is replaced with the probe's label
+    pub alert_topic: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ZabbixConfig {
+    /// the zabbix server or proxy readings are sent to
+    pub server: String,
+    #[serde(default = "default_zabbix_port")]
+    pub port: u16,
+    /// the host the items belong to, as it's named in zabbix
+    pub host: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FederationConfig {
+    /// seconds between polls of each instance, default: probe_interval
+    pub interval: Option<u64>,
+    /// seconds to wait for an instance to answer
+    #[serde(default = "default_federation_timeout")]
+    pub timeout: u64,
+    /// keyed by the name its probes are shown under, e.g. `garage` for
+    /// `garage/fermenter`
+    #[serde(default)]
+    pub instances: HashMap<String, InstanceConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceConfig {
+    /// where the instance's dashboard is, e.g. `http://garage-pi:9184`
+    pub url: String,
+    /// a bearer token, if the instance's api needs one
+    pub token: Option<String>,
+}
+
+impl FederationConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.interval == Some(0) || self.timeout == 0 {
+            return Err("interval and timeout must be at least a second".to_string());
+        }
+        for (name, instance) in &self.instances {
+            if name.is_empty() || name.contains('/') {
+                return Err(format!(
+                    "instance name {:?} can't be empty or have a /",
+                    name
+                ));
+            }
+            if !instance.url.starts_with("http://") && !instance.url.starts_with("https://") {
+                return Err(format!(
+                    "instances.{}: url must start with http:// or https://, got {:?}",
+                    name, instance.url
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistryBackend {
+    Consul,
+    Etcd,
+}
+
+impl RegistryBackend {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RegistryBackend::Consul => "consul",
+            RegistryBackend::Etcd => "etcd",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DbusBus {
+    #[default]
+    System,
+    Session,
+}
+
+impl DbusBus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DbusBus::System => "system",
+            DbusBus::Session => "session",
+        }
+    }
+}
+
+/// A program that reports readings for the `plugin` backend, a JSON object
+/// per line on stdout. Changes need a restart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginConfig {
+    /// what it's logged as
+    pub name: String,
+    /// the program and its arguments
+    pub command: Vec<String>,
+    /// seconds a reading counts for before the probe reads as failed
+    #[serde(default = "default_plugin_stale_after")]
+    pub stale_after: u64,
+}
+
+/// A made-up probe for the `simulate` backend. Changes need a restart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimulateConfig {
+    #[serde(default)]
+    pub waveform: Waveform,
+    /// °c it centres on
+    #[serde(default = "default_simulate_mean")]
+    pub mean: f32,
+    /// °c it goes either side of the mean: the sine wave's peak, or as far
+    /// as the random walk wanders
+    #[serde(default = "default_simulate_amplitude")]
+    pub amplitude: f32,
+    /// seconds per cycle of the sine wave
+    #[serde(default = "default_simulate_period")]
+    pub period: f64,
+    /// furthest the random walk moves in a read, in °c
+    #[serde(default = "default_simulate_step")]
+    pub step: f32,
+    /// °c of uniform noise either side of the signal
+    #[serde(default)]
+    pub noise: f32,
+    /// fraction of reads that fail, from 0 to 1
+    #[serde(default)]
+    pub failure_rate: f64,
+}
+
+impl SimulateConfig {
+    fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.failure_rate) {
+            return Err(format!(
+                "failure_rate must be between 0 and 1, got {}",
+                self.failure_rate
+            ));
+        }
+        if !(self.period.is_finite() && self.period > 0.0) {
+            return Err(format!(
+                "period must be a positive number of seconds, got {}",
+                self.period
+            ));
+        }
+        for (name, value) in [
+            ("mean", self.mean),
+            ("amplitude", self.amplitude),
+            ("step", self.step),
+            ("noise", self.noise),
+        ] {
+            if !value.is_finite() || (name != "mean" && value < 0.0) {
+                return Err(format!("{} can't be {}", name, value));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Waveform {
+    #[default]
+    Sine,
+    RandomWalk,
+}
+
+/// The readings and alerts on dbus.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DbusConfig {
+    #[serde(default)]
+    pub bus: DbusBus,
+}
+
+/// Registration with consul or etcd. Changes need a restart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegistryConfig {
+    pub backend: RegistryBackend,
+    /// the consul agent or an etcd endpoint, e.g. `http://127.0.0.1:8500`
+    pub url: String,
+    /// the consul service name, and the etcd key is under
+    /// `/services/<service>/`
+    #[serde(default = "default_registry_service")]
+    pub service: String,
+    /// default: `<service>-<hostname>`
+    pub id: Option<String>,
+    /// where to scrape this instance, default: its host name
+    pub address: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// seconds the registration lasts without a heartbeat
+    #[serde(default = "default_registry_ttl")]
+    pub ttl: u64,
+    /// a consul acl token, or an etcd auth token
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MdnsConfig {
+    /// what the dashboard is called when browsing, default: the host name
+    pub name: Option<String>,
+}
+
+/// The readings over coap. Changes need a restart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CoapConfig {
+    #[serde(default = "default_coap_bind")]
+    pub bind: String,
+}
+
+/// The grpc api. Changes need a restart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GrpcConfig {
+    #[serde(default = "default_grpc_bind")]
+    pub bind: String,
+}
+
+/// A read-only snmp agent for the readings. Changes need a restart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnmpConfig {
+    /// where to listen, port 161 needs root or CAP_NET_BIND_SERVICE
+    #[serde(default = "default_snmp_bind")]
+    pub bind: String,
+    #[serde(default = "default_snmp_community")]
+    pub community: String,
+    /// the root of tempmon's tree
+    #[serde(default = "default_snmp_oid")]
+    pub oid: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ControlConfig {
+    /// probe id or label the output is driven from
+    pub probe: String,
+    /// what switching the output on does to the probe, for a relay
+    pub mode: Option<ControlMode>,
+    /// °c to hold the probe at, for a relay
+    pub setpoint: Option<f32>,
+    /// setpoints that change through the week, in place of `setpoint`
+    #[serde(default)]
+    pub schedule: Vec<Period<Setpoint>>,
+    /// °c the probe can drift past the setpoint before the output
+    /// switches back on
+    #[serde(default = "default_control_hysteresis")]
+    pub hysteresis: f32,
+    /// seconds a relay stays on once switched on, e.g. to let a compressor
+    /// run a full cycle
+    #[serde(default)]
+    pub min_on: u64,
+    /// seconds a relay stays off once switched off, e.g. to let a
+    /// compressor's pressures equalise before it restarts
+    #[serde(default)]
+    pub min_off: u64,
+    /// (°c, duty cycle %) points for a pwm output, interpolated between
+    #[serde(default)]
+    pub curve: Vec<(f32, f32)>,
+    /// a relay on a gpio pin, switched on and off around the setpoint
+    pub gpio: Option<GpioConfig>,
+    /// a pwm output, e.g. a fan, driven along the curve
+    pub pwm: Option<PwmConfig>,
+    /// hold the setpoint with a pid loop instead of the hysteresis band or
+    /// the curve
+    pub pid: Option<PidConfig>,
+}
+
+impl ControlConfig {
+    fn validate(&self) -> Result<(), String> {
+        if !(self.hysteresis.is_finite() && self.hysteresis >= 0.0) {
+            return Err(format!(
+                "hysteresis can't be negative, got {}",
+                self.hysteresis
+            ));
+        }
+        validate_schedule(&self.schedule)?;
+        if self.schedule.iter().any(|p| !p.value.setpoint.is_finite()) {
+            return Err("scheduled setpoints must be numbers".to_string());
+        }
+        let has_setpoint = self.mode.is_some()
+            && (self.setpoint.is_some_and(f32::is_finite) || !self.schedule.is_empty());
+        if let Some(pid) = &self.pid {
+            if !has_setpoint {
+                return Err("pid needs a mode and a setpoint".to_string());
+            }
+            if ![pid.kp, pid.ki, pid.kd]
+                .iter()
+                .all(|gain| gain.is_finite() && *gain >= 0.0)
+            {
+                return Err("pid gains can't be negative".to_string());
+            }
+        }
+        match (&self.gpio, &self.pwm) {
+            (Some(_), None) => {
+                if !has_setpoint {
+                    return Err("a gpio output needs a mode and a setpoint".to_string());
+                }
+            }
+            (None, Some(pwm)) => {
+                if pwm.chip.is_some() == pwm.hwmon.is_some() {
+                    return Err("pwm needs either chip or hwmon".to_string());
+                }
+                if self.min_on > 0 || self.min_off > 0 {
+                    return Err("min_on and min_off are for gpio outputs".to_string());
+                }
+                if self.pid.is_some() {
+                    if !self.curve.is_empty() {
+                        return Err("use either pid or a curve, not both".to_string());
+                    }
+                    return Ok(());
+                }
+                if self.curve.is_empty() {
+                    return Err("a pwm output needs a curve or pid".to_string());
+                }
+                if self
+                    .curve
+                    .iter()
+                    .any(|&(_, duty)| !(0.0..=100.0).contains(&duty))
+                {
+                    return Err("curve duty cycles must be between 0 and 100".to_string());
+                }
+                if !self.curve.windows(2).all(|w| w[0].0 < w[1].0) {
+                    return Err("curve temperatures must be in increasing order".to_string());
+                }
+            }
+            _ => return Err("needs either gpio or pwm".to_string()),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlarmConfig {
+    /// the buzzer or led, switched on while sounding
+    pub gpio: GpioConfig,
+    #[serde(default)]
+    pub pattern: Pattern,
+    /// the least severe alerts that sound the alarm; any without one
+    pub severity: Option<Severity>,
+    /// probe ids, labels, groups or delta names whose alerts sound the
+    /// alarm; any alert without them
+    #[serde(default)]
+    pub targets: Vec<String>,
+}
+
+impl AlarmConfig {
+    /// Whether a firing alert should sound the alarm. `matches` says whether
+    /// a target covers the alert's probe.
+    pub fn sounds_for(&self, severity: Severity, matches: impl Fn(&str) -> bool) -> bool {
+        self.severity.is_none_or(|least| severity >= least)
+            && (self.targets.is_empty() || self.targets.iter().any(|t| matches(t)))
+    }
+}
+
+/// How an alarm switches its output while sounding.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Pattern {
+    Named(NamedPattern),
+    /// milliseconds on, off, on, ..., repeated
+    Custom(Vec<u64>),
+}
+
+impl Default for Pattern {
+    fn default() -> Self {
+        Pattern::Named(NamedPattern::Blink)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NamedPattern {
+    /// on the whole time
+    Steady,
+    /// half a second on, half a second off
+    Blink,
+    /// two short beeps every two seconds
+    Beep,
+    /// ... --- ... in morse
+    Sos,
+}
+
+impl Pattern {
+    /// Milliseconds on, off, on, ... for one repeat of the pattern.
+    pub fn steps(&self) -> Vec<u64> {
+        match self {
+            Pattern::Named(NamedPattern::Steady) => vec![1000, 0],
+            Pattern::Named(NamedPattern::Blink) => vec![500, 500],
+            Pattern::Named(NamedPattern::Beep) => vec![150, 150, 150, 1550],
+            Pattern::Named(NamedPattern::Sos) => {
+                let (dot, dash) = (200, 600);
+                let mut steps = Vec::new();
+                for (i, on) in [dot, dot, dot, dash, dash, dash, dot, dot, dot]
+                    .into_iter()
+                    .enumerate()
+                {
+                    // a longer gap between letters, and after the word
+                    let off = match i {
+                        2 | 5 => dash,
+                        8 => 7 * dot,
+                        _ => dot,
+                    };
+                    steps.extend([on, off]);
+                }
+                steps
+            }
+            Pattern::Custom(steps) => steps.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlMode {
+    /// the output warms the probe up, e.g. a heat mat
+    Heat,
+    /// the output cools the probe down, e.g. a fridge
+    Cool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpioConfig {
+    /// line offset on the chip, or the sysfs gpio number without one
+    pub pin: u32,
+    /// gpio character device, e.g. /dev/gpiochip0; /sys/class/gpio is
+    /// used without one
+    pub chip: Option<String>,
+    /// drive the pin low to switch the output on
+    #[serde(default)]
+    pub active_low: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PwmConfig {
+    /// sysfs pwm chip, e.g. /sys/class/pwm/pwmchip0
+    pub chip: Option<String>,
+    /// channel on the chip
+    #[serde(default)]
+    pub channel: u32,
+    /// period in nanoseconds for a chip channel, 25khz by default as
+    /// 4-pin fans expect
+    #[serde(default = "default_pwm_period")]
+    pub period: u64,
+    /// hwmon pwm file, e.g. /sys/class/hwmon/hwmon2/pwm1
+    pub hwmon: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PidConfig {
+    /// duty cycle % per °c of error
+    pub kp: f32,
+    /// duty cycle % per °c of error, per second
+    #[serde(default)]
+    pub ki: f32,
+    /// duty cycle % per °c/s the probe is moving
+    #[serde(default)]
+    pub kd: f32,
+    /// seconds over which a relay is switched on for the duty cycle
+    #[serde(default = "default_pid_window")]
+    pub window: u64,
+}
+
+fn default_pid_window() -> u64 {
+    600
+}
+
+fn default_pwm_period() -> u64 {
+    40_000
+}
+
+fn default_control_hysteresis() -> f32 {
+    0.5
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_client_id() -> String {
+    "tempmon".to_string()
+}
+
+fn default_mqtt_topic() -> String {
+    "tempmon/{probe}/temperature".to_string()
+}
+
+fn default_zabbix_port() -> u16 {
+    10051
+}
+
+fn default_federation_timeout() -> u64 {
+    5
+}
+
+fn default_registry_service() -> String {
+    "tempmon".to_string()
+}
+
+fn default_registry_ttl() -> u64 {
+    30
+}
+
+fn default_coap_bind() -> String {
+    "0.0.0.0:5683".to_string()
+}
+
+fn default_grpc_bind() -> String {
+    "0.0.0.0:50051".to_string()
+}
+
+fn default_snmp_bind() -> String {
+    "0.0.0.0:1161".to_string()
+}
+
+fn default_snmp_community() -> String {
+    "public".to_string()
+}
+
+/// Under net-snmp's space for local experiments, NET-SNMP-MIB::netSnmpPlaypen.
+fn default_snmp_oid() -> String {
+    "1.3.6.1.4.1.8072.9999.9999.1".to_string()
+}
+
+fn default_quarantine_after() -> u32 {
+    10
+}
+
+fn default_simulate_mean() -> f32 {
+    20.0
+}
+
+fn default_simulate_amplitude() -> f32 {
+    5.0
+}
+
+fn default_simulate_period() -> f64 {
+    3600.0
+}
+
+fn default_simulate_step() -> f32 {
+    0.1
+}
+
+fn default_plugin_stale_after() -> u64 {
+    60
+}
+
+fn default_quarantine_max_backoff() -> u64 {
+    3600
+}
+
+fn default_adaptive_change_rate() -> f32 {
+    0.5
+}
+
+// these only take effect at startup
+const RESTART_REQUIRED: &[&str] = &[
+    "settings.bind_address",
+    "settings.metrics_port",
+    "settings.dedicated_metrics_port",
+    "settings.unix_socket",
+    "settings.unix_socket_mode",
+    "settings.watch_config",
+    "settings.on_no_probes",
+    "settings.backends",
+    "settings.sysfs_root",
+    "plugins",
+    "simulate",
+    "alerts.telegram",
+    "alerts.telegram.commands",
+    // alerts pick these up on a reload, but commands keep the old bot
+    "alerts.telegram.token",
+    "alerts.telegram.chat_ids",
+    "grpc.bind",
+    "coap.bind",
+];
+
+/// Settings whose values are never logged, wherever they appear.
+const SECRET_KEYS: &[&str] = &["password", "token", "user_key", "webhook_url"];
+
+/// Picks the config file to use: an explicit `--config` path, then
+/// `$TEMPMON_CONFIG`, then the XDG config directory, then `/etc/tempmon`.
+pub fn find_config(cli_path: Option<&Path>) -> PathBuf {
+    let xdg_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| Path::new(&h).join(".config")));
+
+    resolve_config_path(
+        cli_path,
+        env::var_os("TEMPMON_CONFIG").map(PathBuf::from),
+        xdg_dir,
+        |p| p.exists(),
+    )
+}
+
+fn resolve_config_path(
+    cli_path: Option<&Path>,
+    env_path: Option<PathBuf>,
+    xdg_dir: Option<PathBuf>,
+    exists: impl Fn(&Path) -> bool,
+) -> PathBuf {
+    // explicitly requested paths are used even if missing, so the error
+    // points at the file the user asked for
+    if let Some(path) = cli_path {
+        return path.to_path_buf();
+    }
+    if let Some(path) = env_path {
+        return path;
+    }
+
+    if let Some(dir) = xdg_dir {
+        let path = dir.join("tempmon").join("config.toml");
+        if exists(&path) {
+            return path;
+        }
+    }
+
+    PathBuf::from(CONFIG_PATH)
+}
+
+const EXAMPLE_CONFIG: &str = include_str!("../config.toml.example");
+
+/// A starting config: the annotated example with an entry for each probe
+/// id filled in under `[probe_labels]` and `[calibration_offsets]`.
+pub fn generate(probe_ids: &[String]) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut section = "";
+
+    for line in EXAMPLE_CONFIG.lines().chain(std::iter::once("[end]")) {
+        let header = line.trim_start_matches("# ");
+        if header.starts_with('[') && header.ends_with(']') && !header.contains(' ') {
+            let entries: Vec<String> = match section {
+                "[probe_labels]" => probe_ids
+                    .iter()
+                    .enumerate()
+                    .map(|(i, id)| format!("\"{}\" = \"probe_{}\"", id, i + 1))
+                    .collect(),
+                "[calibration_offsets]" => probe_ids
+                    .iter()
+                    .map(|id| format!("\"{}\" = 0.0", id))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            if !entries.is_empty() {
+                while out.last().is_some_and(|l| l.is_empty()) {
+                    out.pop();
+                }
+                out.extend(entries);
+                out.push(String::new());
+            }
+            section = header;
+        }
+        if line != "[end]" {
+            out.push(line.to_string());
+        }
+    }
+
+    out.join("\n") + "\n"
+}
+
+pub fn load_config(path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let config: Config = toml::from_str(&contents)?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// A single setting that differs between two configs.
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub path: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl Change {
+    pub fn requires_restart(&self) -> bool {
+        RESTART_REQUIRED.contains(&self.path.as_str())
+    }
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // don't leak credentials into the logs
+        let key = self.path.rsplit('.').next().unwrap_or_default();
+        if self.path.starts_with("auth.") || SECRET_KEYS.contains(&key) {
+            return write!(f, "{}: changed", self.path);
+        }
+        let show = |v: &Option<String>| v.clone().unwrap_or_else(|| "(unset)".to_string());
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.path,
+            show(&self.old),
+            show(&self.new)
+        )
+    }
+}
+
+/// Lists every setting that differs between `old` and `new`.
+pub fn diff(old: &Config, new: &Config) -> Vec<Change> {
+    let old = toml::Value::try_from(old).ok();
+    let new = toml::Value::try_from(new).ok();
+    let mut changes = Vec::new();
+    diff_values(String::new(), old.as_ref(), new.as_ref(), &mut changes);
+    changes
+}
+
+fn diff_values(
+    path: String,
+    old: Option<&toml::Value>,
+    new: Option<&toml::Value>,
+    changes: &mut Vec<Change>,
+) {
+    match (old, new) {
+        (Some(toml::Value::Table(old)), Some(toml::Value::Table(new))) => {
+            let mut keys: Vec<_> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                diff_values(path, old.get(key), new.get(key), changes);
+            }
+        }
+        (old, new) if old == new => {}
+        (old, new) => changes.push(Change {
+            path,
+            old: old.map(|v| v.to_string()),
+            new: new.map(|v| v.to_string()),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alert_rule_lookup() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[alerts]
+duration = 60
+
+[alerts.groups]
+vivarium = ["28-abc123", "28-def456"]
+
+[alerts.rules.vivarium]
+high = 40.0
+
+[alerts.rules.basking_spot]
+high = 45.0
+duration = 10
+
+[alerts.rules."28-def456"]
+low = 18.0
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let alerts = &config.alerts;
+
+        let rule = alerts.rule("28-abc123", "basking_spot").unwrap();
+        assert_eq!(rule.high, Some(45.0));
+        assert_eq!(alerts.duration(rule), 10);
+
+        let rule = alerts.rule("28-abc123", "28-abc123").unwrap();
+        assert_eq!(rule.high, Some(40.0));
+        assert_eq!(alerts.duration(rule), 60);
+
+        assert_eq!(
+            alerts.rule("28-def456", "cool_side").unwrap().low,
+            Some(18.0)
+        );
+        assert!(alerts.rule("28-999999", "room").is_none());
+    }
+
+    #[test]
+    fn test_parse_valid_config() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+"28-abc123" = "test_probe"
+"28-def456" = "another_probe"
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.settings.metrics_port, 9184);
+        assert_eq!(config.settings.probe_interval, 15);
+        assert_eq!(config.settings.probe_resolution, 10);
+        assert_eq!(
+            config.probe_labels.get("28-abc123"),
+            Some(&"test_probe".to_string())
+        );
+        assert_eq!(
+            config.probe_labels.get("28-def456"),
+            Some(&"another_probe".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_config_with_no_labels() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 30
+probe_resolution = 12
+
+[probe_labels]
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.settings.metrics_port, 9184);
+        assert_eq!(config.settings.probe_interval, 30);
+        assert_eq!(config.settings.probe_resolution, 12);
+        assert!(config.probe_labels.is_empty());
+    }
+
+    #[test]
+    fn test_parse_config_wait_for_probes() {
+        let config = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+on_no_probes = "wait"
+
+[probe_labels]
+            "#,
+        );
+        assert_eq!(config.settings.on_no_probes, NoProbes::Wait);
+    }
+
+    #[test]
+    fn test_validate_read_timeout() {
+        let mut config = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+read_timeout = 2.5
+
+[probe_labels]
+            "#,
+        );
+        assert!(config.validate().is_ok());
+
+        config.settings.read_timeout = 0.0;
+        assert!(config.validate().is_err());
+        config.settings.read_timeout = 1e20;
+        assert!(config.validate().is_err());
+        config.settings.read_timeout = MAX_WAIT;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_adaptive() {
+        let mut config = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[adaptive]
+min_interval = 5
+max_interval = 300
+            "#,
+        );
+        let adaptive = config.adaptive.as_mut().unwrap();
+        assert_eq!(adaptive.change_rate, 0.5);
+        assert!(config.validate().is_ok());
+
+        let adaptive = config.adaptive.as_mut().unwrap();
+        adaptive.min_interval = 600;
+        assert!(config.validate().is_err());
+        let adaptive = config.adaptive.as_mut().unwrap();
+        adaptive.min_interval = 5;
+        adaptive.change_rate = 0.0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_splay() {
+        let mut config = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+splay = 5
+read_splay = 0.5
+
+[probe_labels]
+            "#,
+        );
+        assert_eq!(config.settings.splay, 5.0);
+        assert!(config.validate().is_ok());
+
+        config.settings.read_splay = -1.0;
+        assert!(config.validate().is_err());
+        config.settings.read_splay = 0.0;
+        config.settings.splay = f64::NAN;
+        assert!(config.validate().is_err());
+        config.settings.splay = MAX_WAIT + 1.0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_backends() {
+        let mut config = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+            "#,
+        );
+        assert_eq!(config.settings.backends, ["w1"]);
+        assert!(config.validate().is_ok());
+
+        config.settings.backends = vec!["carrier-pigeon".to_string()];
+        assert!(config.validate().is_err());
+        config.settings.backends.clear();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_simulate() {
+        let mut config = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+backends = ["simulate"]
+
+[probe_labels]
+
+[simulate.sim-fermenter]
+waveform = "random_walk"
+mean = 19.0
+failure_rate = 0.05
+            "#,
+        );
+        assert!(config.validate().is_ok());
+        let simulated = &config.simulate["sim-fermenter"];
+        assert_eq!(simulated.waveform, Waveform::RandomWalk);
+        assert_eq!(simulated.amplitude, 5.0);
+
+        config
+            .simulate
+            .get_mut("sim-fermenter")
+            .unwrap()
+            .failure_rate = 1.5;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_silences() {
+        let config = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[alerts.groups]
+freezers = ["28-abc123"]
+
+[[alerts.silences]]
+target = "freezers"
+until = 2026-10-20T12:00:00Z
+comment = "defrosting"
+            "#,
+        );
+        assert!(config.validate().is_ok());
+
+        let silence = &config.alerts.silences[0];
+        let until = silence.until().unwrap();
+        assert_eq!(
+            until
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            1792497600
+        );
+        assert!(config.alerts.matches(&silence.target, "28-abc123", "chest"));
+        assert!(
+            !config
+                .alerts
+                .matches(&silence.target, "28-def456", "upright")
+        );
+
+        // a local time is ambiguous
+        let mut config = config.clone();
+        config.alerts.silences[0].until = "2026-10-20T12:00:00".parse().unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_hysteresis() {
+        let mut config = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[alerts.rules.tank]
+high = 30.0
+hysteresis = 2.0
+            "#,
+        );
+        assert!(config.validate().is_ok());
+
+        config.alerts.hysteresis = -1.0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_alert_severity_routes() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[alerts]
+severity = "info"
+
+[alerts.routes]
+critical = ["pushover"]
+warning = ["mqtt", "email"]
+
+[alerts.rules.freezer]
+high = -15.0
+severity = "critical"
+
+[alerts.rules.fermenter]
+high = 22.0
+        "#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        let alerts = &config.alerts;
+        let freezer = alerts.rule("28-abc", "freezer").unwrap();
+        assert_eq!(alerts.severity(freezer), Severity::Critical);
+        let fermenter = alerts.rule("28-def", "fermenter").unwrap();
+        assert_eq!(alerts.severity(fermenter), Severity::Info);
+
+        assert_eq!(alerts.routes[&Severity::Critical], ["pushover"]);
+        assert!(!alerts.routes.contains_key(&Severity::Info));
+
+        // still serializes, for the reload diff
+        assert!(toml::to_string(&config).is_ok());
+
+        config
+            .alerts
+            .routes
+            .insert(Severity::Info, vec!["pager".to_string()]);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_control() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[control.fermenter]
+probe = "fermenter"
+mode = "cool"
+setpoint = 18.0
+min_off = 300
+gpio = { pin = 17, chip = "/dev/gpiochip0" }
+
+[control.closet_fan]
+probe = "closet"
+pwm = { chip = "/sys/class/pwm/pwmchip0" }
+curve = [[25.0, 0.0], [30.0, 40.0], [40.0, 100.0]]
+        "#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        let control = &config.control["fermenter"];
+        assert_eq!(control.mode, Some(ControlMode::Cool));
+        assert_eq!(control.hysteresis, 0.5);
+        assert_eq!((control.min_on, control.min_off), (0, 300));
+        let gpio = control.gpio.as_ref().unwrap();
+        assert_eq!(gpio.pin, 17);
+        assert!(!gpio.active_low);
+        let fan = &config.control["closet_fan"];
+        assert_eq!(fan.curve[1], (30.0, 40.0));
+        assert_eq!(fan.pwm.as_ref().unwrap().period, 40_000);
+        assert!(config.validate().is_ok());
+
+        let fan = config.control.get_mut("closet_fan").unwrap();
+        fan.curve.swap(0, 1);
+        assert!(config.validate().is_err());
+        config
+            .control
+            .get_mut("closet_fan")
+            .unwrap()
+            .curve
+            .swap(0, 1);
+
+        let fermenter = config.control.get_mut("fermenter").unwrap();
+        fermenter.pid = Some(PidConfig {
+            kp: 20.0,
+            ki: 0.01,
+            kd: 0.0,
+            window: 600,
+        });
+        assert!(config.validate().is_ok());
+        let pid = config.control["fermenter"].pid.clone();
+        let fan = config.control.get_mut("closet_fan").unwrap();
+        fan.mode = Some(ControlMode::Cool);
+        fan.setpoint = Some(30.0);
+        fan.pid = pid;
+        assert!(config.validate().is_err());
+        let fan = config.control.get_mut("closet_fan").unwrap();
+        fan.curve.clear();
+        assert!(config.validate().is_ok());
+        let fan = config.control.get_mut("closet_fan").unwrap();
+        fan.min_off = 300;
+        assert!(config.validate().is_err());
+        config.control.get_mut("closet_fan").unwrap().min_off = 0;
+        let fermenter = config.control.get_mut("fermenter").unwrap();
+        fermenter.setpoint = None;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_alert_deltas() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+"28-abc123" = "flow"
+
+[alerts.deltas.heating]
+probes = ["flow", "28-def456"]
+low = 5.0
+duration = 300
+        "#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        let delta = &config.alerts.deltas["heating"];
+        assert_eq!(delta.probes, ["flow", "28-def456"]);
+        assert_eq!(delta.rule.low, Some(5.0));
+        assert_eq!(delta.rule.duration, Some(300));
+        assert!(config.validate().is_ok());
+
+        let delta = config.alerts.deltas.remove("heating").unwrap();
+        config
+            .alerts
+            .deltas
+            .insert("flow".to_string(), delta.clone());
+        assert!(config.validate().is_err());
+
+        let mut delta = delta;
+        delta.rule.flatline = Some(600);
+        config.alerts.deltas = HashMap::from([("heating".to_string(), delta)]);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_schedules() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+timezone = "Europe/London"
+
+[probe_labels]
+
+[alerts.rules.lounge]
+high = 26.0
+low = 16.0
+schedule = [
+    { from = "07:00", low = 19.0 },
+    { from = "22:00" },
+]
+
+[control.heater]
+probe = "lounge"
+mode = "heat"
+gpio = { pin = 17 }
+schedule = [
+    { from = "07:00", setpoint = 21.0 },
+    { from = "22:00", setpoint = 18.0 },
+    { days = ["sat", "sun"], from = "09:00", setpoint = 22.0 },
+]
+        "#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        let heater = &config.control["heater"];
+        assert_eq!(heater.schedule[2].days, [Weekday::Sat, Weekday::Sun]);
+        assert_eq!(heater.schedule[2].value.setpoint, 22.0);
+
+        // 2026-10-12 is a monday
+        let morning = time::macros::datetime!(2026-10-12 08:00 UTC);
+        let night = time::macros::datetime!(2026-10-12 23:00 UTC);
+        let rule = config.alerts.scheduled(morning).rules["lounge"].clone();
+        assert_eq!((rule.high, rule.low), (Some(26.0), Some(19.0)));
+        let rule = config.alerts.scheduled(night).rules["lounge"].clone();
+        assert_eq!((rule.high, rule.low), (Some(26.0), Some(16.0)));
+
+        config.control.get_mut("heater").unwrap().schedule[0].from = "7am".to_string();
+        assert!(config.validate().is_err());
+        config.control.get_mut("heater").unwrap().schedule[0].from = "07:00".to_string();
+        config.settings.timezone = Some("Mars/Olympus_Mons".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_probe_schedule() {
+        let mut config = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+probe_schedule = [
+    { from = "22:00", probe_interval = 300 },
+    { from = "07:00", probe_interval = 60 },
+    { days = ["sat"], from = "08:00", probe_interval = 15 },
+]
+
+[probe_labels]
+            "#,
+        );
+        assert!(config.validate().is_ok());
+        // 2026-10-12 is a monday
+        let night = time::macros::datetime!(2026-10-12 03:00 UTC);
+        let day = time::macros::datetime!(2026-10-12 12:00 UTC);
+        let saturday = time::macros::datetime!(2026-10-17 12:00 UTC);
+        assert_eq!(config.settings.probe_interval(night), 300);
+        assert_eq!(config.settings.probe_interval(day), 60);
+        assert_eq!(config.settings.probe_interval(saturday), 15);
+
+        config.settings.probe_schedule[0].value.probe_interval = 0;
+        assert!(config.validate().is_err());
+        config.settings.probe_schedule.clear();
+        assert_eq!(config.settings.probe_interval(night), 15);
+    }
+
+    #[test]
+    fn test_alarms() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[alarm.buzzer]
+gpio = { pin = 27 }
+pattern = "sos"
+severity = "critical"
+
+[alarm.led]
+gpio = { pin = 22, active_low = true }
+pattern = [100, 900]
+targets = ["fermenter"]
+        "#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        let buzzer = &config.alarm["buzzer"];
+        assert_eq!(buzzer.pattern, Pattern::Named(NamedPattern::Sos));
+        assert_eq!(buzzer.severity, Some(Severity::Critical));
+        let led = &config.alarm["led"];
+        assert_eq!(led.pattern, Pattern::Custom(vec![100, 900]));
+        assert_eq!(led.targets, ["fermenter"]);
+
+        config.alarm.get_mut("led").unwrap().pattern = Pattern::Custom(vec![100, 0]);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_colors() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[colors]
+hot = 40.0
+
+[colors.probes.freezer]
+cold = -22.0
+warm = -15.0
+hot = -10.0
+        "#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        let room = config.colors.bands("room");
+        assert_eq!((room.cold, room.warm, room.hot), (22.0, 38.0, 40.0));
+        assert_eq!(config.colors.bands("freezer").warm, -15.0);
+
+        config.colors.probes.get_mut("freezer").unwrap().warm = -30.0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_theme() {
+        let toml_str = r##"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[theme]
+mode = "auto"
+
+[theme.colors]
+accent = "#ff8800"
+surface = "black"
+        "##;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.theme.mode, ThemeMode::Auto);
+
+        // it's written into the page's css as is
+        config.theme.colors.insert(
+            "text".to_string(),
+            "red; } body { display: none".to_string(),
+        );
+        assert!(config.validate().is_err());
+        config.theme.colors.remove("text");
+        config
+            .theme
+            .colors
+            .insert("link".to_string(), "#fff".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_layout() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[layout]
+order = ["fermenter_2", "ambient"]
+
+[[layout.groups]]
+name = "Brewery"
+probes = ["fermenter_2", "fermenter_1", "chiller"]
+
+[[layout.groups]]
+name = "Kitchen"
+probes = ["fridge"]
+collapsed = true
+        "#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        let names = ["ambient", "attic", "fermenter_1", "fermenter_2", "zone"];
+        let arranged: Vec<_> = config
+            .layout
+            .arrange(&names)
+            .into_iter()
+            .map(|(group, names)| (group.map(|g| g.name.as_str()), names))
+            .collect();
+        // kitchen has none of the probes, and fermenter_2 is in a group
+        assert_eq!(
+            arranged,
+            [
+                (Some("Brewery"), vec!["fermenter_2", "fermenter_1"]),
+                (None, vec!["ambient", "attic", "zone"]),
+            ]
+        );
+
+        config.layout.groups[1].probes.push("chiller".to_string());
+        assert!(config.validate().is_err());
+        config.layout.groups[1].probes.pop();
+        config.layout.order.push("ambient".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_federation() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[federation.instances.garage]
+url = "http://garage-pi:9184"
+
+[federation.instances.greenhouse]
+url = "https://greenhouse.example.com"
+token = "secret"
+        "#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        let federation = config.federation.as_mut().unwrap();
+        assert_eq!(federation.timeout, 5);
+        assert_eq!(
+            federation.instances["greenhouse"].token.as_deref(),
+            Some("secret")
+        );
+
+        let garage = federation.instances.remove("garage").unwrap();
+        federation
+            .instances
+            .insert("out/side".to_string(), garage.clone());
+        assert!(config.validate().is_err());
+
+        let federation = config.federation.as_mut().unwrap();
+        federation.instances.remove("out/side");
+        federation.instances.insert(
+            "garage".to_string(),
+            InstanceConfig {
+                url: "garage-pi:9184".to_string(),
+                ..garage
+            },
+        );
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_units() {
+        assert_eq!(Unit::parse("F"), Some(Unit::Fahrenheit));
+        assert_eq!(Unit::parse("kelvin"), Some(Unit::Kelvin));
+        assert_eq!(Unit::parse("rankine"), None);
+        assert_eq!(Unit::Fahrenheit.convert(-40.0), -40.0);
+        assert_eq!(Unit::Fahrenheit.convert(100.0), 212.0);
+        assert_eq!(Unit::Kelvin.convert(0.0), 273.15);
+
+        let settings: Settings = toml::from_str(
+            "metrics_port = 9184\nprobe_interval = 15\nprobe_resolution = 10\nunits = \"fahrenheit\"",
+        )
+        .unwrap();
+        assert_eq!(settings.units, Unit::Fahrenheit);
+        assert!(!settings.fahrenheit_metrics);
+    }
+
+    #[test]
+    fn test_parse_config_minimal() {
+        let toml_str = r#"
+[settings]
+metrics_port = 8080
+probe_interval = 5
+probe_resolution = 9
+
+[probe_labels]
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.settings.bind_addresses, vec!["0.0.0.0"]);
+        assert!(config.settings.dedicated_metrics_port.is_none());
+        assert!(!config.settings.watch_config);
+        assert_eq!(config.settings.on_no_probes, NoProbes::Exit);
+        assert_eq!(config.settings.median_samples, 1);
+        assert_eq!(config.settings.read_retries, 2);
+        assert_eq!(config.settings.read_timeout, 5.0);
+        assert_eq!(config.settings.metrics_port, 8080);
+        assert_eq!(config.settings.probe_interval, 5);
+        assert_eq!(config.settings.probe_resolution, 9);
+    }
+
+    #[test]
+    fn test_parse_config_with_bind_address() {
+        let toml_str = r#"
+[settings]
+bind_address = "127.0.0.1"
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
 
-#[derive(Debug, Deserialize)]
-pub struct Settings {
-    pub metrics_port: u16,
-    pub probe_interval: u64,
-    pub probe_resolution: u8,
-}
+[probe_labels]
+        "#;
 
-pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
-    let contents = fs::read_to_string(CONFIG_PATH)?;
-    let config: Config = toml::from_str(&contents)?;
-    Ok(config)
-}
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.settings.bind_addresses, vec!["127.0.0.1"]);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_parse_config_with_multiple_bind_addresses() {
+        let toml_str = r#"
+[settings]
+bind_address = ["0.0.0.0", "[::]"]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.settings.bind_addresses, vec!["0.0.0.0", "[::]"]);
+    }
 
     #[test]
-    fn test_parse_valid_config() {
+    fn test_parse_config_with_dedicated_metrics_port() {
         let toml_str = r#"
 [settings]
 metrics_port = 9184
+dedicated_metrics_port = 9185
 probe_interval = 15
 probe_resolution = 10
 
 [probe_labels]
-"28-abc123" = "test_probe"
-"28-def456" = "another_probe"
         "#;
 
         let config: Config = toml::from_str(toml_str).unwrap();
-        assert_eq!(config.settings.metrics_port, 9184);
-        assert_eq!(config.settings.probe_interval, 15);
-        assert_eq!(config.settings.probe_resolution, 10);
-        assert_eq!(
-            config.probe_labels.get("28-abc123"),
-            Some(&"test_probe".to_string())
-        );
+        assert_eq!(config.settings.dedicated_metrics_port, Some(9185));
+    }
+
+    #[test]
+    fn test_parse_config_with_unix_socket() {
+        let toml_str = r#"
+[settings]
+bind_address = []
+metrics_port = 9184
+unix_socket = "/run/tempmon/tempmon.sock"
+unix_socket_mode = 0o660
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.settings.bind_addresses.is_empty());
         assert_eq!(
-            config.probe_labels.get("28-def456"),
-            Some(&"another_probe".to_string())
+            config.settings.unix_socket,
+            Some(PathBuf::from("/run/tempmon/tempmon.sock"))
         );
+        assert_eq!(config.settings.unix_socket_mode, Some(0o660));
     }
 
     #[test]
-    fn test_parse_config_with_no_labels() {
+    fn test_parse_config_with_cors() {
         let toml_str = r#"
 [settings]
 metrics_port = 9184
-probe_interval = 30
-probe_resolution = 12
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[cors]
+allowed_origins = ["https://dash.example.com"]
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let cors = config.cors.unwrap();
+        assert_eq!(cors.allowed_origins, vec!["https://dash.example.com"]);
+        assert_eq!(cors.allowed_methods, vec!["GET", "OPTIONS"]);
+        assert_eq!(cors.max_age, 600);
+    }
+
+    #[test]
+    fn test_parse_config_with_rate_limit() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[rate_limit]
+requests_per_minute = 60
+max_connections = 16
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let rate_limit = config.rate_limit.unwrap();
+        assert_eq!(rate_limit.requests_per_minute, Some(60));
+        assert_eq!(rate_limit.burst, 10);
+        assert_eq!(rate_limit.max_connections, Some(16));
+    }
+
+    #[test]
+    fn test_parse_config_with_health() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
 
 [probe_labels]
+
+[health]
+failing_fraction = 0.5
         "#;
 
         let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.health.failing_cycles, 3);
+        assert_eq!(config.health.failing_fraction, 0.5);
+    }
+
+    #[test]
+    fn test_config_path_prefers_cli_flag() {
+        let path = resolve_config_path(
+            Some(Path::new("/tmp/test.toml")),
+            Some(PathBuf::from("/tmp/env.toml")),
+            None,
+            |_| true,
+        );
+        assert_eq!(path, PathBuf::from("/tmp/test.toml"));
+    }
+
+    #[test]
+    fn test_config_path_from_env() {
+        let path = resolve_config_path(None, Some(PathBuf::from("/tmp/env.toml")), None, |_| true);
+        assert_eq!(path, PathBuf::from("/tmp/env.toml"));
+    }
+
+    #[test]
+    fn test_config_path_from_xdg_dir() {
+        let xdg = Some(PathBuf::from("/home/pi/.config"));
+        let path = resolve_config_path(None, None, xdg.clone(), |_| true);
+        assert_eq!(path, PathBuf::from("/home/pi/.config/tempmon/config.toml"));
+
+        let path = resolve_config_path(None, None, xdg, |_| false);
+        assert_eq!(path, PathBuf::from(CONFIG_PATH));
+    }
+
+    #[test]
+    fn test_generate_config() {
+        let ids = vec!["28-abc123".to_string(), "28-def456".to_string()];
+        let generated = generate(&ids);
+        let config = parse(&generated);
+
+        assert_eq!(config.probe_labels.get("28-abc123").unwrap(), "probe_1");
+        assert_eq!(config.probe_labels.get("28-def456").unwrap(), "probe_2");
+        assert_eq!(config.calibration_offsets.get("28-def456"), Some(&0.0));
         assert_eq!(config.settings.metrics_port, 9184);
-        assert_eq!(config.settings.probe_interval, 30);
-        assert_eq!(config.settings.probe_resolution, 12);
+        assert!(config.auth.is_none());
+        // the optional sections stay in, commented out
+        assert!(generated.contains("# [auth]"));
+    }
+
+    #[test]
+    fn test_generate_config_without_probes() {
+        let config = parse(&generate(&[]));
         assert!(config.probe_labels.is_empty());
+        assert!(config.calibration_offsets.is_empty());
+    }
+
+    fn parse(toml_str: &str) -> Config {
+        toml::from_str(toml_str).unwrap()
     }
 
     #[test]
-    fn test_parse_config_minimal() {
+    fn test_diff_identical_configs() {
         let toml_str = r#"
 [settings]
-metrics_port = 8080
-probe_interval = 5
-probe_resolution = 9
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
 
 [probe_labels]
+"28-abc123" = "test_probe"
         "#;
 
-        let config: Config = toml::from_str(toml_str).unwrap();
-        assert_eq!(config.settings.metrics_port, 8080);
-        assert_eq!(config.settings.probe_interval, 5);
-        assert_eq!(config.settings.probe_resolution, 9);
+        assert!(diff(&parse(toml_str), &parse(toml_str)).is_empty());
+    }
+
+    #[test]
+    fn test_diff_lists_changes() {
+        let old = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+"28-abc123" = "test_probe"
+            "#,
+        );
+        let new = parse(
+            r#"
+[settings]
+metrics_port = 9185
+probe_interval = 30
+probe_resolution = 10
+
+[probe_labels]
+"28-abc123" = "renamed_probe"
+
+[calibration_offsets]
+"28-abc123" = 0.5
+            "#,
+        );
+
+        let changes: Vec<_> = diff(&old, &new).iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            changes,
+            vec![
+                "calibration_offsets.28-abc123: (unset) -> 0.5",
+                "probe_labels.28-abc123: \"test_probe\" -> \"renamed_probe\"",
+                "settings.metrics_port: 9184 -> 9185",
+                "settings.probe_interval: 15 -> 30",
+            ]
+        );
+        assert!(diff(&old, &new)[2].requires_restart());
+        assert!(!diff(&old, &new)[3].requires_restart());
+    }
+
+    #[test]
+    fn test_diff_redacts_auth() {
+        let old = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[auth]
+tokens = ["old-secret"]
+            "#,
+        );
+        let mut new = old.clone();
+        new.auth.as_mut().unwrap().tokens = vec!["new-secret".to_string()];
+
+        let changes: Vec<_> = diff(&old, &new).iter().map(|c| c.to_string()).collect();
+        assert_eq!(changes, vec!["auth.tokens: changed"]);
+
+        let mut old = new.clone();
+        old.alerts.email = Some(EmailConfig {
+            host: "smtp.example.com".to_string(),
+            port: None,
+            tls: SmtpTls::Starttls,
+            username: Some("tempmon".to_string()),
+            password: Some("old-secret".to_string()),
+            from: "tempmon@example.com".to_string(),
+            to: vec!["me@example.com".to_string()],
+            timeout: 10,
+        });
+        let mut new = old.clone();
+        new.alerts.email.as_mut().unwrap().password = Some("new-secret".to_string());
+
+        let changes: Vec<_> = diff(&old, &new).iter().map(|c| c.to_string()).collect();
+        assert_eq!(changes, vec!["alerts.email.password: changed"]);
+    }
+
+    #[test]
+    fn test_telegram_commands_need_restart() {
+        let old = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[alerts.telegram]
+token = "123456:old"
+chat_ids = [1]
+commands = true
+            "#,
+        );
+        let mut new = old.clone();
+        let telegram = new.alerts.telegram.as_mut().unwrap();
+        telegram.token = "123456:new".to_string();
+        telegram.chat_ids = vec![1, 2];
+        telegram.timeout = 30;
+
+        let changes = diff(&old, &new);
+        let paths: Vec<_> = changes.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "alerts.telegram.chat_ids",
+                "alerts.telegram.timeout",
+                "alerts.telegram.token"
+            ]
+        );
+        assert!(changes[0].requires_restart());
+        assert!(!changes[1].requires_restart());
+        assert!(changes[2].requires_restart());
     }
 
     #[test]
@@ -113,6 +3070,49 @@ probe_resolution = 10
         assert_eq!(config.calibration_offsets.get("28-def456"), Some(&-0.3));
     }
 
+    #[test]
+    fn test_calibrate() {
+        let config = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[calibration_offsets]
+"28-abc123" = 0.5
+            "#,
+        );
+
+        assert_eq!(config.calibrate("28-abc123", 21.0), 21.5);
+        assert_eq!(config.calibrate("28-def456", 21.0), 21.0);
+    }
+
+    #[test]
+    fn test_calibrate_with_scale() {
+        let config = parse(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[calibration_offsets]
+"28-abc123" = 0.2
+
+[calibration_scales]
+"28-abc123" = 1.01
+            "#,
+        );
+
+        assert!((config.calibrate("28-abc123", 60.0) - 60.8).abs() < 1e-4);
+        assert!((config.calibrate("28-abc123", 0.0) - 0.2).abs() < 1e-4);
+    }
+
     #[test]
     fn test_parse_config_without_calibration_offsets() {
         // Test backwards compatibility - should work without [calibration_offsets] section
@@ -146,4 +3146,77 @@ probe_resolution = 10
         let config: Config = toml::from_str(toml_str).unwrap();
         assert!(config.calibration_offsets.is_empty());
     }
+
+    #[test]
+    fn test_parse_config_with_auth() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[auth]
+username = "admin"
+password_hash = "$2b$10$abcdefghijklmnopqrstuu"
+public_routes = ["metrics", "health"]
+        "#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.validate().is_ok());
+        // a username on its own would leave the dashboard open
+        config.auth.as_mut().unwrap().password_hash = None;
+        assert!(config.validate().is_err());
+        let auth = config.auth.unwrap();
+        assert_eq!(auth.username.as_deref(), Some("admin"));
+        assert_eq!(
+            auth.public_routes,
+            vec![RouteGroup::Metrics, RouteGroup::Health]
+        );
+        assert!(auth.tokens.is_empty());
+        assert_eq!(
+            auth.token_routes,
+            vec![RouteGroup::Api, RouteGroup::Metrics]
+        );
+    }
+
+    #[test]
+    fn test_parse_config_with_tokens() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[auth]
+tokens = ["s3cret"]
+token_file = "/etc/tempmon/tokens"
+token_routes = ["metrics"]
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let auth = config.auth.unwrap();
+        assert!(auth.username.is_none());
+        assert_eq!(auth.tokens, vec!["s3cret".to_string()]);
+        assert_eq!(auth.token_file, Some(PathBuf::from("/etc/tempmon/tokens")));
+        assert_eq!(auth.token_routes, vec![RouteGroup::Metrics]);
+    }
+
+    #[test]
+    fn test_parse_config_without_auth() {
+        let toml_str = r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+        "#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert!(config.auth.is_none());
+    }
 }
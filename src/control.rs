@@ -0,0 +1,533 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::time::{Duration, Instant};
+
+use prometheus::{GaugeVec, register_gauge_vec};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::config::{ControlConfig, ControlMode, PidConfig};
+use crate::{gpio, pwm, schedule};
+
+/// Bang-bang control with a hysteresis band, like an STC-1000: the output
+/// switches on once the probe has drifted `hysteresis` past the setpoint,
+/// and off again when it gets back to it.
+#[derive(Default)]
+struct Thermostat {
+    on: bool,
+    /// when the output last switched, for min_on and min_off
+    switched: Option<Instant>,
+}
+
+impl Thermostat {
+    /// Whether the output should be on after a reading.
+    fn update(&mut self, temp: f32, config: &ControlConfig, now: Instant) -> bool {
+        // both are checked by ControlConfig::validate for relays
+        let (Some(mode), Some(setpoint)) = (config.mode, config.setpoint) else {
+            self.on = false;
+            return false;
+        };
+        let on = match (mode, self.on) {
+            (ControlMode::Heat, true) => temp < setpoint,
+            (ControlMode::Heat, false) => temp <= setpoint - config.hysteresis,
+            (ControlMode::Cool, true) => temp > setpoint,
+            (ControlMode::Cool, false) => temp >= setpoint + config.hysteresis,
+        };
+        self.switch(on, config, now)
+    }
+
+    /// Switches the output, unless it has been on for less than `min_on` or
+    /// off for less than `min_off`. Returns whether it's on.
+    fn switch(&mut self, on: bool, config: &ControlConfig, now: Instant) -> bool {
+        if on != self.on {
+            let min = if self.on {
+                config.min_on
+            } else {
+                config.min_off
+            };
+            let held = self
+                .switched
+                .is_some_and(|at| now.duration_since(at) < Duration::from_secs(min));
+            if !held {
+                self.on = on;
+                self.switched = Some(now);
+            }
+        }
+        self.on
+    }
+}
+
+/// A pid loop, with its output as a duty cycle from 0 to 100%. Runs once
+/// per reading, so its sample time is the probe interval.
+#[derive(Default)]
+struct Pid {
+    integral: f32,
+    /// the last reading and when it was taken
+    last: Option<(f32, Instant)>,
+    /// the proportional, integral and derivative terms of the last output
+    terms: [f32; 3],
+}
+
+impl Pid {
+    fn update(
+        &mut self,
+        temp: f32,
+        config: &ControlConfig,
+        gains: &PidConfig,
+        now: Instant,
+    ) -> f32 {
+        // both are checked by ControlConfig::validate with pid
+        let (Some(mode), Some(setpoint)) = (config.mode, config.setpoint) else {
+            return 0.0;
+        };
+        // positive while the output needs to work harder
+        let sign = match mode {
+            ControlMode::Heat => 1.0,
+            ControlMode::Cool => -1.0,
+        };
+        let error = sign * (setpoint - temp);
+        // the derivative of the reading rather than the error, so changing
+        // the setpoint doesn't kick the output
+        let (dt, derivative) = match self.last {
+            Some((last, at)) if now > at => {
+                let dt = now.duration_since(at).as_secs_f32();
+                (dt, -sign * (temp - last) / dt)
+            }
+            _ => (0.0, 0.0),
+        };
+        self.last = Some((temp, now));
+
+        let p = gains.kp * error;
+        let d = gains.kd * derivative;
+        // anti-windup: hold the integral while the output is saturated in
+        // the direction the error is pushing it
+        let integral = (self.integral + gains.ki * error * dt).clamp(0.0, 100.0);
+        let output = p + integral + d;
+        let saturated = (output > 100.0 && error > 0.0) || (output < 0.0 && error < 0.0);
+        if !saturated {
+            self.integral = integral;
+        }
+        self.terms = [p, self.integral, d];
+        (p + self.integral + d).clamp(0.0, 100.0)
+    }
+}
+
+/// Whether a relay under pid control should be on, switching it on for
+/// `duty`% of every `window` seconds.
+fn proportion(elapsed: Duration, window: u64, duty: f32) -> bool {
+    let window = window.max(1) as f32;
+    elapsed.as_secs_f32() % window < window * duty / 100.0
+}
+
+/// The duty cycle for a temperature on a fan curve, interpolated between
+/// its points and flat beyond either end.
+fn duty(curve: &[(f32, f32)], temp: f32) -> f32 {
+    let Some(&(first_temp, first_duty)) = curve.first() else {
+        return 100.0;
+    };
+    if temp <= first_temp {
+        return first_duty;
+    }
+    for pair in curve.windows(2) {
+        let ((t0, d0), (t1, d1)) = (pair[0], pair[1]);
+        if temp <= t1 {
+            return d0 + (d1 - d0) * (temp - t0) / (t1 - t0);
+        }
+    }
+    curve[curve.len() - 1].1
+}
+
+enum Output {
+    Relay {
+        pin: gpio::Output,
+        thermostat: Thermostat,
+    },
+    Pwm(pwm::Output),
+}
+
+impl Output {
+    fn open(config: &ControlConfig) -> io::Result<Self> {
+        match (&config.gpio, &config.pwm) {
+            (Some(gpio), _) => Ok(Output::Relay {
+                pin: gpio::Output::open(gpio)?,
+                // opened switched off, so min_off holds it off at startup in
+                // case it was running under the last process
+                thermostat: Thermostat {
+                    on: false,
+                    switched: Some(Instant::now()),
+                },
+            }),
+            (None, Some(pwm)) => Ok(Output::Pwm(pwm::Output::open(pwm)?)),
+            (None, None) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "needs either gpio or pwm",
+            )),
+        }
+    }
+}
+
+struct Controller {
+    config: ControlConfig,
+    output: Output,
+    pid: Pid,
+    started: Instant,
+}
+
+/// The outputs in `[control]`, driven from each polling cycle's readings.
+pub struct Controllers {
+    controllers: BTreeMap<String, Controller>,
+    output_metric: GaugeVec,
+    duty_metric: GaugeVec,
+    setpoint_metric: GaugeVec,
+    pid_metric: GaugeVec,
+}
+
+impl Controllers {
+    pub fn start(configs: &HashMap<String, ControlConfig>) -> prometheus::Result<Self> {
+        let mut controllers = Controllers {
+            controllers: BTreeMap::new(),
+            output_metric: register_gauge_vec!(
+                "dash_control_output",
+                "whether a relay control output is switched on (1) or off (0)",
+                &["controller"]
+            )?,
+            duty_metric: register_gauge_vec!(
+                "dash_control_duty_cycle",
+                "duty cycle of a pwm or pid control output, in percent",
+                &["controller"]
+            )?,
+            setpoint_metric: register_gauge_vec!(
+                "dash_control_setpoint",
+                "the temperature a control output is holding its probe at",
+                &["controller"]
+            )?,
+            pid_metric: register_gauge_vec!(
+                "dash_control_pid_term",
+                "the proportional, integral and derivative terms of a pid control output",
+                &["controller", "term"]
+            )?,
+        };
+        controllers.reload(configs);
+        Ok(controllers)
+    }
+
+    /// Applies a new `[control]` config. Outputs on the same pin as before
+    /// carry on as they were, anything else is opened switched off.
+    pub fn reload(&mut self, configs: &HashMap<String, ControlConfig>) {
+        let removed: Vec<_> = self
+            .controllers
+            .keys()
+            .filter(|name| !configs.contains_key(*name))
+            .cloned()
+            .collect();
+        for name in removed {
+            self.stop(&name);
+            self.controllers.remove(&name);
+            self.remove_metrics(&name);
+        }
+
+        for (name, config) in configs {
+            if let Some(controller) = self.controllers.get_mut(name)
+                && controller.config.gpio == config.gpio
+                && controller.config.pwm == config.pwm
+            {
+                controller.config = config.clone();
+            } else {
+                // release the old pin before opening the new one
+                self.controllers.remove(name);
+                self.remove_metrics(name);
+                match Output::open(config) {
+                    Ok(output) => {
+                        let controller = Controller {
+                            config: config.clone(),
+                            output,
+                            pid: Pid::default(),
+                            started: Instant::now(),
+                        };
+                        self.controllers.insert(name.clone(), controller);
+                    }
+                    Err(e) => {
+                        warn!(controller = %name, "control disabled, can't open output: {}", e);
+                        continue;
+                    }
+                }
+            }
+            if config.pid.is_none() {
+                self.remove_pid_metrics(name);
+            }
+            let setpoint = config
+                .setpoint
+                .filter(|_| config.gpio.is_some() || config.pid.is_some());
+            match setpoint {
+                Some(setpoint) => self
+                    .setpoint_metric
+                    .with_label_values(&[name])
+                    .set(setpoint.into()),
+                None => {
+                    let _ = self.setpoint_metric.remove_label_values(&[name]);
+                }
+            }
+        }
+    }
+
+    /// Moves the setpoints of outputs with a schedule to its current
+    /// period.
+    pub fn schedule(&mut self, at: OffsetDateTime) {
+        for (name, c) in &mut self.controllers {
+            let Some(period) = schedule::current(&c.config.schedule, at) else {
+                continue;
+            };
+            if c.config.setpoint != Some(period.setpoint) {
+                info!(controller = %name, setpoint = period.setpoint, "scheduled setpoint");
+                c.config.setpoint = Some(period.setpoint);
+                self.setpoint_metric
+                    .with_label_values(&[name])
+                    .set(period.setpoint.into());
+            }
+        }
+    }
+
+    /// Drives the outputs controlled by a probe after a reading.
+    pub fn update(&mut self, id: &str, name: &str, temp: f32, now: Instant) {
+        for (controller, c) in &mut self.controllers {
+            if c.config.probe != id && c.config.probe != name {
+                continue;
+            }
+            let pid = c.config.pid.as_ref().map(|gains| {
+                let duty = c.pid.update(temp, &c.config, gains, now);
+                for (term, value) in ["p", "i", "d"].into_iter().zip(c.pid.terms) {
+                    self.pid_metric
+                        .with_label_values(&[controller, term])
+                        .set(value.into());
+                }
+                (gains, duty)
+            });
+            match (&mut c.output, pid) {
+                (Output::Relay { pin, thermostat }, Some((gains, duty))) => {
+                    self.duty_metric
+                        .with_label_values(&[controller])
+                        .set(duty.into());
+                    let on = proportion(now.duration_since(c.started), gains.window, duty);
+                    let on = thermostat.switch(on, &c.config, now);
+                    switch(&self.output_metric, controller, pin, on);
+                }
+                (Output::Relay { pin, thermostat }, None) => {
+                    let on = thermostat.update(temp, &c.config, now);
+                    switch(&self.output_metric, controller, pin, on);
+                }
+                (Output::Pwm(pwm), Some((_, duty))) => {
+                    set_duty(&self.duty_metric, controller, pwm, duty)
+                }
+                (Output::Pwm(pwm), None) => set_duty(
+                    &self.duty_metric,
+                    controller,
+                    pwm,
+                    duty(&c.config.curve, temp),
+                ),
+            }
+        }
+    }
+
+    /// Fails safe on the outputs controlled by a probe that has stopped
+    /// giving readings: relays switch off rather than leave a heater running
+    /// blind, and fans run flat out.
+    pub fn probe_down(&mut self, id: &str, name: &str) {
+        let controlled: Vec<_> = self
+            .controllers
+            .iter()
+            .filter(|(_, c)| c.config.probe == id || c.config.probe == name)
+            .map(|(controller, _)| controller.clone())
+            .collect();
+        for controller in controlled {
+            self.stop(&controller);
+        }
+    }
+
+    /// Fails safe on every output, e.g. when tempmon stops.
+    pub fn shutdown(&mut self) {
+        let names: Vec<_> = self.controllers.keys().cloned().collect();
+        for name in names {
+            self.stop(&name);
+        }
+    }
+
+    fn stop(&mut self, name: &str) {
+        let Some(controller) = self.controllers.get_mut(name) else {
+            return;
+        };
+        match &mut controller.output {
+            Output::Relay { pin, thermostat } => {
+                // straight off whatever min_on says, but min_off still
+                // counts from here
+                if thermostat.on {
+                    thermostat.on = false;
+                    thermostat.switched = Some(Instant::now());
+                }
+                switch(&self.output_metric, name, pin, false);
+            }
+            Output::Pwm(pwm) => set_duty(&self.duty_metric, name, pwm, 100.0),
+        }
+    }
+
+    fn remove_metrics(&self, name: &str) {
+        let _ = self.output_metric.remove_label_values(&[name]);
+        let _ = self.duty_metric.remove_label_values(&[name]);
+        let _ = self.setpoint_metric.remove_label_values(&[name]);
+        self.remove_pid_metrics(name);
+    }
+
+    fn remove_pid_metrics(&self, name: &str) {
+        for term in ["p", "i", "d"] {
+            let _ = self.pid_metric.remove_label_values(&[name, term]);
+        }
+    }
+}
+
+fn switch(metric: &GaugeVec, name: &str, pin: &gpio::Output, on: bool) {
+    let gauge = metric.with_label_values(&[name]);
+    if (gauge.get() == 1.0) != on {
+        info!(
+            controller = name,
+            "switching {}",
+            if on { "on" } else { "off" }
+        );
+    }
+    // written every cycle, in case something else touched the pin
+    match pin.set(on) {
+        Ok(()) => gauge.set(if on { 1.0 } else { 0.0 }),
+        Err(e) => warn!(controller = name, "failed to switch output: {}", e),
+    }
+}
+
+fn set_duty(metric: &GaugeVec, name: &str, pwm: &pwm::Output, duty: f32) {
+    match pwm.set(duty) {
+        Ok(()) => metric.with_label_values(&[name]).set(duty.into()),
+        Err(e) => warn!(controller = name, "failed to set duty cycle: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GpioConfig;
+
+    fn config(mode: ControlMode) -> ControlConfig {
+        ControlConfig {
+            probe: "fermenter".to_string(),
+            mode: Some(mode),
+            setpoint: Some(18.0),
+            schedule: Vec::new(),
+            hysteresis: 0.5,
+            min_on: 0,
+            min_off: 0,
+            curve: Vec::new(),
+            gpio: Some(GpioConfig {
+                pin: 17,
+                chip: None,
+                active_low: false,
+            }),
+            pwm: None,
+            pid: None,
+        }
+    }
+
+    #[test]
+    fn test_cooling() {
+        let config = config(ControlMode::Cool);
+        let mut thermostat = Thermostat::default();
+        let now = Instant::now();
+
+        assert!(!thermostat.update(18.25, &config, now));
+        assert!(thermostat.update(18.5, &config, now));
+        // stays on until it's back at the setpoint
+        assert!(thermostat.update(18.25, &config, now));
+        assert!(!thermostat.update(18.0, &config, now));
+        assert!(!thermostat.update(18.25, &config, now));
+    }
+
+    #[test]
+    fn test_heating() {
+        let config = config(ControlMode::Heat);
+        let mut thermostat = Thermostat::default();
+        let now = Instant::now();
+
+        assert!(!thermostat.update(17.75, &config, now));
+        assert!(thermostat.update(17.5, &config, now));
+        assert!(thermostat.update(17.9, &config, now));
+        assert!(!thermostat.update(18.0, &config, now));
+    }
+
+    #[test]
+    fn test_min_on_off() {
+        let mut config = config(ControlMode::Cool);
+        config.min_on = 60;
+        config.min_off = 300;
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut thermostat = Thermostat {
+            on: false,
+            switched: Some(start),
+        };
+
+        // held off for min_off after starting up
+        assert!(!thermostat.update(19.0, &config, at(15)));
+        assert!(thermostat.update(19.0, &config, at(300)));
+        // and on for min_on, even back at the setpoint
+        assert!(thermostat.update(18.0, &config, at(330)));
+        assert!(!thermostat.update(18.0, &config, at(360)));
+        assert!(!thermostat.update(19.0, &config, at(600)));
+        assert!(thermostat.update(19.0, &config, at(660)));
+    }
+
+    #[test]
+    fn test_fan_curve() {
+        let curve = [(25.0, 20.0), (30.0, 40.0), (40.0, 100.0)];
+        assert_eq!(duty(&curve, 20.0), 20.0);
+        assert_eq!(duty(&curve, 27.5), 30.0);
+        assert_eq!(duty(&curve, 35.0), 70.0);
+        assert_eq!(duty(&curve, 45.0), 100.0);
+    }
+
+    #[test]
+    fn test_pid() {
+        let gains = PidConfig {
+            kp: 20.0,
+            ki: 1.0,
+            kd: 0.0,
+            window: 600,
+        };
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let heat = config(ControlMode::Heat);
+        let mut pid = Pid::default();
+        // 1°c under: no integral until there's a second sample
+        assert_eq!(pid.update(17.0, &heat, &gains, at(0)), 20.0);
+        assert_eq!(pid.update(17.0, &heat, &gains, at(10)), 30.0);
+        assert_eq!(pid.terms, [20.0, 10.0, 0.0]);
+
+        // saturated, so the integral stops growing
+        assert_eq!(pid.update(10.0, &heat, &gains, at(20)), 100.0);
+        assert_eq!(pid.update(10.0, &heat, &gains, at(30)), 100.0);
+        assert_eq!(pid.terms[1], 10.0);
+        // and unwinds once it overshoots
+        assert_eq!(pid.update(18.25, &heat, &gains, at(40)), 2.5);
+        assert_eq!(pid.terms[1], 7.5);
+
+        let cool = config(ControlMode::Cool);
+        let mut pid = Pid::default();
+        assert_eq!(pid.update(19.0, &cool, &gains, at(0)), 20.0);
+        assert_eq!(pid.update(17.0, &cool, &gains, at(10)), 0.0);
+    }
+
+    #[test]
+    fn test_time_proportioning() {
+        let secs = Duration::from_secs;
+        assert!(proportion(secs(0), 600, 25.0));
+        assert!(proportion(secs(149), 600, 25.0));
+        assert!(!proportion(secs(150), 600, 25.0));
+        assert!(proportion(secs(600), 600, 25.0));
+        assert!(!proportion(secs(0), 600, 0.0));
+        assert!(proportion(secs(599), 600, 100.0));
+    }
+}
@@ -0,0 +1,66 @@
+use http::header::{self, HeaderValue};
+use http::{Request, Response, StatusCode};
+
+use crate::config::CorsConfig;
+
+pub struct Cors {
+    config: CorsConfig,
+}
+
+impl Cors {
+    pub fn new(config: CorsConfig) -> Self {
+        Cors { config }
+    }
+
+    /// The allowed origin to echo back for this request, if any.
+    fn allowed_origin<B>(&self, request: &Request<B>) -> Option<String> {
+        let origin = request.headers().get(header::ORIGIN)?.to_str().ok()?;
+
+        if self.config.allowed_origins.iter().any(|o| o == "*") {
+            Some("*".to_string())
+        } else if self.config.allowed_origins.iter().any(|o| o == origin) {
+            Some(origin.to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Answers a preflight `OPTIONS` request.
+    pub fn preflight<B>(&self, request: &Request<B>) -> Response<Vec<u8>> {
+        let mut response = Response::new(Vec::new());
+        *response.status_mut() = StatusCode::NO_CONTENT;
+        if self.allowed_origin(request).is_some() {
+            self.apply(request, &mut response);
+            let headers = response.headers_mut();
+            let mut add = |name, value: String| {
+                if let Ok(value) = HeaderValue::try_from(value) {
+                    headers.append(name, value);
+                }
+            };
+            add(
+                header::ACCESS_CONTROL_ALLOW_METHODS,
+                self.config.allowed_methods.join(", "),
+            );
+            add(
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                self.config.allowed_headers.join(", "),
+            );
+            add(
+                header::ACCESS_CONTROL_MAX_AGE,
+                self.config.max_age.to_string(),
+            );
+        }
+        response
+    }
+
+    /// Adds the CORS headers for an allowed origin to `response`.
+    pub fn apply<B, R>(&self, request: &Request<B>, response: &mut Response<R>) {
+        if let Some(origin) = self.allowed_origin(request)
+            && let Ok(origin) = HeaderValue::try_from(origin)
+        {
+            let headers = response.headers_mut();
+            headers.append(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+            headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        }
+    }
+}
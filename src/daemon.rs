@@ -0,0 +1,906 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time;
+
+use prometheus::core::Collector;
+use prometheus::{
+    CounterVec, Gauge, GaugeVec, IntCounter, register_counter_vec, register_gauge,
+    register_gauge_vec, register_int_counter,
+};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::task;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, info_span, warn};
+
+use crate::adaptive::Pace;
+use crate::alarm::Alarms;
+use crate::alert::{self, Alert, Engine};
+use crate::backend::{Registry, replay};
+use crate::config::{self, AlertRule, Config, NoProbes, Unit, load_config};
+use crate::control::Controllers;
+use crate::events::{Consumer, Event, Events, Failure, Reading};
+#[cfg(feature = "grpc")]
+use crate::grpc;
+use crate::notify::{self, Dispatcher};
+use crate::poll::Sample;
+use crate::probe::{self, Probe, ProbeError, display_name};
+use crate::state::{Readings, TempData};
+use crate::watchdog::Watchdog;
+use crate::{
+    coap, dbus, federation, mdns, mqtt, registry, schedule, server, snmp, statefile, watch, zabbix,
+};
+
+const DISCOVERY_BACKOFF_MIN: time::Duration = time::Duration::from_secs(5);
+const DISCOVERY_BACKOFF_MAX: time::Duration = time::Duration::from_secs(300);
+
+/// How long to wait on reads still stuck in the kernel when exiting.
+const SHUTDOWN_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+#[derive(Clone)]
+struct ProbeMetrics {
+    readings: GaugeVec,
+    readings_raw: GaugeVec,
+    readings_smoothed: GaugeVec,
+    readings_fahrenheit: GaugeVec,
+    read_errors: CounterVec,
+    probes_discovered: Gauge,
+    quarantined: GaugeVec,
+    alerts: GaugeVec,
+    poller_restarts: IntCounter,
+}
+
+impl ProbeMetrics {
+    fn register() -> prometheus::Result<Self> {
+        Ok(ProbeMetrics {
+            readings: register_gauge_vec!(
+                "dash_temp_readings",
+                "calibrated readings from the temperature probes",
+                &["probe"]
+            )?,
+            readings_raw: register_gauge_vec!(
+                "dash_temp_readings_raw",
+                "uncalibrated readings from the temperature probes",
+                &["probe"]
+            )?,
+            readings_smoothed: register_gauge_vec!(
+                "dash_temp_readings_smoothed",
+                "calibrated readings after exponential smoothing, for probes with smoothing set",
+                &["probe"]
+            )?,
+            readings_fahrenheit: register_gauge_vec!(
+                "dash_temp_readings_fahrenheit",
+                "calibrated readings in degrees fahrenheit, with fahrenheit_metrics set",
+                &["probe"]
+            )?,
+            read_errors: register_counter_vec!(
+                "dash_temp_read_errors_total",
+                "total number of failed temperature reads",
+                &["probe", "error_type"]
+            )?,
+            quarantined: register_gauge_vec!(
+                "dash_probe_quarantined",
+                "whether a probe is quarantined after repeated failures (1) or not (0)",
+                &["probe"]
+            )?,
+            alerts: register_gauge_vec!(
+                "dash_alert_active",
+                "whether a probe's alert is firing (1) or not (0)",
+                &["probe", "alert"]
+            )?,
+            probes_discovered: register_gauge!(
+                "dash_probes_discovered",
+                "number of probes found by the last discovery"
+            )?,
+            poller_restarts: register_int_counter!(
+                "tempmon_poller_restarts_total",
+                "times the poller was restarted after a cycle's reads hung"
+            )?,
+        })
+    }
+
+    /// Drops every series for a probe, e.g. once it has been renamed.
+    fn remove(&self, name: &str) {
+        let _ = self.readings.remove_label_values(&[name]);
+        let _ = self.readings_raw.remove_label_values(&[name]);
+        let _ = self.readings_smoothed.remove_label_values(&[name]);
+        let _ = self.readings_fahrenheit.remove_label_values(&[name]);
+        let _ = self.quarantined.remove_label_values(&[name]);
+        for kind in alert::Kind::ALL {
+            let _ = self.alerts.remove_label_values(&[name, kind.as_str()]);
+        }
+        for error_type in probe::ERROR_TYPES {
+            let _ = self.read_errors.remove_label_values(&[name, error_type]);
+        }
+    }
+
+    /// A probe's failed reads so far by error type, leaving out any it
+    /// hasn't had.
+    fn read_errors(&self, name: &str) -> HashMap<String, u64> {
+        let mut errors = HashMap::new();
+        for metric in self
+            .read_errors
+            .collect()
+            .iter()
+            .flat_map(|f| f.get_metric())
+        {
+            let label = |key: &str| {
+                metric
+                    .get_label()
+                    .iter()
+                    .find(|l| l.get_name() == key)
+                    .map(|l| l.get_value())
+            };
+            if label("probe") == Some(name)
+                && let Some(error_type) = label("error_type")
+            {
+                errors.insert(
+                    error_type.to_string(),
+                    metric.get_counter().get_value() as u64,
+                );
+            }
+        }
+        errors
+    }
+}
+
+/// How `tempmon serve` was started, beyond its config.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// append every raw reading to this file
+    pub record: Option<PathBuf>,
+    /// read the probes from a recording instead of discovering them
+    pub replay: Option<Replay>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub path: PathBuf,
+    /// how many times faster than it was recorded
+    pub speed: f64,
+}
+
+/// Serves the dashboard and metrics and polls the probes until SIGTERM or
+/// SIGINT, reloading `config_path` on SIGHUP. This is all of `tempmon
+/// serve`.
+pub fn run(
+    config: Config,
+    config_path: &Path,
+    options: &Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    let result = runtime.block_on(serve(config, config_path, options));
+    runtime.shutdown_timeout(SHUTDOWN_TIMEOUT);
+    result
+}
+
+async fn serve(
+    mut config: Config,
+    config_path: &Path,
+    options: &Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let current_temps = TempData::new(Readings::new([]));
+
+    let metrics = ProbeMetrics::register()?;
+    // file i/o, gpio and pwm writes and the like go through block_in_place
+    // so they don't hold up the http server and other tasks on this worker
+    let restored = task::block_in_place(|| restore_state(&config, &current_temps, &metrics));
+    let events = Events::default();
+    // consumers of the events stop once they're dropped
+    let mut _reading_metrics = record_metrics(&metrics, &config, &events);
+    let mut mqtt = start_mqtt(&config, &events);
+    let mut _zabbix = start_zabbix(&config, &events);
+    let notifier = Dispatcher::start(
+        notifiers(&config, mqtt.as_ref()),
+        config.alerts.routes.clone(),
+    )?;
+    notify::start_listeners(&config.alerts, &current_temps);
+    let mut controllers = Controllers::start(&config.control)?;
+    let mut alarms = Alarms::start(&config.alarm)?;
+    let registration = config.registry.as_ref().map(|r| {
+        registry::Registration::start(r, config.settings.metrics_port, current_temps.clone())
+    });
+    let shutdown = shutdown_on_signal()?;
+
+    let reload = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGHUP, Arc::clone(&reload))?;
+    if config.settings.watch_config {
+        watch::watch_config(config_path, Arc::clone(&reload))?;
+    }
+
+    // start http server before discovery so metrics and liveness are
+    // available while waiting for probes
+    let server = server::start(&config, current_temps.clone())?;
+    if let Some(snmp) = &config.snmp {
+        snmp::start(snmp, current_temps.clone())?;
+    }
+    if let Some(coap) = &config.coap {
+        coap::start(coap, current_temps.clone())?;
+    }
+    if let Some(grpc) = &config.grpc {
+        start_grpc(grpc, current_temps.clone())?;
+    }
+
+    let federation = federation::Federation::start(
+        config.federation.as_ref(),
+        config.settings.probe_interval,
+        current_temps.clone(),
+    )?;
+    let mut mdns = start_mdns(&config);
+    let mut dbus = start_dbus(&config, &current_temps);
+    if let Some(mdns) = &mut mdns {
+        mdns.update(0);
+    }
+
+    let registry = match &options.replay {
+        Some(replay) => {
+            let mut registry = Registry::default();
+            registry.add(Box::new(replay::Replay::open(&replay.path, replay.speed)?));
+            registry
+        }
+        None => Registry::new(Some(&config))?,
+    };
+    let Some(mut probes) = wait_for_probes(&registry, &config, &metrics, &shutdown).await? else {
+        task::block_in_place(|| switch_off(&mut controllers, &mut alarms, registration.as_ref()));
+        return Ok(());
+    };
+    drop(registry);
+    if let Some(path) = &options.record {
+        probes = replay::record(probes, path)
+            .map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+        info!("recording readings to {}", path.display());
+    }
+    // a replay is polled faster to keep up
+    let speed = options.replay.as_ref().map_or(1.0, |r| r.speed);
+    // keep anything set through the api while waiting for probes
+    current_temps.update(|readings| {
+        for probe in &probes {
+            readings.temps.insert(probe.name.clone(), None);
+        }
+        let errors = restored.read_errors.iter().map(|(name, _, _)| name);
+        for name in restored.names.iter().chain(errors) {
+            if !probes.iter().any(|p| &p.name == name) {
+                readings.remove(name);
+                metrics.remove(name);
+            }
+        }
+        readings.set_config_silences(config_silences(&config));
+    });
+
+    // probe loop
+    let mut watchdog = Watchdog::start(metrics.poller_restarts.clone())?;
+    let mut alerts = Engine::default();
+    let mut pace = Pace::default();
+    // the scheduled interval last cycle, to log when it changes
+    let mut polling_every = None;
+    let mut interval = time::Duration::from_secs(config.settings.probe_interval).div_f64(speed);
+    let mut cycle: u64 = 0;
+    loop {
+        cycle += 1;
+        if reload.swap(false, Ordering::Relaxed) {
+            task::block_in_place(|| {
+                let old_mqtt = config.mqtt.clone();
+                let old_zabbix = config.zabbix.clone();
+                let old_fahrenheit = config.settings.fahrenheit_metrics;
+                let old_mdns = (config.mdns.clone(), config.settings.metrics_port);
+                let old_dbus = config.dbus.clone();
+                let old_deltas: Vec<String> = config.alerts.deltas.keys().cloned().collect();
+                reload_config(
+                    config_path,
+                    &mut config,
+                    &mut probes,
+                    &current_temps,
+                    &metrics,
+                    &server,
+                    &mut alerts,
+                );
+                for name in old_deltas {
+                    if !config.alerts.deltas.contains_key(&name) {
+                        let now = time::Instant::now();
+                        for alert in alerts.evaluate_delta(&name, 0.0, &config.alerts, now) {
+                            notifier.send(alert);
+                        }
+                        metrics.remove(&name);
+                    }
+                }
+                current_temps
+                    .update(|readings| readings.set_config_silences(config_silences(&config)));
+                if config.mqtt != old_mqtt {
+                    // drop the old connection first, the client id is reused
+                    drop(mqtt.take());
+                    mqtt = start_mqtt(&config, &events);
+                }
+                if (config.mdns.clone(), config.settings.metrics_port) != old_mdns {
+                    drop(mdns.take());
+                    mdns = start_mdns(&config);
+                }
+                if config.dbus != old_dbus {
+                    // release the name before asking for it again
+                    drop(dbus.take());
+                    dbus = start_dbus(&config, &current_temps);
+                }
+                if config.zabbix != old_zabbix {
+                    _zabbix = start_zabbix(&config, &events);
+                }
+                if config.settings.fahrenheit_metrics != old_fahrenheit {
+                    _reading_metrics = record_metrics(&metrics, &config, &events);
+                }
+                controllers.reload(&config.control);
+                federation.reload(config.federation.as_ref(), config.settings.probe_interval);
+                alarms.reload(&config.alarm);
+                let routes = config.alerts.routes.clone();
+                notifier.reload(notifiers(&config, mqtt.as_ref()), routes);
+            });
+        }
+
+        if let Some(mdns) = &mut mdns {
+            mdns.update(probes.len());
+        }
+
+        // so instances that start together don't all read and publish at once
+        if config.settings.splay > 0.0 {
+            let splay = time::Duration::from_secs_f64(fastrand::f64() * config.settings.splay);
+            tokio::select! {
+                () = tokio::time::sleep(splay.div_f64(speed)) => {}
+                () = shutdown.cancelled() => break,
+            }
+        }
+
+        let span = info_span!("poll", cycle).entered();
+        let now = time::Instant::now();
+        // thresholds and setpoints as scheduled for this cycle
+        let local = schedule::now(config.settings.timezone.as_deref());
+        let scheduled = config.alerts.scheduled(local);
+        controllers.schedule(local);
+        let due: Vec<Probe> = current_temps.update(|readings| {
+            readings.update_silenced(time::SystemTime::now(), |target, name| {
+                probes
+                    .iter()
+                    .find(|p| p.name == name)
+                    .is_some_and(|p| config.alerts.matches(target, &p.id, name))
+            });
+            probes
+                .iter()
+                .filter(|p| readings.is_due(&p.name, now))
+                .cloned()
+                .collect()
+        });
+        let probe_config = Arc::new(config.clone());
+        let limit = watchdog_limit(&config, interval);
+        let results = tokio::select! {
+            results = watchdog.read_all(&due, &probe_config, limit) => results,
+            () = shutdown.cancelled() => break,
+        };
+        // the probes a hung poller was reading count as timed out
+        let results = results.unwrap_or_else(|| {
+            due.iter()
+                .map(|_| Err(ProbeError::Timeout(now.elapsed())))
+                .collect()
+        });
+        // the values alerts were checked against, for delta rules
+        let mut values = HashMap::new();
+        for (p, result) in due.iter().zip(results) {
+            let outcome = match result {
+                Ok(sample) => {
+                    let Sample {
+                        raw,
+                        temp,
+                        smoothed,
+                    } = sample;
+                    current_temps.update(|readings| readings.record(&p.name, Some(temp)));
+
+                    debug!(probe = %p.name, temp, raw, smoothed, "reading");
+
+                    // alert on the smoothed value where there is one, so
+                    // noise on a probe doesn't flap its alerts
+                    let value = smoothed.unwrap_or(temp);
+                    let silenced = current_temps.snapshot().silenced.contains_key(&p.name);
+                    // a stuck probe repeats its raw reading exactly, so
+                    // anything within half a step is the same value
+                    let tolerance = probe::resolution_step(config.settings.probe_resolution) / 2.0;
+                    let flatline =
+                        alerts.check_flatline(&p.id, &p.name, raw, tolerance, &scheduled, now);
+                    let changed = alerts.evaluate(&p.id, &p.name, value, &scheduled, now);
+                    for alert in changed.into_iter().chain(flatline) {
+                        send_alert(&notifier, alert, silenced);
+                    }
+                    let rule = scheduled.rule(&p.id, &p.name);
+                    update_alert_metrics(&p.name, rule, &alerts, &metrics);
+                    values.insert(p.name.clone(), value);
+                    task::block_in_place(|| controllers.update(&p.id, &p.name, value, now));
+                    Ok(sample)
+                }
+                Err(e) => {
+                    let error_type = e.error_type();
+                    let down = current_temps.update(|readings| {
+                        readings.record_error(&p.name, error_type, e.to_string());
+                        readings
+                            .last_good(&p.name, config.settings.down_after)
+                            .is_none()
+                    });
+                    if down {
+                        task::block_in_place(|| controllers.probe_down(&p.id, &p.name));
+                    }
+
+                    warn!(probe = %p.name, error_type, "error reading temperature: {}", e);
+                    Err(Failure {
+                        error_type,
+                        message: e.to_string(),
+                    })
+                }
+            };
+            current_temps.update(|readings| update_quarantine(p, &config, readings, &metrics, now));
+            events.send(Event::Reading(Reading {
+                id: p.id.clone(),
+                name: p.name.clone(),
+                at: time::SystemTime::now(),
+                outcome,
+            }));
+        }
+        for (name, delta) in &scheduled.deltas {
+            // both probes have to have been read this cycle
+            let value = |target: &String| {
+                let probe = probes
+                    .iter()
+                    .find(|p| &p.id == target || &p.name == target)?;
+                values.get(&probe.name).copied()
+            };
+            let [a, b] = &delta.probes;
+            let (Some(a), Some(b)) = (value(a), value(b)) else {
+                continue;
+            };
+            let silenced = is_silenced(&current_temps.snapshot(), &config, &probes, name);
+            for alert in alerts.evaluate_delta(name, a - b, &scheduled, now) {
+                send_alert(&notifier, alert, silenced);
+            }
+            update_alert_metrics(name, Some(&delta.rule), &alerts, &metrics);
+        }
+        {
+            let firing: Vec<Alert> = current_temps.update(|readings| {
+                readings.alerts = alerts.alerts();
+                readings
+                    .alerts
+                    .iter()
+                    .filter(|a| a.state == alert::State::Firing)
+                    .filter(|a| !is_silenced(readings, &config, &probes, &a.probe))
+                    .cloned()
+                    .collect()
+            });
+            task::block_in_place(|| {
+                alarms.update(&firing, |target, name| {
+                    // a delta has no probe of its own
+                    let id = probes
+                        .iter()
+                        .find(|p| p.name == name)
+                        .map_or(name, |p| &p.id);
+                    config.alerts.matches(target, id, name)
+                })
+            });
+            notifier.refresh(firing);
+        }
+        if let Some(dbus) = &mut dbus {
+            dbus.update();
+        }
+        events.send(Event::Polled {
+            probes: probes
+                .iter()
+                .map(|p| (p.id.clone(), p.name.clone()))
+                .collect(),
+        });
+        span.exit();
+
+        interval = match &config.adaptive {
+            Some(adaptive) => {
+                let alerting = alerts
+                    .alerts()
+                    .iter()
+                    .any(|a| a.state != alert::State::Resolved);
+                pace.next(adaptive, &values, alerting, now)
+            }
+            None => {
+                let scheduled = config.settings.probe_interval(local);
+                if polling_every
+                    .replace(scheduled)
+                    .is_some_and(|last| last != scheduled)
+                {
+                    info!("polling every {}s", scheduled);
+                }
+                time::Duration::from_secs(scheduled)
+            }
+        }
+        .div_f64(speed);
+        tokio::select! {
+            () = tokio::time::sleep(interval) => {}
+            () = shutdown.cancelled() => break,
+        }
+    }
+
+    task::block_in_place(|| {
+        if let Some(path) = &config.settings.state_file
+            && let Err(e) = statefile::save(path, &probes, &current_temps.snapshot(), |name| {
+                metrics.read_errors(name)
+            })
+        {
+            warn!("failed to save state to {}: {}", path.display(), e);
+        }
+        switch_off(&mut controllers, &mut alarms, registration.as_ref());
+    });
+    Ok(())
+}
+
+/// Puts the last run's readings back on the dashboard and its error counts
+/// back in the metrics, if it saved them.
+fn restore_state(config: &Config, temps: &TempData, metrics: &ProbeMetrics) -> statefile::Restored {
+    let Some(path) = &config.settings.state_file else {
+        return statefile::Restored::default();
+    };
+    match temps.update(|readings| statefile::restore(path, &config.probe_labels, readings)) {
+        Ok(restored) => {
+            if !restored.names.is_empty() {
+                info!(
+                    "restored the last readings of {} probe(s) from {}",
+                    restored.names.len(),
+                    path.display()
+                );
+            }
+            for (name, error_type, count) in &restored.read_errors {
+                metrics
+                    .read_errors
+                    .with_label_values(&[name, error_type])
+                    .inc_by(*count as f64);
+            }
+            restored
+        }
+        Err(e) => {
+            warn!("failed to restore state from {}: {}", path.display(), e);
+            statefile::Restored::default()
+        }
+    }
+}
+
+/// How long a cycle's reads can take before the poller is restarted:
+/// `watchdog_cycles` polling intervals, and never less than the reads could
+/// take without hanging.
+fn watchdog_limit(config: &Config, interval: time::Duration) -> Option<time::Duration> {
+    let settings = &config.settings;
+    let slowest = time::Duration::from_secs_f64(settings.read_timeout + settings.read_splay);
+    (settings.watchdog_cycles > 0)
+        .then(|| (interval * settings.watchdog_cycles).max(slowest + time::Duration::from_secs(1)))
+}
+
+/// The silences set in the config file, as (target, until, comment).
+fn config_silences(config: &Config) -> Vec<(String, time::SystemTime, Option<String>)> {
+    config
+        .alerts
+        .silences
+        .iter()
+        // already checked by Config::validate
+        .filter_map(|s| Some((s.target.clone(), s.until().ok()?, s.comment.clone())))
+        .collect()
+}
+
+/// Whether a probe's alerts, or a delta's, are silenced. A delta is
+/// silenced along with either of its probes.
+fn is_silenced(readings: &Readings, config: &Config, probes: &[Probe], name: &str) -> bool {
+    let silenced = |name: &str| readings.silenced.contains_key(name);
+    silenced(name)
+        || config.alerts.deltas.get(name).is_some_and(|delta| {
+            delta.probes.iter().any(|target| {
+                probes
+                    .iter()
+                    .any(|p| (&p.id == target || &p.name == target) && silenced(&p.name))
+            })
+        })
+}
+
+/// A token cancelled on SIGTERM or SIGINT, to stop polling at the next
+/// await rather than mid-cycle.
+fn shutdown_on_signal() -> std::io::Result<CancellationToken> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let shutdown = CancellationToken::new();
+    let token = shutdown.clone();
+    tokio::spawn(async move {
+        let signal = tokio::select! {
+            _ = terminate.recv() => SIGTERM,
+            _ = interrupt.recv() => SIGINT,
+        };
+        info!(signal, "shutting down");
+        token.cancel();
+    });
+    Ok(shutdown)
+}
+
+/// Switches the control outputs and alarms off before exiting, so a relay
+/// isn't left on with nothing watching it.
+fn switch_off(
+    controllers: &mut Controllers,
+    alarms: &mut Alarms,
+    registration: Option<&registry::Registration>,
+) {
+    controllers.shutdown();
+    alarms.shutdown();
+    if let Some(registration) = registration {
+        registration.deregister();
+    }
+}
+
+/// Passes an alert on to the notifiers, or only logs it while silenced.
+fn send_alert(notifier: &Dispatcher, alert: Alert, silenced: bool) {
+    if silenced {
+        info!(probe = %alert.probe, "silenced: {}", alert);
+    } else {
+        notifier.send(alert);
+    }
+}
+
+/// Sets the alert gauges for every alert a probe's (or delta's) rule defines.
+fn update_alert_metrics(
+    name: &str,
+    rule: Option<&AlertRule>,
+    alerts: &Engine,
+    metrics: &ProbeMetrics,
+) {
+    for kind in alert::Kind::ALL {
+        let gauge = &metrics.alerts;
+        let labels = [name, kind.as_str()];
+        if rule.and_then(|r| kind.threshold(r)).is_some() {
+            let firing = alerts.is_firing(name, kind);
+            gauge
+                .with_label_values(&labels)
+                .set(if firing { 1.0 } else { 0.0 });
+        } else {
+            let _ = gauge.remove_label_values(&labels);
+        }
+    }
+}
+
+/// The configured notifiers, including alerts over mqtt if enabled.
+fn notifiers(config: &Config, mqtt: Option<&mqtt::Publisher>) -> Vec<Box<dyn notify::Notifier>> {
+    let mut notifiers = notify::from_config(&config.alerts);
+    if let Some(alerts) = mqtt.and_then(|m| m.alerts()) {
+        notifiers.push(Box::new(alerts));
+    }
+    notifiers
+}
+
+#[cfg(feature = "grpc")]
+fn start_grpc(config: &config::GrpcConfig, temps: TempData) -> std::io::Result<()> {
+    grpc::start(config, temps)
+}
+
+#[cfg(not(feature = "grpc"))]
+fn start_grpc(_: &config::GrpcConfig, _: TempData) -> std::io::Result<()> {
+    warn!("not serving grpc: tempmon was built without the grpc feature");
+    Ok(())
+}
+
+fn start_dbus(config: &Config, temps: &TempData) -> Option<dbus::Publisher> {
+    match dbus::Publisher::start(config.dbus.as_ref()?, temps.clone()) {
+        Ok(publisher) => Some(publisher),
+        Err(e) => {
+            warn!("not serving on dbus: {}", e);
+            None
+        }
+    }
+}
+
+fn start_mdns(config: &Config) -> Option<mdns::Advertiser> {
+    match mdns::Advertiser::start(config.mdns.as_ref()?, config.settings.metrics_port) {
+        Ok(advertiser) => Some(advertiser),
+        Err(e) => {
+            warn!("not advertising over mdns: {}", e);
+            None
+        }
+    }
+}
+
+fn start_mqtt(config: &Config, events: &Events) -> Option<mqtt::Publisher> {
+    match mqtt::Publisher::start(config.mqtt.as_ref()?, events) {
+        Ok(publisher) => Some(publisher),
+        Err(e) => {
+            warn!("not publishing to mqtt: {}", e);
+            None
+        }
+    }
+}
+
+fn start_zabbix(config: &Config, events: &Events) -> Option<Consumer> {
+    let zabbix = config.zabbix.as_ref()?;
+    Some(zabbix::Sender::start(zabbix).consume(events))
+}
+
+/// Keeps each probe's reading gauges and error counters up to date.
+fn record_metrics(metrics: &ProbeMetrics, config: &Config, events: &Events) -> Consumer {
+    let metrics = metrics.clone();
+    let fahrenheit = config.settings.fahrenheit_metrics;
+    events.consume("metrics", move |event| {
+        let Event::Reading(reading) = event else {
+            return;
+        };
+        let name = reading.name.as_str();
+        let sample = match &reading.outcome {
+            Ok(sample) => sample,
+            Err(failure) => {
+                metrics
+                    .read_errors
+                    .with_label_values(&[name, failure.error_type])
+                    .inc();
+                return;
+            }
+        };
+
+        metrics
+            .readings_raw
+            .with_label_values(&[name])
+            .set(sample.raw.into());
+        metrics
+            .readings
+            .with_label_values(&[name])
+            .set(sample.temp.into());
+        if fahrenheit {
+            let fahrenheit = Unit::Fahrenheit.convert(sample.temp);
+            metrics
+                .readings_fahrenheit
+                .with_label_values(&[name])
+                .set(fahrenheit.into());
+        } else {
+            let _ = metrics.readings_fahrenheit.remove_label_values(&[name]);
+        }
+        match sample.smoothed {
+            Some(smoothed) => metrics
+                .readings_smoothed
+                .with_label_values(&[name])
+                .set(smoothed.into()),
+            None => {
+                let _ = metrics.readings_smoothed.remove_label_values(&[name]);
+            }
+        }
+    })
+}
+
+/// Moves a probe in or out of quarantine after a read.
+fn update_quarantine(
+    probe: &Probe,
+    config: &Config,
+    readings: &mut Readings,
+    metrics: &ProbeMetrics,
+    now: time::Instant,
+) {
+    let was_quarantined = readings.quarantined.contains_key(&probe.name);
+    match &config.quarantine {
+        Some(policy) => {
+            let interval = time::Duration::from_secs(config.settings.probe_interval);
+            readings.update_quarantine(&probe.name, policy, interval, now);
+        }
+        None => {
+            readings.release(&probe.name);
+        }
+    }
+
+    match readings.quarantined.get(&probe.name) {
+        Some(q) if !was_quarantined => warn!(
+            probe = %probe.name,
+            "quarantined after repeated failures, retrying in {}s",
+            q.backoff.as_secs()
+        ),
+        Some(q) => {
+            debug!(probe = %probe.name, "still failing, retrying in {}s", q.backoff.as_secs())
+        }
+        None if was_quarantined => info!(probe = %probe.name, "released from quarantine"),
+        None => {}
+    }
+    let quarantined = readings.quarantined.contains_key(&probe.name);
+    metrics
+        .quarantined
+        .with_label_values(&[&probe.name])
+        .set(if quarantined { 1.0 } else { 0.0 });
+}
+
+/// Runs discovery until it finds at least one probe, or gives up straight
+/// away if the config says to. `None` if shut down while waiting.
+async fn wait_for_probes(
+    registry: &Registry,
+    config: &Config,
+    metrics: &ProbeMetrics,
+    shutdown: &CancellationToken,
+) -> Result<Option<Vec<Probe>>, Box<dyn std::error::Error>> {
+    let mut backoff = DISCOVERY_BACKOFF_MIN;
+
+    loop {
+        info!(
+            "discovering temperature probes with {}...",
+            registry.names().join(", ")
+        );
+        let probes = match registry.discover(&config.probe_labels) {
+            Ok(probes) => probes,
+            Err(e) => {
+                warn!("failed to discover probes: {}", e);
+                Vec::new()
+            }
+        };
+        metrics.probes_discovered.set(probes.len() as f64);
+        info!("found {} probe(s)", probes.len());
+
+        if !probes.is_empty() {
+            for probe in &probes {
+                if let Err(e) = probe.set_resolution(config.settings.probe_resolution) {
+                    warn!("failed to set resolution for {}: {}", probe.name, e);
+                }
+            }
+            return Ok(Some(probes));
+        }
+
+        match config.settings.on_no_probes {
+            NoProbes::Exit => return Err("no probes found".into()),
+            NoProbes::Wait => {
+                warn!("no probes found, retrying in {}s", backoff.as_secs());
+                tokio::select! {
+                    () = tokio::time::sleep(backoff) => {}
+                    () = shutdown.cancelled() => return Ok(None),
+                }
+                backoff = (backoff * 2).min(DISCOVERY_BACKOFF_MAX);
+            }
+        }
+    }
+}
+
+/// Re-reads the config file and applies everything that doesn't need the
+/// http listeners to be rebound.
+fn reload_config(
+    config_path: &Path,
+    config: &mut Config,
+    probes: &mut [Probe],
+    current_temps: &TempData,
+    metrics: &ProbeMetrics,
+    server: &server::Handle,
+    alerts: &mut Engine,
+) {
+    info!("reloading config...");
+    let new_config = match load_config(config_path) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            error!("failed to reload config, keeping current config: {}", e);
+            return;
+        }
+    };
+
+    let changes = config::diff(config, &new_config);
+    if changes.is_empty() {
+        info!("config unchanged");
+        return;
+    }
+    for change in &changes {
+        if change.requires_restart() {
+            warn!("config changed: {} (requires restart)", change);
+        } else {
+            info!("config changed: {}", change);
+        }
+    }
+
+    if let Err(e) = server.reload(&new_config) {
+        warn!("failed to reload http settings: {}", e);
+    }
+
+    for probe in probes.iter_mut() {
+        let name = display_name(&probe.id, &new_config.probe_labels);
+        if name != probe.name {
+            current_temps.update(|readings| readings.rename(&probe.name, &name));
+            alerts.rename(&probe.name, &name);
+            metrics.remove(&probe.name);
+            probe.name = name;
+        }
+
+        if new_config.settings.probe_resolution != config.settings.probe_resolution
+            && let Err(e) = probe.set_resolution(new_config.settings.probe_resolution)
+        {
+            warn!("failed to set resolution for {}: {}", probe.name, e);
+        }
+    }
+
+    *config = new_config;
+}
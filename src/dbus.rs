@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::time::UNIX_EPOCH;
+
+use tracing::{info, warn};
+use zbus::blocking::Connection;
+use zbus::blocking::connection::Builder;
+use zbus::zvariant::Value;
+use zbus::{fdo, interface};
+
+use crate::config::{DbusBus, DbusConfig};
+use crate::state::{Readings, TempData};
+
+const NAME: &str = "org.tempmon.Monitor";
+const PATH: &str = "/org/tempmon/Monitor";
+
+/// (probe, kind, state, severity, value)
+type AlertTuple = (String, String, String, String, f64);
+
+struct Monitor {
+    temps: TempData,
+}
+
+#[interface(name = "org.tempmon.Monitor")]
+impl Monitor {
+    /// The probes with a reading, in °C.
+    #[zbus(property)]
+    fn readings(&self) -> HashMap<String, f64> {
+        readings(&self.temps.snapshot())
+    }
+
+    /// The probes whose last read failed.
+    #[zbus(property)]
+    fn failing_probes(&self) -> Vec<String> {
+        failing_probes(&self.temps.snapshot())
+    }
+
+    #[zbus(property)]
+    fn alerts(&self) -> Vec<AlertTuple> {
+        alerts(&self.temps.snapshot())
+    }
+
+    /// When the probes were last read, as a unix timestamp.
+    #[zbus(property)]
+    fn updated(&self) -> u64 {
+        updated(&self.temps.snapshot())
+    }
+
+    fn get_reading(&self, probe: &str) -> fdo::Result<f64> {
+        let readings = self.temps.snapshot();
+        match readings.temps.get(probe) {
+            Some(Some(temp)) => Ok((*temp).into()),
+            Some(None) => Err(fdo::Error::Failed(format!("{} is failing", probe))),
+            None => Err(fdo::Error::InvalidArgs(format!("no probe {}", probe))),
+        }
+    }
+}
+
+fn readings(readings: &Readings) -> HashMap<String, f64> {
+    readings
+        .temps
+        .iter()
+        .filter_map(|(name, temp)| Some((name.clone(), (*temp)?.into())))
+        .collect()
+}
+
+fn failing_probes(readings: &Readings) -> Vec<String> {
+    let mut failing: Vec<String> = readings
+        .temps
+        .iter()
+        .filter(|(_, temp)| temp.is_none())
+        .map(|(name, _)| name.clone())
+        .collect();
+    failing.sort();
+    failing
+}
+
+fn alerts(readings: &Readings) -> Vec<AlertTuple> {
+    readings
+        .alerts
+        .iter()
+        .map(|a| {
+            (
+                a.probe.clone(),
+                a.kind.as_str().to_string(),
+                a.state.as_str().to_string(),
+                a.severity.as_str().to_string(),
+                a.value.into(),
+            )
+        })
+        .collect()
+}
+
+fn updated(readings: &Readings) -> u64 {
+    readings
+        .updated
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// What was last announced, to signal only what's changed since.
+#[derive(Default, PartialEq)]
+struct Announced {
+    readings: HashMap<String, f64>,
+    failing_probes: Vec<String>,
+    alerts: Vec<AlertTuple>,
+    updated: u64,
+}
+
+impl Announced {
+    fn new(state: &Readings) -> Self {
+        Announced {
+            readings: readings(state),
+            failing_probes: failing_probes(state),
+            alerts: alerts(state),
+            updated: updated(state),
+        }
+    }
+}
+
+/// Serves the readings and alerts on dbus as `org.tempmon.Monitor`.
+pub struct Publisher {
+    connection: Connection,
+    temps: TempData,
+    announced: Announced,
+}
+
+impl Publisher {
+    pub fn start(config: &DbusConfig, temps: TempData) -> zbus::Result<Self> {
+        let builder = match config.bus {
+            DbusBus::System => Builder::system()?,
+            DbusBus::Session => Builder::session()?,
+        };
+        let connection = builder
+            .name(NAME)?
+            .serve_at(
+                PATH,
+                Monitor {
+                    temps: temps.clone(),
+                },
+            )?
+            .build()?;
+        info!(bus = config.bus.as_str(), "serving {} on dbus", NAME);
+        Ok(Publisher {
+            connection,
+            temps,
+            announced: Announced::default(),
+        })
+    }
+
+    /// Signals PropertiesChanged with whatever has changed since the last
+    /// call.
+    pub fn update(&mut self) {
+        let current = Announced::new(&self.temps.snapshot());
+        let changed = changed(&self.announced, &current);
+        if changed.is_empty() {
+            return;
+        }
+        let signal = (NAME, changed, Vec::<String>::new());
+        let emitted = self.connection.emit_signal(
+            None::<()>,
+            PATH,
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+            &signal,
+        );
+        match emitted {
+            Ok(()) => self.announced = current,
+            Err(e) => warn!("failed to signal changes on dbus: {}", e),
+        }
+    }
+}
+
+fn changed<'a>(old: &Announced, new: &'a Announced) -> HashMap<&'static str, Value<'a>> {
+    let mut changed = HashMap::new();
+    if new.readings != old.readings {
+        changed.insert("Readings", Value::from(new.readings.clone()));
+    }
+    if new.failing_probes != old.failing_probes {
+        changed.insert("FailingProbes", Value::from(new.failing_probes.clone()));
+    }
+    if new.alerts != old.alerts {
+        changed.insert("Alerts", Value::from(new.alerts.clone()));
+    }
+    if new.updated != old.updated {
+        changed.insert("Updated", Value::from(new.updated));
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed() {
+        let mut readings = Readings::new(["fermenter", "ambient"]);
+        readings.record("fermenter", Some(18.5));
+        let old = Announced::default();
+        let new = Announced::new(&readings);
+        assert_eq!(new.readings["fermenter"], 18.5);
+        assert_eq!(new.failing_probes, ["ambient"]);
+
+        let changed = changed(&old, &new);
+        let mut names: Vec<_> = changed.keys().copied().collect();
+        names.sort();
+        // no alerts before or after
+        assert_eq!(names, ["FailingProbes", "Readings", "Updated"]);
+        assert!(super::changed(&new, &Announced::new(&readings)).is_empty());
+    }
+}
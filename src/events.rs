@@ -0,0 +1,141 @@
+use std::time::SystemTime;
+
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::poll::Sample;
+
+/// Events a consumer can fall behind by before it starts missing them.
+const CAPACITY: usize = 256;
+
+/// What came of reading a probe.
+#[derive(Debug, Clone)]
+pub struct Reading {
+    pub id: String,
+    pub name: String,
+    pub at: SystemTime,
+    pub outcome: Result<Sample, Failure>,
+}
+
+/// A failed read, as the error counters and api report it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub error_type: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    Reading(Reading),
+    /// every probe due this cycle has been read and the alerts updated;
+    /// `probes` are all of them as (id, name), due or not
+    Polled {
+        probes: Vec<(String, String)>,
+    },
+}
+
+/// The poller's side of the channel, cheap to clone. Events are sent once
+/// the shared [`Readings`](crate::state::Readings) are up to date, so a
+/// consumer can look there for anything the event doesn't carry.
+#[derive(Clone)]
+pub struct Events {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Events {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl Events {
+    pub fn send(&self, event: Event) {
+        // nobody listening is fine
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Calls `consume` with every event from now on, on a task of its own.
+    /// Must be called from within a tokio runtime.
+    pub fn consume(
+        &self,
+        name: &'static str,
+        mut consume: impl FnMut(&Event) + Send + 'static,
+    ) -> Consumer {
+        let mut receiver = self.subscribe();
+        let stop = CancellationToken::new();
+        let stopped = stop.clone();
+        tokio::spawn(async move {
+            loop {
+                let received = tokio::select! {
+                    // finish what's queued before stopping
+                    biased;
+                    received = receiver.recv() => received,
+                    () = stopped.cancelled() => break,
+                };
+                match received {
+                    Ok(event) => consume(&event),
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!(consumer = name, "fell behind, missed {} events", missed)
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        Consumer { stop }
+    }
+}
+
+/// A running consumer, stopped once it has caught up after this is
+/// dropped.
+pub struct Consumer {
+    stop: CancellationToken,
+}
+
+impl Drop for Consumer {
+    fn drop(&mut self) {
+        self.stop.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_consumer_catches_up_before_stopping() {
+        let events = Events::default();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let consumer = {
+            let seen = Arc::clone(&seen);
+            events.consume("test", move |event| {
+                if let Event::Polled { probes } = event {
+                    seen.lock().unwrap().push(probes.len());
+                }
+            })
+        };
+
+        for n in 0..3 {
+            events.send(Event::Polled {
+                probes: vec![(String::new(), String::new()); n],
+            });
+        }
+        drop(consumer);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(*seen.lock().unwrap(), [0, 1, 2]);
+
+        // and sees nothing after
+        events.send(Event::Polled { probes: Vec::new() });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(seen.lock().unwrap().len(), 3);
+    }
+}
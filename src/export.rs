@@ -0,0 +1,145 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+
+use crate::config::Unit;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    /// one json object per line
+    Ndjson,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Format> {
+        match s {
+            "csv" => Some(Format::Csv),
+            "ndjson" | "json" => Some(Format::Ndjson),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Ndjson => "ndjson",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Row<'a> {
+    time: String,
+    probe: &'a str,
+    temperature: f32,
+    unit: &'static str,
+}
+
+/// A time from a query string, as RFC 3339 or unix seconds.
+pub fn parse_time(s: &str) -> Option<SystemTime> {
+    if let Ok(secs) = s.parse::<u64>() {
+        return UNIX_EPOCH.checked_add(Duration::from_secs(secs));
+    }
+    OffsetDateTime::parse(s, &Rfc3339)
+        .ok()
+        .map(SystemTime::from)
+}
+
+/// Readings as `(probe, time, °c)`, oldest first, in `format`.
+pub fn render(readings: &[(&str, SystemTime, f32)], format: Format, unit: Unit) -> String {
+    let time = |at: SystemTime| {
+        OffsetDateTime::from(at)
+            .replace_nanosecond(0)
+            .unwrap_or_else(|_| OffsetDateTime::from(at))
+            .format(&Rfc3339)
+            .unwrap_or_default()
+    };
+    let mut out = String::new();
+    if format == Format::Csv {
+        out.push_str(&format!("time,probe,{}\n", unit.as_str()));
+    }
+    for &(probe, at, temp) in readings {
+        let temp = unit.convert(temp);
+        match format {
+            Format::Csv => {
+                out.push_str(&format!("{},{},{:.3}\n", time(at), csv_field(probe), temp));
+            }
+            Format::Ndjson => {
+                let row = Row {
+                    time: time(at),
+                    probe,
+                    temperature: temp,
+                    unit: unit.as_str(),
+                };
+                out.push_str(&serde_json::to_string(&row).unwrap());
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// A download's name, dated `at` in UTC, e.g. `tempmon-2026-10-20.csv`.
+pub fn filename(format: Format, at: SystemTime) -> String {
+    let date = OffsetDateTime::from(at)
+        .format(format_description!("[year]-[month]-[day]"))
+        .unwrap_or_default();
+    format!("tempmon-{}.{}", date, format.extension())
+}
+
+/// Quotes a csv field if it needs it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time() {
+        let at = UNIX_EPOCH + Duration::from_secs(1_792_497_600);
+        assert_eq!(parse_time("1792497600"), Some(at));
+        assert_eq!(parse_time("2026-10-20T12:00:00Z"), Some(at));
+        assert_eq!(parse_time("2026-10-20T13:00:00+01:00"), Some(at));
+        assert_eq!(parse_time("yesterday"), None);
+        assert_eq!(parse_time("18446744073709551615"), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let at = UNIX_EPOCH + Duration::from_secs(1_792_497_600);
+        let readings = [("fermenter", at, 18.5), ("brew, \"2\"", at, 20.0)];
+
+        let csv = render(&readings, Format::Csv, Unit::Celsius);
+        assert_eq!(
+            csv,
+            "time,probe,celsius\n\
+             2026-10-20T12:00:00Z,fermenter,18.500\n\
+             2026-10-20T12:00:00Z,\"brew, \"\"2\"\"\",20.000\n"
+        );
+
+        let ndjson = render(&readings[..1], Format::Ndjson, Unit::Fahrenheit);
+        let row: serde_json::Value = serde_json::from_str(ndjson.trim_end()).unwrap();
+        assert_eq!(row["temperature"], 65.3);
+        assert_eq!(row["unit"], "fahrenheit");
+        assert_eq!(row["time"], "2026-10-20T12:00:00Z");
+
+        assert_eq!(filename(Format::Ndjson, at), "tempmon-2026-10-20.ndjson");
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use prometheus::{GaugeVec, register_gauge_vec};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+use ureq::{Agent, AgentBuilder};
+
+use crate::config::{FederationConfig, InstanceConfig};
+use crate::state::{Readings, TempData};
+
+#[derive(Deserialize)]
+struct Reading {
+    probe: String,
+    temperature: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct RemoteReadings {
+    readings: Vec<Reading>,
+}
+
+/// Polls other tempmon instances' readings into this one's, so its
+/// dashboard and metrics cover them too. Their probes are shown as
+/// `<instance>/<probe>`.
+pub struct Federation {
+    /// the instances to poll and how often, swapped out on reload
+    config: Arc<Mutex<(FederationConfig, Duration)>>,
+}
+
+struct Metrics {
+    readings: GaugeVec,
+    up: GaugeVec,
+}
+
+impl Federation {
+    pub fn start(
+        config: Option<&FederationConfig>,
+        probe_interval: u64,
+        temps: TempData,
+    ) -> prometheus::Result<Self> {
+        let metrics = Metrics {
+            readings: register_gauge_vec!(
+                "dash_remote_temp_readings",
+                "readings from the probes of other tempmon instances",
+                &["instance", "probe"]
+            )?,
+            up: register_gauge_vec!(
+                "dash_remote_up",
+                "whether another tempmon instance answered its last poll (1) or not (0)",
+                &["instance"]
+            )?,
+        };
+        let config = Arc::new(Mutex::new(settings(config, probe_interval)));
+        let shared = Arc::clone(&config);
+        thread::spawn(move || {
+            // the full names of each instance's probes at its last poll
+            let mut known: HashMap<String, Vec<String>> = HashMap::new();
+            loop {
+                let (config, interval) = shared.lock().unwrap().clone();
+                let removed: Vec<String> = known
+                    .keys()
+                    .filter(|name| !config.instances.contains_key(*name))
+                    .cloned()
+                    .collect();
+                for instance in removed {
+                    let names = known.remove(&instance).unwrap_or_default();
+                    temps.update(|readings| {
+                        for name in &names {
+                            readings.remove(name);
+                        }
+                    });
+                    let _ = metrics.up.remove_label_values(&[&instance]);
+                    info!(instance, "stopped polling instance");
+                }
+
+                let agent = agent(config.timeout);
+                for (instance, remote) in &config.instances {
+                    let result = poll(&agent, remote);
+                    let names = known.entry(instance.clone()).or_default();
+                    temps.update(|readings| update(readings, &metrics, instance, result, names));
+                }
+                thread::sleep(interval);
+            }
+        });
+        Ok(Federation { config })
+    }
+
+    pub fn reload(&self, config: Option<&FederationConfig>, probe_interval: u64) {
+        *self.config.lock().unwrap() = settings(config, probe_interval);
+    }
+}
+
+fn settings(
+    config: Option<&FederationConfig>,
+    probe_interval: u64,
+) -> (FederationConfig, Duration) {
+    let config = config.cloned().unwrap_or_default();
+    let interval = Duration::from_secs(config.interval.unwrap_or(probe_interval).max(1));
+    (config, interval)
+}
+
+fn agent(timeout: u64) -> Agent {
+    AgentBuilder::new()
+        .timeout(Duration::from_secs(timeout))
+        .user_agent(concat!("tempmon/", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// An instance's current readings.
+fn poll(agent: &Agent, remote: &InstanceConfig) -> Result<Vec<Reading>, String> {
+    let url = format!("{}/api/v1/readings", remote.url.trim_end_matches('/'));
+    let mut request = agent.get(&url);
+    if let Some(token) = &remote.token {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    let response = request.call().map_err(|e| e.to_string())?;
+    let readings: RemoteReadings = response.into_json().map_err(|e| e.to_string())?;
+    Ok(readings.readings)
+}
+
+/// Records a poll of `instance`, marking its probes as failing if it
+/// couldn't be reached. `names` are its probes' full names at the last
+/// poll, and are updated to this one's.
+fn update(
+    readings: &mut Readings,
+    metrics: &Metrics,
+    instance: &str,
+    result: Result<Vec<Reading>, String>,
+    names: &mut Vec<String>,
+) {
+    let polled = match result {
+        Ok(polled) => polled,
+        Err(e) => {
+            warn!(instance, "failed to poll instance: {}", e);
+            metrics.up.with_label_values(&[instance]).set(0.0);
+            for name in names.iter() {
+                readings.record_error(
+                    name,
+                    "unreachable",
+                    format!("{} is unreachable: {}", instance, e),
+                );
+                let probe = &name[instance.len() + 1..];
+                let _ = metrics.readings.remove_label_values(&[instance, probe]);
+            }
+            return;
+        }
+    };
+    metrics.up.with_label_values(&[instance]).set(1.0);
+
+    let current: Vec<String> = polled
+        .iter()
+        .map(|r| format!("{}/{}", instance, r.probe))
+        .collect();
+    for name in names.iter().filter(|n| !current.contains(n)) {
+        readings.remove(name);
+        let probe = &name[instance.len() + 1..];
+        let _ = metrics.readings.remove_label_values(&[instance, probe]);
+    }
+    for (reading, name) in polled.iter().zip(&current) {
+        match reading.temperature {
+            Some(temp) => {
+                readings.record(name, Some(temp));
+                metrics
+                    .readings
+                    .with_label_values(&[instance, &reading.probe])
+                    .set(temp.into());
+            }
+            None => {
+                readings.record_error(name, "remote", format!("{} has no reading", instance));
+                let _ = metrics
+                    .readings
+                    .remove_label_values(&[instance, &reading.probe]);
+            }
+        }
+    }
+    debug!(instance, probes = current.len(), "polled instance");
+    *names = current;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::Opts;
+
+    fn metrics() -> Metrics {
+        Metrics {
+            readings: GaugeVec::new(Opts::new("readings", "readings"), &["instance", "probe"])
+                .unwrap(),
+            up: GaugeVec::new(Opts::new("up", "up"), &["instance"]).unwrap(),
+        }
+    }
+
+    fn reading(probe: &str, temperature: Option<f32>) -> Reading {
+        Reading {
+            probe: probe.to_string(),
+            temperature,
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let metrics = metrics();
+        let mut readings = Readings::new(["fermenter"]);
+        let mut names = Vec::new();
+
+        let polled = vec![reading("fermenter", Some(18.5)), reading("ambient", None)];
+        update(&mut readings, &metrics, "garage", Ok(polled), &mut names);
+        assert_eq!(names, ["garage/fermenter", "garage/ambient"]);
+        assert_eq!(readings.temps["garage/fermenter"], Some(18.5));
+        assert_eq!(readings.temps["garage/ambient"], None);
+        assert_eq!(readings.errors["garage/ambient"].0, "remote");
+        assert_eq!(readings.temps["fermenter"], None);
+        let gauge = metrics.readings.with_label_values(&["garage", "fermenter"]);
+        assert_eq!(gauge.get(), 18.5);
+
+        update(
+            &mut readings,
+            &metrics,
+            "garage",
+            Err("timed out".to_string()),
+            &mut names,
+        );
+        assert_eq!(readings.temps["garage/fermenter"], None);
+        assert_eq!(readings.errors["garage/fermenter"].0, "unreachable");
+        assert_eq!(metrics.up.with_label_values(&["garage"]).get(), 0.0);
+
+        // a probe that's gone from the instance goes from here too
+        let polled = vec![reading("fermenter", Some(19.0))];
+        update(&mut readings, &metrics, "garage", Ok(polled), &mut names);
+        assert!(!readings.temps.contains_key("garage/ambient"));
+        assert_eq!(readings.last_good["garage/fermenter"].0, 19.0);
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::Duration;
+
+use gpio_cdev::{Chip, LineHandle, LineRequestFlags};
+
+use crate::config::GpioConfig;
+
+const GPIO_SYSFS_PATH: &str = "/sys/class/gpio";
+
+/// How many times to wait for udev to hand over a newly exported pin.
+const EXPORT_RETRIES: u32 = 10;
+
+enum Pin {
+    /// the pin's sysfs value file
+    Sysfs(PathBuf),
+    Chip(LineHandle),
+}
+
+/// A gpio pin driven as an on/off output, e.g. a relay. Opening it
+/// switches it off.
+pub struct Output {
+    pin: Pin,
+    active_low: bool,
+}
+
+impl Output {
+    pub fn open(config: &GpioConfig) -> io::Result<Self> {
+        let off = u8::from(config.active_low);
+        let pin = match &config.chip {
+            Some(chip) => {
+                let line = Chip::new(chip)
+                    .and_then(|mut c| c.get_line(config.pin))
+                    .and_then(|l| l.request(LineRequestFlags::OUTPUT, off, "tempmon"))
+                    .map_err(io::Error::other)?;
+                Pin::Chip(line)
+            }
+            None => Pin::Sysfs(export(Path::new(GPIO_SYSFS_PATH), config.pin, off)?),
+        };
+        Ok(Output {
+            pin,
+            active_low: config.active_low,
+        })
+    }
+
+    pub fn set(&self, on: bool) -> io::Result<()> {
+        let level = u8::from(on != self.active_low);
+        match &self.pin {
+            Pin::Sysfs(value) => fs::write(value, level.to_string()),
+            Pin::Chip(line) => line.set_value(level).map_err(io::Error::other),
+        }
+    }
+}
+
+/// Exports a pin through sysfs as an output at `level`, returning the path
+/// of its value file.
+fn export(root: &Path, pin: u32, level: u8) -> io::Result<PathBuf> {
+    let dir = root.join(format!("gpio{}", pin));
+    if !dir.exists() {
+        fs::write(root.join("export"), pin.to_string())?;
+    }
+
+    // setting the direction to "low" or "high" sets the level at the same
+    // time, so the output never glitches on
+    let direction = if level == 0 { "low" } else { "high" };
+    write_exported(&dir.join("direction"), direction)?;
+    Ok(dir.join("value"))
+}
+
+/// Writes to a file of a newly exported gpio or pwm channel. udev can take
+/// a moment to create it and give the gpio group access.
+pub fn write_exported(path: &Path, value: &str) -> io::Result<()> {
+    let mut attempt = 0;
+    loop {
+        match fs::write(path, value) {
+            Ok(()) => return Ok(()),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+                ) && attempt < EXPORT_RETRIES =>
+            {
+                attempt += 1;
+                sleep(Duration::from_millis(100));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sysfs_output() {
+        let root = std::env::temp_dir().join(format!("tempmon-gpio-{}", std::process::id()));
+        let dir = root.join("gpio17");
+        fs::create_dir_all(&dir).unwrap();
+
+        let value = export(&root, 17, 1).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("direction")).unwrap(), "high");
+
+        let output = Output {
+            pin: Pin::Sysfs(value.clone()),
+            active_low: true,
+        };
+        output.set(true).unwrap();
+        assert_eq!(fs::read_to_string(&value).unwrap(), "0");
+        output.set(false).unwrap();
+        assert_eq!(fs::read_to_string(&value).unwrap(), "1");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,151 @@
+use serde_json::{Value, json};
+
+/// The datasource every panel queries, picked when the dashboard is imported.
+const DATASOURCE: &str = "${datasource}";
+
+/// A Grafana dashboard charting `probes`, ready to import. Temperatures are
+/// shown in fahrenheit from `dash_temp_readings_fahrenheit` if `fahrenheit`,
+/// so it should only be set with `fahrenheit_metrics` on.
+pub fn dashboard(probes: &[&str], fahrenheit: bool) -> String {
+    let (metric, unit) = if fahrenheit {
+        ("dash_temp_readings_fahrenheit", "fahrenheit")
+    } else {
+        ("dash_temp_readings", "celsius")
+    };
+    let selector = "{probe=~\"$probe\"}";
+
+    let panels = [
+        panel(
+            "stat",
+            "Current",
+            (0, 0, 24, 4),
+            &format!("{}{}", metric, selector),
+            None,
+            unit,
+        ),
+        panel(
+            "timeseries",
+            "Temperature",
+            (0, 4, 24, 10),
+            &format!("{}{}", metric, selector),
+            None,
+            unit,
+        ),
+        panel(
+            "timeseries",
+            "Read errors",
+            (0, 14, 12, 8),
+            &format!(
+                "sum by (probe, error_type) (rate(dash_temp_read_errors_total{}[$__rate_interval]))",
+                selector
+            ),
+            Some("error_type"),
+            "short",
+        ),
+        panel(
+            "timeseries",
+            "Alerts firing",
+            (12, 14, 12, 8),
+            &format!("dash_alert_active{} == 1", selector),
+            Some("alert"),
+            "none",
+        ),
+    ];
+
+    let options: Vec<Value> = probes
+        .iter()
+        .map(|p| json!({ "text": p, "value": p, "selected": false }))
+        .collect();
+
+    json!({
+        "title": "tempmon",
+        "uid": "tempmon",
+        "tags": ["tempmon"],
+        "timezone": "browser",
+        "refresh": "30s",
+        "time": { "from": "now-24h", "to": "now" },
+        "schemaVersion": 39,
+        "templating": {
+            "list": [
+                {
+                    "name": "datasource",
+                    "label": "Datasource",
+                    "type": "datasource",
+                    "query": "prometheus",
+                },
+                {
+                    "name": "probe",
+                    "label": "Probe",
+                    "type": "custom",
+                    "query": probes.join(","),
+                    "options": options,
+                    "multi": true,
+                    "includeAll": true,
+                    "allValue": ".*",
+                    "current": { "text": "All", "value": "$__all" },
+                },
+            ],
+        },
+        "panels": panels,
+    })
+    .to_string()
+}
+
+/// A panel at `(x, y, w, h)` on the grid, one series per probe, or per
+/// probe and its other label with `other`.
+fn panel(
+    kind: &str,
+    title: &str,
+    grid: (u32, u32, u32, u32),
+    expr: &str,
+    other: Option<&str>,
+    unit: &str,
+) -> Value {
+    let (x, y, w, h) = grid;
+    json!({
+        "type": kind,
+        "title": title,
+        "datasource": { "type": "prometheus", "uid": DATASOURCE },
+        "gridPos": { "x": x, "y": y, "w": w, "h": h },
+        "fieldConfig": { "defaults": { "unit": unit }, "overrides": [] },
+        "targets": [{
+            "refId": "A",
+            "datasource": { "type": "prometheus", "uid": DATASOURCE },
+            "expr": expr,
+            "legendFormat": match other {
+                Some(label) => format!("{{{{probe}}}} {{{{{}}}}}", label),
+                None => "{{probe}}".to_string(),
+            },
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dashboard() {
+        let json: Value = serde_json::from_str(&dashboard(&["fermenter", "room"], false)).unwrap();
+        let probe = &json["templating"]["list"][1];
+        assert_eq!(probe["query"], "fermenter,room");
+        assert_eq!(probe["options"][1]["value"], "room");
+
+        let temperature = &json["panels"][1];
+        assert_eq!(
+            temperature["targets"][0]["expr"],
+            "dash_temp_readings{probe=~\"$probe\"}"
+        );
+        assert_eq!(temperature["fieldConfig"]["defaults"]["unit"], "celsius");
+        assert_eq!(
+            json["panels"][3]["targets"][0]["legendFormat"],
+            "{{probe}} {{alert}}"
+        );
+
+        let json: Value = serde_json::from_str(&dashboard(&[], true)).unwrap();
+        assert_eq!(
+            json["panels"][0]["targets"][0]["expr"],
+            "dash_temp_readings_fahrenheit{probe=~\"$probe\"}"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use std::io;
+use std::net::TcpListener;
+use std::pin::Pin;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::sync::mpsc;
+use tokio_stream::Stream;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tracing::{info, warn};
+
+use crate::config::GrpcConfig;
+use crate::state::{Readings, TempData};
+
+mod proto {
+    tonic::include_proto!("tempmon.v1");
+}
+
+use proto::tempmon_server::{Tempmon, TempmonServer};
+use proto::{Alert, GetRequest, Probe, Reading, Snapshot, WatchRequest};
+
+/// How often a watch checks for a new poll.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Serves the grpc api alongside the http server. Must be called from
+/// within a tokio runtime.
+pub fn start(config: &GrpcConfig, temps: TempData) -> io::Result<()> {
+    // bind up front, so a port in use fails startup like the http server
+    let listener = TcpListener::bind(&config.bind)?;
+    listener.set_nonblocking(true)?;
+    info!("grpc server listening on {}", listener.local_addr()?);
+
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    let service = TempmonServer::new(Service { temps });
+    tokio::spawn(async move {
+        let served = Server::builder()
+            .add_service(service)
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await;
+        if let Err(e) = served {
+            warn!("grpc server stopped: {}", e);
+        }
+    });
+    Ok(())
+}
+
+struct Service {
+    temps: TempData,
+}
+
+#[tonic::async_trait]
+impl Tempmon for Service {
+    async fn get(&self, _: Request<GetRequest>) -> Result<Response<Snapshot>, Status> {
+        Ok(Response::new(snapshot(&self.temps.snapshot())))
+    }
+
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<Snapshot, Status>> + Send>>;
+
+    async fn watch(&self, _: Request<WatchRequest>) -> Result<Response<Self::WatchStream>, Status> {
+        let (tx, rx) = mpsc::channel(4);
+        let temps = self.temps.clone();
+        tokio::spawn(async move {
+            let mut last = None;
+            let mut interval = tokio::time::interval(WATCH_INTERVAL);
+            loop {
+                interval.tick().await;
+                let (updated, snapshot) = {
+                    let readings = temps.snapshot();
+                    if last == Some(readings.updated) {
+                        continue;
+                    }
+                    (readings.updated, snapshot(&readings))
+                };
+                last = Some(updated);
+                // the client has gone
+                if tx.send(Ok(snapshot)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
+
+fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+fn unix_seconds(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Everything there is to say about the readings, sorted by probe.
+fn snapshot(readings: &Readings) -> Snapshot {
+    let mut names: Vec<&String> = readings.temps.keys().collect();
+    names.sort();
+    Snapshot {
+        readings: names
+            .iter()
+            .map(|name| Reading {
+                probe: name.to_string(),
+                temperature: readings.temps[*name].map(f64::from),
+            })
+            .collect(),
+        probes: names
+            .iter()
+            .map(|name| {
+                let last_good = readings.last_good.get(*name);
+                let error = readings.errors.get(*name);
+                Probe {
+                    name: name.to_string(),
+                    failures: readings.failures.get(*name).copied().unwrap_or(0),
+                    quarantined: readings.quarantined.contains_key(*name),
+                    last_temperature: last_good.map(|(temp, _)| f64::from(*temp)),
+                    last_read: last_good.map(|(_, at)| unix_millis(*at)),
+                    error_type: error.map(|(kind, _)| kind.to_string()),
+                    error: error.map(|(_, message)| message.clone()),
+                }
+            })
+            .collect(),
+        alerts: readings
+            .alerts
+            .iter()
+            .map(|a| Alert {
+                probe: a.probe.clone(),
+                kind: a.kind.as_str().to_string(),
+                state: a.state.as_str().to_string(),
+                severity: a.severity.as_str().to_string(),
+                threshold: a.threshold.into(),
+                value: a.value.into(),
+                started_at: unix_seconds(a.started),
+                fired_at: a.fired.map(unix_seconds),
+            })
+            .collect(),
+        updated: unix_millis(readings.updated),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::{self, Kind};
+
+    #[test]
+    fn test_snapshot() {
+        let mut readings = Readings::new(["freezer", "fermenter"]);
+        readings.record("fermenter", Some(18.5));
+        readings.record_error("freezer", "crc", "crc check failed".to_string());
+        readings.alerts = vec![alert::Alert::new("fermenter", Kind::High, 18.0, 18.5)];
+
+        let snapshot = snapshot(&readings);
+        assert_eq!(snapshot.readings[0].probe, "fermenter");
+        assert_eq!(snapshot.readings[0].temperature, Some(18.5));
+        assert_eq!(snapshot.readings[1].temperature, None);
+
+        let freezer = &snapshot.probes[1];
+        assert_eq!(freezer.failures, 1);
+        assert_eq!(freezer.error_type.as_deref(), Some("crc"));
+        assert_eq!(freezer.last_temperature, None);
+        assert!(snapshot.probes[0].last_read.is_some());
+
+        assert_eq!(snapshot.alerts[0].kind, "high");
+        assert_eq!(snapshot.alerts[0].state, "pending");
+    }
+}
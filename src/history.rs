@@ -0,0 +1,362 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// How long readings are kept as they were taken.
+const RAW_RETENTION: Duration = Duration::from_secs(3600);
+/// How long per-minute averages are kept, the longest range there is.
+const RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+const BUCKET: Duration = Duration::from_secs(60);
+/// How much minutes are rolled up into to keep them across a restart.
+const ROLLUP: Duration = Duration::from_secs(3600);
+
+/// A stretch of time to chart, back from now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Range {
+    Hour,
+    Day,
+    Week,
+}
+
+impl Range {
+    pub const ALL: [Range; 3] = [Range::Hour, Range::Day, Range::Week];
+
+    pub fn parse(s: &str) -> Option<Range> {
+        Range::ALL.into_iter().find(|r| r.as_str() == s)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Range::Hour => "1h",
+            Range::Day => "24h",
+            Range::Week => "7d",
+        }
+    }
+
+    pub fn duration(self) -> Duration {
+        match self {
+            Range::Hour => RAW_RETENTION,
+            Range::Day => Duration::from_secs(24 * 3600),
+            Range::Week => RETENTION,
+        }
+    }
+}
+
+/// The lowest, highest and average of a probe's readings over a while.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+/// Consecutive minutes rolled up into one, e.g. an hour of them kept
+/// across a restart.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rollup {
+    /// when the first minute started, in seconds since the unix epoch
+    pub start: u64,
+    /// how many minutes had readings
+    pub minutes: u32,
+    pub min: f32,
+    pub max: f32,
+    /// the average of the minutes' averages
+    pub mean: f32,
+}
+
+/// The readings taken in one minute.
+#[derive(Debug, Clone, Copy)]
+struct Minute {
+    start: SystemTime,
+    sum: f32,
+    count: u32,
+    min: f32,
+    max: f32,
+    /// how many minutes it stands in for, more than one when restored
+    /// from a rollup
+    span: u32,
+}
+
+impl Minute {
+    fn mean(&self) -> f32 {
+        self.sum / self.count as f32
+    }
+}
+
+#[derive(Default, Clone)]
+struct Series {
+    raw: VecDeque<(SystemTime, f32)>,
+    /// one per minute, complete ones only
+    minutes: VecDeque<Minute>,
+    /// the minute being averaged
+    bucket: Option<Minute>,
+}
+
+/// Recent readings per probe, kept in memory for the dashboard's charts:
+/// the last hour as read, and a week of per-minute averages.
+#[derive(Default, Clone)]
+pub struct History {
+    series: HashMap<String, Series>,
+}
+
+impl History {
+    /// Adds a reading, dropping any that have aged out.
+    pub fn record(&mut self, name: &str, temp: f32, at: SystemTime) {
+        let series = self.series.entry(name.to_string()).or_default();
+        let since_epoch = at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let start = SystemTime::UNIX_EPOCH
+            + Duration::from_secs(since_epoch.as_secs() / BUCKET.as_secs() * BUCKET.as_secs());
+        series.bucket = match series.bucket {
+            Some(m) if m.start == start => Some(Minute {
+                sum: m.sum + temp,
+                count: m.count + 1,
+                min: m.min.min(temp),
+                max: m.max.max(temp),
+                ..m
+            }),
+            finished => {
+                series.minutes.extend(finished);
+                Some(Minute {
+                    start,
+                    sum: temp,
+                    count: 1,
+                    min: temp,
+                    max: temp,
+                    span: 1,
+                })
+            }
+        };
+        series.raw.push_back((at, temp));
+
+        let cutoff = |retention| at.checked_sub(retention).unwrap_or(SystemTime::UNIX_EPOCH);
+        let raw_cutoff = cutoff(RAW_RETENTION);
+        while series.raw.front().is_some_and(|(t, _)| *t < raw_cutoff) {
+            series.raw.pop_front();
+        }
+        let cutoff = cutoff(RETENTION);
+        while series.minutes.front().is_some_and(|m| m.start < cutoff) {
+            series.minutes.pop_front();
+        }
+    }
+
+    /// A probe's readings from `from` onwards, oldest first: per-minute
+    /// averages up to the last hour, then the readings themselves.
+    pub fn since(&self, name: &str, from: SystemTime) -> Vec<(SystemTime, f32)> {
+        let Some(series) = self.series.get(name) else {
+            return Vec::new();
+        };
+        let raw_from = series.raw.front().map(|(t, _)| *t);
+        series
+            .minutes
+            .iter()
+            .map(|m| (m.start, m.mean()))
+            .filter(|(t, _)| raw_from.is_none_or(|raw_from| *t < raw_from))
+            .chain(series.raw.iter().copied())
+            .filter(|(t, _)| *t >= from)
+            .collect()
+    }
+
+    /// A probe's lowest, highest and average readings in the minutes from
+    /// `from` onwards, the current one included. Each minute counts the
+    /// same towards the average however many readings it had.
+    pub fn summary(&self, name: &str, from: SystemTime) -> Option<Summary> {
+        let series = self.series.get(name)?;
+        let minutes: Vec<_> = series
+            .minutes
+            .iter()
+            .chain(&series.bucket)
+            .filter(|m| m.start >= from)
+            .collect();
+        if minutes.is_empty() {
+            return None;
+        }
+        Some(Summary {
+            min: minutes.iter().map(|m| m.min).fold(f32::INFINITY, f32::min),
+            max: minutes
+                .iter()
+                .map(|m| m.max)
+                .fold(f32::NEG_INFINITY, f32::max),
+            mean: minutes
+                .iter()
+                .map(|m| m.mean() * m.span as f32)
+                .sum::<f32>()
+                / minutes.iter().map(|m| m.span).sum::<u32>() as f32,
+        })
+    }
+
+    /// A probe's minutes from `from` onwards, the current one included,
+    /// rolled up an hour at a time.
+    pub fn rollups(&self, name: &str, from: SystemTime) -> Vec<Rollup> {
+        let Some(series) = self.series.get(name) else {
+            return Vec::new();
+        };
+        let mut rollups: Vec<Rollup> = Vec::new();
+        for m in series.minutes.iter().chain(&series.bucket) {
+            if m.start < from {
+                continue;
+            }
+            let start = m
+                .start
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let start = start / ROLLUP.as_secs() * ROLLUP.as_secs();
+            match rollups.last_mut() {
+                Some(r) if r.start == start => {
+                    r.mean = (r.mean * r.minutes as f32 + m.mean() * m.span as f32)
+                        / (r.minutes + m.span) as f32;
+                    r.minutes += m.span;
+                    r.min = r.min.min(m.min);
+                    r.max = r.max.max(m.max);
+                }
+                _ => rollups.push(Rollup {
+                    start,
+                    minutes: m.span,
+                    min: m.min,
+                    max: m.max,
+                    mean: m.mean(),
+                }),
+            }
+        }
+        rollups
+    }
+
+    /// Puts back a probe's rollups from before a restart, ahead of any
+    /// minutes recorded since.
+    pub fn restore(&mut self, name: &str, rollups: &[Rollup]) {
+        let series = self.series.entry(name.to_string()).or_default();
+        let first = series
+            .minutes
+            .front()
+            .or(series.bucket.as_ref())
+            .map(|m| m.start);
+        for r in rollups.iter().rev() {
+            let start = SystemTime::UNIX_EPOCH + Duration::from_secs(r.start);
+            if first.is_some_and(|first| start >= first) || r.minutes == 0 {
+                continue;
+            }
+            series.minutes.push_front(Minute {
+                start,
+                sum: r.mean,
+                count: 1,
+                min: r.min,
+                max: r.max,
+                span: r.minutes,
+            });
+        }
+    }
+
+    /// Moves a probe's readings over to a new display name.
+    pub fn rename(&mut self, old: &str, new: &str) {
+        if let Some(series) = self.series.remove(old) {
+            self.series.insert(new.to_string(), series);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retention() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut history = History::default();
+        for i in 0..=4 {
+            history.record("freezer", -18.0 - i as f32, at(i * 1200));
+        }
+
+        // the first reading is over an hour older than the last, so it's
+        // only kept as its minute's average
+        let all = history.since("freezer", SystemTime::UNIX_EPOCH);
+        assert_eq!(all.len(), 5);
+        assert_eq!(all[0], (at(0), -18.0));
+        assert_eq!(all[1], (at(1200), -19.0));
+        assert_eq!(history.since("freezer", at(3600)).len(), 2);
+
+        history.rename("freezer", "chest_freezer");
+        assert!(history.since("freezer", SystemTime::UNIX_EPOCH).is_empty());
+        assert_eq!(history.since("chest_freezer", at(0)).len(), 5);
+    }
+
+    #[test]
+    fn test_minute_averages() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut history = History::default();
+        // four readings a minute for two hours
+        for i in 0..480 {
+            let temp = if i % 2 == 0 { 20.0 } else { 21.0 };
+            history.record("room", temp, at(i * 15));
+        }
+
+        let points = history.since("room", SystemTime::UNIX_EPOCH);
+        let (minutes, raw) = points.split_at(points.len() - 241);
+        assert_eq!(minutes.len(), 60);
+        assert!(minutes.iter().all(|(_, t)| *t == 20.5));
+        assert_eq!(minutes[1].0, at(60));
+        assert_eq!(raw[0].0, at(3585));
+
+        // a week later, only the week's worth is left
+        history.record("room", 20.0, at(7 * 24 * 3600 + 1800));
+        let points = history.since("room", SystemTime::UNIX_EPOCH);
+        assert_eq!(points.first().unwrap().0, at(1800));
+    }
+
+    #[test]
+    fn test_summary() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut history = History::default();
+        assert_eq!(history.summary("fridge", start), None);
+
+        // a minute of four readings, then one of a single reading
+        for (secs, temp) in [(0, 3.0), (15, 5.0), (30, 4.0), (45, 4.0), (60, 6.0)] {
+            history.record("fridge", temp, at(secs));
+        }
+        let summary = history.summary("fridge", start).unwrap();
+        assert_eq!(summary.min, 3.0);
+        assert_eq!(summary.max, 6.0);
+        assert_eq!(summary.mean, 5.0);
+        assert_eq!(history.summary("fridge", at(60)).unwrap().min, 6.0);
+    }
+
+    #[test]
+    fn test_rollups() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut history = History::default();
+        // a reading a minute for two hours, 20 then 22 in the first
+        for i in 0..120 {
+            let temp = if i < 60 { 20.0 } else { 22.0 } + (i % 2) as f32;
+            history.record("room", temp, at(i * 60));
+        }
+        let rollups = history.rollups("room", start);
+        assert_eq!(rollups.len(), 2);
+        assert_eq!(rollups[0].minutes, 60);
+        assert_eq!((rollups[0].min, rollups[0].max), (20.0, 21.0));
+        assert_eq!(rollups[1].mean, 22.5);
+
+        // restored after a restart, the summary is the same
+        let mut restored = History::default();
+        restored.restore("room", &rollups);
+        assert_eq!(
+            restored.summary("room", start),
+            history.summary("room", start)
+        );
+        assert!(restored.summary("room", at(3600)).unwrap().min >= 22.0);
+        // with an hour's points on the charts
+        assert_eq!(restored.since("room", start).len(), 2);
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(Range::parse("24h"), Some(Range::Day));
+        assert_eq!(Range::parse("2d"), None);
+        assert_eq!(Range::Week.duration(), RETENTION);
+    }
+}
@@ -1,43 +1,192 @@
-use std::collections::HashMap;
-use time;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-pub fn generate_temperature_page(temps: &HashMap<String, Option<f32>>) -> String {
+use minijinja::{Value, context};
+use time::OffsetDateTime;
+use time::macros::format_description;
+use time_tz::{Offset, OffsetDateTimeExt, TimeZone, Tz};
+
+use crate::config::{ColorBands, ColorsConfig, Config, LayoutConfig, ThemeConfig, ThemeMode, Unit};
+use crate::history::Range;
+use crate::schedule;
+use crate::state::Readings;
+use crate::template::Template;
+
+const SPARKLINE_WIDTH: f32 = 120.0;
+const SPARKLINE_HEIGHT: f32 = 30.0;
+const SPARKLINE_SPAN: Duration = Duration::from_secs(3600);
+/// How far back the dashboard's min, max and average go.
+const SUMMARY_SPAN: Duration = Duration::from_secs(24 * 3600);
+const CHART_WIDTH: f32 = 720.0;
+const CHART_HEIGHT: f32 = 200.0;
+
+/// The dashboard's settings from the config; the unit comes with each
+/// request.
+#[derive(Default)]
+pub struct Dashboard {
+    /// consecutive failures before a probe is marked down
+    pub down_after: u32,
+    pub colors: ColorsConfig,
+    pub theme: ThemeConfig,
+    pub layout: LayoutConfig,
+    /// what times are shown in
+    pub timezone: Option<&'static Tz>,
+}
+
+impl Dashboard {
+    pub fn from_config(config: &Config) -> Self {
+        Dashboard {
+            down_after: config.settings.down_after,
+            colors: config.colors.clone(),
+            theme: config.theme.clone(),
+            layout: config.layout.clone(),
+            timezone: schedule::zone(config.settings.timezone.as_deref()),
+        }
+    }
+}
+
+/// Keeps groups folded or unfolded across the dashboard's refreshes.
+const GROUP_SCRIPT: &str = r#"
+for (const input of document.querySelectorAll("tr.group input")) {
+    const key = "tempmon-group-" + input.dataset.group;
+    const saved = localStorage.getItem(key);
+    if (saved !== null) input.checked = saved === "open";
+    input.addEventListener("change", () =>
+        localStorage.setItem(key, input.checked ? "open" : "closed"));
+}
+"#;
+
+/// Nord's polar night, the dashboard's original look.
+const DARK: &[(&str, &str)] = &[
+    ("background", "#3b4252"),
+    ("surface", "#2e3440"),
+    ("header", "#434c5e"),
+    ("text", "#eceff4"),
+    ("muted", "#d8dee9"),
+    ("border", "#4c566a"),
+    ("accent", "#88c0d0"),
+    ("accent_hover", "#81a1c1"),
+];
+/// Nord's snow storm, with the frost blues darkened enough to read on it.
+const LIGHT: &[(&str, &str)] = &[
+    ("background", "#e5e9f0"),
+    ("surface", "#eceff4"),
+    ("header", "#d8dee9"),
+    ("text", "#2e3440"),
+    ("muted", "#3b4252"),
+    ("border", "#d8dee9"),
+    ("accent", "#5e81ac"),
+    ("accent_hover", "#81a1c1"),
+];
+
+/// Shared by the dashboard's pages, coloured by `theme_css`.
+const STYLE: &str = include_str!("../assets/dashboard.css");
+
+/// The css variables for `theme`'s palette, with its custom colours on top.
+fn theme_css(theme: &ThemeConfig) -> String {
+    let root = |palette: &[(&str, &str)]| {
+        let vars: String = palette
+            .iter()
+            .map(|(name, color)| {
+                let color = theme.colors.get(*name).map_or(*color, String::as_str);
+                format!(" --{}: {};", name, color)
+            })
+            .collect();
+        format!(":root {{{} }}", vars)
+    };
+    match theme.mode {
+        ThemeMode::Dark => format!("\n:root {{ color-scheme: dark; }}\n{}\n", root(DARK)),
+        ThemeMode::Light => format!("\n:root {{ color-scheme: light; }}\n{}\n", root(LIGHT)),
+        ThemeMode::Auto => format!(
+            "\n:root {{ color-scheme: dark light; }}\n{}\n@media (prefers-color-scheme: light) {{ {} }}\n",
+            root(DARK),
+            root(LIGHT)
+        ),
+    }
+}
+
+/// A colour from `theme`'s palette, its custom one if it has one. Auto
+/// counts as dark, the default.
+fn palette_color<'a>(theme: &'a ThemeConfig, name: &str) -> &'a str {
+    let palette = if theme.mode == ThemeMode::Light {
+        LIGHT
+    } else {
+        DARK
+    };
+    theme.colors.get(name).map_or_else(
+        || {
+            palette
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map_or("", |(_, color)| color)
+        },
+        String::as_str,
+    )
+}
+
+/// The `<head>` tags that make the dashboard installable as an app.
+fn app_head(theme: &ThemeConfig) -> String {
+    format!(
+        r#"
+    <link rel="manifest" href="/manifest.webmanifest" crossorigin="use-credentials">
+    <link rel="icon" href="/static/icon.svg" type="image/svg+xml">
+    <link rel="apple-touch-icon" href="/static/icon-180.png">
+    <meta name="theme-color" content="{}">
+    <script>
+        if ("serviceWorker" in navigator) navigator.serviceWorker.register("/sw.js");
+    </script>"#,
+        palette_color(theme, "surface")
+    )
+}
+
+/// The web app manifest, so the dashboard can be added to a home screen.
+pub fn manifest(dashboard: &Dashboard) -> String {
+    serde_json::json!({
+        "name": "Temperature Monitor",
+        "short_name": "TempMon",
+        "start_url": "/",
+        "scope": "/",
+        "display": "standalone",
+        "background_color": palette_color(&dashboard.theme, "background"),
+        "theme_color": palette_color(&dashboard.theme, "surface"),
+        "icons": [
+            { "src": "/static/icon.svg", "sizes": "any", "type": "image/svg+xml" },
+            { "src": "/static/icon-192.png", "sizes": "192x192", "type": "image/png" },
+            { "src": "/static/icon-512.png", "sizes": "512x512", "type": "image/png" },
+        ],
+    })
+    .to_string()
+}
+
+/// Renders the dashboard. A probe that has failed fewer than `down_after`
+/// reads in a row keeps showing its last good value, greyed out with its age.
+pub fn generate_temperature_page(readings: &Readings, unit: Unit, dashboard: &Dashboard) -> String {
+    let layout = &dashboard.layout;
+    let now = SystemTime::now();
+    let day_from = now.checked_sub(SUMMARY_SPAN).unwrap_or(now);
+    let names: Vec<&str> = readings.temps.keys().map(String::as_str).collect();
     let mut rows = String::new();
-    let mut temp_vec: Vec<_> = temps.iter().collect();
-    temp_vec.sort_by_key(|(name, _)| name.as_str());
-
-    for (name, temp) in temp_vec {
-        let temp_display = match temp {
-            Some(t) => {
-                // <22 blue, 22-38 green, 38-42 yellow, >=42 red
-                let color = if *t < 22.0 {
-                    "#88c0d0" // nord8 - frost blue
-                } else if *t < 38.0 {
-                    "#a3be8c" // nord14 - aurora green
-                } else if *t < 42.0 {
-                    "#ebcb8b" // nord13 - aurora yellow
-                } else {
-                    "#bf616a" // nord11 - aurora red
-                };
-                format!(
-                    "<span style='color: {}; font-size: 2em; font-weight: bold;'>{:.2}°C</span>",
-                    color, t
-                )
-            }
-            None => "<span style='color: #d08770; font-style: italic;'>Error</span>".to_string(),
-        };
 
-        rows.push_str(&format!(
-            "<tr><td style='padding: 15px; border-bottom: 1px solid #4c566a;'>{}</td>\
-             <td style='padding: 15px; border-bottom: 1px solid #4c566a; text-align: right;'>{}</td></tr>",
-            name, temp_display
-        ));
+    for (group, names) in layout.arrange(&names) {
+        rows.push_str("<tbody>");
+        // without any groups there's nothing to head
+        if !layout.groups.is_empty() {
+            let (name, collapsed) = group.map_or(("Other", false), |g| (&g.name, g.collapsed));
+            let name = escape(name);
+            rows.push_str(&format!(
+                "<tr class='group'><th colspan='6'><label>\
+                 <input type='checkbox' data-group='{}'{}> {}</label></th></tr>",
+                name,
+                if collapsed { "" } else { " checked" },
+                name
+            ));
+        }
+        for name in names {
+            rows.push_str(&row(readings, name, unit, dashboard, now, day_from));
+        }
+        rows.push_str("</tbody>");
     }
 
-    // truncate the timestamp
-    let datetime = time::OffsetDateTime::now_utc()
-        .replace_nanosecond(0)
-        .unwrap();
+    let datetime = format_time(readings.updated, dashboard.timezone);
 
     format!(
         r#"<!DOCTYPE html>
@@ -51,60 +200,9 @@ pub fn generate_temperature_page(temps: &HashMap<String, Option<f32>>) -> String
     <meta name="apple-mobile-web-app-capable" content="yes">
     <meta name="apple-mobile-web-app-status-bar-style" content="black-translucent">
     <meta name="apple-mobile-web-app-title" content="TempMon">
-
+{}
     <title>Temperature Monitor</title>
-    <style>
-        body {{
-            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, sans-serif;
-            max-width: 800px;
-            margin: 40px auto;
-            padding: 20px;
-            background: #3b4252;
-            color: #eceff4;
-        }}
-        .container {{
-            background: #2e3440;
-            border-radius: 8px;
-            box-shadow: 0 2px 8px rgba(0,0,0,0.3);
-            padding: 30px;
-        }}
-        h1 {{
-            color: #eceff4;
-            margin-top: 0;
-            border-bottom: 3px solid #88c0d0;
-            padding-bottom: 10px;
-        }}
-        table {{
-            width: 100%;
-            border-collapse: collapse;
-            margin-top: 20px;
-        }}
-        th {{
-            text-align: left;
-            padding: 15px;
-            background: #434c5e;
-            color: #eceff4;
-            font-weight: 600;
-        }}
-        td {{
-            color: #d8dee9;
-        }}
-        .footer {{
-            margin-top: 30px;
-            padding-top: 20px;
-            border-top: 1px solid #4c566a;
-            color: #d8dee9;
-            font-size: 0.9em;
-        }}
-        .footer a {{
-            color: #88c0d0;
-            text-decoration: none;
-        }}
-        .footer a:hover {{
-            color: #81a1c1;
-            text-decoration: underline;
-        }}
-    </style>
+    <style>{}{}    </style>
 </head>
 <body>
     <div class="container">
@@ -113,20 +211,820 @@ pub fn generate_temperature_page(temps: &HashMap<String, Option<f32>>) -> String
             <thead>
                 <tr>
                     <th>Probe</th>
+                    <th style="text-align: center;">Last hour</th>
                     <th style="text-align: right;">Temperature</th>
+                    <th style="text-align: right;">24h Min</th>
+                    <th style="text-align: right;">24h Avg</th>
+                    <th style="text-align: right;">24h Max</th>
                 </tr>
             </thead>
-            <tbody>
-                {}
-            </tbody>
+            {}
         </table>
         <div class="footer">
-            Last updated: {} UTC (auto-refresh every 15s)<br>
-            <a href="/metrics">Prometheus Metrics</a> | <a href="/health">Health Check</a>
+            Last updated: {} (auto-refresh every 15s)<br>
+            <a href="/history">History</a> | <a href="/export?unit={}">Export CSV</a> | <a href="/metrics">Prometheus Metrics</a> | <a href="/health">Health Check</a>
+        </div>
+    </div>
+    <script>{}</script>
+</body>
+</html>"#,
+        app_head(&dashboard.theme),
+        theme_css(&dashboard.theme),
+        STYLE,
+        rows,
+        datetime,
+        unit.as_str(),
+        GROUP_SCRIPT
+    )
+}
+
+/// A probe's row on the dashboard.
+fn row(
+    readings: &Readings,
+    name: &str,
+    unit: Unit,
+    dashboard: &Dashboard,
+    now: SystemTime,
+    day_from: SystemTime,
+) -> String {
+    let age = |at: SystemTime| format_age(now.duration_since(at).unwrap_or_default());
+    let mut temp_display = match readings.temps[name] {
+        Some(t) => {
+            format!(
+                "<span style='color: {}; font-size: 2em; font-weight: bold;'>{:.2}{}</span><br>\
+                 <span class='age'>{} ago</span>",
+                band_color(t, dashboard.colors.bands(name)),
+                unit.convert(t),
+                unit.symbol(),
+                readings
+                    .last_good
+                    .get(name)
+                    .map_or_else(|| age(now), |(_, at)| age(*at))
+            )
+        }
+        None => match readings.quarantined.get(name) {
+            Some(q) => format!(
+                "<span style='color: #bf616a; font-style: italic;'>Quarantined since {}</span>",
+                format_time(q.since, dashboard.timezone)
+            ),
+            None => match readings.last_good(name, dashboard.down_after) {
+                Some((t, at)) => format!(
+                    "<span style='color: #4c566a; font-size: 2em; font-weight: bold;'>{:.2}{}</span><br>\
+                     <span style='color: #4c566a; font-style: italic;'>{} ago</span>",
+                    unit.convert(t),
+                    unit.symbol(),
+                    age(at)
+                ),
+                None => "<span style='color: #d08770; font-style: italic;'>Down</span>".to_string(),
+            },
+        },
+    };
+    if let Some((error_type, message)) = readings.errors.get(name) {
+        temp_display.push_str(&format!(
+            "<br><span class='badge' title='{}'>{} &times;{}</span>",
+            escape(message),
+            error_type,
+            readings.failures.get(name).copied().unwrap_or(0)
+        ));
+    }
+    let name_display = match readings.silenced.get(name) {
+        Some(until) => format!(
+            "{}<br><span style='color: #81a1c1; font-size: 0.8em;'>alerts silenced until {}</span>",
+            escape(name),
+            format_time(*until, dashboard.timezone)
+        ),
+        None => escape(name),
+    };
+
+    let from = now.checked_sub(SPARKLINE_SPAN).unwrap_or(now);
+    let trend = sparkline(&history(readings, name, from, unit), from, now, unit);
+
+    let mut row = format!(
+        "<tr><td style='padding: 15px; border-bottom: 1px solid var(--border);'>{}</td>\
+         <td style='padding: 15px; border-bottom: 1px solid var(--border); text-align: center;'>{}</td>\
+         <td style='padding: 15px; border-bottom: 1px solid var(--border); text-align: right;'>{}</td>",
+        name_display, trend, temp_display
+    );
+    let summary = readings.history.read().unwrap().summary(name, day_from);
+    for value in [
+        summary.map(|s| s.min),
+        summary.map(|s| s.mean),
+        summary.map(|s| s.max),
+    ] {
+        let value = value.map_or(String::new(), |t| {
+            format!("{:.1}{}", unit.convert(t), unit.symbol())
+        });
+        row.push_str(&format!(
+            "<td style='padding: 15px; border-bottom: 1px solid var(--border); text-align: right;'>{}</td>",
+            value
+        ));
+    }
+    row.push_str("</tr>");
+    row
+}
+
+/// Renders the dashboard with a user's template instead. It gets `probes`,
+/// in the layout's order, each with its `name`, `group`, `temperature`
+/// (the last good one while `status` is "stale"), `color`, `age` of the
+/// last good reading in seconds, the last `error` while failing,
+/// `silenced_until`, `quarantined_since`, a `sparkline` svg and the last
+/// 24h's `day.min`, `day.mean` and `day.max`, along with the `unit`, its
+/// `symbol`, when the readings were `updated` and the built-in `style`.
+/// Times are unix timestamps.
+pub fn render_template(
+    template: &Template,
+    readings: &Readings,
+    unit: Unit,
+    dashboard: &Dashboard,
+) -> Result<String, minijinja::Error> {
+    let now = SystemTime::now();
+    let from = now.checked_sub(SPARKLINE_SPAN).unwrap_or(now);
+    let day_from = now.checked_sub(SUMMARY_SPAN).unwrap_or(now);
+    // to the dashboard's two places, not f32's noise as an f64
+    let round = |t: f32| (f64::from(unit.convert(t)) * 100.0).round() / 100.0;
+    let names: Vec<&str> = readings.temps.keys().map(String::as_str).collect();
+
+    let probes: Vec<Value> = dashboard
+        .layout
+        .arrange(&names)
+        .into_iter()
+        .flat_map(|(group, names)| names.into_iter().map(move |name| (group, name)))
+        .map(|(group, name)| {
+            let (temperature, status) = match readings.temps[name] {
+                Some(t) => (Some(t), "ok"),
+                None if readings.quarantined.contains_key(name) => (None, "quarantined"),
+                None => match readings.last_good(name, dashboard.down_after) {
+                    Some((t, _)) => (Some(t), "stale"),
+                    None => (None, "down"),
+                },
+            };
+            context! {
+                name,
+                group => group.map(|g| g.name.as_str()),
+                temperature => temperature.map(round),
+                status,
+                color => temperature
+                    .filter(|_| status == "ok")
+                    .map(|t| band_color(t, dashboard.colors.bands(name))),
+                age => readings
+                    .last_good
+                    .get(name)
+                    .map(|(_, at)| secs(now).saturating_sub(secs(*at))),
+                error => readings.errors.get(name).map(|(error_type, message)| context! {
+                    type => error_type,
+                    message,
+                    count => readings.failures.get(name).copied().unwrap_or(0),
+                }),
+                silenced_until => readings.silenced.get(name).map(|t| secs(*t)),
+                quarantined_since => readings.quarantined.get(name).map(|q| secs(q.since)),
+                day => readings.history.read().unwrap().summary(name, day_from).map(|s| context! {
+                    min => round(s.min),
+                    mean => round(s.mean),
+                    max => round(s.max),
+                }),
+                sparkline => Value::from_safe_string(sparkline(
+                    &history(readings, name, from, unit),
+                    from,
+                    now,
+                    unit,
+                )),
+            }
+        })
+        .collect();
+
+    template.render(context! {
+        probes,
+        unit => unit.as_str(),
+        symbol => unit.symbol(),
+        updated => secs(readings.updated),
+        style => Value::from_safe_string(format!("{}{}", theme_css(&dashboard.theme), STYLE)),
+    })
+}
+
+/// The colour of a reading on the dashboard: blue below `cold`, green up
+/// to `warm`, yellow up to `hot` and red from there.
+fn band_color(temp: f32, bands: ColorBands) -> &'static str {
+    if temp < bands.cold {
+        "#88c0d0" // nord8 - frost blue
+    } else if temp < bands.warm {
+        "#a3be8c" // nord14 - aurora green
+    } else if temp < bands.hot {
+        "#ebcb8b" // nord13 - aurora yellow
+    } else {
+        "#bf616a" // nord11 - aurora red
+    }
+}
+
+fn secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// An inline svg line of `points` between `from` and `to`. Empty until
+/// there are two points to join.
+fn sparkline(points: &[(SystemTime, f32)], from: SystemTime, to: SystemTime, unit: Unit) -> String {
+    let Some((min, max)) = bounds(points).filter(|_| points.len() > 1) else {
+        return String::new();
+    };
+    format!(
+        "<svg width='{w}' height='{h}' viewBox='0 0 {w} {h}' role='img' \
+         aria-label='{min:.1} to {max:.1}{symbol} over the last hour'>\
+         <polyline points='{points}' fill='none' style='stroke: var(--accent)' stroke-width='1.5'/></svg>",
+        w = SPARKLINE_WIDTH,
+        h = SPARKLINE_HEIGHT,
+        min = min,
+        max = max,
+        symbol = unit.symbol(),
+        points = plot(points, from, to, SPARKLINE_WIDTH, SPARKLINE_HEIGHT)
+    )
+}
+
+/// A probe's history from `from` onwards, in `unit`.
+fn history(
+    readings: &Readings,
+    name: &str,
+    from: SystemTime,
+    unit: Unit,
+) -> Vec<(SystemTime, f32)> {
+    let mut points = readings.history.read().unwrap().since(name, from);
+    for (_, t) in &mut points {
+        *t = unit.convert(*t);
+    }
+    points
+}
+
+/// The lowest and highest temperatures in `points`.
+fn bounds(points: &[(SystemTime, f32)]) -> Option<(f32, f32)> {
+    points
+        .iter()
+        .map(|(_, t)| *t)
+        .fold(None, |bounds, t| match bounds {
+            Some((lo, hi)) => Some((t.min(lo), t.max(hi))),
+            None => Some((t, t)),
+        })
+}
+
+/// Polyline coordinates for `points` between `from` and `to`, scaled to
+/// fill a `width` by `height` box.
+fn plot(
+    points: &[(SystemTime, f32)],
+    from: SystemTime,
+    to: SystemTime,
+    width: f32,
+    height: f32,
+) -> String {
+    let Some((min, max)) = bounds(points) else {
+        return String::new();
+    };
+    let span = to.duration_since(from).unwrap_or_default().as_secs_f32();
+    // a flat line sits in the middle rather than along the bottom
+    let range = if max > min { max - min } else { 1.0 };
+    let mid = if max > min { 0.0 } else { 0.5 };
+    let coords: Vec<String> = points
+        .iter()
+        .map(|(at, t)| {
+            let age = at.duration_since(from).unwrap_or_default().as_secs_f32();
+            let x = if span > 0.0 { age / span } else { 1.0 } * width;
+            // svg y grows downwards, and leave a pixel for the stroke
+            let y = 1.0 + (1.0 - mid - (t - min) / range) * (height - 2.0);
+            format!("{:.1},{:.1}", x, y)
+        })
+        .collect();
+    coords.join(" ")
+}
+
+/// Averages `points` down to at most `buckets`, evenly spaced between
+/// `from` and `to`, so a week of readings doesn't become a huge page.
+fn downsample(
+    points: &[(SystemTime, f32)],
+    from: SystemTime,
+    to: SystemTime,
+    buckets: usize,
+) -> Vec<(SystemTime, f32)> {
+    if points.len() <= buckets {
+        return points.to_vec();
+    }
+    let span = to.duration_since(from).unwrap_or_default().as_secs_f64();
+    let mut sums = vec![(0.0, 0.0, 0u32); buckets];
+    for (at, t) in points {
+        let age = at.duration_since(from).unwrap_or_default().as_secs_f64();
+        let i = ((age / span * buckets as f64) as usize).min(buckets - 1);
+        sums[i].0 += age;
+        sums[i].1 += f64::from(*t);
+        sums[i].2 += 1;
+    }
+    sums.into_iter()
+        .filter(|(_, _, count)| *count > 0)
+        .map(|(age, t, count)| {
+            let n = f64::from(count);
+            (from + Duration::from_secs_f64(age / n), (t / n) as f32)
+        })
+        .collect()
+}
+
+/// Renders `/kiosk`: a tile per probe with just its name and temperature,
+/// sized to fill the screen, for a wall-mounted display.
+pub fn generate_kiosk_page(readings: &Readings, unit: Unit, dashboard: &Dashboard) -> String {
+    let names: Vec<&str> = readings.temps.keys().map(String::as_str).collect();
+    let names: Vec<&str> = dashboard
+        .layout
+        .arrange(&names)
+        .into_iter()
+        .flat_map(|(_, names)| names)
+        .collect();
+    // as square a grid as the probes fill
+    let columns = (names.len() as f32).sqrt().ceil().max(1.0) as usize;
+    let rows = names.len().div_ceil(columns).max(1);
+
+    let mut tiles = String::new();
+    for name in names {
+        let (value, color) = match readings.temps[name] {
+            Some(t) => (
+                format!("{:.1}{}", unit.convert(t), unit.symbol()),
+                band_color(t, dashboard.colors.bands(name)),
+            ),
+            None => match readings.last_good(name, dashboard.down_after) {
+                Some((t, _)) => (
+                    format!("{:.1}{}", unit.convert(t), unit.symbol()),
+                    "#4c566a",
+                ),
+                None => ("Down".to_string(), "#d08770"),
+            },
+        };
+        tiles.push_str(&format!(
+            "<div class='tile'><div class='name'>{}</div>\
+             <div class='value' style='color: {};'>{}</div></div>",
+            escape(name),
+            color,
+            value
+        ));
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1, viewport-fit=cover">
+    <meta http-equiv="refresh" content="15">
+    <title>Temperature Monitor</title>
+    <style>{}
+html, body {{
+    margin: 0;
+    height: 100%;
+    background: var(--background);
+    color: var(--text);
+    font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, sans-serif;
+    overflow: hidden;
+}}
+.tiles {{
+    display: grid;
+    grid-template-columns: repeat({columns}, 1fr);
+    grid-template-rows: repeat({rows}, 1fr);
+    gap: 1vmin;
+    height: 100%;
+    padding: 1vmin;
+    box-sizing: border-box;
+    font-size: min(calc(100vw / {columns}), calc(100vh / {rows}));
+}}
+.tile {{
+    background: var(--surface);
+    display: flex;
+    flex-direction: column;
+    align-items: center;
+    justify-content: center;
+    min-width: 0;
+}}
+.name {{
+    font-size: 0.08em;
+    color: var(--muted);
+}}
+.value {{
+    font-size: 0.22em;
+    font-weight: bold;
+}}
+    </style>
+</head>
+<body>
+    <div class="tiles">{}</div>
+</body>
+</html>"#,
+        theme_css(&dashboard.theme),
+        tiles,
+        columns = columns,
+        rows = rows,
+    )
+}
+
+/// Renders `/history`: a chart per probe of its readings over `range`.
+pub fn generate_history_page(
+    readings: &Readings,
+    range: Range,
+    unit: Unit,
+    dashboard: &Dashboard,
+) -> String {
+    let now = SystemTime::now();
+    let from = now.checked_sub(range.duration()).unwrap_or(now);
+    let names: Vec<&str> = readings.temps.keys().map(String::as_str).collect();
+    let names = dashboard
+        .layout
+        .arrange(&names)
+        .into_iter()
+        .flat_map(|(_, names)| names);
+
+    let links: Vec<String> = Range::ALL
+        .iter()
+        .map(|r| {
+            let class = if *r == range { " class='current'" } else { "" };
+            format!(
+                "<a href='/history?range={0}&amp;unit={1}'{2}>{0}</a>",
+                r.as_str(),
+                unit.as_str(),
+                class
+            )
+        })
+        .collect();
+
+    let mut charts = String::new();
+    for name in names {
+        let points = downsample(
+            &history(readings, name, from, unit),
+            from,
+            now,
+            CHART_WIDTH as usize,
+        );
+        let chart = match bounds(&points).filter(|_| points.len() > 1) {
+            Some((min, max)) => format!(
+                "<svg class='chart' width='100%' viewBox='0 -15 {w} {h}' role='img' \
+                 aria-label='{name} over the last {range}'>\
+                 <rect y='-15' width='{w}' height='{h}' style='fill: var(--background)' rx='4'/>\
+                 <polyline points='{points}' fill='none' style='stroke: var(--accent)' stroke-width='1.5'/>\
+                 <text x='4' y='-3'>{max:.1}{symbol}</text>\
+                 <text x='4' y='{bottom}'>{min:.1}{symbol}</text>\
+                 <text x='{w}' y='{bottom}' dx='-4' text-anchor='end'>{range} to {time}</text>\
+                 </svg>",
+                w = CHART_WIDTH,
+                h = CHART_HEIGHT + 30.0,
+                bottom = CHART_HEIGHT + 12.0,
+                name = escape(name),
+                range = range.as_str(),
+                points = plot(&points, from, now, CHART_WIDTH, CHART_HEIGHT),
+                min = min,
+                max = max,
+                symbol = unit.symbol(),
+                time = format_time(now, dashboard.timezone),
+            ),
+            None => {
+                "<p style='color: #4c566a; font-style: italic;'>No readings yet</p>".to_string()
+            }
+        };
+        charts.push_str(&format!(
+            "<h2>{}</h2>\n        {}\n        ",
+            escape(name),
+            chart
+        ));
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1, viewport-fit=cover">
+    <meta http-equiv="refresh" content="60">{}
+    <title>Temperature History</title>
+    <style>{}{}    </style>
+</head>
+<body>
+    <div class="container">
+        <h1>Temperature History</h1>
+        <div class="ranges">{}</div>
+        {}
+        <div class="footer">
+            <a href="/">Current Readings</a> | <a href="/export?from={}&amp;unit={}">Export CSV</a> | <a href="/metrics">Prometheus Metrics</a>
         </div>
     </div>
 </body>
 </html>"#,
-        rows, datetime
+        app_head(&dashboard.theme),
+        theme_css(&dashboard.theme),
+        STYLE,
+        links.join(""),
+        charts,
+        secs(from),
+        unit.as_str()
     )
 }
+
+/// A time to the second in `timezone`, or UTC without one, e.g.
+/// "2026-10-20 13:00:00 BST".
+fn format_time(time: SystemTime, timezone: Option<&Tz>) -> String {
+    let utc = OffsetDateTime::from(time);
+    let (local, zone) = match timezone {
+        Some(tz) => (
+            utc.to_timezone(tz),
+            tz.get_offset_utc(&utc).name().to_string(),
+        ),
+        None => (utc, "UTC".to_string()),
+    };
+    let format = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+    format!("{} {}", local.format(format).unwrap_or_default(), zone)
+}
+
+/// Escapes text for an html attribute or element.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&#39;")
+        .replace('"', "&quot;")
+}
+
+/// A short, coarse age such as "40s", "5m" or "2h".
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}h", secs / 3600)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Group;
+
+    fn dashboard(down_after: u32) -> Dashboard {
+        Dashboard {
+            down_after,
+            ..Dashboard::default()
+        }
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(Duration::from_secs(42)), "42s");
+        assert_eq!(format_age(Duration::from_secs(150)), "2m");
+        assert_eq!(format_age(Duration::from_secs(7300)), "2h");
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let from = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let at = |secs| from + Duration::from_secs(secs);
+        let to = at(3600);
+
+        assert_eq!(sparkline(&[(at(0), 20.0)], from, to, Unit::Celsius), "");
+        let svg = sparkline(
+            &[(at(0), 20.0), (at(1800), 21.0), (at(3600), 22.0)],
+            from,
+            to,
+            Unit::Celsius,
+        );
+        assert!(svg.contains("points='0.0,29.0 60.0,15.0 120.0,1.0'"));
+        assert!(svg.contains("20.0 to 22.0°C"));
+
+        let flat = sparkline(&[(at(0), 20.0), (at(3600), 20.0)], from, to, Unit::Kelvin);
+        assert!(flat.contains("points='0.0,15.0 120.0,15.0'"));
+        assert!(flat.contains("20.0 to 20.0 K"));
+    }
+
+    #[test]
+    fn test_downsample() {
+        let from = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let at = |secs| from + Duration::from_secs(secs);
+        let points: Vec<_> = (0..100).map(|i| (at(i * 36), (i % 2) as f32)).collect();
+
+        assert_eq!(downsample(&points, from, at(3600), 200).len(), 100);
+        let averaged = downsample(&points, from, at(3600), 10);
+        assert_eq!(averaged.len(), 10);
+        assert_eq!(averaged[0], (at(162), 0.5));
+    }
+
+    #[test]
+    fn test_history_page() {
+        let mut readings = Readings::new(["freezer", "room"]);
+        readings.record("freezer", Some(-18.0));
+        readings.record("freezer", Some(-17.5));
+
+        let page = generate_history_page(&readings, Range::Day, Unit::Celsius, &dashboard(3));
+        assert!(page.contains("<h2>freezer</h2>"));
+        assert!(page.contains("-17.5°C"));
+        assert!(page.contains("<a href='/history?range=24h&amp;unit=celsius' class='current'>"));
+        assert_eq!(page.matches("No readings yet").count(), 1);
+    }
+
+    #[test]
+    fn test_day_summary() {
+        let mut readings = Readings::new(["fridge", "room"]);
+        readings.record("fridge", Some(3.0));
+        readings.record("fridge", Some(5.0));
+
+        let page = generate_temperature_page(&readings, Unit::Celsius, &dashboard(3));
+        assert!(page.contains("24h Avg"));
+        // the average depends on whether the readings straddle a minute
+        assert!(page.contains(">3.0°C</td>"));
+        assert!(page.contains(">5.0°C</td>"));
+    }
+
+    #[test]
+    fn test_color_bands() {
+        let mut readings = Readings::new(["freezer", "vivarium"]);
+        readings.record("freezer", Some(-12.0));
+        readings.record("vivarium", Some(30.0));
+        let mut dashboard = dashboard(3);
+        let page = generate_temperature_page(&readings, Unit::Celsius, &dashboard);
+        // both green would be wrong for the freezer
+        assert_eq!(page.matches("color: #88c0d0; font-size: 2em").count(), 1);
+        assert_eq!(page.matches("color: #a3be8c; font-size: 2em").count(), 1);
+
+        dashboard.colors.probes.insert(
+            "freezer".to_string(),
+            ColorBands {
+                cold: -22.0,
+                warm: -15.0,
+                hot: -10.0,
+            },
+        );
+        let page = generate_temperature_page(&readings, Unit::Celsius, &dashboard);
+        assert_eq!(page.matches("color: #ebcb8b; font-size: 2em").count(), 1);
+        assert_eq!(page.matches("color: #a3be8c; font-size: 2em").count(), 1);
+    }
+
+    #[test]
+    fn test_manifest() {
+        let mut dashboard = dashboard(3);
+        let json: serde_json::Value = serde_json::from_str(&manifest(&dashboard)).unwrap();
+        assert_eq!(json["start_url"], "/");
+        assert_eq!(json["theme_color"], "#2e3440");
+
+        dashboard.theme.mode = ThemeMode::Light;
+        dashboard
+            .theme
+            .colors
+            .insert("surface".to_string(), "white".to_string());
+        let json: serde_json::Value = serde_json::from_str(&manifest(&dashboard)).unwrap();
+        assert_eq!(json["theme_color"], "white");
+        assert_eq!(json["background_color"], "#e5e9f0");
+    }
+
+    #[test]
+    fn test_kiosk_page() {
+        let mut readings = Readings::new(["a", "b", "c", "d", "e"]);
+        readings.record("a", Some(30.25));
+        let page = generate_kiosk_page(&readings, Unit::Celsius, &dashboard(3));
+        assert_eq!(page.matches("class='tile'").count(), 5);
+        // five probes go in a three by two grid
+        assert!(page.contains("repeat(3, 1fr)"));
+        assert!(page.contains("repeat(2, 1fr)"));
+        assert!(page.contains("color: #a3be8c;'>30.2°C"));
+        assert_eq!(page.matches(">Down</div>").count(), 4);
+
+        let page = generate_kiosk_page(&Readings::new([]), Unit::Celsius, &dashboard(3));
+        assert!(page.contains("repeat(1, 1fr)"));
+    }
+
+    #[test]
+    fn test_theme() {
+        let mut theme = ThemeConfig::default();
+        let css = theme_css(&theme);
+        assert!(css.contains("--background: #3b4252;"));
+        assert!(!css.contains("@media"));
+
+        theme.mode = ThemeMode::Auto;
+        theme
+            .colors
+            .insert("accent".to_string(), "#ff8800".to_string());
+        let css = theme_css(&theme);
+        let (dark, light) = css
+            .split_once("@media (prefers-color-scheme: light)")
+            .unwrap();
+        assert!(dark.contains("--background: #3b4252;"));
+        assert!(light.contains("--background: #e5e9f0;"));
+        assert_eq!(css.matches("--accent: #ff8800;").count(), 2);
+    }
+
+    #[test]
+    fn test_render_template() {
+        let mut readings = Readings::new(["freezer", "room"]);
+        readings.record("freezer", Some(-18.0));
+        readings.record("room", Some(21.5));
+        readings.record("room", None);
+
+        let template = Template::compile(
+            "{% for p in probes %}{{ p.name }}={{ p.temperature }}{{ symbol }} \
+             {{ p.status }} {{ p.color or \"-\" }};{% endfor %}"
+                .to_string(),
+        )
+        .unwrap();
+        let html = render_template(&template, &readings, Unit::Fahrenheit, &dashboard(3)).unwrap();
+        assert_eq!(html, "freezer=-0.4°F ok #88c0d0;room=70.7°F stale -;");
+    }
+
+    #[test]
+    fn test_groups() {
+        let readings = Readings::new(["ambient", "fermenter_1", "fermenter_2"]);
+        let mut dashboard = dashboard(3);
+        let page = generate_temperature_page(&readings, Unit::Celsius, &dashboard);
+        assert!(!page.contains("class='group'"));
+
+        dashboard.layout = LayoutConfig {
+            order: Vec::new(),
+            groups: vec![Group {
+                name: "Brewery".to_string(),
+                probes: vec!["fermenter_2".to_string(), "fermenter_1".to_string()],
+                collapsed: true,
+            }],
+        };
+        let page = generate_temperature_page(&readings, Unit::Celsius, &dashboard);
+        let position = |s| page.find(s).unwrap();
+        assert!(position("data-group='Brewery'>") < position("fermenter_2</td>"));
+        assert!(position("fermenter_2</td>") < position("fermenter_1</td>"));
+        assert!(position("data-group='Other' checked>") < position("ambient</td>"));
+    }
+
+    #[test]
+    fn test_names_are_escaped() {
+        // e.g. from a federated instance
+        let name = "pi/<script>alert(1)</script>";
+        let mut readings = Readings::new([name]);
+        readings.record(name, Some(20.0));
+        readings.record(name, Some(20.5));
+        readings
+            .silenced
+            .insert(name.to_string(), SystemTime::now());
+        let mut dashboard = dashboard(3);
+        dashboard.layout.groups = vec![Group {
+            name: "<b>".to_string(),
+            probes: vec![name.to_string()],
+            collapsed: false,
+        }];
+
+        let pages = [
+            generate_temperature_page(&readings, Unit::Celsius, &dashboard),
+            generate_kiosk_page(&readings, Unit::Celsius, &dashboard),
+            generate_history_page(&readings, Range::Day, Unit::Celsius, &dashboard),
+        ];
+        for page in &pages {
+            assert!(!page.contains("<script>alert"));
+            assert!(page.contains("pi/&lt;script&gt;alert(1)&lt;/script&gt;"));
+        }
+        assert!(!pages[0].contains("<b>"));
+    }
+
+    #[test]
+    fn test_silenced_probe() {
+        let mut readings = Readings::new(["freezer", "room"]);
+        let until = SystemTime::UNIX_EPOCH + Duration::from_secs(1792497600);
+        readings.silenced.insert("freezer".to_string(), until);
+
+        let page = generate_temperature_page(&readings, Unit::Celsius, &dashboard(3));
+        assert!(page.contains("freezer<br>"));
+        assert!(page.contains("silenced until 2026-10-20 12:00:00 UTC"));
+        assert_eq!(page.matches("silenced until").count(), 1);
+
+        let dashboard = Dashboard {
+            timezone: schedule::zone(Some("Europe/London")),
+            ..dashboard(3)
+        };
+        let page = generate_temperature_page(&readings, Unit::Celsius, &dashboard);
+        assert!(page.contains("silenced until 2026-10-20 13:00:00 BST"));
+    }
+
+    #[test]
+    fn test_error_badge() {
+        let mut readings = Readings::new(["fridge", "freezer"]);
+        readings.record("fridge", Some(4.0));
+        readings.record_error("freezer", "crc_failure", "crc check failed".to_string());
+        readings.record_error("freezer", "timeout", "read <timed> out".to_string());
+
+        let page = generate_temperature_page(&readings, Unit::Celsius, &dashboard(1));
+        assert!(page.contains(">Down</span><br><span class='badge' title='read &lt;timed&gt; out'>timeout &times;2</span>"));
+        assert_eq!(page.matches("class='badge'").count(), 1);
+        // the fridge's reading is fresh
+        assert!(page.contains("<span class='age'>0s ago</span>"));
+    }
+
+    #[test]
+    fn test_last_good_value_is_greyed() {
+        let mut readings = Readings::new(["basking_spot"]);
+        readings.record("basking_spot", Some(31.25));
+        readings.record("basking_spot", None);
+
+        let page = generate_temperature_page(&readings, Unit::Celsius, &dashboard(3));
+        assert!(page.contains("31.25°C"));
+        assert!(page.contains("#4c566a; font-size: 2em"));
+        assert!(page.contains("0s ago"));
+
+        let page = generate_temperature_page(&readings, Unit::Fahrenheit, &dashboard(3));
+        assert!(page.contains("88.25°F"));
+
+        let page = generate_temperature_page(&readings, Unit::Celsius, &dashboard(1));
+        assert!(page.contains("Down"));
+        assert!(!page.contains("31.25°C"));
+    }
+}
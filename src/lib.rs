@@ -0,0 +1,69 @@
+//! tempmon's polling core, for use in other programs. The pieces are:
+//!
+//! - [`config::Config`] and [`config::load_config`] for tempmon's config file
+//! - [`probe::Sensor`], something the poller can read, and [`probe::Probe`],
+//!   the probes tempmon finds with a [`backend::Registry`]
+//! - [`poll::Poller`], which reads sensors with the configured calibration,
+//!   retries and filters
+//! - [`state::Readings`], the latest readings the server shows
+//! - [`events::Events`], each probe's reading as it's taken, for outputs
+//!   that want them as they happen
+//! - [`server::start`], the dashboard, api and metrics
+//!
+//! [`run`] puts them together, and is what the tempmon binary serves.
+
+mod adaptive;
+mod alarm;
+mod alert;
+mod api;
+mod assets;
+mod auth;
+pub mod backend;
+#[doc(hidden)]
+pub mod calibrate;
+#[doc(hidden)]
+pub mod cli;
+mod coap;
+mod collect;
+#[doc(hidden)]
+pub mod commands;
+mod compress;
+pub mod config;
+mod control;
+mod cors;
+mod daemon;
+mod dbus;
+pub mod events;
+mod export;
+mod federation;
+mod gpio;
+mod grafana;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod history;
+mod html;
+#[doc(hidden)]
+pub mod logfile;
+#[doc(hidden)]
+pub mod logger;
+mod mdns;
+mod mqtt;
+mod nagios;
+mod notify;
+pub mod poll;
+pub mod probe;
+mod pwm;
+mod ratelimit;
+mod registry;
+mod schedule;
+pub mod server;
+mod snmp;
+pub mod state;
+mod statefile;
+mod systemd;
+mod template;
+mod watch;
+mod watchdog;
+mod zabbix;
+
+pub use daemon::{Options, Replay, run};
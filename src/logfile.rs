@@ -0,0 +1,194 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Rotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl Rotation {
+    /// Which hour or day (UTC) a point in time falls in.
+    fn period(self, time: SystemTime) -> Option<u64> {
+        let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        match self {
+            Rotation::Never => None,
+            Rotation::Hourly => Some(secs / 3600),
+            Rotation::Daily => Some(secs / 86400),
+        }
+    }
+}
+
+/// A log file that rotates itself once it grows past `max_size` bytes or a
+/// new hour/day starts. Rotated files are renamed `<path>.1` (newest) up to
+/// `<path>.<keep>`, older ones are deleted.
+pub struct LogFile {
+    path: PathBuf,
+    max_size: Option<u64>,
+    rotation: Rotation,
+    keep: usize,
+    file: File,
+    size: u64,
+    period: Option<u64>,
+}
+
+impl LogFile {
+    pub fn open(
+        path: &Path,
+        max_size: Option<u64>,
+        rotation: Rotation,
+        keep: usize,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        // an existing file belongs to the period it was last written in
+        let period = rotation.period(metadata.modified().unwrap_or_else(|_| SystemTime::now()));
+
+        Ok(LogFile {
+            path: path.to_path_buf(),
+            max_size,
+            rotation,
+            keep,
+            file,
+            size: metadata.len(),
+            period,
+        })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    fn should_rotate(&self, now: SystemTime, incoming: usize) -> bool {
+        let too_big = self
+            .max_size
+            .is_some_and(|max| self.size > 0 && self.size + incoming as u64 > max);
+        too_big || self.rotation.period(now) != self.period
+    }
+
+    fn rotate(&mut self, now: SystemTime) -> io::Result<()> {
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.keep));
+            for n in (1..self.keep).rev() {
+                let from = self.rotated_path(n);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.period = self.rotation.period(now);
+        Ok(())
+    }
+
+    fn write_at(&mut self, buf: &[u8], now: SystemTime) -> io::Result<usize> {
+        if self.should_rotate(now, buf.len()) {
+            self.rotate(now)?;
+        }
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_at(buf, SystemTime::now())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tempmon-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_rotates_by_size() {
+        let dir = temp_dir("logfile-size");
+        let path = dir.join("tempmon.log");
+        let mut log = LogFile::open(&path, Some(10), Rotation::Never, 2).unwrap();
+
+        let now = SystemTime::now();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            log.write_at(line.as_bytes(), now).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("tempmon.log.1")).unwrap(),
+            "third\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("tempmon.log.2")).unwrap(),
+            "second\n"
+        );
+        // only `keep` rotated files are retained
+        assert!(!dir.join("tempmon.log.3").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotates_daily() {
+        let dir = temp_dir("logfile-daily");
+        let path = dir.join("tempmon.log");
+        let mut log = LogFile::open(&path, None, Rotation::Daily, 7).unwrap();
+
+        let today = SystemTime::now();
+        log.write_at(b"today\n", today).unwrap();
+        log.write_at(b"later today\n", today).unwrap();
+        log.write_at(b"tomorrow\n", today + Duration::from_secs(86400))
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "tomorrow\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("tempmon.log.1")).unwrap(),
+            "today\nlater today\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_keep_zero_truncates() {
+        let dir = temp_dir("logfile-keep0");
+        let path = dir.join("tempmon.log");
+        let mut log = LogFile::open(&path, Some(8), Rotation::Never, 0).unwrap();
+
+        let now = SystemTime::now();
+        log.write_at(b"first\n", now).unwrap();
+        log.write_at(b"second\n", now).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+        assert!(!dir.join("tempmon.log.1").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
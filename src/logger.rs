@@ -0,0 +1,100 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::Mutex;
+
+use clap::ValueEnum;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+use crate::logfile::LogFile;
+
+/// Environment variable read for the log filter when `--log-level` isn't
+/// given.
+pub const LOG_ENV: &str = "TEMPMON_LOG";
+pub const LOG_FORMAT_ENV: &str = "TEMPMON_LOG_FORMAT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// journald when running under systemd, text otherwise
+    Auto,
+    /// human readable lines
+    Text,
+    /// one JSON object per event, for shipping logs to Loki or ELK
+    Json,
+    /// native journald entries with structured fields such as PROBE= and TEMP=
+    Journald,
+}
+
+/// Checks a filter such as `info` or `warn,tempmon::server=debug`.
+pub fn parse_filter(filter: &str) -> Result<String, String> {
+    EnvFilter::try_new(filter)
+        .map(|_| filter.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// systemd sets `JOURNAL_STREAM` when a service's stderr goes to the journal.
+fn under_systemd() -> bool {
+    env::var_os("JOURNAL_STREAM").is_some()
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+fn fmt_layer<W>(format: Format, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    match format {
+        Format::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_writer(writer)
+            .boxed(),
+        _ => tracing_subscriber::fmt::layer()
+            .with_ansi(ansi)
+            .with_writer(writer)
+            .boxed(),
+    }
+}
+
+/// Logs to the journal, a log file, or stderr to keep stdout free for the
+/// output of subcommands.
+pub fn init(filter: &str, format: Format, file: Option<LogFile>) {
+    let use_journald = match format {
+        Format::Journald => true,
+        Format::Auto => file.is_none() && under_systemd(),
+        Format::Text | Format::Json => false,
+    };
+    let fallback = move || match file {
+        Some(file) => fmt_layer(format, Mutex::new(file), false),
+        None => fmt_layer(format, io::stderr, io::stderr().is_terminal()),
+    };
+
+    let mut journald_error = None;
+    let output = if use_journald {
+        match tracing_journald::layer() {
+            Ok(layer) => layer.with_field_prefix(None).boxed(),
+            Err(e) => {
+                journald_error = Some(e);
+                fallback()
+            }
+        }
+    } else {
+        fallback()
+    };
+
+    tracing_subscriber::registry()
+        .with(output)
+        .with(EnvFilter::new(filter))
+        .init();
+
+    if let Some(e) = journald_error {
+        tracing::warn!(
+            "failed to connect to journald, using the fallback output: {}",
+            e
+        );
+    }
+}
@@ -1,135 +1,121 @@
-mod config;
-mod html;
-mod probe;
-mod server;
-
-use std::collections::HashMap;
-use std::io;
-use std::sync::{Arc, Mutex};
-use std::thread::sleep;
+use std::path::Path;
 use std::time;
 
-use prometheus::{register_counter_vec, register_gauge_vec};
-
-use config::load_config;
-use probe::{Probe, discover_probes};
-use server::TempData;
+use clap::Parser;
+use tracing::{error, info};
 
-fn run_loop(
-    probes: &[Probe],
-    port: u16,
-    interval: time::Duration,
-    calibration_offsets: &HashMap<String, f32>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let current_temps: TempData = Arc::new(Mutex::new(HashMap::new()));
+use tempmon::calibrate;
+use tempmon::cli::{Cli, Command};
+use tempmon::commands;
+use tempmon::config::{self, load_config};
+use tempmon::logfile::LogFile;
+use tempmon::logger;
+use tempmon::{Options, Replay};
 
-    // sets up a scope so that the lock is dropped once done
-    {
-        let mut temps = current_temps.lock().unwrap();
-        for probe in probes {
-            temps.insert(probe.name.clone(), None);
+fn serve(config_path: &Path, options: &Options) {
+    info!("using config {}", config_path.display());
+    let config = match load_config(config_path) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            error!("failed to load config: {}", e);
+            std::process::exit(1);
         }
-    }
-
-    let prom_temp_readings = register_gauge_vec!(
-        "dash_temp_readings",
-        "calibrated readings from the temperature probes",
-        &["probe"]
-    )?;
-
-    let prom_temp_readings_raw = register_gauge_vec!(
-        "dash_temp_readings_raw",
-        "uncalibrated readings from the temperature probes",
-        &["probe"]
-    )?;
-
-    let prom_temp_read_errors = register_counter_vec!(
-        "dash_temp_read_errors_total",
-        "total number of failed temperature reads",
-        &["probe", "error_type"]
-    )?;
-
-    // start http server with two request handler thread
-    server::start(port, Arc::clone(&current_temps), 2)?;
-
-    // probe loop
-    loop {
-        for p in probes {
-            match p.read_temperature() {
-                Ok(raw_temp) => {
-                    let offset = calibration_offsets.get(&p.id).copied().unwrap_or(0.0);
-                    let temp = raw_temp + offset;
-
-                    prom_temp_readings_raw
-                        .with_label_values(&[&p.name])
-                        .set(raw_temp.into());
-                    prom_temp_readings
-                        .with_label_values(&[&p.name])
-                        .set(temp.into());
-
-                    let mut temps = current_temps.lock().unwrap();
-                    temps.insert(p.name.clone(), Some(temp));
-
-                    println!("probe: {}, temperature: {:.2}°c", p.name, temp);
-                }
-                Err(e) => {
-                    let error_type = match e.kind() {
-                        io::ErrorKind::NotFound => "not_found",
-                        io::ErrorKind::PermissionDenied => "permission_denied",
-                        io::ErrorKind::InvalidData => "invalid_data",
-                        _ => "other",
-                    };
-                    prom_temp_read_errors
-                        .with_label_values(&[&p.name, error_type])
-                        .inc();
-
-                    let mut temps = current_temps.lock().unwrap();
-                    temps.insert(p.name.clone(), None);
+    };
 
-                    println!("probe: {}, error reading temperature: {}", p.name, e);
-                }
-            }
-        }
-        sleep(interval);
+    if let Err(e) = tempmon::run(config, config_path, options) {
+        error!("{e}");
+        std::process::exit(1);
     }
 }
 
 fn main() {
-    let config = match load_config() {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            eprintln!("error loading config: {}", e);
-            std::process::exit(1);
-        }
+    let cli = Cli::parse();
+    let log_file = match &cli.log_file {
+        Some(path) => match LogFile::open(
+            path,
+            cli.log_max_size.map(|mb| mb * 1024 * 1024),
+            cli.log_rotate,
+            cli.log_keep,
+        ) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                eprintln!("error: failed to open log file {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    logger::init(&cli.log_level, cli.log_format, log_file);
+
+    let config_path = config::find_config(cli.config.as_deref());
+    let options = Options {
+        record: cli.record,
+        replay: cli.replay.map(|path| Replay {
+            path,
+            speed: cli.replay_speed,
+        }),
     };
 
-    let probe_interval = time::Duration::from_secs(config.settings.probe_interval);
-
-    println!("discovering ds18b20 temperature probes...");
-    match discover_probes(&config.probe_labels) {
-        Ok(probes) => {
-            println!("found {} probe(s):\n", probes.len());
-            if !probes.is_empty() {
-                for probe in &probes {
-                    if let Err(e) = probe.set_resolution(config.settings.probe_resolution) {
-                        eprintln!(
-                            "warning: failed to set resolution for {}: {}",
-                            probe.name, e
-                        );
-                    }
-                }
-                if let Err(e) = run_loop(
-                    &probes,
-                    config.settings.metrics_port,
-                    probe_interval,
-                    &config.calibration_offsets,
-                ) {
-                    eprintln!("error on loop initialisation: {e}");
-                };
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(&config_path, &options),
+        Command::Read { probe } => match commands::read(&config_path, probe.as_deref()) {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                error!("failed to read probes: {}", e);
+                std::process::exit(1);
             }
+        },
+        Command::Collect { format } => match commands::collect(&config_path, format) {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                error!("failed to read probes: {}", e);
+                std::process::exit(1);
+            }
+        },
+        Command::Check { probe, warn, crit } => {
+            let status = commands::check(&config_path, &probe, warn.as_ref(), crit.as_ref());
+            std::process::exit(status.code());
         }
-        Err(e) => {
-            eprintln!("error discovering probes: {}", e);
+        Command::Calibrate {
+            reference,
+            samples,
+            interval,
+            yes,
+            probe,
+        } => {
+            let opts = calibrate::Options {
+                references: reference,
+                samples,
+                interval: time::Duration::from_secs(interval),
+                probe: probe.as_deref(),
+                yes,
+            };
+            if let Err(e) = calibrate::run(&config_path, &opts) {
+                error!("failed to calibrate: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::GenerateConfig { output, force } => {
+            if let Err(e) = commands::generate_config(output.as_deref(), force) {
+                error!("failed to generate config: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::NotifyTest => match commands::notify_test(&config_path) {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                error!("failed to send test alerts: {}", e);
+                std::process::exit(1);
+            }
+        },
+        Command::List { json } => {
+            if let Err(e) = commands::list(&config_path, json) {
+                error!("failed to list probes: {}", e);
+                std::process::exit(1);
+            }
         }
     }
 }
@@ -0,0 +1,100 @@
+use std::fs;
+
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use tracing::{info, warn};
+
+use crate::config::MdnsConfig;
+
+/// Found by other tempmon instances and the companion dashboard.
+const TEMPMON_SERVICE: &str = "_tempmon._tcp.local.";
+/// Found by anything browsing for web pages.
+const HTTP_SERVICE: &str = "_http._tcp.local.";
+
+/// Advertises the dashboard on the local network over multicast DNS.
+pub struct Advertiser {
+    daemon: ServiceDaemon,
+    name: String,
+    host: String,
+    port: u16,
+    /// the probe count last advertised
+    probes: Option<usize>,
+}
+
+impl Advertiser {
+    pub fn start(config: &MdnsConfig, port: u16) -> Result<Self, mdns_sd::Error> {
+        let hostname = hostname();
+        Ok(Advertiser {
+            daemon: ServiceDaemon::new()?,
+            name: config.name.clone().unwrap_or_else(|| hostname.clone()),
+            host: format!("{}.local.", hostname),
+            port,
+            probes: None,
+        })
+    }
+
+    /// Advertises `probes` in the TXT records, re-announcing when it has
+    /// changed.
+    pub fn update(&mut self, probes: usize) {
+        if self.probes == Some(probes) {
+            return;
+        }
+        for service in [TEMPMON_SERVICE, HTTP_SERVICE] {
+            let info = ServiceInfo::new(
+                service,
+                &self.name,
+                &self.host,
+                (),
+                self.port,
+                &txt(probes)[..],
+            )
+            .map(ServiceInfo::enable_addr_auto)
+            .and_then(|info| self.daemon.register(info));
+            if let Err(e) = info {
+                warn!(service, "failed to advertise over mdns: {}", e);
+                return;
+            }
+        }
+        if self.probes.is_none() {
+            info!(name = %self.name, "advertising over mdns");
+        }
+        self.probes = Some(probes);
+    }
+}
+
+impl Drop for Advertiser {
+    fn drop(&mut self) {
+        // says goodbye, so browsers forget the old name straight away
+        let _ = self.daemon.shutdown();
+    }
+}
+
+fn txt(probes: usize) -> Vec<(&'static str, String)> {
+    vec![
+        ("probes", probes.to_string()),
+        ("version", env!("CARGO_PKG_VERSION").to_string()),
+        ("path", "/".to_string()),
+        ("api", "/api/v1/readings".to_string()),
+    ]
+}
+
+/// This machine's name, without a domain.
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|h| h.trim().split('.').next().unwrap_or_default().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "tempmon".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_txt() {
+        let txt = txt(3);
+        assert_eq!(txt[0], ("probes", "3".to_string()));
+        assert!(txt.iter().any(|(k, v)| *k == "path" && v == "/"));
+        assert!(!hostname().contains('.'));
+    }
+}
@@ -0,0 +1,139 @@
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+use rumqttc::{Client, MqttOptions, Packet, QoS};
+use tracing::{debug, info, warn};
+
+use crate::alert::Alert;
+use crate::config::MqttConfig;
+use crate::events::{Consumer, Event, Events, Reading};
+use crate::notify::{Notifier, NotifyError, Payload};
+
+/// Wait before reconnecting after the connection to the broker drops.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Publishes readings to an MQTT broker.
+pub struct Publisher {
+    client: Client,
+    qos: QoS,
+    alert_topic: Option<String>,
+    _readings: Consumer,
+}
+
+impl Publisher {
+    /// Connects to the broker in the background, reconnecting whenever the
+    /// connection drops, and publishes the good readings from `events` until
+    /// the publisher is dropped.
+    pub fn start(config: &MqttConfig, events: &Events) -> Result<Self, String> {
+        let qos = rumqttc::qos(config.qos)
+            .map_err(|_| format!("mqtt.qos must be 0, 1 or 2, got {}", config.qos))?;
+
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.clone().unwrap_or_default());
+        }
+
+        // readings are dropped rather than queued while the broker is down
+        let (client, mut connection) = Client::new(options, 64);
+        let broker = format!("{}:{}", config.host, config.port);
+        thread::spawn(move || {
+            for event in connection.iter() {
+                match event {
+                    Ok(rumqttc::Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("connected to mqtt broker {}", broker)
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("mqtt connection to {} failed: {}", broker, e);
+                        sleep(RECONNECT_DELAY);
+                    }
+                }
+            }
+        });
+
+        let readings = {
+            let (client, template, retain) = (client.clone(), config.topic.clone(), config.retain);
+            events.consume("mqtt", move |event| {
+                if let Event::Reading(Reading {
+                    id,
+                    name,
+                    outcome: Ok(sample),
+                    ..
+                }) = event
+                {
+                    let topic = topic(&template, id, name);
+                    if let Err(e) = client.try_publish(&topic, qos, retain, sample.temp.to_string())
+                    {
+                        debug!(topic, "failed to queue mqtt publish: {}", e);
+                    }
+                }
+            })
+        };
+
+        Ok(Publisher {
+            client,
+            qos,
+            alert_topic: config.alert_topic.clone(),
+            _readings: readings,
+        })
+    }
+
+    /// A notifier for alerts over the same connection, if an alert topic
+    /// is set.
+    pub fn alerts(&self) -> Option<AlertPublisher> {
+        Some(AlertPublisher {
+            client: self.client.clone(),
+            topic: self.alert_topic.clone()?,
+            qos: self.qos,
+        })
+    }
+}
+
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        let _ = self.client.disconnect();
+    }
+}
+
+/// Publishes alerts to the broker as JSON.
+pub struct AlertPublisher {
+    client: Client,
+    topic: String,
+    qos: QoS,
+}
+
+impl Notifier for AlertPublisher {
+    fn name(&self) -> &str {
+        "mqtt"
+    }
+
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let topic = self.topic.replace("{probe}", &alert.probe);
+        let payload = serde_json::to_vec(&Payload::new(alert))?;
+        self.client.try_publish(topic, self.qos, false, payload)?;
+        Ok(())
+    }
+}
+
+/// Fills in `{probe}` (the label) and `{id}` in a topic template.
+fn topic(template: &str, id: &str, name: &str) -> String {
+    template.replace("{probe}", name).replace("{id}", id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic() {
+        assert_eq!(
+            topic("tempmon/{probe}/temperature", "28-abc", "basking_spot"),
+            "tempmon/basking_spot/temperature"
+        );
+        assert_eq!(
+            topic("sensors/{id}", "28-abc", "basking_spot"),
+            "sensors/28-abc"
+        );
+    }
+}
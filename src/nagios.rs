@@ -0,0 +1,174 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A nagios plugin's result, which is also its exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl Status {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Critical => "CRITICAL",
+            Status::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+/// A threshold in the nagios plugin range format: `25` alerts outside
+/// 0..=25, `10:` below 10, `~:-15` above -15, `10:20` outside 10..=20, and
+/// `@10:20` inside it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Threshold {
+    start: Option<f32>,
+    end: Option<f32>,
+    inside: bool,
+    text: String,
+}
+
+impl Threshold {
+    /// Whether `value` should raise the alert.
+    pub fn alerts(&self, value: f32) -> bool {
+        let within = self.start.is_none_or(|s| value >= s) && self.end.is_none_or(|e| value <= e);
+        within == self.inside
+    }
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (inside, range) = match s.strip_prefix('@') {
+            Some(range) => (true, range),
+            None => (false, s),
+        };
+        let number = |n: &str| {
+            n.parse::<f32>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .ok_or_else(|| format!("invalid threshold {:?}", s))
+        };
+        let (start, end) = match range.split_once(':') {
+            Some(("~", end)) => (None, end),
+            Some((start, end)) => (Some(number(start)?), end),
+            None => (Some(0.0), range),
+        };
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(number(end)?)
+        };
+        if let (Some(start), Some(end)) = (start, end)
+            && start > end
+        {
+            return Err(format!("threshold {:?} starts after it ends", s));
+        }
+        Ok(Threshold {
+            start,
+            end,
+            inside,
+            text: s.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// The status of a probe reading `temp` and the plugin output for it: a
+/// status line, then perfdata after the `|`.
+pub fn report(
+    name: &str,
+    temp: f32,
+    symbol: &str,
+    warn: Option<&Threshold>,
+    crit: Option<&Threshold>,
+) -> (Status, String) {
+    let status = if crit.is_some_and(|t| t.alerts(temp)) {
+        Status::Critical
+    } else if warn.is_some_and(|t| t.alerts(temp)) {
+        Status::Warning
+    } else {
+        Status::Ok
+    };
+    let threshold = |t: Option<&Threshold>| t.map_or(String::new(), Threshold::to_string);
+    let output = format!(
+        "TEMPMON {} - {} is {:.2}{} | '{}'={:.2};{};{};;",
+        status.as_str(),
+        name,
+        temp,
+        symbol,
+        name.replace('\'', "''"),
+        temp,
+        threshold(warn),
+        threshold(crit),
+    );
+    (status, output)
+}
+
+/// The plugin output when there's no reading to check.
+pub fn unknown(message: &str) -> String {
+    format!("TEMPMON UNKNOWN - {}", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn threshold(s: &str) -> Threshold {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_threshold() {
+        let t = threshold("25");
+        assert!(!t.alerts(25.0));
+        assert!(t.alerts(25.5));
+        assert!(t.alerts(-1.0));
+
+        assert!(threshold("10:").alerts(9.9));
+        assert!(!threshold("10:").alerts(100.0));
+        assert!(threshold("~:-15").alerts(-12.0));
+        assert!(!threshold("~:-15").alerts(-18.0));
+        assert!(threshold("@18:20").alerts(19.0));
+        assert!(!threshold("@18:20").alerts(21.0));
+
+        assert!("20:10".parse::<Threshold>().is_err());
+        assert!("hot".parse::<Threshold>().is_err());
+        assert!("10:x".parse::<Threshold>().is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        let (warn, crit) = (threshold("25"), threshold("30"));
+        let (status, output) = report("fermenter", 21.5, "°C", Some(&warn), Some(&crit));
+        assert_eq!(status, Status::Ok);
+        assert_eq!(
+            output,
+            "TEMPMON OK - fermenter is 21.50°C | 'fermenter'=21.50;25;30;;"
+        );
+
+        let (status, _) = report("fermenter", 26.0, "°C", Some(&warn), Some(&crit));
+        assert_eq!(status, Status::Warning);
+        let (status, _) = report("fermenter", 31.0, "°C", Some(&warn), Some(&crit));
+        assert_eq!(status, Status::Critical);
+
+        let (status, output) = report("bob's", 99.0, "°F", None, None);
+        assert_eq!(status, Status::Ok);
+        assert!(output.ends_with("| 'bob''s'=99.00;;;;"));
+        assert_eq!(Status::Unknown.code(), 3);
+    }
+}
@@ -0,0 +1,399 @@
+mod alertmanager;
+mod discord;
+mod email;
+mod gotify;
+mod ntfy;
+mod pushover;
+mod slack;
+mod telegram;
+mod webhook;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, sleep};
+use std::time::{Duration, UNIX_EPOCH};
+
+use prometheus::{IntCounterVec, register_int_counter_vec};
+use serde::Serialize;
+use tracing::{debug, info, warn};
+use ureq::{Agent, AgentBuilder, Response};
+
+use crate::alert::{Alert, Kind, State};
+use crate::config::{AlertsConfig, Severity};
+use crate::state::TempData;
+
+pub type NotifyError = Box<dyn Error + Send + Sync>;
+
+/// Wait before the first retry of a failed delivery, doubled for each retry after.
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// A way of telling someone that an alert fired or resolved.
+pub trait Notifier: Send {
+    /// short name for logs, e.g. "webhook"
+    fn name(&self) -> &str;
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError>;
+
+    /// Called every polling cycle with the alerts that are firing, for
+    /// services that expire alerts unless they are sent again.
+    fn refresh(&self, _firing: &[Alert]) -> Result<(), NotifyError> {
+        Ok(())
+    }
+}
+
+/// Writes alerts to the log, so they are recorded even with no other
+/// notifiers configured.
+pub struct Log;
+
+impl Notifier for Log {
+    fn name(&self) -> &str {
+        "log"
+    }
+
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let severity = alert.severity.as_str();
+        match (alert.state, alert.severity) {
+            (State::Resolved, _) => {
+                info!(probe = %alert.probe, alert = alert.kind.as_str(), severity, "resolved: {}", alert)
+            }
+            (_, Severity::Info) => {
+                info!(probe = %alert.probe, alert = alert.kind.as_str(), severity, "firing: {}", alert)
+            }
+            _ => {
+                warn!(probe = %alert.probe, alert = alert.kind.as_str(), severity, "firing: {}", alert)
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds the notifiers enabled in the config, after the log.
+pub fn from_config(config: &AlertsConfig) -> Vec<Box<dyn Notifier>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = vec![Box::new(Log)];
+    notifiers.extend(configured(config));
+    notifiers
+}
+
+/// The notifiers set up in the config, i.e. without the log.
+fn configured(config: &AlertsConfig) -> Vec<Box<dyn Notifier>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if let Some(webhook) = &config.webhook {
+        notifiers.push(Box::new(webhook::Webhook::new(webhook)));
+    }
+    if let Some(ntfy) = &config.ntfy {
+        notifiers.push(Box::new(ntfy::Ntfy::new(ntfy)));
+    }
+    if let Some(pushover) = &config.pushover {
+        notifiers.push(Box::new(pushover::Pushover::new(pushover)));
+    }
+    if let Some(gotify) = &config.gotify {
+        notifiers.push(Box::new(gotify::Gotify::new(gotify)));
+    }
+    if let Some(telegram) = &config.telegram {
+        notifiers.push(Box::new(telegram::Telegram::new(telegram)));
+    }
+    if let Some(slack) = &config.slack {
+        notifiers.push(Box::new(slack::Slack::new(slack)));
+    }
+    if let Some(discord) = &config.discord {
+        notifiers.push(Box::new(discord::Discord::new(discord)));
+    }
+    if let Some(alertmanager) = &config.alertmanager {
+        notifiers.push(Box::new(alertmanager::Alertmanager::new(alertmanager)));
+    }
+    if let Some(email) = &config.email {
+        match email::Email::new(email) {
+            Ok(email) => notifiers.push(Box::new(email)),
+            Err(e) => warn!("email alerts disabled, bad config: {}", e),
+        }
+    }
+    notifiers
+}
+
+/// Sends a made up firing alert through every configured notifier, whatever
+/// the routes say, and returns how each one got on.
+pub fn send_test(config: &AlertsConfig) -> Vec<(String, Result<(), NotifyError>)> {
+    let mut alert = Alert::new("tempmon test", Kind::High, 30.0, 31.5);
+    alert.state = State::Firing;
+    let notifiers = configured(config);
+    if !notifiers.is_empty() {
+        info!("sending a test alert: {}", alert);
+    }
+    notifiers
+        .iter()
+        .map(|notifier| (notifier.name().to_string(), notifier.send(&alert)))
+        .collect()
+}
+
+/// Starts anything that answers requests from notification services,
+/// i.e. telegram bot commands.
+pub fn start_listeners(config: &AlertsConfig, current_temps: &TempData) {
+    if let Some(telegram) = &config.telegram
+        && telegram.commands
+    {
+        telegram::listen(telegram, current_temps.clone());
+    }
+}
+
+/// An alert as JSON, for webhooks and mqtt.
+#[derive(Serialize)]
+pub struct Payload<'a> {
+    probe: &'a str,
+    alert: &'a str,
+    state: &'a str,
+    severity: &'a str,
+    value: f32,
+    threshold: f32,
+    /// unix timestamp of the state change
+    timestamp: u64,
+}
+
+impl<'a> Payload<'a> {
+    pub fn new(alert: &'a Alert) -> Self {
+        Payload {
+            probe: &alert.probe,
+            alert: alert.kind.as_str(),
+            state: alert.state.as_str(),
+            severity: alert.severity.as_str(),
+            value: alert.value,
+            threshold: alert.threshold,
+            timestamp: alert
+                .since
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+}
+
+/// A short notification title, e.g. "freezer too high".
+fn title(alert: &Alert) -> String {
+    match alert.state {
+        State::Resolved => format!("{} resolved", alert.probe),
+        _ if alert.kind == Kind::Flatline => format!("{} stuck", alert.probe),
+        _ => format!("{} too {}", alert.probe, alert.kind.as_str()),
+    }
+}
+
+/// An rgb colour for chat messages: red while firing, green once resolved.
+fn color(alert: &Alert) -> u32 {
+    match alert.state {
+        State::Resolved => 0xa3be8c, // nord14 - aurora green
+        _ => 0xbf616a,               // nord11 - aurora red
+    }
+}
+
+/// A ureq error without the url, for services that keep their secret in it.
+fn without_url(e: ureq::Error) -> NotifyError {
+    match e {
+        ureq::Error::Status(status, _) => format!("http status {}", status).into(),
+        ureq::Error::Transport(t) => match t.message() {
+            Some(message) => format!("{}: {}", t.kind(), message).into(),
+            None => t.kind().to_string().into(),
+        },
+    }
+}
+
+/// An http client for notifiers, giving up on requests after `timeout` seconds.
+fn agent(timeout: u64) -> Agent {
+    AgentBuilder::new()
+        .timeout(Duration::from_secs(timeout))
+        .user_agent(concat!("tempmon/", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// Makes an http request, trying again up to `retries` more times with
+/// backoff if it fails.
+fn with_retries(
+    retries: u32,
+    mut backoff: Duration,
+    mut request: impl FnMut() -> Result<Response, NotifyError>,
+) -> Result<(), NotifyError> {
+    let mut attempt = 0;
+    loop {
+        match request() {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt < retries => {
+                attempt += 1;
+                debug!(attempt, "notification failed, retrying: {}", e);
+                sleep(backoff);
+                backoff *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Notifier names that get alerts of each severity, see `alerts.routes`.
+pub type Routes = HashMap<Severity, Vec<String>>;
+
+enum Message {
+    Alert(Alert),
+    Firing(Vec<Alert>),
+    Reload(Vec<Box<dyn Notifier>>, Routes),
+}
+
+/// Sends notifications from a background thread so a slow or unreachable
+/// notifier doesn't hold up polling.
+pub struct Dispatcher {
+    tx: Sender<Message>,
+}
+
+impl Dispatcher {
+    pub fn start(
+        mut notifiers: Vec<Box<dyn Notifier>>,
+        mut routes: Routes,
+    ) -> prometheus::Result<Self> {
+        let failures: IntCounterVec = register_int_counter_vec!(
+            "dash_notification_failures_total",
+            "notifications that couldn't be delivered, after retries",
+            &["notifier"]
+        )?;
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for message in rx {
+                match message {
+                    Message::Alert(alert) => {
+                        for notifier in &notifiers {
+                            if !routed(&routes, &alert, notifier.name()) {
+                                continue;
+                            }
+                            if let Err(e) = notifier.send(&alert) {
+                                failures.with_label_values(&[notifier.name()]).inc();
+                                warn!(
+                                    probe = %alert.probe,
+                                    notifier = notifier.name(),
+                                    "failed to send notification: {}",
+                                    e
+                                );
+                            }
+                        }
+                    }
+                    Message::Firing(firing) => {
+                        for notifier in &notifiers {
+                            let firing: Vec<_> = firing
+                                .iter()
+                                .filter(|a| routed(&routes, a, notifier.name()))
+                                .cloned()
+                                .collect();
+                            if let Err(e) = notifier.refresh(&firing) {
+                                failures.with_label_values(&[notifier.name()]).inc();
+                                warn!(
+                                    notifier = notifier.name(),
+                                    "failed to refresh firing alerts: {}", e
+                                );
+                            }
+                        }
+                    }
+                    Message::Reload(new, new_routes) => {
+                        notifiers = new;
+                        routes = new_routes;
+                    }
+                }
+            }
+        });
+        Ok(Dispatcher { tx })
+    }
+
+    pub fn send(&self, alert: Alert) {
+        let _ = self.tx.send(Message::Alert(alert));
+    }
+
+    /// Passes on the alerts that are firing, see [`Notifier::refresh`].
+    pub fn refresh(&self, firing: Vec<Alert>) {
+        let _ = self.tx.send(Message::Firing(firing));
+    }
+
+    /// Swaps in notifiers and routes from a reloaded config.
+    pub fn reload(&self, notifiers: Vec<Box<dyn Notifier>>, routes: Routes) {
+        let _ = self.tx.send(Message::Reload(notifiers, routes));
+    }
+}
+
+/// Whether an alert goes to a notifier. Every alert is logged, whatever
+/// the routes say.
+fn routed(routes: &Routes, alert: &Alert, notifier: &str) -> bool {
+    notifier == Log.name()
+        || routes
+            .get(&alert.severity)
+            .is_none_or(|notifiers| notifiers.iter().any(|n| n == notifier))
+}
+
+/// A request received by a [`capture_server`].
+#[cfg(test)]
+pub(crate) struct Captured {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+#[cfg(test)]
+impl Captured {
+    /// The value of a header, whatever its case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Serves on a free local port, answering a request with each of
+/// `statuses` in turn. Returns the server's url, ending in `/`, and a
+/// handle that gives back the requests once they've all been answered.
+#[cfg(test)]
+pub(crate) fn capture_server(statuses: Vec<u16>) -> (String, thread::JoinHandle<Vec<Captured>>) {
+    let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", server.server_addr().to_ip().unwrap());
+    let handle = thread::spawn(move || {
+        let mut requests = Vec::new();
+        for status in statuses {
+            let mut request = server.recv().unwrap();
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            requests.push(Captured {
+                url: request.url().to_string(),
+                headers: request
+                    .headers()
+                    .iter()
+                    .map(|h| (h.field.to_string(), h.value.to_string()))
+                    .collect(),
+                body,
+            });
+            request.respond(tiny_http::Response::empty(status)).unwrap();
+        }
+        requests
+    });
+    (url, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routed() {
+        let routes = Routes::from([
+            (Severity::Critical, vec!["pushover".to_string()]),
+            (
+                Severity::Warning,
+                vec!["mqtt".to_string(), "email".to_string()],
+            ),
+        ]);
+        let mut alert = Alert::new("freezer", Kind::High, -15.0, -12.5);
+
+        alert.severity = Severity::Critical;
+        assert!(routed(&routes, &alert, "pushover"));
+        assert!(!routed(&routes, &alert, "email"));
+        assert!(routed(&routes, &alert, "log"));
+
+        alert.severity = Severity::Warning;
+        assert!(routed(&routes, &alert, "mqtt"));
+        assert!(!routed(&routes, &alert, "pushover"));
+
+        // no route, so every notifier
+        alert.severity = Severity::Info;
+        assert!(routed(&routes, &alert, "pushover"));
+    }
+}
@@ -0,0 +1,181 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Serialize;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use ureq::Agent;
+
+use super::{Notifier, NotifyError, RETRY_BACKOFF, with_retries};
+use crate::alert::{Alert, Kind, State};
+use crate::config::AlertmanagerConfig;
+
+/// How often firing alerts are sent again. Alertmanager resolves alerts
+/// it hasn't heard about for its resolve_timeout, 5 minutes by default.
+const RESEND_INTERVAL: Duration = Duration::from_secs(60);
+
+/// An alert in the form alertmanager's /api/v2/alerts takes.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PostableAlert {
+    labels: BTreeMap<String, String>,
+    annotations: BTreeMap<String, String>,
+    starts_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ends_at: Option<String>,
+}
+
+/// Sends alerts to Prometheus Alertmanager, so it can group, inhibit and
+/// route them.
+pub struct Alertmanager {
+    agent: Agent,
+    urls: Vec<String>,
+    labels: BTreeMap<String, String>,
+    annotations: BTreeMap<String, String>,
+    retries: u32,
+    backoff: Duration,
+    last_refresh: Cell<Option<Instant>>,
+}
+
+impl Alertmanager {
+    pub fn new(config: &AlertmanagerConfig) -> Self {
+        Alertmanager {
+            agent: super::agent(config.timeout),
+            urls: config.urls.clone(),
+            labels: config.labels.clone().into_iter().collect(),
+            annotations: config.annotations.clone().into_iter().collect(),
+            retries: config.retries,
+            backoff: RETRY_BACKOFF,
+            last_refresh: Cell::new(None),
+        }
+    }
+
+    fn alert(&self, alert: &Alert) -> PostableAlert {
+        let alertname = match alert.kind {
+            Kind::High => "TemperatureHigh",
+            Kind::Low => "TemperatureLow",
+            Kind::Flatline => "TemperatureFlatline",
+        };
+        let mut labels = self.labels.clone();
+        labels.insert("alertname".to_string(), alertname.to_string());
+        labels.insert("probe".to_string(), alert.probe.clone());
+        labels.insert("severity".to_string(), alert.severity.as_str().to_string());
+
+        let mut annotations = self.annotations.clone();
+        annotations.insert("summary".to_string(), alert.to_string());
+        annotations.insert("value".to_string(), format!("{:.2}", alert.value));
+        annotations.insert("threshold".to_string(), format!("{:.2}", alert.threshold));
+
+        PostableAlert {
+            labels,
+            annotations,
+            starts_at: rfc3339(alert.started),
+            ends_at: (alert.state == State::Resolved).then(|| rfc3339(alert.since)),
+        }
+    }
+
+    fn post(&self, alerts: &[Alert]) -> Result<(), NotifyError> {
+        let body: Vec<_> = alerts.iter().map(|a| self.alert(a)).collect();
+        let failed: Vec<_> = self
+            .urls
+            .iter()
+            .filter_map(|url| {
+                let endpoint = format!("{}/api/v2/alerts", url.trim_end_matches('/'));
+                with_retries(self.retries, self.backoff, || {
+                    Ok(self.agent.post(&endpoint).send_json(&body)?)
+                })
+                .err()
+                .map(|e| format!("{}: {}", url, e))
+            })
+            .collect();
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(failed.join(", ").into())
+        }
+    }
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    OffsetDateTime::from(time).format(&Rfc3339).unwrap()
+}
+
+impl Notifier for Alertmanager {
+    fn name(&self) -> &str {
+        "alertmanager"
+    }
+
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.post(std::slice::from_ref(alert))
+    }
+
+    fn refresh(&self, firing: &[Alert]) -> Result<(), NotifyError> {
+        let due = self
+            .last_refresh
+            .get()
+            .is_none_or(|last| last.elapsed() >= RESEND_INTERVAL);
+        if firing.is_empty() || !due {
+            return Ok(());
+        }
+        self.last_refresh.set(Some(Instant::now()));
+        self.post(firing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::notify::capture_server;
+
+    fn alertmanager(url: String) -> Alertmanager {
+        Alertmanager::new(&AlertmanagerConfig {
+            urls: vec![url],
+            labels: HashMap::from([("instance".to_string(), "pi".to_string())]),
+            annotations: HashMap::new(),
+            retries: 0,
+            timeout: 5,
+        })
+    }
+
+    #[test]
+    fn test_posts_alerts() {
+        let (url, server) = capture_server(vec![200, 200]);
+        let alertmanager = alertmanager(url);
+        let mut alert = Alert::new("freezer", Kind::High, -15.0, -12.5);
+        alert.state = State::Firing;
+        alertmanager.send(&alert).unwrap();
+        alert.state = State::Resolved;
+        alertmanager.send(&alert).unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].url, "/api/v2/alerts");
+        let firing: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        let labels = &firing[0]["labels"];
+        assert_eq!(labels["alertname"], "TemperatureHigh");
+        assert_eq!(labels["probe"], "freezer");
+        assert_eq!(labels["severity"], "warning");
+        assert_eq!(labels["instance"], "pi");
+        assert!(firing[0]["startsAt"].as_str().unwrap().ends_with('Z'));
+        assert!(firing[0].get("endsAt").is_none());
+
+        let resolved: serde_json::Value = serde_json::from_str(&requests[1].body).unwrap();
+        assert!(resolved[0]["endsAt"].is_string());
+    }
+
+    #[test]
+    fn test_refresh() {
+        let (url, server) = capture_server(vec![200]);
+        let alertmanager = alertmanager(url);
+        let mut alert = Alert::new("freezer", Kind::High, -15.0, -12.5);
+        alert.state = State::Firing;
+
+        alertmanager.refresh(&[]).unwrap();
+        alertmanager.refresh(&[alert.clone()]).unwrap();
+        // not due again yet, so nothing is sent
+        alertmanager.refresh(&[alert]).unwrap();
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use ureq::Agent;
+
+use super::{Notifier, NotifyError, RETRY_BACKOFF, with_retries};
+use crate::alert::Alert;
+use crate::config::ChatWebhookConfig;
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    embeds: [Embed<'a>; 1],
+}
+
+#[derive(Serialize)]
+struct Embed<'a> {
+    title: &'a str,
+    description: &'a str,
+    color: u32,
+    fields: [Field; 2],
+}
+
+#[derive(Serialize)]
+struct Field {
+    name: &'static str,
+    value: String,
+    inline: bool,
+}
+
+/// Posts alerts to a Discord webhook.
+pub struct Discord {
+    agent: Agent,
+    url: String,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Discord {
+    pub fn new(config: &ChatWebhookConfig) -> Self {
+        Discord {
+            agent: super::agent(config.timeout),
+            url: config.webhook_url.clone(),
+            retries: config.retries,
+            backoff: RETRY_BACKOFF,
+        }
+    }
+}
+
+fn payload<'a>(alert: &Alert, title: &'a str, description: &'a str) -> Payload<'a> {
+    Payload {
+        embeds: [Embed {
+            title,
+            description,
+            color: super::color(alert),
+            fields: [
+                Field {
+                    name: "Value",
+                    value: format!("{:.2}°C", alert.value),
+                    inline: true,
+                },
+                Field {
+                    name: "Threshold",
+                    value: format!("{:.2}°C", alert.threshold),
+                    inline: true,
+                },
+            ],
+        }],
+    }
+}
+
+impl Notifier for Discord {
+    fn name(&self) -> &str {
+        "discord"
+    }
+
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let title = super::title(alert);
+        let description = alert.to_string();
+        let payload = payload(alert, &title, &description);
+        with_retries(self.retries, self.backoff, || {
+            self.agent
+                .post(&self.url)
+                .send_json(&payload)
+                .map_err(super::without_url)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::{Kind, State};
+
+    #[test]
+    fn test_payload() {
+        let mut alert = Alert::new("fermenter", Kind::Low, 18.0, 16.0);
+        alert.state = State::Firing;
+        let json = serde_json::to_value(payload(&alert, "title", "text")).unwrap();
+        let embed = &json["embeds"][0];
+        assert_eq!(embed["color"], 0xbf616a);
+        assert_eq!(embed["fields"][1]["value"], "18.00°C");
+    }
+}
@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use lettre::message::Mailbox;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+
+use super::{Notifier, NotifyError};
+use crate::alert::{Alert, Kind, State};
+use crate::config::{EmailConfig, SmtpTls};
+
+/// Emails alerts through an SMTP server.
+pub struct Email {
+    transport: SmtpTransport,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl Email {
+    pub fn new(config: &EmailConfig) -> Result<Self, NotifyError> {
+        let mut builder = match config.tls {
+            SmtpTls::Starttls => SmtpTransport::starttls_relay(&config.host)?,
+            SmtpTls::Tls => SmtpTransport::relay(&config.host)?,
+            SmtpTls::None => SmtpTransport::builder_dangerous(&config.host),
+        };
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+        if let Some(username) = &config.username {
+            let password = config.password.clone().unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+        let transport = builder
+            .timeout(Some(Duration::from_secs(config.timeout)))
+            .build();
+
+        if config.to.is_empty() {
+            return Err("no recipients in alerts.email.to".into());
+        }
+        Ok(Email {
+            transport,
+            from: config.from.parse()?,
+            to: config
+                .to
+                .iter()
+                .map(|to| to.parse())
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn message(&self, alert: &Alert) -> Result<Message, NotifyError> {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(subject(alert))
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        Ok(builder.body(body(alert))?)
+    }
+}
+
+fn subject(alert: &Alert) -> String {
+    let state = match alert.state {
+        State::Resolved => "RESOLVED",
+        _ => "FIRING",
+    };
+    format!("[tempmon] {}: {}", state, alert)
+}
+
+fn body(alert: &Alert) -> String {
+    format!(
+        "{}\n\nprobe: {}\nalert: {}\nstate: {}\nseverity: {}\nvalue: {:.2}°C\nthreshold: {}\ntime: {} UTC\n",
+        alert,
+        alert.probe,
+        alert.kind.as_str(),
+        alert.state.as_str(),
+        alert.severity.as_str(),
+        alert.value,
+        match alert.kind {
+            Kind::Flatline => format!("{:.0}s", alert.threshold),
+            _ => format!("{:.2}°C", alert.threshold),
+        },
+        time::OffsetDateTime::from(alert.since)
+            .replace_nanosecond(0)
+            .unwrap()
+    )
+}
+
+impl Notifier for Email {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError> {
+        self.transport.send(&self.message(alert)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::Kind;
+
+    fn email(to: &[&str]) -> Result<Email, NotifyError> {
+        let config: EmailConfig = toml::from_str(&format!(
+            "host = \"localhost\"\ntls = \"none\"\nfrom = \"tempmon <tempmon@example.com>\"\nto = {:?}\n",
+            to
+        ))
+        .unwrap();
+        Email::new(&config)
+    }
+
+    #[test]
+    fn test_message() {
+        let mut alert = Alert::new("freezer", Kind::High, -15.0, -12.5);
+        alert.state = State::Firing;
+
+        let email = email(&["me@example.com", "Someone Else <them@example.com>"]).unwrap();
+        let message = String::from_utf8(email.message(&alert).unwrap().formatted()).unwrap();
+        assert!(message.contains("From: tempmon <tempmon@example.com>"));
+        assert!(message.contains("me@example.com"));
+        assert!(message.contains("them@example.com"));
+        assert!(message.contains("Subject: [tempmon] FIRING: freezer is above"));
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        assert!(email(&[]).is_err());
+        assert!(email(&["not an address"]).is_err());
+    }
+}
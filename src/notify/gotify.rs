@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use ureq::Agent;
+
+use super::{Notifier, NotifyError, RETRY_BACKOFF, with_retries};
+use crate::alert::{Alert, State};
+use crate::config::GotifyConfig;
+
+#[derive(Serialize)]
+struct Message<'a> {
+    title: &'a str,
+    message: &'a str,
+    priority: u8,
+}
+
+/// Sends alerts to a Gotify server.
+pub struct Gotify {
+    agent: Agent,
+    url: String,
+    token: String,
+    priority: u8,
+    resolved_priority: u8,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Gotify {
+    pub fn new(config: &GotifyConfig) -> Self {
+        Gotify {
+            agent: super::agent(config.timeout),
+            url: format!("{}/message", config.server.trim_end_matches('/')),
+            token: config.token.clone(),
+            priority: config.priority,
+            resolved_priority: config.resolved_priority,
+            retries: config.retries,
+            backoff: RETRY_BACKOFF,
+        }
+    }
+}
+
+impl Notifier for Gotify {
+    fn name(&self) -> &str {
+        "gotify"
+    }
+
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let priority = match alert.state {
+            State::Resolved => self.resolved_priority,
+            _ => self.priority,
+        };
+        let title = super::title(alert);
+        let message = alert.to_string();
+        let body = Message {
+            title: &title,
+            message: &message,
+            priority,
+        };
+
+        with_retries(self.retries, self.backoff, || {
+            Ok(self
+                .agent
+                .post(&self.url)
+                .set("X-Gotify-Key", &self.token)
+                .send_json(&body)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::Kind;
+    use crate::notify::capture_server;
+
+    #[test]
+    fn test_sends_message() {
+        let (url, server) = capture_server(vec![200]);
+        let config: GotifyConfig =
+            toml::from_str(&format!("server = \"{}\"\ntoken = \"app\"\n", url)).unwrap();
+        let mut alert = Alert::new("fermenter", Kind::Low, 18.0, 16.5);
+        alert.state = State::Firing;
+        Gotify::new(&config).send(&alert).unwrap();
+
+        let request = server.join().unwrap().remove(0);
+        assert_eq!(request.url, "/message");
+        assert_eq!(request.header("X-Gotify-Key"), Some("app"));
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body["priority"], 8);
+        assert_eq!(body["title"], "fermenter too low");
+    }
+}
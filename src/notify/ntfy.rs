@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use ureq::Agent;
+
+use super::{Notifier, NotifyError, RETRY_BACKOFF, with_retries};
+use crate::alert::{Alert, Kind, State};
+use crate::config::NtfyConfig;
+
+/// Publishes alerts to an ntfy topic, on ntfy.sh or a self-hosted server.
+pub struct Ntfy {
+    agent: Agent,
+    url: String,
+    token: Option<String>,
+    priority: String,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Ntfy {
+    pub fn new(config: &NtfyConfig) -> Self {
+        Ntfy {
+            agent: super::agent(config.timeout),
+            url: format!("{}/{}", config.server.trim_end_matches('/'), config.topic),
+            token: config.token.clone(),
+            priority: config.priority.clone(),
+            retries: config.retries,
+            backoff: RETRY_BACKOFF,
+        }
+    }
+
+    /// The priority and tags (emoji) to publish an alert with.
+    fn headers(&self, alert: &Alert) -> (&str, &'static str) {
+        match (alert.state, alert.kind) {
+            (State::Resolved, _) => ("default", "white_check_mark"),
+            (_, Kind::High) => (&self.priority, "warning,thermometer,arrow_up"),
+            (_, Kind::Low) => (&self.priority, "warning,thermometer,arrow_down"),
+            (_, Kind::Flatline) => (&self.priority, "warning,thermometer,zzz"),
+        }
+    }
+}
+
+impl Notifier for Ntfy {
+    fn name(&self) -> &str {
+        "ntfy"
+    }
+
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let title = super::title(alert);
+        let (priority, tags) = self.headers(alert);
+        let message = alert.to_string();
+
+        with_retries(self.retries, self.backoff, || {
+            let mut request = self
+                .agent
+                .post(&self.url)
+                .set("Title", &title)
+                .set("Priority", priority)
+                .set("Tags", tags);
+            if let Some(token) = &self.token {
+                request = request.set("Authorization", &format!("Bearer {}", token));
+            }
+            Ok(request.send_string(&message)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::capture_server;
+
+    #[test]
+    fn test_publishes_to_topic() {
+        let (url, server) = capture_server(vec![200]);
+        let config: NtfyConfig = toml::from_str(&format!(
+            "server = \"{}\"\ntopic = \"freezer-alerts\"\ntoken = \"tk_secret\"\n",
+            url
+        ))
+        .unwrap();
+        let mut alert = Alert::new("freezer", Kind::High, -15.0, -12.5);
+        alert.state = State::Firing;
+        Ntfy::new(&config).send(&alert).unwrap();
+
+        let request = server.join().unwrap().remove(0);
+        assert_eq!(request.url, "/freezer-alerts");
+        assert_eq!(request.header("Priority"), Some("high"));
+        assert!(request.header("Tags").unwrap().contains("arrow_up"));
+        assert_eq!(request.header("Authorization"), Some("Bearer tk_secret"));
+        assert!(request.body.starts_with("freezer is above"));
+    }
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use ureq::Agent;
+
+use super::{Notifier, NotifyError, RETRY_BACKOFF, with_retries};
+use crate::alert::{Alert, State};
+use crate::config::PushoverConfig;
+
+const API_URL: &str = "https://api.pushover.net/1/messages.json";
+
+/// Pushover's emergency priority, which repeats until acknowledged.
+const EMERGENCY: i8 = 2;
+
+/// Sends alerts as Pushover notifications.
+pub struct Pushover {
+    agent: Agent,
+    url: String,
+    token: String,
+    user_key: String,
+    priority: i8,
+    resolved_priority: i8,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Pushover {
+    pub fn new(config: &PushoverConfig) -> Self {
+        Pushover {
+            agent: super::agent(config.timeout),
+            url: API_URL.to_string(),
+            token: config.token.clone(),
+            user_key: config.user_key.clone(),
+            priority: config.priority,
+            resolved_priority: config.resolved_priority,
+            retries: config.retries,
+            backoff: RETRY_BACKOFF,
+        }
+    }
+}
+
+impl Notifier for Pushover {
+    fn name(&self) -> &str {
+        "pushover"
+    }
+
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let priority = match alert.state {
+            State::Resolved => self.resolved_priority,
+            _ => self.priority,
+        };
+        let title = super::title(alert);
+        let message = alert.to_string();
+        let priority_field = priority.to_string();
+
+        let mut form = vec![
+            ("token", self.token.as_str()),
+            ("user", &self.user_key),
+            ("title", &title),
+            ("message", &message),
+            ("priority", &priority_field),
+        ];
+        if priority == EMERGENCY {
+            // re-notify every minute for up to an hour until acknowledged
+            form.extend([("retry", "60"), ("expire", "3600")]);
+        }
+
+        with_retries(self.retries, self.backoff, || {
+            Ok(self.agent.post(&self.url).send_form(&form)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::Kind;
+    use crate::notify::capture_server;
+
+    fn send(alert: &Alert, priority: i8) -> String {
+        let (url, server) = capture_server(vec![200]);
+        let config: PushoverConfig = toml::from_str(&format!(
+            "token = \"app\"\nuser_key = \"user\"\npriority = {}\n",
+            priority
+        ))
+        .unwrap();
+        let pushover = Pushover {
+            url: format!("{}1/messages.json", url),
+            ..Pushover::new(&config)
+        };
+        pushover.send(alert).unwrap();
+        server.join().unwrap().remove(0).body
+    }
+
+    #[test]
+    fn test_priorities() {
+        let mut alert = Alert::new("freezer", Kind::High, -15.0, -12.5);
+        alert.state = State::Firing;
+        let body = send(&alert, 1);
+        assert!(body.contains("token=app"));
+        assert!(body.contains("user=user"));
+        assert!(body.contains("priority=1"));
+        assert!(!body.contains("retry="));
+
+        let body = send(&alert, 2);
+        assert!(body.contains("priority=2"));
+        assert!(body.contains("retry=60"));
+
+        alert.state = State::Resolved;
+        assert!(send(&alert, 2).contains("priority=0"));
+    }
+}
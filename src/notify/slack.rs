@@ -0,0 +1,116 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::Serialize;
+use ureq::Agent;
+
+use super::{Notifier, NotifyError, RETRY_BACKOFF, with_retries};
+use crate::alert::Alert;
+use crate::config::ChatWebhookConfig;
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    /// shown in notifications, where attachments aren't
+    text: &'a str,
+    attachments: [Attachment<'a>; 1],
+}
+
+#[derive(Serialize)]
+struct Attachment<'a> {
+    color: String,
+    title: &'a str,
+    text: &'a str,
+    fields: [Field; 2],
+    ts: u64,
+}
+
+#[derive(Serialize)]
+struct Field {
+    title: &'static str,
+    value: String,
+    short: bool,
+}
+
+/// Posts alerts to a Slack incoming webhook.
+pub struct Slack {
+    agent: Agent,
+    url: String,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Slack {
+    pub fn new(config: &ChatWebhookConfig) -> Self {
+        Slack {
+            agent: super::agent(config.timeout),
+            url: config.webhook_url.clone(),
+            retries: config.retries,
+            backoff: RETRY_BACKOFF,
+        }
+    }
+}
+
+fn payload<'a>(alert: &Alert, title: &'a str, text: &'a str) -> Payload<'a> {
+    Payload {
+        text,
+        attachments: [Attachment {
+            color: format!("#{:06x}", super::color(alert)),
+            title,
+            text,
+            fields: [
+                Field {
+                    title: "Value",
+                    value: format!("{:.2}°C", alert.value),
+                    short: true,
+                },
+                Field {
+                    title: "Threshold",
+                    value: format!("{:.2}°C", alert.threshold),
+                    short: true,
+                },
+            ],
+            ts: alert
+                .since
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }],
+    }
+}
+
+impl Notifier for Slack {
+    fn name(&self) -> &str {
+        "slack"
+    }
+
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let title = super::title(alert);
+        let text = alert.to_string();
+        let payload = payload(alert, &title, &text);
+        with_retries(self.retries, self.backoff, || {
+            self.agent
+                .post(&self.url)
+                .send_json(&payload)
+                .map_err(super::without_url)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::{Kind, State};
+
+    #[test]
+    fn test_payload() {
+        let mut alert = Alert::new("fermenter", Kind::High, 22.0, 23.5);
+        alert.state = State::Firing;
+        let json = serde_json::to_value(payload(&alert, "title", "text")).unwrap();
+        let attachment = &json["attachments"][0];
+        assert_eq!(attachment["color"], "#bf616a");
+        assert_eq!(attachment["fields"][0]["value"], "23.50°C");
+
+        alert.state = State::Resolved;
+        let json = serde_json::to_value(payload(&alert, "title", "text")).unwrap();
+        assert_eq!(json["attachments"][0]["color"], "#a3be8c");
+    }
+}
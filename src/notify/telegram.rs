@@ -0,0 +1,218 @@
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use ureq::Agent;
+
+use super::{Notifier, NotifyError, RETRY_BACKOFF, with_retries};
+use crate::alert::{Alert, State};
+use crate::config::TelegramConfig;
+use crate::state::{Readings, TempData};
+
+const API_URL: &str = "https://api.telegram.org";
+
+/// How long each getUpdates call waits for a message, in seconds.
+const POLL_TIMEOUT: u64 = 50;
+
+#[derive(Serialize)]
+struct SendMessage<'a> {
+    chat_id: i64,
+    text: &'a str,
+}
+
+#[derive(Deserialize)]
+struct Updates {
+    result: Vec<Update>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+/// Sends alerts to Telegram chats through a bot.
+pub struct Telegram {
+    agent: Agent,
+    url: String,
+    chat_ids: Vec<i64>,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Telegram {
+    pub fn new(config: &TelegramConfig) -> Self {
+        Telegram {
+            agent: super::agent(config.timeout),
+            url: format!("{}/bot{}", API_URL, config.token),
+            chat_ids: config.chat_ids.clone(),
+            retries: config.retries,
+            backoff: RETRY_BACKOFF,
+        }
+    }
+
+    fn send_message(&self, chat_id: i64, text: &str) -> Result<(), NotifyError> {
+        let url = format!("{}/sendMessage", self.url);
+        with_retries(self.retries, self.backoff, || {
+            self.agent
+                .post(&url)
+                .send_json(SendMessage { chat_id, text })
+                .map_err(super::without_url)
+        })
+    }
+
+    fn updates(&self, offset: i64) -> Result<Vec<Update>, NotifyError> {
+        let updates: Updates = self
+            .agent
+            .get(&format!("{}/getUpdates", self.url))
+            .query("offset", &offset.to_string())
+            .query("timeout", &POLL_TIMEOUT.to_string())
+            .call()
+            .map_err(super::without_url)?
+            .into_json()?;
+        Ok(updates.result)
+    }
+}
+
+impl Notifier for Telegram {
+    fn name(&self) -> &str {
+        "telegram"
+    }
+
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let icon = match alert.state {
+            State::Resolved => "✅",
+            _ => "⚠️",
+        };
+        let text = format!("{} {}", icon, alert);
+
+        let failed: Vec<_> = self
+            .chat_ids
+            .iter()
+            .filter_map(|&chat_id| {
+                self.send_message(chat_id, &text)
+                    .err()
+                    .map(|e| format!("chat {}: {}", chat_id, e))
+            })
+            .collect();
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(failed.join(", ").into())
+        }
+    }
+}
+
+/// Answers `/temps` from the configured chats with the current readings,
+/// on a background thread. Messages from any other chat are ignored.
+pub fn listen(config: &TelegramConfig, current_temps: TempData) {
+    let bot = Telegram {
+        // long enough for the long poll to come back on its own
+        agent: super::agent(POLL_TIMEOUT + config.timeout),
+        ..Telegram::new(config)
+    };
+    thread::spawn(move || {
+        info!("answering telegram commands");
+        let mut offset = 0;
+        loop {
+            let updates = match bot.updates(offset) {
+                Ok(updates) => updates,
+                Err(e) => {
+                    warn!("failed to get telegram updates: {}", e);
+                    sleep(Duration::from_secs(30));
+                    continue;
+                }
+            };
+            for update in updates {
+                offset = update.update_id + 1;
+                let Some(message) = update.message else {
+                    continue;
+                };
+                if !bot.chat_ids.contains(&message.chat.id) {
+                    debug!(chat = message.chat.id, "ignoring message from unknown chat");
+                    continue;
+                }
+                let Some(reply) = message
+                    .text
+                    .as_deref()
+                    .and_then(|text| command_reply(text, &current_temps.snapshot()))
+                else {
+                    continue;
+                };
+                if let Err(e) = bot.send_message(message.chat.id, &reply) {
+                    warn!("failed to answer telegram command: {}", e);
+                }
+            }
+        }
+    });
+}
+
+/// The reply to a bot command, or None if it isn't one we know.
+fn command_reply(text: &str, readings: &Readings) -> Option<String> {
+    // commands in groups are sent as /temps@botname
+    let command = text.split_whitespace().next()?.split('@').next()?;
+    if command != "/temps" {
+        return None;
+    }
+
+    let mut temps: Vec<_> = readings.temps.iter().collect();
+    temps.sort_by_key(|(name, _)| name.as_str());
+    let lines: Vec<_> = temps
+        .into_iter()
+        .map(|(name, temp)| match temp {
+            Some(t) => format!("{}: {:.2}°C", name, t),
+            None => format!("{}: no reading", name),
+        })
+        .collect();
+    Some(if lines.is_empty() {
+        "no probes".to_string()
+    } else {
+        lines.join("\n")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temps_command() {
+        let mut readings = Readings::new(["cool_side", "basking_spot"]);
+        readings.record("basking_spot", Some(31.25));
+
+        assert_eq!(
+            command_reply("/temps", &readings).unwrap(),
+            "basking_spot: 31.25°C\ncool_side: no reading"
+        );
+        assert!(command_reply("/temps@tempmon_bot", &readings).is_some());
+        assert!(command_reply("/start", &readings).is_none());
+        assert!(command_reply("hello", &readings).is_none());
+    }
+
+    #[test]
+    fn test_parse_updates() {
+        let updates: Updates = serde_json::from_str(
+            r#"{"ok":true,"result":[
+                {"update_id":7,"message":{"message_id":1,"chat":{"id":42,"type":"private"},"text":"/temps"}},
+                {"update_id":8,"edited_message":{}}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(updates.result.len(), 2);
+        let message = updates.result[0].message.as_ref().unwrap();
+        assert_eq!(message.chat.id, 42);
+        assert!(updates.result[1].message.is_none());
+    }
+}
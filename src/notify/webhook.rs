@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use ureq::Agent;
+
+use super::{Notifier, NotifyError, Payload, RETRY_BACKOFF, with_retries};
+use crate::alert::Alert;
+use crate::config::WebhookConfig;
+
+/// POSTs alerts as JSON to each configured url.
+pub struct Webhook {
+    agent: Agent,
+    urls: Vec<String>,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Webhook {
+    pub fn new(config: &WebhookConfig) -> Self {
+        Webhook {
+            agent: super::agent(config.timeout),
+            urls: config.urls.clone(),
+            retries: config.retries,
+            backoff: RETRY_BACKOFF,
+        }
+    }
+
+    fn post(&self, url: &str, payload: &Payload) -> Result<(), NotifyError> {
+        with_retries(self.retries, self.backoff, || {
+            Ok(self.agent.post(url).send_json(payload)?)
+        })
+    }
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn send(&self, alert: &Alert) -> Result<(), NotifyError> {
+        let payload = Payload::new(alert);
+        let failed: Vec<_> = self
+            .urls
+            .iter()
+            .filter_map(|url| {
+                self.post(url, &payload)
+                    .err()
+                    .map(|e| format!("{}: {}", url, e))
+            })
+            .collect();
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(failed.join(", ").into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert::{Kind, State};
+    use crate::notify::capture_server;
+
+    fn alert() -> Alert {
+        let mut alert = Alert::new("freezer", Kind::High, -15.0, -12.5);
+        alert.state = State::Firing;
+        alert
+    }
+
+    fn webhook(url: String, retries: u32) -> Webhook {
+        Webhook {
+            agent: crate::notify::agent(5),
+            urls: vec![url],
+            retries,
+            backoff: Duration::from_millis(1),
+        }
+    }
+
+    #[test]
+    fn test_posts_payload() {
+        let (url, server) = capture_server(vec![200]);
+        webhook(format!("{}hook", url), 0).send(&alert()).unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].url, "/hook");
+        let payload: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(payload["probe"], "freezer");
+        assert_eq!(payload["alert"], "high");
+        assert_eq!(payload["state"], "firing");
+        assert_eq!(payload["value"], -12.5);
+        assert_eq!(payload["threshold"], -15.0);
+        assert_eq!(payload["severity"], "warning");
+        assert!(payload["timestamp"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_retries() {
+        let (url, server) = capture_server(vec![500, 503, 200]);
+        webhook(url, 2).send(&alert()).unwrap();
+        assert_eq!(server.join().unwrap().len(), 3);
+
+        let (url, server) = capture_server(vec![500, 500]);
+        assert!(webhook(url, 1).send(&alert()).is_err());
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,423 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use tokio::task::{self, AbortHandle};
+use tracing::debug;
+
+use crate::config::Config;
+use crate::probe::{ProbeError, Sensor};
+
+/// Wait before the first retry of a bad read, doubled for each retry after.
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// A successful read, before and after calibration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub raw: f32,
+    pub temp: f32,
+    /// exponential moving average of `temp`, for probes with smoothing set
+    pub smoothed: Option<f32>,
+}
+
+/// Per-probe state carried between polling cycles. Clones share it, so a
+/// poller started in place of a hung one carries on where that left off.
+#[derive(Clone, Default)]
+pub struct Poller {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    averages: HashMap<String, f32>,
+    /// last accepted reading for probes with a maximum change rate
+    previous: HashMap<String, (f32, Instant)>,
+    /// the latest read of each probe, which may not have returned yet
+    reads: HashMap<String, AbortHandle>,
+}
+
+impl Poller {
+    /// Reads every probe on a blocking task of its own so a hung read
+    /// can't hold up the others, giving up on any that take longer than the
+    /// configured timeout. Each read starts up to `read_splay` late, so they
+    /// don't all hit the bus at once. Results are in the same order as
+    /// `probes`.
+    pub async fn read_all<S: Sensor>(
+        &self,
+        probes: &[S],
+        config: &Arc<Config>,
+    ) -> Vec<Result<Sample, ProbeError>> {
+        let timeout = Duration::from_secs_f64(config.settings.read_timeout);
+        let started = tokio::time::Instant::now();
+
+        let pending: Vec<_> = {
+            let mut state = self.state.lock().unwrap();
+            probes
+                .iter()
+                .map(|probe| {
+                    // don't pile up tasks behind a read that is still hanging
+                    if let Some(read) = state.reads.get(probe.id())
+                        && !read.is_finished()
+                    {
+                        return None;
+                    }
+                    let id = probe.id().to_string();
+                    let (probe, config) = (probe.clone(), Arc::clone(config));
+                    let splay =
+                        Duration::from_secs_f64(fastrand::f64() * config.settings.read_splay);
+                    let task = task::spawn_blocking(move || {
+                        sleep(splay);
+                        read(&probe, &config)
+                    });
+                    state.reads.insert(id, task.abort_handle());
+                    // the timeout starts once the read does
+                    Some((started + splay + timeout, task))
+                })
+                .collect()
+        };
+
+        let mut results = Vec::with_capacity(probes.len());
+        for (probe, pending) in probes.iter().zip(pending) {
+            let result = match pending {
+                None => Err(ProbeError::Timeout(timeout)),
+                Some((deadline, task)) => match tokio::time::timeout_at(deadline, task).await {
+                    Ok(joined) => {
+                        joined.unwrap_or_else(|e| Err(ProbeError::Io(io::Error::other(e))))
+                    }
+                    Err(_) => Err(ProbeError::Timeout(timeout)),
+                },
+            };
+            let result = result.and_then(|sample| {
+                let mut state = self.state.lock().unwrap();
+                state.process(probe.id(), sample, config)
+            });
+            results.push(result);
+        }
+        results
+    }
+}
+
+impl State {
+    /// Applies the filters that depend on earlier readings.
+    fn process(
+        &mut self,
+        id: &str,
+        mut sample: Sample,
+        config: &Config,
+    ) -> Result<Sample, ProbeError> {
+        if let Some(max_rate) = config.max_change_rate.get(id) {
+            self.check_rate(id, sample.temp, Instant::now(), *max_rate)?;
+        }
+        sample.smoothed = config
+            .smoothing
+            .get(id)
+            .map(|alpha| self.smooth(id, sample.temp, *alpha));
+        Ok(sample)
+    }
+
+    /// Rejects a reading that moved away from the last accepted one faster
+    /// than `max_rate`. Measuring from the last accepted reading means a
+    /// real step change is let through once enough time has passed.
+    fn check_rate(
+        &mut self,
+        id: &str,
+        temp: f32,
+        now: Instant,
+        max_rate: f32,
+    ) -> Result<(), ProbeError> {
+        if let Some((previous, at)) = self.previous.get(id) {
+            let elapsed = now.duration_since(*at).as_secs_f32().max(1.0);
+            let rate = (temp - previous).abs() / elapsed;
+            if rate > max_rate {
+                return Err(ProbeError::Outlier { temp, rate });
+            }
+        }
+        self.previous.insert(id.to_string(), (temp, now));
+        Ok(())
+    }
+
+    fn smooth(&mut self, id: &str, temp: f32, alpha: f32) -> f32 {
+        let alpha = alpha.clamp(f32::MIN_POSITIVE, 1.0);
+        let average = self
+            .averages
+            .entry(id.to_string())
+            .and_modify(|avg| *avg += alpha * (temp - *avg))
+            .or_insert(temp);
+        *average
+    }
+}
+
+/// Reads a probe and applies its calibration, rejecting implausible values.
+pub fn read(probe: &impl Sensor, config: &Config) -> Result<Sample, ProbeError> {
+    let raw = read_median(
+        probe,
+        config.settings.median_samples,
+        config.settings.read_retries,
+    )?;
+    check(probe.id(), raw, config)
+}
+
+/// Takes `samples` consecutive reads and returns the median of the ones
+/// that succeeded, so a single glitched sample can't get through.
+fn read_median(probe: &impl Sensor, samples: u32, retries: u32) -> Result<f32, ProbeError> {
+    let mut readings = Vec::new();
+    let mut last_error = None;
+    for _ in 0..samples.max(1) {
+        match read_with_retry(probe, retries) {
+            Ok(temp) => readings.push(temp),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    match median(&mut readings) {
+        Some(temp) => Ok(temp),
+        None => Err(last_error.unwrap()),
+    }
+}
+
+/// Reads a probe, retrying with backoff when the data is corrupt. The
+/// DS18B20 datasheet expects the odd bad crc, so one isn't worth reporting.
+fn read_with_retry(probe: &impl Sensor, retries: u32) -> Result<f32, ProbeError> {
+    let mut backoff = RETRY_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        match probe.read_temperature() {
+            Err(e) if e.is_transient() && attempt < retries => {
+                attempt += 1;
+                debug!(probe = probe.name(), attempt, "retrying bad read: {}", e);
+                sleep(backoff);
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn median(values: &mut [f32]) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f32::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some((values[mid - 1] + values[mid]) / 2.0)
+    } else {
+        Some(values[mid])
+    }
+}
+
+fn check(id: &str, raw: f32, config: &Config) -> Result<Sample, ProbeError> {
+    let temp = config.calibrate(id, raw);
+    if let Some(range) = config.plausible_ranges.get(id)
+        && !range.contains(temp)
+    {
+        return Err(ProbeError::OutOfRange(temp));
+    }
+    Ok(Sample {
+        raw,
+        temp,
+        smoothed: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "w1")]
+    use crate::{backend::w1, probe::Probe};
+
+    fn config() -> Config {
+        toml::from_str(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+
+[calibration_offsets]
+"28-outdoor" = 1.0
+
+[plausible_ranges]
+"28-outdoor" = { min = -30.0, max = 60.0 }
+"28-freezer" = { max = 0.0 }
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_check_within_range() {
+        let sample = check("28-outdoor", 20.0, &config()).unwrap();
+        assert_eq!(
+            sample,
+            Sample {
+                raw: 20.0,
+                temp: 21.0,
+                smoothed: None,
+            }
+        );
+
+        // probes without a range accept anything
+        assert!(check("28-other", 127.0, &config()).is_ok());
+    }
+
+    #[test]
+    fn test_check_out_of_range() {
+        let config = config();
+
+        // the range applies to the calibrated value
+        let err = check("28-outdoor", 59.5, &config).unwrap_err();
+        assert_eq!(err.error_type(), "out_of_range");
+        assert!(check("28-outdoor", -31.5, &config).is_err());
+
+        assert!(check("28-freezer", 4.0, &config).is_err());
+        assert!(check("28-freezer", -80.0, &config).is_ok());
+    }
+
+    #[cfg(feature = "w1")]
+    #[test]
+    fn test_read_from_sysfs_file() {
+        let dir = std::env::temp_dir().join(format!("tempmon-poll-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("w1_slave");
+        std::fs::write(
+            &path,
+            "6d 01 55 05 7f a5 a5 66 3e : crc=3e YES\n6d 01 55 05 7f a5 a5 66 3e t=22812\n",
+        )
+        .unwrap();
+        let probe = Probe {
+            id: "28-outdoor".to_string(),
+            name: "outdoor".to_string(),
+            source: Arc::new(w1::Device::new(&path)),
+        };
+
+        let mut config = config();
+        config.settings.median_samples = 3;
+        let sample = read(&probe, &config).unwrap();
+        assert_eq!(sample.raw, 22.812);
+        assert_eq!(sample.temp, 23.812);
+
+        std::fs::remove_file(&path).unwrap();
+        let err = read(&probe, &config).unwrap_err();
+        assert_eq!(err.error_type(), "not_found");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "w1")]
+    #[test]
+    fn test_bad_crc_is_retried() {
+        let dir = std::env::temp_dir().join(format!("tempmon-retry-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("w1_slave");
+        std::fs::write(
+            &path,
+            "6d 01 55 05 7f a5 a5 66 3e : crc=3e NO\n6d 01 55 05 7f a5 a5 66 3e t=22812\n",
+        )
+        .unwrap();
+        let probe = Probe {
+            id: "28-outdoor".to_string(),
+            name: "outdoor".to_string(),
+            source: Arc::new(w1::Device::new(&path)),
+        };
+
+        let started = Instant::now();
+        let err = read_with_retry(&probe, 2).unwrap_err();
+        assert!(matches!(err, ProbeError::CrcFailure));
+        // 100ms then 200ms of backoff
+        assert!(started.elapsed() >= Duration::from_millis(300));
+
+        // missing files aren't worth retrying
+        std::fs::remove_file(&path).unwrap();
+        let started = Instant::now();
+        assert!(matches!(
+            read_with_retry(&probe, 2),
+            Err(ProbeError::NotFound(_))
+        ));
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "w1")]
+    #[tokio::test]
+    async fn test_hung_read_times_out() {
+        let dir = std::env::temp_dir().join(format!("tempmon-timeout-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // reading a fifo blocks until something writes to it
+        let path = dir.join("w1_slave");
+        let status = std::process::Command::new("mkfifo")
+            .arg(&path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let probe = Probe {
+            id: "28-outdoor".to_string(),
+            name: "outdoor".to_string(),
+            source: Arc::new(w1::Device::new(&path)),
+        };
+
+        let mut config = config();
+        config.settings.read_timeout = 0.2;
+        let config = Arc::new(config);
+        let poller = Poller::default();
+
+        let results = poller.read_all(std::slice::from_ref(&probe), &config).await;
+        assert_eq!(results[0].as_ref().unwrap_err().error_type(), "timeout");
+
+        // while the first read is still stuck no new one is started
+        let started = Instant::now();
+        let results = poller.read_all(std::slice::from_ref(&probe), &config).await;
+        assert_eq!(results[0].as_ref().unwrap_err().error_type(), "timeout");
+        assert!(started.elapsed() < Duration::from_millis(100));
+
+        // unblock the stuck read
+        std::fs::write(&path, "").unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_smooth() {
+        let mut state = State::default();
+        // the first reading seeds the average
+        assert_eq!(state.smooth("28-a", 20.0, 0.5), 20.0);
+        assert_eq!(state.smooth("28-a", 22.0, 0.5), 21.0);
+        assert_eq!(state.smooth("28-a", 22.0, 0.5), 21.5);
+        // probes are averaged separately
+        assert_eq!(state.smooth("28-b", 10.0, 0.5), 10.0);
+        // alpha = 1 is no smoothing at all
+        assert_eq!(state.smooth("28-a", 30.0, 1.0), 30.0);
+    }
+
+    #[test]
+    fn test_check_rate() {
+        let mut state = State::default();
+        let start = Instant::now();
+        let secs = |n| start + Duration::from_secs(n);
+
+        assert!(state.check_rate("28-a", 40.0, start, 0.1).is_ok());
+        assert!(state.check_rate("28-a", 41.0, secs(15), 0.1).is_ok());
+
+        let err = state.check_rate("28-a", 127.0, secs(30), 0.1).unwrap_err();
+        assert_eq!(err.error_type(), "outlier");
+
+        // compared against the last accepted reading, not the outlier
+        assert!(state.check_rate("28-a", 42.0, secs(45), 0.1).is_ok());
+
+        // a real step change gets through once enough time has passed
+        assert!(state.check_rate("28-a", 50.0, secs(60), 0.1).is_err());
+        assert!(state.check_rate("28-a", 50.0, secs(200), 0.1).is_ok());
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&mut [21.0, 85.0, 21.5]), Some(21.5));
+        assert_eq!(median(&mut [21.0, 22.0]), Some(21.5));
+        assert_eq!(median(&mut [21.0]), Some(21.0));
+        assert_eq!(median(&mut []), None);
+    }
+}
@@ -1,149 +1,173 @@
 use std::collections::HashMap;
-use std::fs;
+use std::fmt;
 use std::io;
-use std::path::Path;
-
-const W1_DEVICES_PATH: &str = "/sys/bus/w1/devices";
-
-pub struct Probe {
-    pub id: String,
-    pub name: String,
-    pub path: String,
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Every `error_type` label used on the read errors counter.
+pub const ERROR_TYPES: &[&str] = &[
+    "crc_failure",
+    "parse",
+    "not_found",
+    "permission_denied",
+    "timeout",
+    "out_of_range",
+    "outlier",
+    "io",
+];
+
+/// Why a probe didn't give a usable reading.
+#[derive(Debug, thiserror::Error)]
+pub enum ProbeError {
+    #[error("crc check failed")]
+    CrcFailure,
+    #[error("couldn't parse the reading: {0}")]
+    Parse(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    /// the read didn't finish within the configured timeout
+    #[error("read timed out after {:.1}s", .0.as_secs_f32())]
+    Timeout(Duration),
+    /// the calibrated reading is outside the probe's plausible range
+    #[error("{0:.2}°c is outside the plausible range")]
+    OutOfRange(f32),
+    /// the reading changed faster than the probe's maximum rate, in °c/s
+    #[error("{temp:.2}°c is a jump of {rate:.2}°c/s, ignoring it")]
+    Outlier { temp: f32, rate: f32 },
+    #[error(transparent)]
+    Io(io::Error),
 }
 
-impl Probe {
-    pub fn set_resolution(&self, bits: u8) -> io::Result<()> {
-        let resolution_path = self.path.replace("/w1_slave", "/resolution");
-        fs::write(resolution_path, bits.to_string())
+impl ProbeError {
+    /// Its `error_type` label, one of [`ERROR_TYPES`].
+    pub fn error_type(&self) -> &'static str {
+        match self {
+            ProbeError::CrcFailure => "crc_failure",
+            ProbeError::Parse(_) => "parse",
+            ProbeError::NotFound(_) => "not_found",
+            ProbeError::PermissionDenied(_) => "permission_denied",
+            ProbeError::Timeout(_) => "timeout",
+            ProbeError::OutOfRange(_) => "out_of_range",
+            ProbeError::Outlier { .. } => "outlier",
+            ProbeError::Io(_) => "io",
+        }
     }
 
-    pub fn read_temperature(&self) -> io::Result<f32> {
-        let data = fs::read_to_string(&self.path)?;
-        parse_temperature_data(&data)
+    /// Whether reading again straight away might well work, as after the
+    /// odd bad crc the DS18B20 datasheet expects.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ProbeError::CrcFailure | ProbeError::Parse(_))
     }
 }
 
-fn parse_temperature_data(data: &str) -> io::Result<f32> {
-    // the file format looks like:
-    // 6d 01 55 05 7f a5 a5 66 3e : crc=3e YES
-    // 6d 01 55 05 7f a5 a5 66 3e t=22812
-
-    // check the crc
-    if !data.contains("YES") {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "crc check failed",
-        ));
-    }
-
-    // find and read the temperature data
-    if let Some(temp_pos) = data.find("t=") {
-        let temp_str = data[temp_pos + 2..].trim();
-        if let Ok(temp_raw) = temp_str.parse::<i32>() {
-            return Ok(temp_raw as f32 / 1000.0);
+impl From<io::Error> for ProbeError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => ProbeError::NotFound(e.to_string()),
+            io::ErrorKind::PermissionDenied => ProbeError::PermissionDenied(e.to_string()),
+            _ => ProbeError::Io(e),
         }
     }
-
-    Err(io::Error::new(
-        io::ErrorKind::InvalidData,
-        "failed to parse temperature",
-    ))
 }
 
-pub fn discover_probes(labels: &HashMap<String, String>) -> io::Result<Vec<Probe>> {
-    let mut probes = Vec::new();
+/// How a probe is read, which each backend provides for its own.
+pub trait Source: fmt::Debug + Send + Sync {
+    /// A reading in °c. Transient errors are retried.
+    fn read_temperature(&self) -> Result<f32, ProbeError>;
 
-    if !Path::new(W1_DEVICES_PATH).exists() {
-        eprintln!(
-            "warning: {} not found. make sure w1-gpio is enabled.",
-            W1_DEVICES_PATH
-        );
-        return Ok(probes);
+    /// Sets the resolution in bits, on probes that have one.
+    fn set_resolution(&self, _bits: u8) -> io::Result<()> {
+        Ok(())
     }
 
-    for entry in fs::read_dir(W1_DEVICES_PATH)? {
-        let id = entry?.file_name().to_string_lossy().to_string();
-
-        if id.starts_with("28-") {
-            let name = labels.get(&id).cloned().unwrap_or_else(|| id.clone());
-            probes.push(Probe {
-                id: id.clone(),
-                name,
-                path: format!("{}/{}/w1_slave", W1_DEVICES_PATH, id),
-            });
-        }
+    /// The resolution currently configured on the probe, in bits.
+    fn resolution(&self) -> io::Result<u8> {
+        Err(io::ErrorKind::Unsupported.into())
     }
 
-    Ok(probes)
+    /// Where it's read from, for `tempmon list`.
+    fn location(&self) -> String;
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_parse_valid_temperature() {
-        let data = "6d 01 55 05 7f a5 a5 66 3e : crc=3e YES\n\
-                    6d 01 55 05 7f a5 a5 66 3e t=22812\n";
+#[derive(Debug, Clone)]
+pub struct Probe {
+    pub id: String,
+    pub name: String,
+    pub source: Arc<dyn Source>,
+}
 
-        let temp = parse_temperature_data(data).unwrap();
-        assert_eq!(temp, 22.812);
+impl Probe {
+    pub fn set_resolution(&self, bits: u8) -> io::Result<()> {
+        self.source.set_resolution(bits)
     }
 
-    #[test]
-    fn test_parse_negative_temperature() {
-        let data = "50 05 4b 46 7f ff 0c 10 1c : crc=1c YES\n\
-                    50 05 4b 46 7f ff 0c 10 1c t=-10562\n";
+    /// The resolution currently configured on the probe, in bits.
+    pub fn resolution(&self) -> io::Result<u8> {
+        self.source.resolution()
+    }
 
-        let temp = parse_temperature_data(data).unwrap();
-        assert_eq!(temp, -10.562);
+    pub fn read_temperature(&self) -> Result<f32, ProbeError> {
+        self.source.read_temperature()
     }
+}
 
-    #[test]
-    fn test_parse_zero_temperature() {
-        let data = "00 00 00 00 00 00 00 00 00 : crc=00 YES\n\
-                    00 00 00 00 00 00 00 00 00 t=0\n";
+/// A temperature sensor the poller can read. tempmon's own are the
+/// [`Probe`]s its backends find; implement this to poll others with the same
+/// calibration, filters and timeouts.
+pub trait Sensor: Clone + Send + 'static {
+    /// What its calibration and filters are configured under.
+    fn id(&self) -> &str;
+    /// What it's shown as.
+    fn name(&self) -> &str;
+    /// A reading in °c. Transient errors are retried.
+    fn read_temperature(&self) -> Result<f32, ProbeError>;
+}
 
-        let temp = parse_temperature_data(data).unwrap();
-        assert_eq!(temp, 0.0);
+impl Sensor for Probe {
+    fn id(&self) -> &str {
+        &self.id
     }
 
-    #[test]
-    fn test_parse_crc_failure() {
-        let data = "6d 01 55 05 7f a5 a5 66 3e : crc=3e NO\n\
-                    6d 01 55 05 7f a5 a5 66 3e t=22812\n";
+    fn name(&self) -> &str {
+        &self.name
+    }
 
-        let result = parse_temperature_data(data);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    fn read_temperature(&self) -> Result<f32, ProbeError> {
+        Probe::read_temperature(self)
     }
+}
 
-    #[test]
-    fn test_parse_missing_temperature() {
-        let data = "6d 01 55 05 7f a5 a5 66 3e : crc=3e YES\n\
-                    6d 01 55 05 7f a5 a5 66 3e\n";
+/// Degrees between successive readings at a resolution in bits, e.g.
+/// 0.25°C at 10 bits.
+pub fn resolution_step(bits: u8) -> f32 {
+    0.5 / f32::from(1u16 << bits.saturating_sub(9))
+}
 
-        let result = parse_temperature_data(data);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
-    }
+/// The configured label for a probe, falling back to its hardware id.
+pub fn display_name(id: &str, labels: &HashMap<String, String>) -> String {
+    labels.get(id).cloned().unwrap_or_else(|| id.to_string())
+}
 
-    #[test]
-    fn test_parse_malformed_temperature() {
-        let data = "6d 01 55 05 7f a5 a5 66 3e : crc=3e YES\n\
-                    6d 01 55 05 7f a5 a5 66 3e t=invalid\n";
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let result = parse_temperature_data(data);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    #[test]
+    fn test_error_from_io() {
+        let e = ProbeError::from(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(e.error_type(), "permission_denied");
+        let e = ProbeError::from(io::Error::other("bus reset"));
+        assert_eq!(e.error_type(), "io");
+        assert_eq!(e.to_string(), "bus reset");
+        assert!(ERROR_TYPES.contains(&ProbeError::CrcFailure.error_type()));
     }
 
     #[test]
-    fn test_parse_empty_string() {
-        let data = "";
-
-        let result = parse_temperature_data(data);
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    fn test_resolution_step() {
+        assert_eq!(resolution_step(9), 0.5);
+        assert_eq!(resolution_step(10), 0.25);
+        assert_eq!(resolution_step(12), 0.0625);
     }
 }
@@ -0,0 +1,123 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::PwmConfig;
+use crate::gpio::write_exported;
+
+enum Target {
+    /// a sysfs pwm channel's duty_cycle file, in nanoseconds of `period`
+    Chip { duty_cycle: PathBuf, period: u64 },
+    /// a hwmon pwm file, 0 to 255
+    Hwmon(PathBuf),
+}
+
+/// A pwm output, e.g. a fan, set as a duty cycle percentage.
+pub struct Output {
+    target: Target,
+}
+
+impl Output {
+    pub fn open(config: &PwmConfig) -> io::Result<Self> {
+        let target = match (&config.chip, &config.hwmon) {
+            (Some(chip), _) => Target::Chip {
+                duty_cycle: export(Path::new(chip), config.channel, config.period)?,
+                period: config.period,
+            },
+            (None, Some(hwmon)) => {
+                // 1 is manual control, rather than the chip's own curve
+                let enable = PathBuf::from(format!("{}_enable", hwmon));
+                if enable.exists() {
+                    fs::write(enable, "1")?;
+                }
+                Target::Hwmon(PathBuf::from(hwmon))
+            }
+            (None, None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "pwm needs either chip or hwmon",
+                ));
+            }
+        };
+        Ok(Output { target })
+    }
+
+    /// Sets the duty cycle, from 0 to 100%.
+    pub fn set(&self, duty: f32) -> io::Result<()> {
+        let duty = duty.clamp(0.0, 100.0) / 100.0;
+        match &self.target {
+            Target::Chip { duty_cycle, period } => {
+                let ns = (*period as f64 * f64::from(duty)).round() as u64;
+                fs::write(duty_cycle, ns.to_string())
+            }
+            Target::Hwmon(path) => fs::write(path, ((duty * 255.0).round() as u8).to_string()),
+        }
+    }
+}
+
+/// Exports and enables a channel of a sysfs pwm chip with its duty cycle
+/// at 0, returning the path of its duty_cycle file.
+fn export(chip: &Path, channel: u32, period: u64) -> io::Result<PathBuf> {
+    let dir = chip.join(format!("pwm{}", channel));
+    if !dir.exists() {
+        fs::write(chip.join("export"), channel.to_string())?;
+    }
+    // the duty cycle can't be longer than the period, so clear it first
+    write_exported(&dir.join("duty_cycle"), "0")?;
+    fs::write(dir.join("period"), period.to_string())?;
+    fs::write(dir.join("enable"), "1")?;
+    Ok(dir.join("duty_cycle"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tempmon-pwm-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_chip() {
+        let chip = temp_dir("chip");
+        fs::create_dir_all(chip.join("pwm0")).unwrap();
+        let output = Output::open(&PwmConfig {
+            chip: Some(chip.to_string_lossy().to_string()),
+            channel: 0,
+            period: 40_000,
+            hwmon: None,
+        })
+        .unwrap();
+        assert_eq!(fs::read_to_string(chip.join("pwm0/enable")).unwrap(), "1");
+
+        output.set(25.0).unwrap();
+        let duty_cycle = chip.join("pwm0/duty_cycle");
+        assert_eq!(fs::read_to_string(&duty_cycle).unwrap(), "10000");
+        output.set(150.0).unwrap();
+        assert_eq!(fs::read_to_string(&duty_cycle).unwrap(), "40000");
+
+        fs::remove_dir_all(&chip).unwrap();
+    }
+
+    #[test]
+    fn test_hwmon() {
+        let hwmon = temp_dir("hwmon");
+        let pwm = hwmon.join("pwm1");
+        fs::write(hwmon.join("pwm1_enable"), "2").unwrap();
+        let output = Output::open(&PwmConfig {
+            chip: None,
+            channel: 0,
+            period: 40_000,
+            hwmon: Some(pwm.to_string_lossy().to_string()),
+        })
+        .unwrap();
+        assert_eq!(fs::read_to_string(hwmon.join("pwm1_enable")).unwrap(), "1");
+
+        output.set(50.0).unwrap();
+        assert_eq!(fs::read_to_string(&pwm).unwrap(), "128");
+
+        fs::remove_dir_all(&hwmon).unwrap();
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// forget clients that have been idle long enough to have a full bucket
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Per-client token bucket rate limiter.
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32, burst: u32) -> Self {
+        RateLimiter {
+            per_second: f64::from(requests_per_minute) / 60.0,
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token for `ip`, or returns how long until one is available.
+    pub fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            let refill = Duration::from_secs_f64(self.burst / self.per_second);
+            buckets.retain(|_, b| now.duration_since(b.updated) < refill);
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.per_second,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows_burst_then_limits() {
+        let limiter = RateLimiter::new(60, 3);
+        let ip: IpAddr = "192.168.1.10".parse().unwrap();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check(ip, now).is_ok());
+        }
+        let retry_after = limiter.check(ip, now).unwrap_err();
+        assert_eq!(retry_after.as_secs(), 1);
+    }
+
+    #[test]
+    fn test_refills_over_time() {
+        let limiter = RateLimiter::new(60, 1);
+        let ip: IpAddr = "192.168.1.10".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(limiter.check(ip, now).is_ok());
+        assert!(limiter.check(ip, now).is_err());
+        assert!(limiter.check(ip, now + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn test_clients_are_independent() {
+        let limiter = RateLimiter::new(60, 1);
+        let now = Instant::now();
+
+        assert!(limiter.check("10.0.0.1".parse().unwrap(), now).is_ok());
+        assert!(limiter.check("10.0.0.2".parse().unwrap(), now).is_ok());
+    }
+}
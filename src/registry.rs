@@ -0,0 +1,293 @@
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{Value, json};
+use tracing::{info, warn};
+use ureq::{Agent, AgentBuilder};
+
+use crate::config::{RegistryBackend, RegistryConfig};
+use crate::state::TempData;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Registers this instance with consul or etcd, keeps the registration
+/// alive while tempmon runs, and takes it away again on shutdown.
+#[derive(Clone)]
+pub struct Registration {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    config: RegistryConfig,
+    agent: Agent,
+    id: String,
+    address: String,
+    port: u16,
+    /// the etcd lease the key is attached to
+    lease: Mutex<Option<String>>,
+}
+
+impl Registration {
+    /// Registers in the background, re-registering whenever the probes
+    /// change or the registry has lost track of us.
+    pub fn start(config: &RegistryConfig, port: u16, temps: TempData) -> Self {
+        let hostname = hostname();
+        let inner = Arc::new(Inner {
+            config: config.clone(),
+            agent: AgentBuilder::new()
+                .timeout(TIMEOUT)
+                .user_agent(concat!("tempmon/", env!("CARGO_PKG_VERSION")))
+                .build(),
+            id: config
+                .id
+                .clone()
+                .unwrap_or_else(|| format!("{}-{}", config.service, hostname)),
+            address: config.address.clone().unwrap_or(hostname),
+            port,
+            lease: Mutex::new(None),
+        });
+
+        let registration = Registration { inner };
+        let worker = registration.clone();
+        thread::spawn(move || {
+            let inner = &worker.inner;
+            // heartbeat well inside the ttl, so one slow request doesn't
+            // let it lapse
+            let interval = Duration::from_secs((inner.config.ttl / 3).max(1));
+            let mut registered: Option<Vec<String>> = None;
+            loop {
+                let mut probes: Vec<String> = temps.snapshot().temps.keys().cloned().collect();
+                probes.sort();
+                if registered.as_ref() != Some(&probes) {
+                    match inner.register(&probes).and_then(|()| inner.heartbeat()) {
+                        Ok(()) => {
+                            if registered.is_none() {
+                                info!(
+                                    id = inner.id,
+                                    "registered with {}",
+                                    inner.config.backend.as_str()
+                                );
+                            }
+                            registered = Some(probes);
+                        }
+                        Err(e) => warn!(
+                            "failed to register with {}: {}",
+                            inner.config.backend.as_str(),
+                            e
+                        ),
+                    }
+                } else if let Err(e) = inner.heartbeat() {
+                    warn!(
+                        "registration with {} lapsed, registering again: {}",
+                        inner.config.backend.as_str(),
+                        e
+                    );
+                    registered = None;
+                    continue;
+                }
+                thread::sleep(interval);
+            }
+        });
+        registration
+    }
+
+    /// Takes the registration away, so nothing scrapes a stopped instance.
+    pub fn deregister(&self) {
+        let inner = &self.inner;
+        match inner.deregister() {
+            Ok(()) => info!(id = inner.id, "deregistered"),
+            Err(e) => warn!("failed to deregister: {}", e),
+        }
+    }
+}
+
+impl Inner {
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.config.url.trim_end_matches('/'), path)
+    }
+
+    /// Makes a request with a JSON body, or none for `Value::Null`.
+    fn call(&self, method: &str, path: &str, body: Value) -> Result<Value, String> {
+        let mut request = self.agent.request(method, &self.url(path));
+        if let Some(token) = &self.config.token {
+            request = match self.config.backend {
+                RegistryBackend::Consul => request.set("X-Consul-Token", token),
+                RegistryBackend::Etcd => request.set("Authorization", token),
+            };
+        }
+        let response = if body.is_null() {
+            request.call()
+        } else {
+            request.send_json(body)
+        }
+        .map_err(|e| e.to_string())?;
+        // consul answers some requests with an empty body
+        let text = response.into_string().map_err(|e| e.to_string())?;
+        Ok(serde_json::from_str(&text).unwrap_or(Value::Null))
+    }
+
+    fn register(&self, probes: &[String]) -> Result<(), String> {
+        match self.config.backend {
+            RegistryBackend::Consul => {
+                let service = consul_service(self, probes);
+                self.call("PUT", "/v1/agent/service/register", service)?;
+            }
+            RegistryBackend::Etcd => {
+                let grant =
+                    self.call("POST", "/v3/lease/grant", json!({ "TTL": self.config.ttl }))?;
+                let lease = grant["ID"]
+                    .as_str()
+                    .ok_or("etcd didn't grant a lease")?
+                    .to_string();
+                let put = json!({
+                    "key": STANDARD.encode(etcd_key(self)),
+                    "value": STANDARD.encode(etcd_value(self, probes).to_string()),
+                    "lease": lease,
+                });
+                self.call("POST", "/v3/kv/put", put)?;
+                *self.lease.lock().unwrap() = Some(lease);
+            }
+        }
+        Ok(())
+    }
+
+    fn heartbeat(&self) -> Result<(), String> {
+        match self.config.backend {
+            RegistryBackend::Consul => {
+                let path = format!("/v1/agent/check/pass/{}", consul_check_id(&self.id));
+                self.call("PUT", &path, Value::Null)?;
+            }
+            RegistryBackend::Etcd => {
+                let lease = self.lease.lock().unwrap().clone().ok_or("no lease")?;
+                let reply = self.call("POST", "/v3/lease/keepalive", json!({ "ID": lease }))?;
+                // an expired lease comes back without a ttl
+                if reply["result"]["TTL"].as_str().is_none_or(|ttl| ttl == "0") {
+                    return Err("the lease has expired".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn deregister(&self) -> Result<(), String> {
+        match self.config.backend {
+            RegistryBackend::Consul => {
+                let path = format!("/v1/agent/service/deregister/{}", self.id);
+                self.call("PUT", &path, Value::Null)?;
+            }
+            RegistryBackend::Etcd => {
+                if let Some(lease) = self.lease.lock().unwrap().take() {
+                    self.call("POST", "/v3/lease/revoke", json!({ "ID": lease }))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn consul_check_id(id: &str) -> String {
+    format!("service:{}", id)
+}
+
+/// A consul service definition, with a ttl check that's deregistered if
+/// it stays critical, e.g. when the pi loses power.
+fn consul_service(inner: &Inner, probes: &[String]) -> Value {
+    let ttl = inner.config.ttl;
+    json!({
+        "ID": inner.id,
+        "Name": inner.config.service,
+        "Address": inner.address,
+        "Port": inner.port,
+        "Tags": inner.config.tags,
+        "Meta": {
+            "version": env!("CARGO_PKG_VERSION"),
+            "probe_count": probes.len().to_string(),
+            "probes": probes.join(","),
+        },
+        "Check": {
+            "CheckID": consul_check_id(&inner.id),
+            "Name": format!("{} heartbeat", inner.config.service),
+            "TTL": format!("{}s", ttl),
+            "DeregisterCriticalServiceAfter": format!("{}s", ttl * 10),
+        },
+    })
+}
+
+fn etcd_key(inner: &Inner) -> String {
+    format!("/services/{}/{}", inner.config.service, inner.id)
+}
+
+fn etcd_value(inner: &Inner, probes: &[String]) -> Value {
+    json!({
+        "address": inner.address,
+        "port": inner.port,
+        "tags": inner.config.tags,
+        "version": env!("CARGO_PKG_VERSION"),
+        "probes": probes,
+    })
+}
+
+/// This machine's name, without a domain.
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inner(backend: RegistryBackend) -> Inner {
+        Inner {
+            config: RegistryConfig {
+                backend,
+                url: "http://127.0.0.1:8500/".to_string(),
+                service: "tempmon".to_string(),
+                id: None,
+                address: None,
+                tags: vec!["brewery".to_string()],
+                ttl: 30,
+                token: None,
+            },
+            agent: AgentBuilder::new().build(),
+            id: "tempmon-brewpi".to_string(),
+            address: "brewpi.local".to_string(),
+            port: 9184,
+            lease: Mutex::new(None),
+        }
+    }
+
+    #[test]
+    fn test_consul_service() {
+        let inner = inner(RegistryBackend::Consul);
+        let probes = ["ambient".to_string(), "fermenter".to_string()];
+        let service = consul_service(&inner, &probes);
+        assert_eq!(service["ID"], "tempmon-brewpi");
+        assert_eq!(service["Port"], 9184);
+        assert_eq!(service["Meta"]["probes"], "ambient,fermenter");
+        assert_eq!(service["Meta"]["probe_count"], "2");
+        assert_eq!(service["Check"]["CheckID"], "service:tempmon-brewpi");
+        assert_eq!(service["Check"]["TTL"], "30s");
+        assert_eq!(
+            inner.url("/v1/agent/service/register"),
+            "http://127.0.0.1:8500/v1/agent/service/register"
+        );
+    }
+
+    #[test]
+    fn test_etcd_value() {
+        let inner = inner(RegistryBackend::Etcd);
+        assert_eq!(etcd_key(&inner), "/services/tempmon/tempmon-brewpi");
+        let value = etcd_value(&inner, &["fermenter".to_string()]);
+        assert_eq!(value["address"], "brewpi.local");
+        assert_eq!(value["probes"][0], "fermenter");
+        assert_eq!(value["tags"][0], "brewery");
+    }
+}
@@ -0,0 +1,107 @@
+use time::OffsetDateTime;
+use time_tz::{OffsetDateTimeExt, Tz, system, timezones};
+
+use crate::config::Period;
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+const MINUTES_PER_WEEK: u32 = 7 * MINUTES_PER_DAY;
+
+/// A timezone by its IANA name, or the system's without one.
+pub fn zone(name: Option<&str>) -> Option<&'static Tz> {
+    match name {
+        Some(name) => timezones::get_by_name(name),
+        None => system::get_timezone().ok(),
+    }
+}
+
+/// The current time in `timezone`, or the system's timezone without one.
+/// Falls back to UTC if the system's can't be worked out.
+pub fn now(timezone: Option<&str>) -> OffsetDateTime {
+    let now = OffsetDateTime::now_utc();
+    match zone(timezone) {
+        Some(tz) => now.to_timezone(tz),
+        None => now,
+    }
+}
+
+/// Minutes past midnight for a "HH:MM" time of day.
+pub fn parse_time(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.split_once(':')?;
+    if hours.len() != 2 || minutes.len() != 2 {
+        return None;
+    }
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// The value of the period in effect at `at`: whichever started most
+/// recently, going back round the week if none has started yet this week.
+pub fn current<T>(periods: &[Period<T>], at: OffsetDateTime) -> Option<&T> {
+    let now = u32::from(at.weekday().number_days_from_monday()) * MINUTES_PER_DAY
+        + u32::from(at.hour()) * 60
+        + u32::from(at.minute());
+    periods
+        .iter()
+        // already checked by Config::validate
+        .filter_map(|p| Some((p, parse_time(&p.from)?)))
+        .flat_map(|(p, from)| {
+            let days: Vec<u32> = if p.days.is_empty() {
+                (0..7).collect()
+            } else {
+                p.days.iter().map(|d| *d as u32).collect()
+            };
+            days.into_iter()
+                .map(move |day| (day * MINUTES_PER_DAY + from, &p.value))
+        })
+        .min_by_key(|(start, _)| (now + MINUTES_PER_WEEK - start) % MINUTES_PER_WEEK)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Setpoint, Weekday};
+    use time::macros::datetime;
+
+    fn period(days: Vec<Weekday>, from: &str, setpoint: f32) -> Period<Setpoint> {
+        Period {
+            days,
+            from: from.to_string(),
+            value: Setpoint { setpoint },
+        }
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("07:00"), Some(420));
+        assert_eq!(parse_time("23:59"), Some(1439));
+        assert_eq!(parse_time("24:00"), None);
+        assert_eq!(parse_time("7:00"), None);
+        assert_eq!(parse_time("07:60"), None);
+    }
+
+    #[test]
+    fn test_current() {
+        let periods = [
+            period(Vec::new(), "07:00", 21.0),
+            period(Vec::new(), "22:00", 18.0),
+            period(vec![Weekday::Sat, Weekday::Sun], "09:00", 22.0),
+        ];
+        let setpoint = |at| current(&periods, at).map(|s| s.setpoint);
+
+        // 2026-10-12 is a monday
+        assert_eq!(setpoint(datetime!(2026-10-12 06:59 UTC)), Some(18.0));
+        assert_eq!(setpoint(datetime!(2026-10-12 07:00 UTC)), Some(21.0));
+        assert_eq!(setpoint(datetime!(2026-10-12 12:00 UTC)), Some(21.0));
+        assert_eq!(setpoint(datetime!(2026-10-12 22:30 UTC)), Some(18.0));
+        assert_eq!(setpoint(datetime!(2026-10-17 08:00 UTC)), Some(21.0));
+        assert_eq!(setpoint(datetime!(2026-10-17 09:00 UTC)), Some(22.0));
+        // sunday night wraps round to monday morning
+        assert_eq!(setpoint(datetime!(2026-10-18 23:00 UTC)), Some(18.0));
+
+        let weekdays = [period(vec![Weekday::Mon], "08:00", 20.0)];
+        let setpoint = current(&weekdays, datetime!(2026-10-12 07:00 UTC));
+        assert_eq!(setpoint.map(|s| s.setpoint), Some(20.0));
+        assert!(current::<Setpoint>(&[], datetime!(2026-10-12 07:00 UTC)).is_none());
+    }
+}
@@ -1,72 +1,1203 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::thread;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use prometheus::{Encoder, TextEncoder};
-use tiny_http::{Header, Response, Server};
+use bytes::Bytes;
+use http::header::{self, HeaderName, HeaderValue};
+use http::{Method, Request, Response, StatusCode};
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::{TokioIo, TokioTimer};
+use percent_encoding::percent_decode_str;
+use prometheus::{
+    CounterVec, Encoder, HistogramVec, TextEncoder, register_counter_vec, register_histogram_vec,
+};
+use socket2::{Domain, Socket, Type};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, UnixListener};
+use tracing::{Instrument, debug, info, info_span, warn};
 
-use crate::html;
+use crate::auth::{Authenticator, RouteGroup};
+use crate::config::{AlertsConfig, Config, HealthConfig, Settings, Unit};
+use crate::cors::Cors;
+use crate::history::Range;
+use crate::html::Dashboard;
+use crate::ratelimit::RateLimiter;
+use crate::state::TempData;
+use crate::template::Template;
+use crate::{api, assets, compress, export, grafana, html, notify, systemd};
 
-pub type TempData = Arc<Mutex<HashMap<String, Option<f32>>>>;
+/// A request with its body read in full.
+type HttpRequest = Request<Bytes>;
+type HttpResponse = Response<Vec<u8>>;
 
+const SERVICE_WORKER: &str = include_str!("../assets/sw.js");
+
+/// Largest request body the api will read.
+const MAX_BODY_SIZE: usize = 16 * 1024;
+
+/// How long a client gets to send a request's headers, which is also how
+/// long an idle keep-alive connection is held open.
+const HEADER_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Wait after a failed accept, e.g. when out of file descriptors, before
+/// trying again.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+struct HttpMetrics {
+    requests: CounterVec,
+    duration: HistogramVec,
+}
+
+/// Settings that can be swapped out on a config reload.
+struct Policy {
+    auth: Option<Authenticator>,
+    cors: Option<Cors>,
+    rate_limiter: Option<RateLimiter>,
+    max_connections: Option<usize>,
+    health: HealthConfig,
+    /// what the dashboard shows temperatures in, unless `?unit=` says
+    units: Unit,
+    /// whether fahrenheit metrics are exported, for the grafana dashboard
+    fahrenheit_metrics: bool,
+    dashboard: Dashboard,
+    /// the user's dashboard template, if they have one
+    template: Option<Template>,
+    /// for sending test alerts
+    alerts: AlertsConfig,
+}
+
+impl Policy {
+    fn from_config(config: &Config) -> io::Result<Self> {
+        let auth = match &config.auth {
+            Some(a) => Some(Authenticator::new(a.clone())?),
+            None => None,
+        };
+
+        let template = match &config.settings.template {
+            Some(path) => Some(Template::load(path)?),
+            None => None,
+        };
+
+        Ok(Policy {
+            auth,
+            cors: config.cors.clone().map(Cors::new),
+            rate_limiter: config
+                .rate_limit
+                .as_ref()
+                .and_then(|r| Some(RateLimiter::new(r.requests_per_minute?, r.burst))),
+            max_connections: config.rate_limit.as_ref().and_then(|r| r.max_connections),
+            health: config.health.clone(),
+            units: config.settings.units,
+            fahrenheit_metrics: config.settings.fahrenheit_metrics,
+            dashboard: Dashboard::from_config(config),
+            template,
+            alerts: config.alerts.clone(),
+        })
+    }
+}
+
+/// State shared by the connection tasks.
+struct Context {
+    current_temps: TempData,
+    policy: RwLock<Policy>,
+    /// set once every listener is bound and serving
+    bound: AtomicBool,
+    metrics: HttpMetrics,
+}
+
+/// Handle to the running http server.
+pub struct Handle {
+    ctx: Arc<Context>,
+}
+
+impl Handle {
+    /// Applies the reloadable parts of `config`; listeners are left alone.
+    pub fn reload(&self, config: &Config) -> io::Result<()> {
+        let policy = Policy::from_config(config)?;
+        *self.ctx.policy.write().unwrap() = policy;
+        // pages rendered under the old config are stale now
+        self.ctx.current_temps.invalidate();
+        Ok(())
+    }
+}
+
+/// Which routes a listener serves.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    All,
+    Dashboard,
+    Metrics,
+}
+
+impl Role {
+    fn serves(self, group: RouteGroup) -> bool {
+        match self {
+            Role::All => true,
+            Role::Dashboard => group != RouteGroup::Metrics,
+            Role::Metrics => matches!(group, RouteGroup::Metrics | RouteGroup::Health),
+        }
+    }
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    fn from_std(listener: systemd::Listener) -> io::Result<Self> {
+        Ok(match listener {
+            systemd::Listener::Tcp(listener) => {
+                listener.set_nonblocking(true)?;
+                Listener::Tcp(TcpListener::from_std(listener)?)
+            }
+            systemd::Listener::Unix(listener) => {
+                listener.set_nonblocking(true)?;
+                Listener::Unix(UnixListener::from_std(listener)?)
+            }
+        })
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => write!(f, "{}", addr),
+                Err(_) => write!(f, "tcp"),
+            },
+            Listener::Unix(listener) => match listener.local_addr() {
+                Ok(addr) => match addr.as_pathname() {
+                    Some(path) => write!(f, "unix:{}", path.display()),
+                    None => write!(f, "unix"),
+                },
+                Err(_) => write!(f, "unix"),
+            },
+        }
+    }
+}
+
+/// Starts serving on every configured (or socket activated) listener. Must
+/// be called from within a tokio runtime, which the server then runs on.
 pub fn start(
-    port: u16,
+    config: &Config,
     current_temps: TempData,
-    threads: usize,
+) -> Result<Handle, Box<dyn std::error::Error>> {
+    let settings = &config.settings;
+    let mut listeners = Vec::new();
+
+    let activated = systemd::listen_fds();
+    if activated.is_empty() {
+        bind_configured(settings, &mut listeners)?;
+    } else {
+        // sockets named "metrics" (FileDescriptorName=metrics) only serve /metrics
+        let main_role = if activated.iter().any(|(_, name)| name == "metrics") {
+            Role::Dashboard
+        } else {
+            Role::All
+        };
+        for (listener, name) in activated {
+            let role = if name == "metrics" {
+                Role::Metrics
+            } else {
+                main_role
+            };
+            listeners.push((listener, role));
+        }
+    }
+
+    if listeners.is_empty() {
+        return Err("no http listeners configured".into());
+    }
+
+    let metrics = HttpMetrics {
+        requests: register_counter_vec!(
+            "tempmon_http_requests_total",
+            "total number of http requests handled",
+            &["path", "status"]
+        )?,
+        duration: register_histogram_vec!(
+            "tempmon_http_request_duration_seconds",
+            "time taken to handle and respond to http requests",
+            &["path"],
+            vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0]
+        )?,
+    };
+    let ctx = Arc::new(Context {
+        current_temps,
+        policy: RwLock::new(Policy::from_config(config)?),
+        bound: AtomicBool::new(false),
+        metrics,
+    });
+
+    for (listener, role) in listeners {
+        let listener = Listener::from_std(listener)
+            .map_err(|e| format!("failed to start http server: {}", e))?;
+        info!("http server listening on {} ({:?})", listener, role);
+        tokio::spawn(accept(listener, role, Arc::clone(&ctx)));
+    }
+
+    ctx.bound.store(true, Ordering::Relaxed);
+    Ok(Handle { ctx })
+}
+
+/// Binds the listeners from the config file.
+fn bind_configured(
+    settings: &Settings,
+    listeners: &mut Vec<(systemd::Listener, Role)>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let server = Arc::new(
-        Server::http(format!("0.0.0.0:{port}"))
-            .map_err(|e| format!("failed to start http server: {}", e))?,
-    );
+    let main_role = match settings.dedicated_metrics_port {
+        Some(port) => {
+            for listener in bind_all(&settings.bind_addresses, port)? {
+                listeners.push((systemd::Listener::Tcp(listener), Role::Metrics));
+            }
+            Role::Dashboard
+        }
+        None => Role::All,
+    };
+
+    for listener in bind_all(&settings.bind_addresses, settings.metrics_port)? {
+        listeners.push((systemd::Listener::Tcp(listener), main_role));
+    }
 
-    println!("http server listening on 0.0.0.0:{}", port);
-
-    for _ in 0..threads {
-        let server = server.clone();
-        let current_temps = current_temps.clone();
-
-        thread::spawn(move || {
-            for request in server.incoming_requests() {
-                match request.url() {
-                    "/metrics" => {
-                        let encoder = TextEncoder::new();
-                        let metric_families = prometheus::gather();
-                        let mut buffer = vec![];
-                        encoder.encode(&metric_families, &mut buffer).unwrap();
-
-                        let response = Response::from_data(buffer).with_header(
-                            Header::from_bytes(
-                                &b"Content-Type"[..],
-                                &b"text/plain; version=0.0.4"[..],
-                            )
-                            .unwrap(),
-                        );
-                        let _ = request.respond(response);
-                    }
-                    "/" => {
-                        let temps = current_temps.lock().unwrap();
-                        let html = html::generate_temperature_page(&temps);
-                        let response = Response::from_string(html).with_header(
-                            Header::from_bytes(
-                                &b"Content-Type"[..],
-                                &b"text/html; charset=utf-8"[..],
-                            )
-                            .unwrap(),
-                        );
-                        let _ = request.respond(response);
-                    }
-                    "/health" => {
-                        let response = Response::from_string("OK");
-                        let _ = request.respond(response);
-                    }
-                    _ => {
-                        let response = Response::from_string("404 Not Found").with_status_code(404);
-                        let _ = request.respond(response);
-                    }
+    if let Some(path) = &settings.unix_socket {
+        let listener = bind_unix(path, settings.unix_socket_mode)?;
+        listeners.push((systemd::Listener::Unix(listener), main_role));
+    }
+
+    Ok(())
+}
+
+/// Accepts connections for as long as the runtime is up, serving each on
+/// a task of its own.
+async fn accept(listener: Listener, role: Role, ctx: Arc<Context>) {
+    // open connections on this listener, for max_connections
+    let open = Arc::new(AtomicUsize::new(0));
+    loop {
+        let accepted = match &listener {
+            Listener::Tcp(listener) => listener
+                .accept()
+                .await
+                .map(|(stream, addr)| serve(stream, Some(addr.ip()), role, &ctx, &open)),
+            // unix socket clients have no address
+            Listener::Unix(listener) => listener
+                .accept()
+                .await
+                .map(|(stream, _)| serve(stream, None, role, &ctx, &open)),
+        };
+        if let Err(e) = accepted {
+            warn!("failed to accept connection: {}", e);
+            tokio::time::sleep(ACCEPT_BACKOFF).await;
+        }
+    }
+}
+
+/// Serves http/1.1 on a connection until the client closes it, or closes
+/// it straight away if the listener already has `max_connections` open.
+fn serve<S>(
+    stream: S,
+    peer: Option<IpAddr>,
+    role: Role,
+    ctx: &Arc<Context>,
+    open: &Arc<AtomicUsize>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    // only the accept loop opens connections, so the count can't go up
+    // between checking it and adding this one
+    if let Some(max) = ctx.policy.read().unwrap().max_connections
+        && open.load(Ordering::Relaxed) >= max
+    {
+        debug!(max, "closing connection over max_connections");
+        return;
+    }
+    let ctx = Arc::clone(ctx);
+    let open = Arc::clone(open);
+    open.fetch_add(1, Ordering::Relaxed);
+    tokio::spawn(async move {
+        let service = service_fn(|request| handle(request, peer, role, Arc::clone(&ctx)));
+        let served = http1::Builder::new()
+            .timer(TokioTimer::new())
+            .header_read_timeout(HEADER_READ_TIMEOUT)
+            .serve_connection(TokioIo::new(stream), service)
+            .await;
+        if let Err(e) = served {
+            debug!("connection closed: {}", e);
+        }
+        open.fetch_sub(1, Ordering::Relaxed);
+    });
+}
+
+async fn handle(
+    request: Request<Incoming>,
+    peer: Option<IpAddr>,
+    role: Role,
+    ctx: Arc<Context>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let started = Instant::now();
+    let path = route_label(url(&request));
+    let span = info_span!("request", method = %request.method(), url = url(&request));
+
+    let response = respond(request, peer, role, Arc::clone(&ctx))
+        .instrument(span.clone())
+        .await;
+
+    let status = response.status().as_u16();
+    span.in_scope(|| debug!(status, elapsed = ?started.elapsed(), "handled request"));
+    ctx.metrics
+        .requests
+        .with_label_values(&[path, &status.to_string()])
+        .inc();
+    ctx.metrics
+        .duration
+        .with_label_values(&[path])
+        .observe(started.elapsed().as_secs_f64());
+
+    Ok(response.map(|body| Full::new(Bytes::from(body))))
+}
+
+async fn respond(
+    request: Request<Incoming>,
+    peer: Option<IpAddr>,
+    role: Role,
+    ctx: Arc<Context>,
+) -> HttpResponse {
+    if let Some(rejection) = admit(peer, &ctx.policy.read().unwrap()) {
+        return rejection;
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = match Limited::new(body, MAX_BODY_SIZE).collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(e) if e.is::<LengthLimitError>() => {
+            return text("413 Payload Too Large").with_status(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        Err(e) => return bad_request(&e.to_string()),
+    };
+    let request = Request::from_parts(parts, body);
+
+    // handlers block on password hashing and sending test alerts, so they
+    // run off the async workers
+    let span = tracing::Span::current();
+    let routed = tokio::task::spawn_blocking(move || {
+        let _span = span.entered();
+        let policy = ctx.policy.read().unwrap();
+        route(&request, role, &ctx, &policy)
+    })
+    .await;
+    routed.unwrap_or_else(|e| {
+        warn!("request handler failed: {}", e);
+        text("500 Internal Server Error").with_status(StatusCode::INTERNAL_SERVER_ERROR)
+    })
+}
+
+/// Turns away requests over the client's rate limit.
+fn admit(peer: Option<IpAddr>, policy: &Policy) -> Option<HttpResponse> {
+    // unix socket clients have no address and are trusted
+    if let Some(limiter) = &policy.rate_limiter
+        && let Some(ip) = peer
+        && let Err(retry_after) = limiter.check(ip, Instant::now())
+    {
+        return Some(
+            text("429 Too Many Requests")
+                .with_status(StatusCode::TOO_MANY_REQUESTS)
+                .with_header(
+                    header::RETRY_AFTER,
+                    retry_after.as_secs().max(1).to_string(),
+                ),
+        );
+    }
+
+    None
+}
+
+fn route(request: &HttpRequest, role: Role, ctx: &Context, policy: &Policy) -> HttpResponse {
+    let group = RouteGroup::for_path(request.uri().path());
+    if !role.serves(group) {
+        return not_found();
+    }
+
+    let cors = policy.cors.as_ref().filter(|_| group == RouteGroup::Api);
+
+    // preflight requests never carry credentials
+    if let Some(cors) = cors
+        && request.method() == Method::OPTIONS
+    {
+        return cors.preflight(request);
+    }
+
+    let mut response = dispatch(request, ctx, policy);
+    if let Some(cors) = cors {
+        cors.apply(request, &mut response);
+    }
+    response
+}
+
+/// Chained setters for building up a response.
+trait ResponseExt {
+    fn with_status(self, status: StatusCode) -> Self;
+    /// Adds a header, alongside any already set under the same name.
+    fn with_header(self, name: HeaderName, value: impl AsRef<str>) -> Self;
+}
+
+impl ResponseExt for HttpResponse {
+    fn with_status(mut self, status: StatusCode) -> Self {
+        *self.status_mut() = status;
+        self
+    }
+
+    fn with_header(mut self, name: HeaderName, value: impl AsRef<str>) -> Self {
+        match HeaderValue::from_str(value.as_ref()) {
+            Ok(value) => {
+                self.headers_mut().append(name, value);
+            }
+            Err(e) => warn!("dropping invalid {} header: {}", name, e),
+        }
+        self
+    }
+}
+
+/// A plain text response.
+fn text(body: impl Into<String>) -> HttpResponse {
+    Response::new(body.into().into_bytes())
+        .with_header(header::CONTENT_TYPE, "text/plain; charset=UTF-8")
+}
+
+/// The path and query string a request was for.
+fn url<B>(request: &Request<B>) -> &str {
+    request
+        .uri()
+        .path_and_query()
+        .map_or("/", |url| url.as_str())
+}
+
+fn dispatch(request: &HttpRequest, ctx: &Context, policy: &Policy) -> HttpResponse {
+    if let Some(auth) = &policy.auth
+        && !auth.authorize(request)
+    {
+        return text("401 Unauthorized")
+            .with_status(StatusCode::UNAUTHORIZED)
+            .with_header(header::WWW_AUTHENTICATE, auth.challenge());
+    }
+
+    match url(request) {
+        "/metrics" => {
+            let encoder = TextEncoder::new();
+            let metric_families = prometheus::gather();
+            let mut buffer = vec![];
+            encoder.encode(&metric_families, &mut buffer).unwrap();
+
+            compressed_response(request, buffer, "text/plain; version=0.0.4")
+        }
+        url if page_query(url, "/").is_some() => {
+            let query = page_query(url, "/").unwrap_or_default();
+            let unit = match unit(query, policy.units) {
+                Ok(unit) => unit,
+                Err(message) => return bad_request(message),
+            };
+            let readings = ctx.current_temps.snapshot();
+            // the same url serves scripts
+            if header(request, header::ACCEPT).is_some_and(wants_json) {
+                let json = api::readings(&readings.temps);
+                return compressed_response(request, json.into_bytes(), "application/json")
+                    .with_header(header::VARY, "Accept");
+            }
+            let etag = etag(readings.updated, readings.generation);
+            let last_modified = httpdate::fmt_http_date(readings.updated);
+            if is_not_modified(request, &etag, readings.updated) {
+                return Response::new(Vec::new())
+                    .with_status(StatusCode::NOT_MODIFIED)
+                    .with_header(header::ETAG, etag);
+            }
+
+            let html = match &policy.template {
+                Some(template) => {
+                    html::render_template(template, &readings, unit, &policy.dashboard)
+                }
+                None => Ok(html::generate_temperature_page(
+                    &readings,
+                    unit,
+                    &policy.dashboard,
+                )),
+            };
+            let html = match html {
+                Ok(html) => html,
+                Err(e) => {
+                    warn!("failed to render dashboard template: {}", e);
+                    return text("500 Internal Server Error")
+                        .with_status(StatusCode::INTERNAL_SERVER_ERROR);
                 }
+            };
+
+            compressed_response(request, html.into_bytes(), "text/html; charset=utf-8")
+                .with_header(header::ETAG, etag)
+                .with_header(header::LAST_MODIFIED, last_modified)
+                .with_header(header::CACHE_CONTROL, "no-cache")
+                .with_header(header::VARY, "Accept")
+        }
+        "/health" => {
+            let readings = ctx.current_temps.snapshot();
+            let health = &policy.health;
+            if readings.is_unhealthy(health.failing_cycles, health.failing_fraction) {
+                let failing = readings.failing_probes(health.failing_cycles);
+                json_response(api::unhealthy(failing)).with_status(StatusCode::SERVICE_UNAVAILABLE)
+            } else {
+                text("OK")
+            }
+        }
+        "/healthz" => text("OK"),
+        "/readyz" => {
+            let first_reading = ctx.current_temps.snapshot().first_reading.is_some();
+            let readiness = api::Readiness::new(first_reading, ctx.bound.load(Ordering::Relaxed));
+            let status = if readiness.ready {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            json_response(readiness.to_json()).with_status(status)
+        }
+        "/manifest.webmanifest" => compressed_response(
+            request,
+            html::manifest(&policy.dashboard).into_bytes(),
+            "application/manifest+json",
+        ),
+        // served from the root so it can look after every page
+        "/sw.js" => compressed_response(
+            request,
+            SERVICE_WORKER.as_bytes().to_vec(),
+            "text/javascript; charset=utf-8",
+        )
+        .with_header(header::CACHE_CONTROL, "no-cache"),
+        "/grafana/dashboard.json" => {
+            let readings = ctx.current_temps.snapshot();
+            let names: Vec<&str> = readings.temps.keys().map(String::as_str).collect();
+            let probes: Vec<&str> = policy
+                .dashboard
+                .layout
+                .arrange(&names)
+                .into_iter()
+                .flat_map(|(_, names)| names)
+                .collect();
+            let fahrenheit = policy.units == Unit::Fahrenheit && policy.fahrenheit_metrics;
+            let json = grafana::dashboard(&probes, fahrenheit);
+            compressed_response(request, json.into_bytes(), "application/json")
+        }
+        "/api/v1/readings" => {
+            let readings = ctx.current_temps.snapshot();
+            json_response(api::readings(&readings.temps))
+        }
+        "/api/v1/alerts" => {
+            let readings = ctx.current_temps.snapshot();
+            json_response(api::alerts(&readings.alerts))
+        }
+        "/api/v1/alerts/test" => test_alert(request, policy),
+        "/api/v1/silences" => silences(request, ctx),
+        url => {
+            if let Some(query) = page_query(url, "/history") {
+                history(request, ctx, policy, query)
+            } else if let Some(query) = page_query(url, "/kiosk") {
+                kiosk(request, ctx, policy, query)
+            } else if let Some(query) = page_query(url, "/export") {
+                export(request, ctx, policy, query)
+            } else if let Some(probe) = reset_target(url) {
+                let probe = probe.into_owned();
+                reset_probe(request, ctx, &probe)
+            } else if let Some(id) = silence_target(url) {
+                remove_silence(request, ctx, id)
+            } else if let Some(asset) = assets::get(url) {
+                compressed_response(request, asset.body.to_vec(), asset.content_type)
+                    .with_header(header::CACHE_CONTROL, "max-age=86400")
+            } else {
+                not_found()
             }
-        });
+        }
     }
+}
 
-    Ok(())
+/// Maps a request url onto a fixed set of metric labels, so scanners
+/// probing random paths can't blow up the label cardinality.
+fn route_label(url: &str) -> &'static str {
+    match url {
+        url if page_query(url, "/").is_some() => "/",
+        "/metrics" => "/metrics",
+        "/health" => "/health",
+        "/healthz" => "/healthz",
+        "/readyz" => "/readyz",
+        "/manifest.webmanifest" => "/manifest.webmanifest",
+        "/sw.js" => "/sw.js",
+        "/favicon.ico" => "/favicon.ico",
+        url if url.starts_with("/static/") => "/static",
+        "/grafana/dashboard.json" => "/grafana/dashboard.json",
+        "/api/v1/readings" => "/api/v1/readings",
+        "/api/v1/alerts" => "/api/v1/alerts",
+        "/api/v1/alerts/test" => "/api/v1/alerts/test",
+        "/api/v1/silences" => "/api/v1/silences",
+        url if page_query(url, "/history").is_some() => "/history",
+        url if page_query(url, "/kiosk").is_some() => "/kiosk",
+        url if page_query(url, "/export").is_some() => "/export",
+        url if reset_target(url).is_some() => "/api/v1/probes/reset",
+        url if silence_target(url).is_some() => "/api/v1/silences/id",
+        _ => "other",
+    }
+}
+
+/// The probe named in a `/api/v1/probes/<name>/reset` url, percent-decoded
+/// so names with spaces or slashes can be reset too.
+fn reset_target(url: &str) -> Option<Cow<'_, str>> {
+    let name = url
+        .strip_prefix("/api/v1/probes/")?
+        .strip_suffix("/reset")?;
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    percent_decode_str(name).decode_utf8().ok()
+}
+
+/// Releases a probe from quarantine so it is polled again next cycle.
+fn reset_probe(request: &HttpRequest, ctx: &Context, probe: &str) -> HttpResponse {
+    if request.method() != Method::POST {
+        return method_not_allowed("POST");
+    }
+
+    let Some(released) = ctx.current_temps.update(|readings| {
+        readings
+            .temps
+            .contains_key(probe)
+            .then(|| readings.release(probe))
+    }) else {
+        return not_found();
+    };
+
+    if released {
+        info!(probe, "released from quarantine by api request");
+    }
+    json_response(api::reset(probe, released))
+}
+
+/// The query string of a request for the page at `path`, empty without
+/// one.
+fn page_query<'a>(url: &'a str, path: &str) -> Option<&'a str> {
+    match url.strip_prefix(path)? {
+        "" => Some(""),
+        rest => rest.strip_prefix('?'),
+    }
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+}
+
+/// The unit a page asked for with `?unit=`, or the configured one.
+fn unit(query: &str, default: Unit) -> Result<Unit, &'static str> {
+    match query_param(query, "unit") {
+        Some(unit) => Unit::parse(unit).ok_or("unit must be celsius, fahrenheit or kelvin"),
+        None => Ok(default),
+    }
+}
+
+/// Serves the history charts over `?range=1h`, `24h` (the default) or `7d`.
+fn history(request: &HttpRequest, ctx: &Context, policy: &Policy, query: &str) -> HttpResponse {
+    let range = query_param(query, "range").map_or(Some(Range::Day), Range::parse);
+    let Some(range) = range else {
+        return bad_request("range must be 1h, 24h or 7d");
+    };
+    let unit = match unit(query, policy.units) {
+        Ok(unit) => unit,
+        Err(message) => return bad_request(message),
+    };
+    let readings = ctx.current_temps.snapshot();
+    let html = html::generate_history_page(&readings, range, unit, &policy.dashboard);
+    compressed_response(request, html.into_bytes(), "text/html; charset=utf-8")
+}
+
+/// Serves the wall display, in `?unit=` or the configured unit.
+fn kiosk(request: &HttpRequest, ctx: &Context, policy: &Policy, query: &str) -> HttpResponse {
+    let unit = match unit(query, policy.units) {
+        Ok(unit) => unit,
+        Err(message) => return bad_request(message),
+    };
+    let readings = ctx.current_temps.snapshot();
+    let html = html::generate_kiosk_page(&readings, unit, &policy.dashboard);
+    compressed_response(request, html.into_bytes(), "text/html; charset=utf-8")
+}
+
+/// Downloads the kept readings as `?format=csv` (the default) or `ndjson`,
+/// between `?from=` and `?to=`, each RFC 3339 or unix seconds.
+fn export(request: &HttpRequest, ctx: &Context, policy: &Policy, query: &str) -> HttpResponse {
+    let Some(format) = export::Format::parse(query_param(query, "format").unwrap_or("csv")) else {
+        return bad_request("format must be csv or ndjson");
+    };
+    let mut bounds = [None, None];
+    for (bound, name) in bounds.iter_mut().zip(["from", "to"]) {
+        if let Some(value) = query_param(query, name) {
+            match export::parse_time(value) {
+                Some(time) => *bound = Some(time),
+                None => return bad_request(&format!("{} must be RFC 3339 or unix seconds", name)),
+            }
+        }
+    }
+    let [from, to] = bounds;
+    let unit = match unit(query, policy.units) {
+        Ok(unit) => unit,
+        Err(message) => return bad_request(message),
+    };
+
+    let readings = ctx.current_temps.snapshot();
+    let names: Vec<&str> = readings.temps.keys().map(String::as_str).collect();
+    let mut rows = Vec::new();
+    for name in names {
+        let points = readings
+            .history
+            .read()
+            .unwrap()
+            .since(name, from.unwrap_or(UNIX_EPOCH));
+        rows.extend(
+            points
+                .into_iter()
+                .filter(|(at, _)| to.is_none_or(|to| *at <= to))
+                .map(|(at, temp)| (name, at, temp)),
+        );
+    }
+    // oldest first, probes in a fixed order within each time
+    rows.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(b.0)));
+    let body = export::render(&rows, format, unit);
+
+    let disposition = format!(
+        "attachment; filename=\"{}\"",
+        export::filename(format, SystemTime::now())
+    );
+    compressed_response(request, body.into_bytes(), format.content_type())
+        .with_header(header::CONTENT_DISPOSITION, disposition)
+}
+
+/// The id in a `/api/v1/silences/<id>` url.
+fn silence_target(url: &str) -> Option<u64> {
+    url.strip_prefix("/api/v1/silences/")?.parse().ok()
+}
+
+fn method_not_allowed(allow: &'static str) -> HttpResponse {
+    text("405 Method Not Allowed")
+        .with_status(StatusCode::METHOD_NOT_ALLOWED)
+        .with_header(header::ALLOW, allow)
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    text(format!("400 Bad Request: {}", message)).with_status(StatusCode::BAD_REQUEST)
+}
+
+/// Sends a test alert through every notifier, answering 502 if any of them
+/// failed.
+fn test_alert(request: &HttpRequest, policy: &Policy) -> HttpResponse {
+    if request.method() != Method::POST {
+        return method_not_allowed("POST");
+    }
+
+    let results = notify::send_test(&policy.alerts);
+    let status = if results.iter().all(|(_, r)| r.is_ok()) {
+        StatusCode::OK
+    } else {
+        StatusCode::BAD_GATEWAY
+    };
+    json_response(api::test_results(&results)).with_status(status)
+}
+
+/// Lists silences, or adds one from a JSON body like
+/// `{"target": "freezer", "duration": 3600, "comment": "defrosting"}`.
+fn silences(request: &HttpRequest, ctx: &Context) -> HttpResponse {
+    match *request.method() {
+        Method::GET => {
+            let readings = ctx.current_temps.snapshot();
+            json_response(api::silences(&readings.silences))
+        }
+        Method::POST => {
+            let new: api::NewSilence = match serde_json::from_slice(request.body()) {
+                Ok(new) => new,
+                Err(e) => return bad_request(&e.to_string()),
+            };
+
+            let Some(until) = SystemTime::now().checked_add(Duration::from_secs(new.duration))
+            else {
+                return bad_request("duration is too long");
+            };
+            let silence = ctx
+                .current_temps
+                .update(|readings| readings.add_silence(&new.target, until, new.comment, false));
+            info!(target = %silence.target, "silenced for {}s by api request", new.duration);
+            json_response(api::silence(&silence)).with_status(StatusCode::CREATED)
+        }
+        _ => method_not_allowed("GET, POST"),
+    }
+}
+
+/// Ends a silence added through the api.
+fn remove_silence(request: &HttpRequest, ctx: &Context, id: u64) -> HttpResponse {
+    if request.method() != Method::DELETE {
+        return method_not_allowed("DELETE");
+    }
+    if ctx
+        .current_temps
+        .update(|readings| readings.remove_silence(id))
+    {
+        info!(id, "silence removed by api request");
+        text("OK")
+    } else {
+        not_found()
+    }
+}
+
+/// A weak validator for the page generated from readings last updated at
+/// `updated`, `generation` telling apart the other changes that show on it.
+fn etag(updated: SystemTime, generation: u64) -> String {
+    let millis = updated
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    format!("W/\"{:x}-{:x}\"", millis, generation)
+}
+
+fn header(request: &HttpRequest, name: HeaderName) -> Option<&str> {
+    request.headers().get(name)?.to_str().ok()
+}
+
+/// Whether an `Accept` header prefers json to html. Wildcards count for
+/// neither, so browsers and `*/*` get the page.
+fn wants_json(accept: &str) -> bool {
+    let quality = |media_type: &str| {
+        accept
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let name = parts.next()?.trim();
+                name.eq_ignore_ascii_case(media_type).then(|| {
+                    parts
+                        .find_map(|p| p.trim().strip_prefix("q="))
+                        .and_then(|q| q.parse::<f32>().ok())
+                        .unwrap_or(1.0)
+                })
+            })
+            .fold(0.0, f32::max)
+    };
+    quality("application/json") > quality("text/html")
+}
+
+fn is_not_modified(request: &HttpRequest, etag: &str, updated: SystemTime) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since
+    if let Some(if_none_match) = header(request, header::IF_NONE_MATCH) {
+        let etag = etag.trim_start_matches("W/");
+        return if_none_match
+            .split(',')
+            .map(|t| t.trim().trim_start_matches("W/"))
+            .any(|t| t == "*" || t == etag);
+    }
+
+    match header(request, header::IF_MODIFIED_SINCE).map(httpdate::parse_http_date) {
+        // http dates only have second precision
+        Some(Ok(since)) => !updated.duration_since(since).is_ok_and(|d| d.as_secs() > 0),
+        _ => false,
+    }
+}
+
+fn json_response(body: String) -> HttpResponse {
+    Response::new(body.into_bytes()).with_header(header::CONTENT_TYPE, "application/json")
+}
+
+fn not_found() -> HttpResponse {
+    text("404 Not Found").with_status(StatusCode::NOT_FOUND)
+}
+
+/// Builds a response for `body`, compressed if the client accepts it.
+fn compressed_response(
+    request: &HttpRequest,
+    mut body: Vec<u8>,
+    content_type: &str,
+) -> HttpResponse {
+    let encoding = header(request, header::ACCEPT_ENCODING).and_then(compress::negotiate);
+
+    let mut content_encoding = None;
+    if let Some(encoding) = encoding
+        && let Ok(Some(compressed)) = compress::compress(&body, encoding)
+    {
+        body = compressed;
+        content_encoding = Some(encoding.name());
+    }
+
+    let mut response = Response::new(body)
+        .with_header(header::CONTENT_TYPE, content_type)
+        .with_header(header::VARY, "Accept-Encoding");
+    if let Some(name) = content_encoding {
+        response = response.with_header(header::CONTENT_ENCODING, name);
+    }
+
+    response
+}
+
+/// Resolves the configured bind addresses, which may be ip literals
+/// (optionally in `[v6]` brackets) or host names.
+fn resolve_addresses(bind_addresses: &[String], port: u16) -> io::Result<Vec<SocketAddr>> {
+    let mut addrs = Vec::new();
+
+    for address in bind_addresses {
+        let host = address.trim_start_matches('[').trim_end_matches(']');
+        match host.parse::<IpAddr>() {
+            Ok(ip) => addrs.push(SocketAddr::new(ip, port)),
+            Err(_) => addrs.extend((host, port).to_socket_addrs()?),
+        }
+    }
+
+    // keep the first of each, wherever the repeats are
+    let mut seen = HashSet::new();
+    addrs.retain(|addr| seen.insert(*addr));
+    Ok(addrs)
+}
+
+fn bind_all(
+    bind_addresses: &[String],
+    port: u16,
+) -> Result<Vec<std::net::TcpListener>, Box<dyn std::error::Error>> {
+    let addrs = resolve_addresses(bind_addresses, port)?;
+
+    // an ipv6 wildcard socket also accepts ipv4 connections by default, which
+    // would clash with an explicit ipv4 listener on the same port
+    let v6_only = addrs.iter().any(SocketAddr::is_ipv4);
+
+    let mut listeners = Vec::new();
+    for addr in addrs {
+        let listener = bind_tcp(addr, v6_only)
+            .map_err(|e| format!("failed to bind http server to {}: {}", addr, e))?;
+        listeners.push(listener);
+    }
+
+    Ok(listeners)
+}
+
+fn bind_tcp(addr: SocketAddr, v6_only: bool) -> io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6_only)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(128)?;
+    Ok(socket.into())
+}
+
+fn bind_unix(
+    path: &Path,
+    mode: Option<u32>,
+) -> Result<std::os::unix::net::UnixListener, Box<dyn std::error::Error>> {
+    // clear out a stale socket left behind by a previous run
+    if let Ok(metadata) = fs::symlink_metadata(path)
+        && metadata.file_type().is_socket()
+    {
+        fs::remove_file(path)?;
+    }
+
+    let listener = std::os::unix::net::UnixListener::bind(path)
+        .map_err(|e| format!("failed to bind http server to {}: {}", path.display(), e))?;
+
+    if let Some(mode) = mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::{HistogramOpts, Opts};
+
+    use super::*;
+    use crate::state::Readings;
+
+    fn context() -> Context {
+        let config: Config = toml::from_str(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+            "#,
+        )
+        .unwrap();
+        Context {
+            current_temps: TempData::new(Readings::new(["freezer"])),
+            policy: RwLock::new(Policy::from_config(&config).unwrap()),
+            bound: AtomicBool::new(false),
+            metrics: HttpMetrics {
+                requests: CounterVec::new(Opts::new("requests", "requests"), &["path", "status"])
+                    .unwrap(),
+                duration: HistogramVec::new(HistogramOpts::new("duration", "duration"), &["path"])
+                    .unwrap(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_connections_closes_extra_sockets() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpStream;
+
+        let ctx = context();
+        ctx.policy.write().unwrap().max_connections = Some(1);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(accept(Listener::Tcp(listener), Role::All, Arc::new(ctx)));
+
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        // closed without a response
+        let mut response = Vec::new();
+        let closed =
+            tokio::time::timeout(Duration::from_secs(5), second.read_to_end(&mut response));
+        assert!(closed.await.is_ok());
+        assert!(response.is_empty());
+        first
+            .write_all(b"GET /health HTTP/1.1\r\nHost: tempmon\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        first.read_to_end(&mut response).await.unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 200"));
+    }
+
+    #[test]
+    fn test_silence_duration_out_of_range() {
+        let ctx = context();
+        let post = |body: &'static str| {
+            let request = Request::post("/api/v1/silences")
+                .body(Bytes::from(body))
+                .unwrap();
+            silences(&request, &ctx).status()
+        };
+        assert_eq!(
+            post(r#"{"target": "freezer", "duration": 18446744073709551615}"#),
+            StatusCode::BAD_REQUEST
+        );
+        assert!(ctx.current_temps.snapshot().silences.is_empty());
+        assert_eq!(
+            post(r#"{"target": "freezer", "duration": 3600}"#),
+            StatusCode::CREATED
+        );
+    }
+
+    #[test]
+    fn test_wants_json() {
+        assert!(wants_json("application/json"));
+        assert!(wants_json("application/json, text/html;q=0.9"));
+        assert!(!wants_json("text/html,application/xhtml+xml,*/*;q=0.8"));
+        assert!(!wants_json("*/*"));
+        assert!(!wants_json("application/json;q=0.5, text/html"));
+        assert!(!wants_json("application/json;q=0"));
+    }
+
+    #[test]
+    fn test_etag_is_weak_and_stable() {
+        let updated = UNIX_EPOCH + std::time::Duration::from_millis(0x1234);
+        assert_eq!(etag(updated, 0x1f), "W/\"1234-1f\"");
+        assert_eq!(etag(updated, 1), etag(updated, 1));
+        assert_ne!(etag(updated, 1), etag(updated, 2));
+    }
+
+    #[test]
+    fn test_etag_changes_with_state() {
+        let ctx = context();
+        let current = || {
+            let readings = ctx.current_temps.snapshot();
+            etag(readings.updated, readings.generation)
+        };
+        let before = current();
+        let request = Request::post("/api/v1/silences")
+            .body(Bytes::from(r#"{"target": "freezer", "duration": 3600}"#))
+            .unwrap();
+        assert_eq!(silences(&request, &ctx).status(), StatusCode::CREATED);
+        let silenced = current();
+        assert_ne!(before, silenced);
+        ctx.current_temps.invalidate();
+        assert_ne!(silenced, current());
+    }
+
+    #[test]
+    fn test_route_label() {
+        assert_eq!(route_label("/metrics"), "/metrics");
+        assert_eq!(route_label("/wp-login.php"), "other");
+        assert_eq!(route_label("/history?range=7d"), "/history");
+        assert_eq!(route_label("/historyx"), "other");
+        assert_eq!(route_label("/?unit=f"), "/");
+        assert_eq!(route_label("/sw.js"), "/sw.js");
+        assert_eq!(route_label("/static/icon-192.png"), "/static");
+        assert_eq!(
+            route_label("/api/v1/probes/cool_side/reset"),
+            "/api/v1/probes/reset"
+        );
+    }
+
+    #[test]
+    fn test_reset_target() {
+        assert_eq!(
+            reset_target("/api/v1/probes/cool_side/reset").as_deref(),
+            Some("cool_side")
+        );
+        assert_eq!(
+            reset_target("/api/v1/probes/Fermenter%202/reset").as_deref(),
+            Some("Fermenter 2")
+        );
+        assert_eq!(
+            reset_target("/api/v1/probes/pi%2Fmash/reset").as_deref(),
+            Some("pi/mash")
+        );
+        assert_eq!(reset_target("/api/v1/probes/%FF/reset"), None);
+        assert_eq!(reset_target("/api/v1/probes//reset"), None);
+        assert_eq!(reset_target("/api/v1/probes/a/b/reset"), None);
+        assert_eq!(reset_target("/api/v1/probes/cool_side"), None);
+    }
+
+    #[test]
+    fn test_dashboard_role_hides_metrics() {
+        assert!(!Role::Dashboard.serves(RouteGroup::Metrics));
+        assert!(Role::Dashboard.serves(RouteGroup::Dashboard));
+        assert!(Role::Metrics.serves(RouteGroup::Metrics));
+        assert!(Role::Metrics.serves(RouteGroup::Health));
+        assert!(!Role::Metrics.serves(RouteGroup::Dashboard));
+        assert!(Role::All.serves(RouteGroup::Metrics));
+    }
+
+    #[test]
+    fn test_resolve_ipv4_address() {
+        let addrs = resolve_addresses(&["127.0.0.1".to_string()], 9184).unwrap();
+        assert_eq!(addrs, vec!["127.0.0.1:9184".parse().unwrap()]);
+    }
+
+    #[test]
+    fn test_resolve_drops_repeated_addresses() {
+        let addresses = ["0.0.0.0", "[::]", "0.0.0.0"].map(String::from);
+        let addrs = resolve_addresses(&addresses, 80).unwrap();
+        assert_eq!(
+            addrs,
+            vec!["0.0.0.0:80".parse().unwrap(), "[::]:80".parse().unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_resolve_bracketed_ipv6_address() {
+        let addrs = resolve_addresses(&["[::]".to_string(), "::1".to_string()], 9184).unwrap();
+        assert_eq!(
+            addrs,
+            vec!["[::]:9184".parse().unwrap(), "[::1]:9184".parse().unwrap()]
+        );
+    }
 }
@@ -0,0 +1,496 @@
+use std::io;
+use std::net::UdpSocket;
+use std::thread;
+
+use tracing::{debug, info};
+
+use crate::config::SnmpConfig;
+use crate::state::{Readings, TempData};
+
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const NULL: u8 = 0x05;
+const OBJECT_ID: u8 = 0x06;
+const SEQUENCE: u8 = 0x30;
+const GAUGE32: u8 = 0x42;
+const NO_SUCH_OBJECT: u8 = 0x80;
+const NO_SUCH_INSTANCE: u8 = 0x81;
+const END_OF_MIB_VIEW: u8 = 0x82;
+
+const GET: u8 = 0xa0;
+const GET_NEXT: u8 = 0xa1;
+const RESPONSE: u8 = 0xa2;
+const GET_BULK: u8 = 0xa5;
+
+const VERSION_1: i64 = 0;
+const VERSION_2C: i64 = 1;
+/// v1's error for an oid that isn't there.
+const NO_SUCH_NAME: i64 = 2;
+/// Caps the varbinds in a get-bulk response so it fits in a datagram.
+const MAX_BULK: usize = 64;
+
+type Oid = Vec<u32>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Integer(i64),
+    String(String),
+    Gauge(u32),
+    Null,
+    NoSuchObject,
+    NoSuchInstance,
+    EndOfMibView,
+}
+
+/// Answers snmp v1 and v2c gets and walks of the readings from a thread of
+/// its own. Under the configured oid:
+///
+/// - `.1.0` the number of probes
+/// - `.2.1.1.<n>` each probe's index, from 1 in order of name
+/// - `.2.1.2.<n>` its label
+/// - `.2.1.3.<n>` its temperature in thousandths of a °c, missing while
+///   the probe is failing
+/// - `.2.1.4.<n>` how many reads in a row have failed
+pub fn start(config: &SnmpConfig, temps: TempData) -> io::Result<()> {
+    let base = parse_oid(&config.oid).map_err(io::Error::other)?;
+    let socket = UdpSocket::bind(&config.bind)?;
+    info!("answering snmp on {}", socket.local_addr()?);
+    let community = config.community.clone();
+
+    thread::spawn(move || {
+        let mut buf = [0; 65535];
+        loop {
+            let (len, peer) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) => {
+                    debug!("failed to receive snmp request: {}", e);
+                    continue;
+                }
+            };
+            let mib = mib(&base, &temps.snapshot());
+            match respond(&buf[..len], &community, &mib) {
+                Some(response) => {
+                    if let Err(e) = socket.send_to(&response, peer) {
+                        debug!(%peer, "failed to send snmp response: {}", e);
+                    }
+                }
+                None => debug!(%peer, "ignoring invalid snmp request"),
+            }
+        }
+    });
+    Ok(())
+}
+
+pub fn parse_oid(s: &str) -> Result<Oid, String> {
+    let oid: Oid = s
+        .trim_start_matches('.')
+        .split('.')
+        .map(|n| n.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid oid {:?}", s))?;
+    if oid.len() < 2 || oid[0] > 2 {
+        return Err(format!("invalid oid {:?}", s));
+    }
+    Ok(oid)
+}
+
+/// Every oid there is to read, in order.
+fn mib(base: &[u32], readings: &Readings) -> Vec<(Oid, Value)> {
+    let oid = |suffix: &[u32]| [base, suffix].concat();
+    let mut names: Vec<&String> = readings.temps.keys().collect();
+    names.sort();
+
+    let mut mib = vec![(oid(&[1, 0]), Value::Integer(names.len() as i64))];
+    let rows = || names.iter().zip(1..);
+    mib.extend(rows().map(|(_, i)| (oid(&[2, 1, 1, i]), Value::Integer(i.into()))));
+    mib.extend(rows().map(|(name, i)| (oid(&[2, 1, 2, i]), Value::String(name.to_string()))));
+    mib.extend(rows().filter_map(|(name, i)| {
+        let temp = readings.temps[*name]?;
+        Some((
+            oid(&[2, 1, 3, i]),
+            Value::Integer((temp * 1000.0).round() as i64),
+        ))
+    }));
+    mib.extend(rows().map(|(name, i)| {
+        let failures = readings.failures.get(*name).copied().unwrap_or(0);
+        (oid(&[2, 1, 4, i]), Value::Gauge(failures))
+    }));
+    mib
+}
+
+/// The response to a request, or none if it isn't one we can answer.
+fn respond(request: &[u8], community: &str, mib: &[(Oid, Value)]) -> Option<Vec<u8>> {
+    let (message, _) = read(request, SEQUENCE)?;
+    let (version, rest) = read(message, INTEGER)?;
+    let version = decode_integer(version)?;
+    let (given, rest) = read(rest, OCTET_STRING)?;
+    if !matches!(version, VERSION_1 | VERSION_2C) || given != community.as_bytes() {
+        return None;
+    }
+    let (pdu_type, pdu, _) = tlv(rest)?;
+    let (request_id, rest) = read(pdu, INTEGER)?;
+    let (first, rest) = read(rest, INTEGER)?;
+    let (second, rest) = read(rest, INTEGER)?;
+    let (mut list, _) = read(rest, SEQUENCE)?;
+    let mut oids = Vec::new();
+    while !list.is_empty() {
+        let (varbind, rest) = read(list, SEQUENCE)?;
+        let (oid, _) = read(varbind, OBJECT_ID)?;
+        oids.push(decode_oid(oid)?);
+        list = rest;
+    }
+
+    let next = |oid: &Oid| {
+        mib.iter()
+            .find(|(o, _)| o > oid)
+            .cloned()
+            .unwrap_or_else(|| (oid.clone(), Value::EndOfMibView))
+    };
+    let varbinds: Vec<(Oid, Value)> = match pdu_type {
+        GET => oids
+            .iter()
+            .map(|oid| match mib.iter().find(|(o, _)| o == oid) {
+                Some(found) => found.clone(),
+                None if mib
+                    .iter()
+                    .any(|(o, _)| o.starts_with(&oid[..oid.len() - 1])) =>
+                {
+                    (oid.clone(), Value::NoSuchInstance)
+                }
+                None => (oid.clone(), Value::NoSuchObject),
+            })
+            .collect(),
+        GET_NEXT => oids.iter().map(next).collect(),
+        GET_BULK if version == VERSION_2C => {
+            let non_repeaters = decode_integer(first)?.clamp(0, oids.len() as i64) as usize;
+            let repetitions = decode_integer(second)?.max(0) as usize;
+            let (once, repeated) = oids.split_at(non_repeaters);
+            let mut varbinds: Vec<_> = once.iter().map(next).collect();
+            let mut cursors = repeated.to_vec();
+            for _ in 0..repetitions {
+                if cursors.is_empty() || varbinds.len() + cursors.len() > MAX_BULK {
+                    break;
+                }
+                for cursor in &mut cursors {
+                    let found = next(cursor);
+                    *cursor = found.0.clone();
+                    varbinds.push(found);
+                }
+                if varbinds
+                    .iter()
+                    .rev()
+                    .take(cursors.len())
+                    .all(|(_, v)| *v == Value::EndOfMibView)
+                {
+                    break;
+                }
+            }
+            varbinds
+        }
+        _ => return None,
+    };
+
+    // v1 has no exceptions, only an error for the first missing oid
+    let (mut status, mut index) = (0, 0);
+    let varbinds = if version == VERSION_1
+        && let Some(i) = varbinds.iter().position(|(_, v)| {
+            matches!(
+                v,
+                Value::NoSuchObject | Value::NoSuchInstance | Value::EndOfMibView
+            )
+        }) {
+        status = NO_SUCH_NAME;
+        index = i as i64 + 1;
+        oids.into_iter().map(|oid| (oid, Value::Null)).collect()
+    } else {
+        varbinds
+    };
+
+    let list: Vec<u8> = varbinds
+        .iter()
+        .flat_map(|(oid, value)| {
+            encode(
+                SEQUENCE,
+                &[encode(OBJECT_ID, &encode_oid(oid)), encode_value(value)].concat(),
+            )
+        })
+        .collect();
+    let pdu = [
+        encode(INTEGER, request_id),
+        encode(INTEGER, &encode_integer(status)),
+        encode(INTEGER, &encode_integer(index)),
+        encode(SEQUENCE, &list),
+    ]
+    .concat();
+    let message = [
+        encode(INTEGER, &encode_integer(version)),
+        encode(OCTET_STRING, community.as_bytes()),
+        encode(RESPONSE, &pdu),
+    ]
+    .concat();
+    Some(encode(SEQUENCE, &message))
+}
+
+/// Splits the first tag, its contents and what follows off `data`.
+fn tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = data.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let (bytes, rest) = rest.split_at(count);
+        (bytes.iter().fold(0, |n, &b| n << 8 | b as usize), rest)
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let (contents, rest) = rest.split_at(len);
+    Some((tag, contents, rest))
+}
+
+/// Like [`tlv`], for a tag that has to be `expected`.
+fn read(data: &[u8], expected: u8) -> Option<(&[u8], &[u8])> {
+    let (tag, contents, rest) = tlv(data)?;
+    (tag == expected).then_some((contents, rest))
+}
+
+fn encode(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = contents.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|&b| b == 0)
+            .collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend(bytes);
+    }
+    out.extend(contents);
+    out
+}
+
+fn encode_value(value: &Value) -> Vec<u8> {
+    match value {
+        Value::Integer(n) => encode(INTEGER, &encode_integer(*n)),
+        Value::String(s) => encode(OCTET_STRING, s.as_bytes()),
+        // unsigned, so a leading zero keeps the top bit from reading as a sign
+        Value::Gauge(n) => encode(GAUGE32, &encode_integer(i64::from(*n))),
+        Value::Null => encode(NULL, &[]),
+        Value::NoSuchObject => encode(NO_SUCH_OBJECT, &[]),
+        Value::NoSuchInstance => encode(NO_SUCH_INSTANCE, &[]),
+        Value::EndOfMibView => encode(END_OF_MIB_VIEW, &[]),
+    }
+}
+
+/// The shortest two's complement form of `n`.
+fn encode_integer(n: i64) -> Vec<u8> {
+    let bytes = n.to_be_bytes();
+    let mut start = 0;
+    while start < 7 {
+        let (b, next) = (bytes[start], bytes[start + 1]);
+        if (b == 0 && next & 0x80 == 0) || (b == 0xff && next & 0x80 != 0) {
+            start += 1;
+        } else {
+            break;
+        }
+    }
+    bytes[start..].to_vec()
+}
+
+fn decode_integer(bytes: &[u8]) -> Option<i64> {
+    if bytes.is_empty() || bytes.len() > 8 {
+        return None;
+    }
+    let sign = if bytes[0] & 0x80 != 0 { -1 } else { 0 };
+    Some(bytes.iter().fold(sign, |n, &b| n << 8 | b as i64))
+}
+
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let first = oid.first().copied().unwrap_or(0) * 40 + oid.get(1).copied().unwrap_or(0);
+    for &n in std::iter::once(&first).chain(oid.iter().skip(2)) {
+        let mut groups = vec![(n & 0x7f) as u8];
+        let mut rest = n >> 7;
+        while rest > 0 {
+            groups.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        out.extend(groups.into_iter().rev());
+    }
+    out
+}
+
+fn decode_oid(bytes: &[u8]) -> Option<Oid> {
+    let mut ids = Vec::new();
+    let mut n: u32 = 0;
+    for &b in bytes {
+        n = n.checked_mul(128)? | (b & 0x7f) as u32;
+        if b & 0x80 == 0 {
+            ids.push(n);
+            n = 0;
+        }
+    }
+    let (&first, rest) = ids.split_first()?;
+    let (a, b) = match first {
+        0..40 => (0, first),
+        40..80 => (1, first - 40),
+        _ => (2, first - 80),
+    };
+    Some([&[a, b], rest].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &[u32] = &[1, 3, 6, 1, 4, 1, 8072, 9999, 9999, 1];
+
+    fn request(version: i64, pdu_type: u8, oids: &[&[u32]], first: i64, second: i64) -> Vec<u8> {
+        let list: Vec<u8> = oids
+            .iter()
+            .flat_map(|oid| {
+                encode(
+                    SEQUENCE,
+                    &[encode(OBJECT_ID, &encode_oid(oid)), encode(NULL, &[])].concat(),
+                )
+            })
+            .collect();
+        let pdu = [
+            encode(INTEGER, &encode_integer(42)),
+            encode(INTEGER, &encode_integer(first)),
+            encode(INTEGER, &encode_integer(second)),
+            encode(SEQUENCE, &list),
+        ]
+        .concat();
+        let message = [
+            encode(INTEGER, &encode_integer(version)),
+            encode(OCTET_STRING, b"public"),
+            encode(pdu_type, &pdu),
+        ]
+        .concat();
+        encode(SEQUENCE, &message)
+    }
+
+    /// The error status and varbinds of a response.
+    fn parse(response: &[u8]) -> (i64, Vec<(Oid, u8, Vec<u8>)>) {
+        let (message, _) = read(response, SEQUENCE).unwrap();
+        let (_, rest) = read(message, INTEGER).unwrap();
+        let (_, rest) = read(rest, OCTET_STRING).unwrap();
+        let (pdu, _) = read(rest, RESPONSE).unwrap();
+        let (request_id, rest) = read(pdu, INTEGER).unwrap();
+        assert_eq!(decode_integer(request_id), Some(42));
+        let (status, rest) = read(rest, INTEGER).unwrap();
+        let (_, rest) = read(rest, INTEGER).unwrap();
+        let (mut list, _) = read(rest, SEQUENCE).unwrap();
+        let mut varbinds = Vec::new();
+        while !list.is_empty() {
+            let (varbind, rest) = read(list, SEQUENCE).unwrap();
+            let (oid, value) = read(varbind, OBJECT_ID).unwrap();
+            let (tag, contents, _) = tlv(value).unwrap();
+            varbinds.push((decode_oid(oid).unwrap(), tag, contents.to_vec()));
+            list = rest;
+        }
+        (decode_integer(status).unwrap(), varbinds)
+    }
+
+    fn mib_for_test() -> Vec<(Oid, Value)> {
+        let mut readings = Readings::new(["fermenter", "ambient"]);
+        readings.record("fermenter", Some(18.625));
+        readings.record("ambient", None);
+        mib(BASE, &readings)
+    }
+
+    #[test]
+    fn test_encoding() {
+        for n in [0, 1, 127, 128, 255, 256, -1, -128, -129, 18625, i64::MIN] {
+            assert_eq!(decode_integer(&encode_integer(n)), Some(n));
+        }
+        assert_eq!(encode_integer(128), [0x00, 0x80]);
+        assert_eq!(
+            encode_oid(&[1, 3, 6, 1, 4, 1, 8072]),
+            [0x2b, 6, 1, 4, 1, 0xbf, 0x08]
+        );
+        assert_eq!(decode_oid(&encode_oid(BASE)).unwrap(), BASE);
+        assert_eq!(
+            encode(OCTET_STRING, &[0; 200])[..3],
+            [OCTET_STRING, 0x81, 200]
+        );
+        assert_eq!(parse_oid(".1.3.6.1.4.1.8072").unwrap()[6], 8072);
+        assert!(parse_oid("1.3.x").is_err());
+    }
+
+    #[test]
+    fn test_get() {
+        let mib = mib_for_test();
+        let count = [BASE, &[1, 0]].concat();
+        let temp = [BASE, &[2, 1, 3, 2]].concat();
+        let missing = [BASE, &[2, 1, 3, 1]].concat();
+        let response = respond(
+            &request(VERSION_2C, GET, &[&count, &temp, &missing], 0, 0),
+            "public",
+            &mib,
+        )
+        .unwrap();
+        let (status, varbinds) = parse(&response);
+        assert_eq!(status, 0);
+        assert_eq!(varbinds[0].2, [2]);
+        // fermenter sorts after ambient, and reads 18.625°c
+        assert_eq!(decode_integer(&varbinds[1].2), Some(18625));
+        assert_eq!(varbinds[2].1, NO_SUCH_INSTANCE);
+
+        assert!(respond(&request(VERSION_2C, GET, &[&count], 0, 0), "private", &mib).is_none());
+        assert!(respond(b"\x30\x03\x02\x01", "public", &mib).is_none());
+
+        let response = respond(
+            &request(VERSION_1, GET, &[&count, &missing], 0, 0),
+            "public",
+            &mib,
+        );
+        let (status, varbinds) = parse(&response.unwrap());
+        assert_eq!(status, NO_SUCH_NAME);
+        assert_eq!(varbinds[1].1, NULL);
+    }
+
+    #[test]
+    fn test_walk() {
+        let mib = mib_for_test();
+        let mut oid = BASE.to_vec();
+        let mut walked = Vec::new();
+        loop {
+            let response = respond(
+                &request(VERSION_2C, GET_NEXT, &[&oid], 0, 0),
+                "public",
+                &mib,
+            );
+            let (_, varbinds) = parse(&response.unwrap());
+            let (next, tag, _) = varbinds.into_iter().next().unwrap();
+            if tag == END_OF_MIB_VIEW {
+                break;
+            }
+            walked.push(next.clone());
+            oid = next;
+        }
+        assert_eq!(
+            walked,
+            mib.iter().map(|(o, _)| o.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!(walked.len(), 8);
+
+        let response = respond(
+            &request(VERSION_2C, GET_BULK, &[BASE], 0, 5),
+            "public",
+            &mib,
+        );
+        let (_, varbinds) = parse(&response.unwrap());
+        assert_eq!(varbinds.len(), 5);
+        assert_eq!(varbinds[4].0, [BASE, &[2, 1, 2, 2]].concat());
+        assert_eq!(varbinds[4].2, b"fermenter");
+    }
+}
@@ -0,0 +1,460 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::alert::Alert;
+use crate::config::QuarantineConfig;
+use crate::history::History;
+
+/// The latest readings, shared between the probe loop and everything that
+/// shows them. Readers work on a snapshot rather than under a lock, so a
+/// slow one never holds up the probe loop; an update copies the readings
+/// first if an older snapshot is still in use. The history is too big to
+/// copy like that, so every snapshot shares it and sees it as it is now.
+#[derive(Clone)]
+pub struct TempData(Arc<RwLock<Arc<Readings>>>);
+
+impl TempData {
+    pub fn new(readings: Readings) -> Self {
+        TempData(Arc::new(RwLock::new(Arc::new(readings))))
+    }
+
+    /// The readings as they are now, unaffected by later updates.
+    pub fn snapshot(&self) -> Arc<Readings> {
+        Arc::clone(&self.0.read().unwrap())
+    }
+
+    pub fn update<T>(&self, update: impl FnOnce(&mut Readings) -> T) -> T {
+        let mut current = self.0.write().unwrap();
+        let readings = Arc::make_mut(&mut current);
+        readings.generation += 1;
+        update(readings)
+    }
+
+    /// Bumps the generation without changing anything, for when what's
+    /// shown changes some other way, e.g. the config is reloaded.
+    pub fn invalidate(&self) {
+        self.update(|_| ());
+    }
+}
+
+/// Latest readings shared between the probe loop and the http server.
+#[derive(Clone)]
+pub struct Readings {
+    pub temps: HashMap<String, Option<f32>>,
+    /// consecutive failed reads per probe
+    pub failures: HashMap<String, u32>,
+    /// when a reading (or failed read) was last recorded
+    pub updated: SystemTime,
+    /// when the first successful reading was recorded
+    pub first_reading: Option<SystemTime>,
+    /// probes that kept failing and are now only retried occasionally
+    pub quarantined: HashMap<String, Quarantine>,
+    /// last successful reading per probe and when it was taken
+    pub last_good: HashMap<String, (f32, SystemTime)>,
+    /// pending and firing alerts sorted by probe, then recently resolved ones
+    pub alerts: Vec<Alert>,
+    /// silences that haven't expired yet, from the config and the api
+    pub silences: Vec<Silence>,
+    next_silence_id: u64,
+    /// probes covered by a silence, and when the last of them ends
+    pub silenced: HashMap<String, SystemTime>,
+    /// recent successful readings per probe
+    pub history: Arc<RwLock<History>>,
+    /// the type and message of the last error per failing probe
+    pub errors: HashMap<String, (&'static str, String)>,
+    /// bumped by every update, so alerts, silences and quarantine changes
+    /// show in the dashboard's etag along with new readings
+    pub generation: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Silence {
+    pub id: u64,
+    /// probe id, probe label or alert group
+    pub target: String,
+    pub until: SystemTime,
+    pub comment: Option<String>,
+    /// set in the config file rather than through the api
+    pub from_config: bool,
+}
+
+#[derive(Clone)]
+pub struct Quarantine {
+    pub since: SystemTime,
+    pub retry_at: Instant,
+    pub backoff: Duration,
+}
+
+impl Readings {
+    pub fn new<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        Readings {
+            temps: names.into_iter().map(|n| (n.to_string(), None)).collect(),
+            failures: HashMap::new(),
+            updated: SystemTime::now(),
+            first_reading: None,
+            quarantined: HashMap::new(),
+            last_good: HashMap::new(),
+            alerts: Vec::new(),
+            silences: Vec::new(),
+            next_silence_id: 1,
+            silenced: HashMap::new(),
+            history: Arc::default(),
+            errors: HashMap::new(),
+            generation: 0,
+        }
+    }
+
+    pub fn record(&mut self, name: &str, temp: Option<f32>) {
+        self.temps.insert(name.to_string(), temp);
+        let failures = self.failures.entry(name.to_string()).or_default();
+        *failures = match temp {
+            Some(_) => 0,
+            None => *failures + 1,
+        };
+        self.updated = SystemTime::now();
+        if let Some(temp) = temp {
+            self.errors.remove(name);
+            self.last_good
+                .insert(name.to_string(), (temp, self.updated));
+            self.history
+                .write()
+                .unwrap()
+                .record(name, temp, self.updated);
+        }
+        if temp.is_some() && self.first_reading.is_none() {
+            self.first_reading = Some(self.updated);
+        }
+    }
+
+    /// Records a failed read along with why it failed.
+    pub fn record_error(&mut self, name: &str, error_type: &'static str, message: String) {
+        self.record(name, None);
+        self.errors.insert(name.to_string(), (error_type, message));
+    }
+
+    /// Moves a probe's state over to a new display name.
+    pub fn rename(&mut self, old: &str, new: &str) {
+        if let Some(temp) = self.temps.remove(old) {
+            self.temps.insert(new.to_string(), temp);
+        }
+        if let Some(failures) = self.failures.remove(old) {
+            self.failures.insert(new.to_string(), failures);
+        }
+        if let Some(quarantine) = self.quarantined.remove(old) {
+            self.quarantined.insert(new.to_string(), quarantine);
+        }
+        if let Some(last_good) = self.last_good.remove(old) {
+            self.last_good.insert(new.to_string(), last_good);
+        }
+        if let Some(until) = self.silenced.remove(old) {
+            self.silenced.insert(new.to_string(), until);
+        }
+        if let Some(error) = self.errors.remove(old) {
+            self.errors.insert(new.to_string(), error);
+        }
+        self.history.write().unwrap().rename(old, new);
+    }
+
+    /// Forgets a probe that's gone for good, bar its history.
+    pub fn remove(&mut self, name: &str) {
+        self.temps.remove(name);
+        self.failures.remove(name);
+        self.quarantined.remove(name);
+        self.last_good.remove(name);
+        self.silenced.remove(name);
+        self.errors.remove(name);
+    }
+
+    /// Whether a probe should be read this cycle, i.e. it isn't
+    /// quarantined or it is time to retry it.
+    pub fn is_due(&self, name: &str, now: Instant) -> bool {
+        self.quarantined.get(name).is_none_or(|q| now >= q.retry_at)
+    }
+
+    /// Quarantines a probe once it has failed enough reads in a row, backs
+    /// off further each time a quarantined probe fails again, and releases
+    /// it after a successful read. `interval` is the normal polling interval.
+    pub fn update_quarantine(
+        &mut self,
+        name: &str,
+        policy: &QuarantineConfig,
+        interval: Duration,
+        now: Instant,
+    ) {
+        let failures = self.failures.get(name).copied().unwrap_or(0);
+        if failures == 0 {
+            self.quarantined.remove(name);
+            return;
+        }
+
+        let max_backoff = Duration::from_secs(policy.max_backoff);
+        if let Some(q) = self.quarantined.get_mut(name) {
+            q.backoff = (q.backoff * 2).min(max_backoff);
+            q.retry_at = now + q.backoff;
+        } else if failures >= policy.after_failures {
+            let backoff = (interval * 2).min(max_backoff);
+            self.quarantined.insert(
+                name.to_string(),
+                Quarantine {
+                    since: SystemTime::now(),
+                    retry_at: now + backoff,
+                    backoff,
+                },
+            );
+        }
+    }
+
+    /// Lifts a probe's quarantine so it is read again from the next cycle.
+    /// Returns false if it wasn't quarantined.
+    pub fn release(&mut self, name: &str) -> bool {
+        if self.quarantined.remove(name).is_some() {
+            self.failures.insert(name.to_string(), 0);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The last good reading and when it was taken, while a probe has
+    /// failed fewer than `down_after` reads in a row.
+    pub fn last_good(&self, name: &str, down_after: u32) -> Option<(f32, SystemTime)> {
+        let failures = self.failures.get(name).copied().unwrap_or(0);
+        if failures >= down_after {
+            return None;
+        }
+        self.last_good.get(name).copied()
+    }
+
+    pub fn add_silence(
+        &mut self,
+        target: &str,
+        until: SystemTime,
+        comment: Option<String>,
+        from_config: bool,
+    ) -> Silence {
+        let silence = Silence {
+            id: self.next_silence_id,
+            target: target.to_string(),
+            until,
+            comment,
+            from_config,
+        };
+        self.next_silence_id += 1;
+        self.silences.push(silence.clone());
+        silence
+    }
+
+    /// Replaces the silences from the config file, leaving api ones alone.
+    pub fn set_config_silences(&mut self, silences: Vec<(String, SystemTime, Option<String>)>) {
+        self.silences.retain(|s| !s.from_config);
+        for (target, until, comment) in silences {
+            self.add_silence(&target, until, comment, true);
+        }
+    }
+
+    /// Ends a silence added through the api early. Returns false if there
+    /// isn't one with this id.
+    pub fn remove_silence(&mut self, id: u64) -> bool {
+        let before = self.silences.len();
+        self.silences.retain(|s| s.from_config || s.id != id);
+        self.silences.len() != before
+    }
+
+    /// Drops expired silences and works out which probes are silenced now,
+    /// `matches(target, name)` says whether a silence covers a probe.
+    pub fn update_silenced(&mut self, now: SystemTime, matches: impl Fn(&str, &str) -> bool) {
+        self.silences.retain(|s| s.until > now);
+        self.silenced.clear();
+        for name in self.temps.keys() {
+            let until = self
+                .silences
+                .iter()
+                .filter(|s| matches(&s.target, name))
+                .map(|s| s.until)
+                .max();
+            if let Some(until) = until {
+                self.silenced.insert(name.clone(), until);
+            }
+        }
+    }
+
+    /// Probes that have failed at least `cycles` reads in a row, sorted by name.
+    pub fn failing_probes(&self, cycles: u32) -> Vec<&str> {
+        let mut failing: Vec<_> = self
+            .failures
+            .iter()
+            .filter(|(_, count)| **count >= cycles)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        failing.sort();
+        failing
+    }
+
+    /// Whether enough probes are failing to consider the service unhealthy.
+    pub fn is_unhealthy(&self, cycles: u32, fraction: f32) -> bool {
+        let failing = self.failing_probes(cycles).len();
+        failing > 0 && failing as f32 >= fraction * self.temps.len() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_is_unaffected_by_updates() {
+        let temps = TempData::new(Readings::new(["a"]));
+        let before = temps.snapshot();
+        temps.update(|readings| readings.record("a", Some(20.5)));
+
+        assert_eq!(before.temps["a"], None);
+        assert_eq!(temps.snapshot().temps["a"], Some(20.5));
+        // bar the history, which isn't copied
+        assert!(Arc::ptr_eq(&before.history, &temps.snapshot().history));
+    }
+
+    #[test]
+    fn test_failures_reset_on_success() {
+        let mut readings = Readings::new(["a", "b"]);
+        readings.record("a", None);
+        readings.record("a", None);
+        assert_eq!(readings.failing_probes(2), vec!["a"]);
+
+        readings.record_error("a", "timeout", "read timed out after 2.0s".to_string());
+        assert_eq!(readings.failures["a"], 3);
+        assert_eq!(readings.errors["a"].0, "timeout");
+
+        readings.record("a", Some(21.0));
+        assert!(readings.failing_probes(1).is_empty());
+        assert!(readings.errors.is_empty());
+    }
+
+    #[test]
+    fn test_rename_keeps_state() {
+        let mut readings = Readings::new(["28-abc123"]);
+        readings.record("28-abc123", None);
+        readings.rename("28-abc123", "basking_spot");
+
+        assert_eq!(readings.temps.get("basking_spot"), Some(&None));
+        assert!(!readings.temps.contains_key("28-abc123"));
+        assert_eq!(readings.failing_probes(1), vec!["basking_spot"]);
+    }
+
+    #[test]
+    fn test_last_good_until_down() {
+        let mut readings = Readings::new(["a"]);
+        assert!(readings.last_good("a", 3).is_none());
+
+        readings.record("a", Some(21.5));
+        readings.record("a", None);
+        readings.record("a", None);
+        assert_eq!(readings.last_good("a", 3).map(|(t, _)| t), Some(21.5));
+
+        readings.record("a", None);
+        assert!(readings.last_good("a", 3).is_none());
+    }
+
+    #[test]
+    fn test_silences() {
+        let mut readings = Readings::new(["chest", "upright", "room"]);
+        let now = SystemTime::now();
+        let hour = Duration::from_secs(3600);
+
+        readings.set_config_silences(vec![("freezers".to_string(), now + hour, None)]);
+        let added = readings.add_silence("room", now + hour * 2, None, false);
+        let expired = readings.add_silence("upright", now - hour, None, false);
+
+        let freezers = ["chest", "upright"];
+        readings.update_silenced(now, |target, name| {
+            target == name || (target == "freezers" && freezers.contains(&name))
+        });
+        assert_eq!(readings.silenced.get("chest"), Some(&(now + hour)));
+        assert_eq!(readings.silenced.get("upright"), Some(&(now + hour)));
+        assert_eq!(readings.silenced.get("room"), Some(&(now + hour * 2)));
+        assert!(readings.silences.iter().all(|s| s.id != expired.id));
+
+        // config silences can only be removed from the config
+        let config_id = readings.silences[0].id;
+        assert!(!readings.remove_silence(config_id));
+        assert!(readings.remove_silence(added.id));
+
+        readings.set_config_silences(Vec::new());
+        assert!(readings.silences.is_empty());
+    }
+
+    #[test]
+    fn test_first_reading_is_latched() {
+        let mut readings = Readings::new(["a"]);
+        readings.record("a", None);
+        assert!(readings.first_reading.is_none());
+
+        readings.record("a", Some(21.0));
+        let first = readings.first_reading;
+        assert!(first.is_some());
+
+        readings.record("a", Some(22.0));
+        assert_eq!(readings.first_reading, first);
+    }
+
+    #[test]
+    fn test_quarantine_backoff() {
+        let policy = QuarantineConfig {
+            after_failures: 2,
+            max_backoff: 100,
+        };
+        let interval = Duration::from_secs(15);
+        let now = Instant::now();
+        let mut readings = Readings::new(["a"]);
+
+        readings.record("a", None);
+        readings.update_quarantine("a", &policy, interval, now);
+        assert!(readings.is_due("a", now));
+
+        readings.record("a", None);
+        readings.update_quarantine("a", &policy, interval, now);
+        assert!(!readings.is_due("a", now));
+        assert!(readings.is_due("a", now + Duration::from_secs(30)));
+
+        // failing again while quarantined doubles the wait, up to the cap
+        readings.record("a", None);
+        readings.update_quarantine("a", &policy, interval, now);
+        assert_eq!(readings.quarantined["a"].backoff, Duration::from_secs(60));
+        readings.record("a", None);
+        readings.update_quarantine("a", &policy, interval, now);
+        assert_eq!(readings.quarantined["a"].backoff, Duration::from_secs(100));
+
+        // a good read releases it
+        readings.record("a", Some(21.0));
+        readings.update_quarantine("a", &policy, interval, now);
+        assert!(readings.quarantined.is_empty());
+    }
+
+    #[test]
+    fn test_release() {
+        let policy = QuarantineConfig {
+            after_failures: 1,
+            max_backoff: 100,
+        };
+        let now = Instant::now();
+        let mut readings = Readings::new(["a"]);
+        readings.record("a", None);
+        readings.update_quarantine("a", &policy, Duration::from_secs(15), now);
+
+        assert!(readings.release("a"));
+        assert!(readings.is_due("a", now));
+        assert!(readings.failing_probes(1).is_empty());
+        assert!(!readings.release("a"));
+    }
+
+    #[test]
+    fn test_unhealthy_fraction() {
+        let mut readings = Readings::new(["a", "b"]);
+        readings.record("a", None);
+        readings.record("b", Some(21.0));
+
+        assert!(readings.is_unhealthy(1, 0.5));
+        assert!(!readings.is_unhealthy(1, 1.0));
+        assert!(!readings.is_unhealthy(2, 0.5));
+    }
+}
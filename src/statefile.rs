@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::history::Rollup;
+use crate::probe::{self, Probe, display_name};
+use crate::state::Readings;
+
+/// How much of each probe's history is kept, for the dashboard's lowest,
+/// highest and average readings.
+const DAY: Duration = Duration::from_secs(24 * 3600);
+
+/// What's kept across a restart, as JSON.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Saved {
+    /// the last good reading per probe id
+    #[serde(default)]
+    last_good: HashMap<String, LastGood>,
+    /// failed reads per probe id and error type, over every run
+    #[serde(default)]
+    read_errors: HashMap<String, HashMap<String, u64>>,
+    /// the last day's readings per probe id, an hour at a time
+    #[serde(default)]
+    day: HashMap<String, Vec<Rollup>>,
+}
+
+/// What [`restore`] put back.
+#[derive(Debug, Default)]
+pub struct Restored {
+    /// the probes with a last reading on the dashboard
+    pub names: Vec<String>,
+    /// failed reads so far as (probe name, error type, count), for the
+    /// counters to carry on from
+    pub read_errors: Vec<(String, &'static str, u64)>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LastGood {
+    temp: f32,
+    /// when it was taken, in seconds since the unix epoch
+    at: f64,
+}
+
+/// Loads the readings saved by the last run into `readings`. They show as
+/// stale, with their age, until each probe is read again. A missing file
+/// restores nothing.
+pub fn restore(
+    path: &Path,
+    labels: &HashMap<String, String>,
+    readings: &mut Readings,
+) -> io::Result<Restored> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Restored::default()),
+        Err(e) => return Err(e),
+    };
+    let saved: Saved =
+        serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut restored = Restored::default();
+    for (id, last) in saved.last_good {
+        let Ok(since_epoch) = Duration::try_from_secs_f64(last.at) else {
+            continue;
+        };
+        let name = display_name(&id, labels);
+        readings.temps.entry(name.clone()).or_insert(None);
+        readings
+            .last_good
+            .insert(name.clone(), (last.temp, UNIX_EPOCH + since_epoch));
+        restored.names.push(name);
+    }
+    restored.names.sort();
+
+    for (id, errors) in saved.read_errors {
+        let name = display_name(&id, labels);
+        for (error_type, count) in errors {
+            // from a newer version, or one that's since been renamed
+            if let Some(error_type) = probe::ERROR_TYPES.iter().find(|t| **t == error_type) {
+                restored.read_errors.push((name.clone(), error_type, count));
+            }
+        }
+    }
+    for (id, rollups) in saved.day {
+        readings
+            .history
+            .write()
+            .unwrap()
+            .restore(&display_name(&id, labels), &rollups);
+    }
+    Ok(restored)
+}
+
+/// Saves each probe's last good reading, its failed reads by error type as
+/// `read_errors` counts them, and its last day of history for the next run
+/// to [`restore`]. The file is replaced in one go, so it's never left half
+/// written.
+pub fn save(
+    path: &Path,
+    probes: &[Probe],
+    readings: &Readings,
+    read_errors: impl Fn(&str) -> HashMap<String, u64>,
+) -> io::Result<()> {
+    let last_good = probes
+        .iter()
+        .filter_map(|p| {
+            let (temp, at) = readings.last_good.get(&p.name)?;
+            let at = at.duration_since(UNIX_EPOCH).ok()?.as_secs_f64();
+            Some((p.id.clone(), LastGood { temp: *temp, at }))
+        })
+        .collect();
+    let read_errors = probes
+        .iter()
+        .map(|p| (p.id.clone(), read_errors(&p.name)))
+        .filter(|(_, errors)| !errors.is_empty())
+        .collect();
+    let from = SystemTime::now().checked_sub(DAY).unwrap_or(UNIX_EPOCH);
+    let day = probes
+        .iter()
+        .map(|p| {
+            (
+                p.id.clone(),
+                readings.history.read().unwrap().rollups(&p.name, from),
+            )
+        })
+        .filter(|(_, rollups)| !rollups.is_empty())
+        .collect();
+    let saved = Saved {
+        last_good,
+        read_errors,
+        day,
+    };
+    let json = serde_json::to_string(&saved).expect("state serializes");
+
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".tmp");
+    fs::write(&partial, json)?;
+    fs::rename(&partial, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::probe::{ProbeError, Source};
+
+    #[derive(Debug)]
+    struct Fixed;
+
+    impl Source for Fixed {
+        fn read_temperature(&self) -> Result<f32, ProbeError> {
+            Ok(20.0)
+        }
+
+        fn location(&self) -> String {
+            "fixed".to_string()
+        }
+    }
+
+    #[test]
+    fn test_save_and_restore() {
+        let path = std::env::temp_dir().join(format!("tempmon-state-{}", std::process::id()));
+        let probe = |id: &str, name: &str| Probe {
+            id: id.to_string(),
+            name: name.to_string(),
+            source: Arc::new(Fixed),
+        };
+        let mut readings = Readings::new(["fermenter", "28-def"]);
+        readings.record("fermenter", Some(18.5));
+        readings.record("28-def", None);
+        let taken = readings.last_good["fermenter"].1;
+        let read_errors = |name: &str| match name {
+            "28-def" => HashMap::from([("crc_failure".to_string(), 3), ("gone".to_string(), 1)]),
+            _ => HashMap::new(),
+        };
+        save(
+            &path,
+            &[probe("28-abc", "fermenter"), probe("28-def", "28-def")],
+            &readings,
+            read_errors,
+        )
+        .unwrap();
+
+        // renamed since
+        let labels = HashMap::from([("28-abc".to_string(), "mash tun".to_string())]);
+        let mut readings = Readings::new([]);
+        let restored = restore(&path, &labels, &mut readings).unwrap();
+        assert_eq!(restored.names, ["mash tun"]);
+        assert_eq!(
+            restored.read_errors,
+            [("28-def".to_string(), "crc_failure", 3)]
+        );
+        let day = readings
+            .history
+            .read()
+            .unwrap()
+            .summary("mash tun", taken - DAY)
+            .unwrap();
+        assert_eq!((day.min, day.max), (18.5, 18.5));
+        assert_eq!(readings.temps["mash tun"], None);
+        let (temp, at) = readings.last_good("mash tun", 3).unwrap();
+        assert_eq!(temp, 18.5);
+        assert!(
+            at.duration_since(taken).unwrap_or_else(|e| e.duration()) < Duration::from_millis(1)
+        );
+        assert!(readings.first_reading.is_none());
+        fs::remove_file(&path).unwrap();
+
+        let restored = restore(&path, &labels, &mut readings).unwrap();
+        assert!(restored.names.is_empty());
+    }
+}
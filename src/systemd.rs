@@ -0,0 +1,96 @@
+use std::env;
+use std::net::TcpListener;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixListener;
+use std::process;
+
+use socket2::Socket;
+
+// sd_listen_fds(3): passed sockets start at fd 3
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A listening socket, blocking until handed to the server.
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// Sockets passed in by systemd socket activation, with their
+/// `FileDescriptorName=`.
+pub fn listen_fds() -> Vec<(Listener, String)> {
+    let fds = parse_listen_env(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        env::var("LISTEN_FDNAMES").ok().as_deref(),
+        process::id(),
+    );
+
+    fds.into_iter()
+        .map(|(fd, name)| {
+            // safety: systemd hands these fds to this process and nothing
+            // else in tempmon takes ownership of them
+            let socket = unsafe { Socket::from_raw_fd(fd) };
+            let is_tcp = socket
+                .local_addr()
+                .is_ok_and(|addr| addr.as_socket().is_some());
+
+            let listener = if is_tcp {
+                Listener::Tcp(TcpListener::from(socket))
+            } else {
+                Listener::Unix(UnixListener::from(OwnedFd::from(socket)))
+            };
+            (listener, name)
+        })
+        .collect()
+}
+
+fn parse_listen_env(
+    pid: Option<&str>,
+    fds: Option<&str>,
+    names: Option<&str>,
+    own_pid: u32,
+) -> Vec<(RawFd, String)> {
+    // the variables are inherited by children, so make sure they're for us
+    if pid.and_then(|p| p.parse::<u32>().ok()) != Some(own_pid) {
+        return Vec::new();
+    }
+    let count = fds.and_then(|n| n.parse::<RawFd>().ok()).unwrap_or(0);
+    let mut names = names.unwrap_or("").split(':');
+
+    (0..count)
+        .map(|i| {
+            let name = names
+                .next()
+                .filter(|n| !n.is_empty())
+                .unwrap_or("unknown")
+                .to_string();
+            (LISTEN_FDS_START + i, name)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listen_env() {
+        let fds = parse_listen_env(Some("42"), Some("2"), Some("http:metrics"), 42);
+        assert_eq!(
+            fds,
+            vec![(3, "http".to_string()), (4, "metrics".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_parse_listen_env_without_names() {
+        let fds = parse_listen_env(Some("42"), Some("1"), None, 42);
+        assert_eq!(fds, vec![(3, "unknown".to_string())]);
+    }
+
+    #[test]
+    fn test_parse_listen_env_for_other_process() {
+        assert!(parse_listen_env(Some("41"), Some("1"), None, 42).is_empty());
+        assert!(parse_listen_env(None, None, None, 42).is_empty());
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use minijinja::{AutoEscape, Environment, Value};
+
+const NAME: &str = "dashboard";
+
+/// A user's dashboard template, rendered in place of the built-in page.
+pub struct Template {
+    env: Environment<'static>,
+}
+
+impl Template {
+    /// Reads and compiles the template at `path`, so a syntax error shows
+    /// up at startup or on reload rather than on the first request.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        Template::compile(source)
+            .map_err(|e| io::Error::other(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn compile(source: String) -> Result<Self, minijinja::Error> {
+        let mut env = Environment::new();
+        // probe names come from the config and the api, so escape whatever
+        // the file is called
+        env.set_auto_escape_callback(|_| AutoEscape::Html);
+        env.add_template_owned(NAME, source)?;
+        Ok(Template { env })
+    }
+
+    pub fn render(&self, ctx: Value) -> Result<String, minijinja::Error> {
+        self.env.get_template(NAME)?.render(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minijinja::context;
+
+    #[test]
+    fn test_render() {
+        let template =
+            Template::compile("{% for p in probes %}<b>{{ p.name }}</b>{% endfor %}".to_string())
+                .unwrap();
+        let html = template
+            .render(context! { probes => [context! { name => "<script>" }] })
+            .unwrap();
+        assert_eq!(html, "<b>&lt;script&gt;</b>");
+
+        assert!(Template::compile("{% for p in probes %}".to_string()).is_err());
+        assert!(Template::load(Path::new("/nonexistent/dashboard.html")).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use inotify::{Inotify, WatchMask};
+
+/// Sets `reload` whenever the config file at `path` is written or replaced.
+pub fn watch_config(path: &Path, reload: Arc<AtomicBool>) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "config path has no file name"))?
+        .to_owned();
+
+    let mut inotify = Inotify::init()?;
+    // watch the directory rather than the file, since most editors save by
+    // writing a new file and renaming it over the old one
+    inotify.watches().add(
+        dir,
+        WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE,
+    )?;
+
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        loop {
+            match inotify.read_events_blocking(&mut buffer) {
+                Ok(mut events) => {
+                    if events.any(|e| e.name == Some(file_name.as_os_str())) {
+                        reload.store(true, Ordering::Relaxed);
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("failed to watch config file, giving up: {}", e);
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn test_replacing_config_sets_reload() {
+        let dir = std::env::temp_dir().join(format!("tempmon-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, "").unwrap();
+
+        let reload = Arc::new(AtomicBool::new(false));
+        watch_config(&path, Arc::clone(&reload)).unwrap();
+
+        // unrelated files in the same directory are ignored
+        fs::write(dir.join("other.toml"), "").unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(!reload.load(Ordering::Relaxed));
+
+        let tmp = dir.join("config.toml.tmp");
+        fs::write(&tmp, "[settings]").unwrap();
+        fs::rename(&tmp, &path).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !reload.load(Ordering::Relaxed) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(reload.load(Ordering::Relaxed));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use prometheus::IntCounter;
+use tokio::sync::oneshot;
+use tracing::error;
+
+use crate::config::Config;
+use crate::poll::{Poller, Sample};
+use crate::probe::{Probe, ProbeError};
+
+type Results = Vec<Result<Sample, ProbeError>>;
+type Request = (Vec<Probe>, Arc<Config>, oneshot::Sender<Results>);
+
+/// The poller on a thread of its own, started again if a cycle's reads
+/// haven't finished in time. Whatever the hung thread was stuck on is left
+/// to it, so a read that never returns costs a thread but not the
+/// readings. The new thread shares the old one's [`Poller`], so smoothing
+/// carries on and reads still stuck aren't started again.
+pub struct Watchdog {
+    requests: mpsc::Sender<Request>,
+    poller: Poller,
+    restarts: IntCounter,
+}
+
+impl Watchdog {
+    pub fn start(restarts: IntCounter) -> std::io::Result<Self> {
+        let poller = Poller::default();
+        Ok(Watchdog {
+            requests: spawn(poller.clone())?,
+            poller,
+            restarts,
+        })
+    }
+
+    /// Reads `probes` on the poller thread, as [`Poller::read_all`] does.
+    /// `None` if they haven't all been read within `limit`, or the thread
+    /// died, after which the next cycle's reads go to a new one.
+    pub async fn read_all(
+        &mut self,
+        probes: &[Probe],
+        config: &Arc<Config>,
+        limit: Option<Duration>,
+    ) -> Option<Results> {
+        let (reply, results) = oneshot::channel();
+        let request = (probes.to_vec(), Arc::clone(config), reply);
+        if self.requests.send(request).is_err() {
+            error!("poller thread died, restarting it");
+        } else {
+            let results = match limit {
+                Some(limit) => tokio::time::timeout(limit, results).await,
+                None => Ok(results.await),
+            };
+            match results {
+                Ok(Ok(results)) => return Some(results),
+                Ok(Err(_)) => error!("poller thread died, restarting it"),
+                Err(_) => error!(
+                    "poller hasn't finished a cycle in {:.0}s, restarting it",
+                    limit.unwrap_or_default().as_secs_f64()
+                ),
+            }
+        }
+        self.restarts.inc();
+        match spawn(self.poller.clone()) {
+            // dropping the old sender lets a hung thread exit if it ever
+            // gets going again
+            Ok(requests) => self.requests = requests,
+            Err(e) => error!("failed to start a new poller thread: {}", e),
+        }
+        None
+    }
+}
+
+/// Starts a poller thread, returning where to send it reads.
+fn spawn(poller: Poller) -> std::io::Result<mpsc::Sender<Request>> {
+    let (requests, received) = mpsc::channel::<Request>();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()?;
+    thread::Builder::new()
+        .name("poller".to_string())
+        .spawn(move || {
+            while let Ok((probes, config, reply)) = received.recv() {
+                let results = runtime.block_on(poller.read_all(&probes, &config));
+                let _ = reply.send(results);
+            }
+            // don't wait on reads that are still stuck
+            runtime.shutdown_background();
+        })?;
+    Ok(requests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::probe::Source;
+
+    /// A probe that takes `.0` to read.
+    #[derive(Debug)]
+    struct Slow(Duration);
+
+    impl Source for Slow {
+        fn read_temperature(&self) -> Result<f32, ProbeError> {
+            thread::sleep(self.0);
+            Ok(20.0)
+        }
+
+        fn location(&self) -> String {
+            "slow".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restarts_a_hung_poller() {
+        let probe = |id: &str, delay| Probe {
+            id: id.to_string(),
+            name: id.to_string(),
+            source: Arc::new(Slow(delay)),
+        };
+        let config: Config = toml::from_str(
+            r#"
+[settings]
+metrics_port = 9184
+probe_interval = 15
+probe_resolution = 10
+
+[probe_labels]
+            "#,
+        )
+        .unwrap();
+        let config = Arc::new(config);
+        let restarts = IntCounter::new("restarts", "restarts").unwrap();
+        let mut watchdog = Watchdog::start(restarts.clone()).unwrap();
+        let limit = Some(Duration::from_millis(100));
+
+        let hung = [probe("a", Duration::from_secs(2))];
+        assert!(watchdog.read_all(&hung, &config, limit).await.is_none());
+        assert_eq!(restarts.get(), 1);
+
+        // the new thread isn't held up by the old one's read
+        let results = watchdog
+            .read_all(&[probe("b", Duration::ZERO)], &config, limit)
+            .await;
+        assert_eq!(results.unwrap()[0].as_ref().unwrap().temp, 20.0);
+        assert_eq!(restarts.get(), 1);
+
+        // nor does it start another read of the probe still stuck on it
+        let results = watchdog.read_all(&hung, &config, limit).await;
+        let error = results.unwrap().remove(0).unwrap_err();
+        assert_eq!(error.error_type(), "timeout");
+        assert_eq!(restarts.get(), 1);
+    }
+}
@@ -0,0 +1,248 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use crate::config::ZabbixConfig;
+use crate::events::{Consumer, Event, Events, Reading};
+
+/// The low-level discovery item probes are sent to.
+pub const DISCOVERY_KEY: &str = "tempmon.discovery";
+const HEADER: &[u8] = b"ZBXD\x01";
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Discovery is sent again this often, so a server that missed it (or was
+/// set up later) catches up.
+const REDISCOVER: Duration = Duration::from_secs(3600);
+/// The most responses the server can fall behind by before cycles are
+/// dropped rather than queued.
+const BACKLOG: usize = 4;
+
+#[derive(Debug, Serialize)]
+struct Item {
+    host: String,
+    key: String,
+    value: String,
+    clock: u64,
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    response: String,
+    info: Option<String>,
+}
+
+/// The trapper item a probe's readings are sent to.
+pub fn temperature_key(name: &str) -> String {
+    format!("tempmon.temperature[\"{}\"]", name.replace('"', "\\\""))
+}
+
+/// The low-level discovery data for probes as `(id, name)`, which zabbix
+/// turns into items from the `{#PROBE}` and `{#PROBE.ID}` macros.
+pub fn discovery(probes: &[(&str, &str)]) -> String {
+    let data: Vec<_> = probes
+        .iter()
+        .map(|(id, name)| json!({ "{#PROBE}": name, "{#PROBE.ID}": id }))
+        .collect();
+    serde_json::Value::from(data).to_string()
+}
+
+/// Pushes readings to a zabbix server or proxy with the sender protocol,
+/// from a thread of its own so a slow server can't hold up polling.
+pub struct Sender {
+    tx: SyncSender<Vec<Item>>,
+    host: String,
+    /// the probes last sent for discovery, and when
+    discovered: Option<(Vec<(String, String)>, Instant)>,
+}
+
+impl Sender {
+    pub fn start(config: &ZabbixConfig) -> Self {
+        let (tx, rx) = mpsc::sync_channel::<Vec<Item>>(BACKLOG);
+        let server = format!("{}:{}", config.server, config.port);
+        thread::spawn(move || {
+            for items in rx {
+                match send(&server, &items) {
+                    Ok(info) => debug!(server, "sent {} items to zabbix: {}", items.len(), info),
+                    Err(e) => warn!(server, "failed to send to zabbix: {}", e),
+                }
+            }
+        });
+        Sender {
+            tx,
+            host: config.host.clone(),
+            discovered: None,
+        }
+    }
+
+    /// Sends each cycle's good readings from `events` until the returned
+    /// consumer is dropped.
+    pub fn consume(mut self, events: &Events) -> Consumer {
+        let mut read = Vec::new();
+        events.consume("zabbix", move |event| match event {
+            Event::Reading(Reading {
+                name,
+                outcome: Ok(sample),
+                ..
+            }) => read.push((name.clone(), sample.temp)),
+            Event::Reading(_) => {}
+            Event::Polled { probes } => {
+                let probes: Vec<(&str, &str)> = probes
+                    .iter()
+                    .map(|(id, name)| (id.as_str(), name.as_str()))
+                    .collect();
+                let readings: Vec<(&str, f32)> = read
+                    .iter()
+                    .map(|(name, temp)| (name.as_str(), *temp))
+                    .collect();
+                self.send(&probes, &readings);
+                read.clear();
+            }
+        })
+    }
+
+    /// Sends a cycle's readings as `(name, °c)`, with the probes as
+    /// `(id, name)` for discovery first if they've changed.
+    pub fn send(&mut self, probes: &[(&str, &str)], readings: &[(&str, f32)]) {
+        let clock = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let item = |key: String, value: String| Item {
+            host: self.host.clone(),
+            key,
+            value,
+            clock,
+        };
+
+        let mut items = Vec::new();
+        let current: Vec<(String, String)> = probes
+            .iter()
+            .map(|(id, name)| (id.to_string(), name.to_string()))
+            .collect();
+        let stale = self
+            .discovered
+            .as_ref()
+            .is_none_or(|(sent, at)| *sent != current || at.elapsed() >= REDISCOVER);
+        if stale {
+            items.push(item(DISCOVERY_KEY.to_string(), discovery(probes)));
+        }
+        items.extend(
+            readings
+                .iter()
+                .map(|(name, temp)| item(temperature_key(name), temp.to_string())),
+        );
+        if items.is_empty() {
+            return;
+        }
+
+        match self.tx.try_send(items) {
+            Ok(()) => {
+                if stale {
+                    self.discovered = Some((current, Instant::now()));
+                }
+            }
+            Err(TrySendError::Full(_)) => warn!("zabbix server is behind, dropping readings"),
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+/// Frames a sender protocol message.
+fn frame(body: &[u8]) -> Vec<u8> {
+    let mut message = HEADER.to_vec();
+    message.extend((body.len() as u64).to_le_bytes());
+    message.extend(body);
+    message
+}
+
+/// Sends `items` and returns the server's summary, e.g. `processed: 2;
+/// failed: 0; total: 2; seconds spent: 0.000055`.
+fn send(server: &str, items: &[Item]) -> io::Result<String> {
+    let addr = server
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::other(format!("{} has no addresses", server)))?;
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let body = json!({ "request": "sender data", "data": items }).to_string();
+    stream.write_all(&frame(body.as_bytes()))?;
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply)?;
+    let reply = parse_reply(&reply)?;
+    match reply.response.as_str() {
+        "success" => Ok(reply.info.unwrap_or_default()),
+        other => Err(io::Error::other(format!(
+            "server answered {}: {}",
+            other,
+            reply.info.unwrap_or_default()
+        ))),
+    }
+}
+
+fn parse_reply(reply: &[u8]) -> io::Result<Reply> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a zabbix response");
+    let rest = reply.strip_prefix(HEADER).ok_or_else(invalid)?;
+    let (len, body) = rest.split_first_chunk::<8>().ok_or_else(invalid)?;
+    let len = u64::from_le_bytes(*len) as usize;
+    let body = body.get(..len).ok_or_else(invalid)?;
+    serde_json::from_slice(body).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_discovery() {
+        let json: serde_json::Value =
+            serde_json::from_str(&discovery(&[("28-abc123", "fermenter")])).unwrap();
+        assert_eq!(json[0]["{#PROBE}"], "fermenter");
+        assert_eq!(json[0]["{#PROBE.ID}"], "28-abc123");
+        assert_eq!(
+            temperature_key("fermenter"),
+            "tempmon.temperature[\"fermenter\"]"
+        );
+    }
+
+    #[test]
+    fn test_send() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0; 13];
+            stream.read_exact(&mut header).unwrap();
+            assert!(header.starts_with(HEADER));
+            let len = u64::from_le_bytes(header[5..].try_into().unwrap());
+            let mut body = vec![0; len as usize];
+            stream.read_exact(&mut body).unwrap();
+            let reply = r#"{"response":"success","info":"processed: 1; failed: 0"}"#;
+            stream.write_all(&frame(reply.as_bytes())).unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        });
+
+        let items = [Item {
+            host: "brewery".to_string(),
+            key: temperature_key("fermenter"),
+            value: "18.5".to_string(),
+            clock: 1_800_000_000,
+        }];
+        assert_eq!(send(&server, &items).unwrap(), "processed: 1; failed: 0");
+
+        let request = handle.join().unwrap();
+        assert_eq!(request["request"], "sender data");
+        assert_eq!(request["data"][0]["host"], "brewery");
+        assert_eq!(request["data"][0]["value"], "18.5");
+
+        assert!(parse_reply(b"HTTP/1.1 400").is_err());
+    }
+}